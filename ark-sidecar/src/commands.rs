@@ -23,7 +23,7 @@ pub(crate) fn decode_code(code: &str, is_base64: bool) -> Result<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::Command;
+    use crate::types::{Command, HistoryAction, HistoryExportFormat};
 
     fn parse_from(args: &[&str]) -> Cli {
         Cli::parse_from(args)
@@ -80,7 +80,31 @@ mod tests {
     #[test]
     fn parse_console_mode() {
         let cli = parse_from(&["sidecar", "console", "--connection-file", "connection.json"]);
-        assert!(matches!(cli.command, Command::Console { .. }));
+        match cli.command {
+            Command::Console(args) => {
+                assert_eq!(args.connection_file, "connection.json");
+                assert_eq!(args.edit_mode, None);
+            }
+            _ => panic!("Expected Console command"),
+        }
+    }
+
+    #[test]
+    fn parse_console_vi_edit_mode() {
+        let cli = parse_from(&[
+            "sidecar",
+            "console",
+            "--connection-file",
+            "connection.json",
+            "--edit-mode",
+            "vi",
+        ]);
+        match cli.command {
+            Command::Console(args) => {
+                assert_eq!(args.edit_mode, Some(crate::types::ConsoleEditMode::Vi));
+            }
+            _ => panic!("Expected Console command"),
+        }
     }
 
     #[test]
@@ -112,6 +136,28 @@ mod tests {
         assert!(matches!(cli.command, Command::WatchPlot { .. }));
     }
 
+    #[test]
+    fn parse_history_commands() {
+        let cli = parse_from(&["sidecar", "history", "import", ".Rhistory"]);
+        match cli.command {
+            Command::History {
+                action: HistoryAction::Import { path, project },
+            } => {
+                assert_eq!(path, ".Rhistory");
+                assert_eq!(project, None);
+            }
+            _ => panic!("Expected history import command"),
+        }
+
+        let cli = parse_from(&["sidecar", "history", "export", "--format", "json"]);
+        match cli.command {
+            Command::History {
+                action: HistoryAction::Export { format, .. },
+            } => assert_eq!(format, HistoryExportFormat::Json),
+            _ => panic!("Expected history export command"),
+        }
+    }
+
     #[test]
     fn decode_code_handles_base64() {
         let encoded = base64::engine::general_purpose::STANDARD.encode("plot(1:10)");
@@ -8,7 +8,7 @@ use zeromq::util::PeerIdentity;
 use zeromq::{DealerSocket, Socket as ZmqSocket, SocketOptions};
 
 use runtimelib::{
-    CommId, CommOpen, Connection, ConnectionInfo, ExecutionState, JupyterMessage,
+    CommClose, CommId, CommOpen, Connection, ConnectionInfo, ExecutionState, JupyterMessage,
     JupyterMessageContent,
 };
 
@@ -64,6 +64,21 @@ pub(crate) async fn send_help_comm_open(
         .context("Failed to send help comm_open")
 }
 
+pub(crate) async fn send_comm_close(
+    shell: &mut runtimelib::ClientShellConnection,
+    comm_id: &str,
+) -> Result<()> {
+    let comm_close = CommClose {
+        comm_id: CommId(comm_id.to_string()),
+        data: Map::new(),
+    };
+    let message = JupyterMessage::new(comm_close, None);
+    shell
+        .send(message)
+        .await
+        .context("Failed to send comm_close")
+}
+
 pub(crate) async fn send_ui_comm_open(
     shell: &mut runtimelib::ClientShellConnection,
     comm_id: &str,
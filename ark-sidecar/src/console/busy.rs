@@ -0,0 +1,197 @@
+// Busy feedback for console executions.
+//
+// While R runs without printing, a spinner with the elapsed time is drawn
+// on stderr and cleared as soon as output arrives. Runs longer than the
+// `notify_after` threshold end with a dimmed "finished in" footer and a
+// terminal notification, so a long model fit can run in the background.
+
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+use super::output::format_duration;
+use super::terminal::TerminalInfo;
+use crate::types::Notification;
+
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// How often the spinner is redrawn.
+pub(crate) const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Quick executions finish before the spinner would appear.
+const SPINNER_DELAY: Duration = Duration::from_millis(500);
+
+/// Busy feedback settings from the console config.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BusyOptions {
+    pub spinner: bool,
+    /// Executions at least this long print a footer and notify.
+    pub notify_after: Option<Duration>,
+    pub notification: Notification,
+}
+
+/// Pick a notification escape for the terminal.
+///
+/// iTerm2, WezTerm, Ghostty and kitty show OSC 9 messages; VTE terminals
+/// (GNOME Terminal, Tilix) and urxvt use OSC 777. Elsewhere the bell is
+/// the only notification, and multiplexers would swallow the escapes.
+pub(crate) fn detect_notification(terminal: &TerminalInfo) -> Notification {
+    if terminal.multiplexed {
+        return Notification::Bell;
+    }
+    if terminal.is_iterm2() || terminal.is_wezterm() || terminal.is_ghostty() || terminal.is_kitty()
+    {
+        Notification::Osc9
+    } else if terminal.vte || terminal.term.starts_with("rxvt") {
+        Notification::Osc777
+    } else {
+        Notification::Bell
+    }
+}
+
+/// Resolve `auto` against the current environment.
+pub(crate) fn resolve_notification(setting: Notification) -> Notification {
+    match setting {
+        Notification::Auto => detect_notification(&TerminalInfo::current()),
+        notification => notification,
+    }
+}
+
+/// The escape sequence that shows `message` as a notification.
+fn notification_sequence(notification: Notification, message: &str) -> String {
+    // Control characters would end the escape early
+    let message: String = message.chars().filter(|ch| !ch.is_control()).collect();
+    match notification {
+        Notification::Osc9 => format!("\x1b]9;{message}\x07"),
+        Notification::Osc777 => format!("\x1b]777;notify;R console;{message}\x07"),
+        Notification::Bell => "\x07".to_string(),
+        Notification::Auto | Notification::None => String::new(),
+    }
+}
+
+/// Send a terminal notification.
+pub(crate) fn notify(notification: Notification, message: &str) {
+    let sequence = notification_sequence(notification, message);
+    if sequence.is_empty() {
+        return;
+    }
+    let mut stdout = io::stdout();
+    let _ = stdout.write_all(sequence.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// The spinner line: a frame and the time R has been busy.
+fn spinner_line(frame: usize, elapsed: Duration) -> String {
+    let spinner = SPINNER_FRAMES[frame % SPINNER_FRAMES.len()];
+    format!("{spinner} R is busy ({})", format_duration(elapsed))
+}
+
+/// Spinner for one execution.
+pub(crate) struct BusyIndicator {
+    enabled: bool,
+    started: Instant,
+    frame: usize,
+    visible: bool,
+    /// The output so far ends with a newline, so the spinner has a line
+    /// of its own.
+    at_line_start: bool,
+}
+
+impl BusyIndicator {
+    /// Start timing an execution; the spinner is only drawn on a terminal.
+    pub fn start(enabled: bool) -> Self {
+        Self {
+            enabled: enabled && io::stderr().is_terminal(),
+            started: Instant::now(),
+            frame: 0,
+            visible: false,
+            at_line_start: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Redraw the spinner while no output is streaming.
+    pub fn tick(&mut self) {
+        let elapsed = self.started.elapsed();
+        if !self.enabled || !self.at_line_start || elapsed < SPINNER_DELAY {
+            return;
+        }
+        let mut stderr = io::stderr();
+        let _ = write!(stderr, "\r{}\x1b[K", spinner_line(self.frame, elapsed));
+        let _ = stderr.flush();
+        self.frame += 1;
+        self.visible = true;
+    }
+
+    /// Remove the spinner before anything else is printed.
+    pub fn clear(&mut self) {
+        if self.visible {
+            let mut stderr = io::stderr();
+            let _ = write!(stderr, "\r\x1b[K");
+            let _ = stderr.flush();
+            self.visible = false;
+        }
+    }
+
+    /// Note printed output; the spinner waits for the end of its line.
+    pub fn printed(&mut self, text: &str) {
+        if !text.is_empty() {
+            self.at_line_start = text.ends_with('\n');
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::terminal::test_terminal;
+
+    #[test]
+    fn notification_follows_the_terminal() {
+        assert_eq!(
+            detect_notification(&test_terminal(&[("TERM_PROGRAM", "iTerm.app")])),
+            Notification::Osc9
+        );
+        assert_eq!(
+            detect_notification(&test_terminal(&[("VTE_VERSION", "7600")])),
+            Notification::Osc777
+        );
+        assert_eq!(
+            detect_notification(&test_terminal(&[
+                ("TERM", "xterm-kitty"),
+                ("TMUX", "/tmp/t")
+            ])),
+            Notification::Bell
+        );
+        assert_eq!(detect_notification(&test_terminal(&[])), Notification::Bell);
+    }
+
+    #[test]
+    fn notification_sequences() {
+        assert_eq!(
+            notification_sequence(Notification::Osc9, "finished in 3m12s"),
+            "\x1b]9;finished in 3m12s\x07"
+        );
+        assert_eq!(
+            notification_sequence(Notification::Osc777, "done\x07"),
+            "\x1b]777;notify;R console;done\x07"
+        );
+        assert_eq!(notification_sequence(Notification::Bell, "done"), "\x07");
+        assert_eq!(notification_sequence(Notification::None, "done"), "");
+    }
+
+    #[test]
+    fn spinner_shows_elapsed_time() {
+        assert_eq!(
+            spinner_line(0, Duration::from_millis(4200)),
+            "⠋ R is busy (4.2s)"
+        );
+        assert!(spinner_line(11, Duration::ZERO).starts_with('⠙'));
+    }
+}
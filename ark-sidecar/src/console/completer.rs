@@ -1,16 +1,23 @@
-// LSP-backed tab completion for console mode.
+// Tab completion for console mode.
 //
 // Implements reedline::Completer by sending textDocument/completion
-// requests to Ark's LSP server via the LspClient.
+// requests to Ark's LSP server via the LspClient. Meta-command lines
+// (`:help`, `:log-level debug`, ...) complete from their own candidates,
+// and strings also complete file paths.
 
 use std::sync::Arc;
 use std::time::Duration;
 
-use reedline::{Completer, Suggestion};
+use reedline::{Completer, Span, Suggestion};
 use tracing::{debug, warn};
 
-use crate::lsp_client::completion::completion_response_to_suggestions;
+use super::completion_menu::CompletionDocs;
+use super::meta_command::meta_command_suggestions;
+use super::path_completion::{path_suggestions, string_content_start};
+use super::session_state::{lock_session_state, SharedSessionState};
+use crate::lsp_client::completion::completion_response_to_entries;
 use crate::lsp_client::virtual_document::DebouncedVirtualDocument;
+use crate::types::CompletionMatching;
 
 const LSP_COMPLETION_TIMEOUT: Duration = Duration::from_millis(1500);
 
@@ -20,22 +27,39 @@ pub(crate) struct LspCompleter {
     virtual_document: Arc<DebouncedVirtualDocument>,
     /// Handle to the tokio runtime for blocking on async operations.
     runtime_handle: tokio::runtime::Handle,
+    /// How candidates are matched against the typed word.
+    matching: CompletionMatching,
+    /// Results shared with the completion menu for documentation lookups.
+    docs: Arc<CompletionDocs>,
 }
 
 impl LspCompleter {
     pub fn new(
         virtual_document: Arc<DebouncedVirtualDocument>,
         runtime_handle: tokio::runtime::Handle,
+        matching: CompletionMatching,
+        docs: Arc<CompletionDocs>,
     ) -> Self {
         Self {
             virtual_document,
             runtime_handle,
+            matching,
+            docs,
         }
     }
 }
 
 impl Completer for LspCompleter {
     fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        // The menu reloads suggestions after resolving documentation
+        if let Some(suggestions) = self.docs.cached(line, pos) {
+            debug!(
+                count = suggestions.len(),
+                "LspCompleter: reusing completions"
+            );
+            return suggestions;
+        }
+
         debug!(
             pos = pos,
             line_len = line.len(),
@@ -55,8 +79,13 @@ impl Completer for LspCompleter {
             .await
         }) {
             Ok(Ok(response)) => {
-                let suggestions = completion_response_to_suggestions(response, line, pos);
+                let entries = completion_response_to_entries(response, line, pos, self.matching);
+                let suggestions: Vec<Suggestion> = entries
+                    .iter()
+                    .map(|(_, suggestion)| suggestion.clone())
+                    .collect();
                 debug!(count = suggestions.len(), "LspCompleter: got completions");
+                self.docs.store(line, pos, entries);
                 suggestions
             }
             Ok(Err(err)) => {
@@ -73,3 +102,89 @@ impl Completer for LspCompleter {
         }
     }
 }
+
+/// Console completer: meta-command candidates for `:` lines, LSP
+/// completion for R code when an LSP client is available, and file paths
+/// inside strings.
+pub(crate) struct ConsoleCompleter {
+    lsp: Option<LspCompleter>,
+    /// Source of the kernel's working directory for relative paths.
+    session_state: SharedSessionState,
+}
+
+impl ConsoleCompleter {
+    pub fn new(lsp: Option<LspCompleter>, session_state: SharedSessionState) -> Self {
+        Self { lsp, session_state }
+    }
+}
+
+impl Completer for ConsoleCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        if let Some(suggestions) = meta_command_suggestions(line, pos) {
+            debug!(
+                count = suggestions.len(),
+                "ConsoleCompleter: meta-command completions"
+            );
+            return suggestions;
+        }
+        let mut suggestions = match &mut self.lsp {
+            Some(lsp) => lsp.complete(line, pos),
+            None => vec![],
+        };
+
+        if let Some(content_start) = string_content_start(line, pos) {
+            let working_directory = lock_session_state(&self.session_state)
+                .working_directory
+                .clone();
+            // Paths go after the LSP items, whose positions the completion
+            // menu uses to resolve documentation.
+            let paths: Vec<Suggestion> = path_suggestions(
+                &line[content_start..pos],
+                Span::new(content_start, pos),
+                working_directory.as_deref(),
+            )
+            .into_iter()
+            .filter(|path| !suggestions.iter().any(|item| item.value == path.value))
+            .collect();
+            debug!(count = paths.len(), "ConsoleCompleter: path completions");
+            suggestions.extend(paths);
+        }
+        suggestions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::session_state::SessionState;
+    use crate::console::test_dir::TestDir;
+
+    #[test]
+    fn meta_commands_complete_without_lsp() {
+        let mut completer = ConsoleCompleter::new(None, SessionState::default().shared());
+        let values: Vec<String> = completer
+            .complete(":inter", 6)
+            .into_iter()
+            .map(|suggestion| suggestion.value)
+            .collect();
+        assert_eq!(values, vec![":interrupt".to_string()]);
+        assert!(completer.complete("mean(", 5).is_empty());
+    }
+
+    #[test]
+    fn strings_complete_paths_without_lsp() {
+        let directory = TestDir::new("completer");
+        std::fs::create_dir_all(directory.join("data")).unwrap();
+        let state = SessionState {
+            working_directory: Some(directory.path().display().to_string()),
+            ..SessionState::default()
+        };
+        let mut completer = ConsoleCompleter::new(None, state.shared());
+
+        let suggestions = completer.complete("read.csv(\"da", 12);
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].value, "data/");
+        assert_eq!(suggestions[0].span, Span::new(10, 12));
+        assert!(completer.complete("read.csv(da", 11).is_empty());
+    }
+}
@@ -0,0 +1,538 @@
+// Completion menu with a documentation panel.
+//
+// Wraps reedline's IdeMenu, which shows the highlighted suggestion's
+// description beside the list. Ark leaves documentation out of completion
+// responses, so the highlighted item and the few after it are resolved
+// with `completionItem/resolve` in the background. Resolved documentation
+// is kept per item while the menu is open and filled in on the first
+// repaint after it arrives.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lsp_types::CompletionItem;
+use reedline::{Completer, Editor, IdeMenu, Menu, MenuEvent, Painter, Suggestion};
+use tracing::debug;
+
+use super::indent::{record_menu_active, SharedBufferView};
+use crate::lsp_client::completion::completion_item_description;
+use crate::lsp_client::virtual_document::DebouncedVirtualDocument;
+
+/// Resolves slower than this are dropped; the item keeps its short
+/// description.
+const LSP_RESOLVE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Entries resolved from the highlighted one on, so moving down the list
+/// usually finds the documentation already there.
+const RESOLVE_AHEAD: usize = 3;
+
+/// The documentation panel takes half the terminal, within these bounds.
+const MIN_DESCRIPTION_WIDTH: u16 = 30;
+const MAX_DESCRIPTION_WIDTH: u16 = 80;
+
+/// Width of the documentation panel, including its border.
+pub(crate) fn description_width(terminal_columns: u16) -> u16 {
+    (terminal_columns / 2).clamp(MIN_DESCRIPTION_WIDTH, MAX_DESCRIPTION_WIDTH)
+}
+
+#[derive(Debug)]
+struct CachedEntry {
+    item: CompletionItem,
+    suggestion: Suggestion,
+}
+
+/// Completion results for one input, kept while the menu is open.
+#[derive(Debug, Default)]
+struct CompletionCache {
+    /// Bumped whenever the entries are replaced; zero until the first
+    /// completion.
+    generation: u64,
+    line: String,
+    pos: usize,
+    entries: Vec<CachedEntry>,
+}
+
+/// Identifies a completion item: items from different packages share a
+/// label, e.g. `filter` from dplyr and stats, but not their detail and
+/// resolve data.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ItemKey {
+    label: String,
+    detail: Option<String>,
+    data: Option<String>,
+}
+
+impl ItemKey {
+    fn new(item: &CompletionItem) -> Self {
+        Self {
+            label: item.label.clone(),
+            detail: item.detail.clone(),
+            data: item.data.as_ref().map(|data| data.to_string()),
+        }
+    }
+}
+
+/// Documentation resolved in the background, by completion item.
+#[derive(Debug, Default)]
+struct ResolvedDocs {
+    /// `None` while the resolve is in flight, or if it found nothing.
+    /// Failed resolves are removed, so they are tried again.
+    by_item: HashMap<ItemKey, Option<String>>,
+    /// Bumped whenever the menu closes, so a resolve still in flight
+    /// doesn't store into the next menu's documentation.
+    generation: u64,
+    /// Documentation arrived that the menu hasn't filled in yet.
+    arrived: bool,
+}
+
+impl ResolvedDocs {
+    /// Record a resolve's outcome: `None` when it failed, so the item is
+    /// tried again, and `Some(None)` when it found no documentation.
+    fn finish(&mut self, key: ItemKey, description: Option<Option<String>>) {
+        match description {
+            None => {
+                self.by_item.remove(&key);
+            }
+            Some(None) => {}
+            Some(Some(description)) => {
+                debug!(label = %key.label, "CompletionMenu: documentation resolved");
+                self.by_item.insert(key, Some(description));
+                self.arrived = true;
+            }
+        }
+    }
+
+    /// Fill in the resolved documentation of `entries`.
+    ///
+    /// Returns whether a description changed.
+    fn apply(&self, entries: &mut [CachedEntry]) -> bool {
+        let mut changed = false;
+        for entry in entries {
+            let Some(Some(description)) = self.by_item.get(&ItemKey::new(&entry.item)) else {
+                continue;
+            };
+            if entry.suggestion.description.as_ref() != Some(description) {
+                entry.suggestion.description = Some(description.clone());
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// Completion items behind the menu's suggestions, and their resolved
+/// documentation.
+pub(crate) struct CompletionDocs {
+    virtual_document: Arc<DebouncedVirtualDocument>,
+    runtime_handle: tokio::runtime::Handle,
+    cache: Mutex<CompletionCache>,
+    resolved: Arc<Mutex<ResolvedDocs>>,
+}
+
+impl CompletionDocs {
+    pub fn new(
+        virtual_document: Arc<DebouncedVirtualDocument>,
+        runtime_handle: tokio::runtime::Handle,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            virtual_document,
+            runtime_handle,
+            cache: Mutex::new(CompletionCache::default()),
+            resolved: Arc::new(Mutex::new(ResolvedDocs::default())),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CompletionCache> {
+        self.cache.lock().expect("completion cache mutex poisoned")
+    }
+
+    fn lock_resolved(&self) -> std::sync::MutexGuard<'_, ResolvedDocs> {
+        self.resolved.lock().expect("resolved docs mutex poisoned")
+    }
+
+    /// Suggestions already computed for this input, with any resolved
+    /// documentation.
+    pub fn cached(&self, line: &str, pos: usize) -> Option<Vec<Suggestion>> {
+        let cache = self.lock();
+        (cache.generation > 0 && cache.line == line && cache.pos == pos).then(|| {
+            cache
+                .entries
+                .iter()
+                .map(|entry| entry.suggestion.clone())
+                .collect()
+        })
+    }
+
+    /// Remember the completion entries for this input, with the
+    /// documentation already resolved for them.
+    pub fn store(&self, line: &str, pos: usize, entries: Vec<(CompletionItem, Suggestion)>) {
+        let mut cache = self.lock();
+        cache.generation += 1;
+        cache.line = line.to_string();
+        cache.pos = pos;
+        cache.entries = entries
+            .into_iter()
+            .map(|(item, suggestion)| CachedEntry { item, suggestion })
+            .collect();
+        self.lock_resolved().apply(&mut cache.entries);
+    }
+
+    /// Forget the cached entries and their documentation, so the next
+    /// completion asks the server.
+    pub fn clear(&self) {
+        let mut cache = self.lock();
+        cache.generation += 1;
+        cache.entries.clear();
+        let mut resolved = self.lock_resolved();
+        resolved.generation += 1;
+        resolved.by_item.clear();
+        resolved.arrived = false;
+    }
+
+    /// Resolve the entries from `index` on in the background, skipping
+    /// items that are documented or being resolved.
+    fn request(&self, index: usize) {
+        let (generation, items): (u64, Vec<CompletionItem>) = {
+            let cache = self.lock();
+            let mut resolved = self.lock_resolved();
+            let generation = resolved.generation;
+            let items = cache
+                .entries
+                .iter()
+                .skip(index)
+                .take(RESOLVE_AHEAD)
+                .filter(|entry| entry.item.documentation.is_none())
+                .filter(|entry| {
+                    resolved
+                        .by_item
+                        .insert(ItemKey::new(&entry.item), None)
+                        .is_none()
+                })
+                .map(|entry| entry.item.clone())
+                .collect();
+            (generation, items)
+        };
+
+        for item in items {
+            let virtual_document = Arc::clone(&self.virtual_document);
+            let resolved = Arc::clone(&self.resolved);
+            self.runtime_handle.spawn(async move {
+                let description = match tokio::time::timeout(
+                    LSP_RESOLVE_TIMEOUT,
+                    virtual_document.resolve_completion(&item),
+                )
+                .await
+                {
+                    Ok(Ok(resolved)) => Some(completion_item_description(&resolved)),
+                    Ok(Err(err)) => {
+                        debug!(error = ?err, label = %item.label, "CompletionMenu: resolve failed");
+                        None
+                    }
+                    Err(_) => {
+                        debug!(
+                            label = %item.label,
+                            "CompletionMenu: resolve timed out ({}ms)",
+                            LSP_RESOLVE_TIMEOUT.as_millis()
+                        );
+                        None
+                    }
+                };
+                let mut resolved = resolved.lock().expect("resolved docs mutex poisoned");
+                if resolved.generation != generation {
+                    return;
+                }
+                resolved.finish(ItemKey::new(&item), description);
+            });
+        }
+    }
+
+    /// Fill in documentation that arrived since the last call.
+    ///
+    /// Returns whether a description changed.
+    fn apply_arrived(&self) -> bool {
+        let mut cache = self.lock();
+        let mut resolved = self.lock_resolved();
+        if !std::mem::take(&mut resolved.arrived) {
+            return false;
+        }
+        resolved.apply(&mut cache.entries)
+    }
+}
+
+/// How a menu event moves IdeMenu's selection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SelectionChange {
+    Reset,
+    Next,
+    Previous,
+}
+
+/// Apply `change` the way IdeMenu does, wrapping at both ends.
+fn next_selection(selected: usize, change: SelectionChange, count: usize) -> usize {
+    match change {
+        SelectionChange::Reset => 0,
+        SelectionChange::Next if selected + 1 < count => selected + 1,
+        SelectionChange::Next => 0,
+        SelectionChange::Previous if selected > 0 => selected - 1,
+        SelectionChange::Previous => count.saturating_sub(1),
+    }
+}
+
+/// IdeMenu that fills in documentation for the highlighted suggestion.
+pub(crate) struct CompletionMenu {
+    inner: IdeMenu,
+    docs: Option<Arc<CompletionDocs>>,
+    /// IdeMenu's selection is private; this follows it through the events.
+    selected: usize,
+    pending: Option<SelectionChange>,
+    /// Told when the menu opens or closes, so Enter isn't auto-indented
+    /// while it accepts a completion.
+    buffer_view: Option<SharedBufferView>,
+}
+
+impl CompletionMenu {
+    pub fn new(inner: IdeMenu, docs: Option<Arc<CompletionDocs>>) -> Self {
+        Self {
+            inner,
+            docs,
+            selected: 0,
+            pending: None,
+            buffer_view: None,
+        }
+    }
+
+    pub fn with_buffer_view(mut self, buffer_view: SharedBufferView) -> Self {
+        self.buffer_view = Some(buffer_view);
+        self
+    }
+
+    fn clear_docs(&self) {
+        if let Some(docs) = &self.docs {
+            docs.clear();
+        }
+    }
+}
+
+impl Menu for CompletionMenu {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn indicator(&self) -> &str {
+        self.inner.indicator()
+    }
+
+    fn is_active(&self) -> bool {
+        self.inner.is_active()
+    }
+
+    fn menu_event(&mut self, event: MenuEvent) {
+        // IdeMenu only keeps the latest event, so the pending change is
+        // replaced the same way.
+        self.pending = match &event {
+            MenuEvent::Activate(_) => {
+                self.clear_docs();
+                Some(SelectionChange::Reset)
+            }
+            MenuEvent::Deactivate => {
+                self.clear_docs();
+                None
+            }
+            MenuEvent::Edit(_) => Some(SelectionChange::Reset),
+            MenuEvent::NextElement | MenuEvent::MoveDown => Some(SelectionChange::Next),
+            MenuEvent::PreviousElement | MenuEvent::MoveUp => Some(SelectionChange::Previous),
+            _ => None,
+        };
+        self.inner.menu_event(event);
+        if let Some(buffer_view) = &self.buffer_view {
+            record_menu_active(buffer_view, self.inner.is_active());
+        }
+    }
+
+    fn can_quick_complete(&self) -> bool {
+        self.inner.can_quick_complete()
+    }
+
+    fn can_partially_complete(
+        &mut self,
+        values_updated: bool,
+        editor: &mut Editor,
+        completer: &mut dyn Completer,
+    ) -> bool {
+        self.selected = 0;
+        self.inner
+            .can_partially_complete(values_updated, editor, completer)
+    }
+
+    fn update_values(&mut self, editor: &mut Editor, completer: &mut dyn Completer) {
+        self.selected = 0;
+        self.inner.update_values(editor, completer);
+    }
+
+    fn update_working_details(
+        &mut self,
+        editor: &mut Editor,
+        completer: &mut dyn Completer,
+        painter: &Painter,
+    ) {
+        self.inner
+            .update_working_details(editor, completer, painter);
+        if let Some(change) = self.pending.take() {
+            let count = self.inner.get_values().len();
+            self.selected = next_selection(self.selected, change, count);
+        }
+
+        let Some(docs) = &self.docs else {
+            return;
+        };
+        if !self.inner.is_active() {
+            return;
+        }
+        docs.request(self.selected);
+        if !docs.apply_arrived() {
+            return;
+        }
+
+        // The completer now returns the documented suggestions. Reloading
+        // resets IdeMenu's selection, whose position is private, so step
+        // back to the highlighted entry.
+        self.inner.update_values(editor, completer);
+        self.inner.menu_event(MenuEvent::Edit(true));
+        self.inner
+            .update_working_details(editor, completer, painter);
+        for _ in 0..self.selected {
+            self.inner.menu_event(MenuEvent::NextElement);
+            self.inner
+                .update_working_details(editor, completer, painter);
+        }
+    }
+
+    fn replace_in_buffer(&self, editor: &mut Editor) {
+        self.inner.replace_in_buffer(editor);
+    }
+
+    fn menu_required_lines(&self, terminal_columns: u16) -> u16 {
+        self.inner.menu_required_lines(terminal_columns)
+    }
+
+    fn menu_string(&self, available_lines: u16, use_ansi_coloring: bool) -> String {
+        self.inner.menu_string(available_lines, use_ansi_coloring)
+    }
+
+    fn min_rows(&self) -> u16 {
+        self.inner.min_rows()
+    }
+
+    fn get_values(&self) -> &[Suggestion] {
+        self.inner.get_values()
+    }
+
+    fn set_cursor_pos(&mut self, pos: (u16, u16)) {
+        self.inner.set_cursor_pos(pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_follows_ide_menu_wrapping() {
+        assert_eq!(next_selection(0, SelectionChange::Next, 3), 1);
+        assert_eq!(next_selection(2, SelectionChange::Next, 3), 0);
+        assert_eq!(next_selection(0, SelectionChange::Previous, 3), 2);
+        assert_eq!(next_selection(2, SelectionChange::Previous, 3), 1);
+        assert_eq!(next_selection(2, SelectionChange::Reset, 3), 0);
+        assert_eq!(next_selection(0, SelectionChange::Previous, 0), 0);
+    }
+
+    fn item(label: &str, detail: &str) -> CompletionItem {
+        CompletionItem {
+            label: label.to_string(),
+            detail: Some(detail.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn entry(label: &str, description: Option<&str>) -> CachedEntry {
+        CachedEntry {
+            item: item(label, "{base}"),
+            suggestion: Suggestion {
+                value: label.to_string(),
+                description: description.map(str::to_string),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn resolved_docs_are_filled_in_by_item() {
+        let mut resolved = ResolvedDocs::default();
+        resolved.by_item.insert(
+            ItemKey::new(&item("mean", "{base}")),
+            Some("Arithmetic Mean".to_string()),
+        );
+        resolved
+            .by_item
+            .insert(ItemKey::new(&item("median", "{base}")), None);
+
+        let mut entries = vec![entry("mean", None), entry("median", Some("function"))];
+        assert!(resolved.apply(&mut entries));
+        assert_eq!(
+            entries[0].suggestion.description.as_deref(),
+            Some("Arithmetic Mean")
+        );
+        assert_eq!(
+            entries[1].suggestion.description.as_deref(),
+            Some("function")
+        );
+        assert!(!resolved.apply(&mut entries));
+    }
+
+    #[test]
+    fn same_named_items_keep_their_own_docs() {
+        let mut resolved = ResolvedDocs::default();
+        resolved.by_item.insert(
+            ItemKey::new(&item("filter", "{dplyr}")),
+            Some("Keep rows that match a condition".to_string()),
+        );
+
+        let mut entries = vec![CachedEntry {
+            item: item("filter", "{stats}"),
+            suggestion: Suggestion {
+                value: "filter".to_string(),
+                ..Default::default()
+            },
+        }];
+        assert!(!resolved.apply(&mut entries));
+        assert_eq!(entries[0].suggestion.description, None);
+    }
+
+    #[test]
+    fn failed_resolves_are_tried_again() {
+        let mut resolved = ResolvedDocs::default();
+        let mean = ItemKey::new(&item("mean", "{base}"));
+        let median = ItemKey::new(&item("median", "{stats}"));
+        resolved.by_item.insert(mean.clone(), None);
+        resolved.by_item.insert(median.clone(), None);
+
+        // A timeout or error forgets the item
+        resolved.finish(mean.clone(), None);
+        assert!(!resolved.by_item.contains_key(&mean));
+        // An answer without documentation is not asked for again
+        resolved.finish(median.clone(), Some(None));
+        assert_eq!(resolved.by_item.get(&median), Some(&None));
+        assert!(!resolved.arrived);
+
+        resolved.finish(mean.clone(), Some(Some("Arithmetic Mean".to_string())));
+        assert!(resolved.arrived);
+    }
+
+    #[test]
+    fn description_width_tracks_terminal() {
+        assert_eq!(description_width(40), MIN_DESCRIPTION_WIDTH);
+        assert_eq!(description_width(120), 60);
+        assert_eq!(description_width(300), MAX_DESCRIPTION_WIDTH);
+    }
+}
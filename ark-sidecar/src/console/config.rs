@@ -0,0 +1,423 @@
+// Console configuration.
+//
+// Settings are read from $XDG_CONFIG_HOME/krarkode/console.json and
+// overridden by command-line flags. Every field has a default, so a
+// missing or partial file is fine.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use super::history::APP_NAME;
+use super::prompt::DEFAULT_PROMPT_TEMPLATE;
+use crate::types::{
+    CompletionMatching, ConsoleArgs, ConsoleEditMode, ImageProtocol, LongOutput, Notification,
+    TranscriptFormat,
+};
+
+/// Settings for the interactive console.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "snake_case")]
+pub(crate) struct ConsoleConfig {
+    /// Line editing keymap (`emacs` or `vi`).
+    pub edit_mode: ConsoleEditMode,
+    /// Left prompt template; `{cwd}` and `{env}` expand to the kernel's
+    /// working directory and active pixi/conda environment.
+    pub prompt: String,
+    /// Show the duration and status of the last execution on the right.
+    pub right_prompt: bool,
+    /// Completion matching (`prefix` or `fuzzy`).
+    pub completion_matching: CompletionMatching,
+    /// Suggest the rest of the line from the project's history, accepted
+    /// with Right-arrow or word by word with Alt+F.
+    pub history_hints: bool,
+    /// Spaces per level when continuing multi-line input; 0 turns
+    /// auto-indentation off.
+    pub indent_width: usize,
+    /// Graphics protocol for plots (`auto`, `kitty`, `iterm2`, `sixel` or
+    /// `none`).
+    pub image_protocol: ImageProtocol,
+    /// Show HTML and markdown output (tables, headings, code) even when
+    /// the kernel also sends plain text.
+    pub rich_output: bool,
+    /// Output past `max_output_lines` (`print`, `truncate` or `page`).
+    pub long_output: LongOutput,
+    /// Lines of an execution's output printed before the rest is held
+    /// back for `:more`.
+    pub max_output_lines: usize,
+    /// Show a spinner with the elapsed time while R is busy.
+    pub spinner: bool,
+    /// Seconds after which a finished execution prints its duration and
+    /// notifies the terminal; 0 turns this off.
+    pub notify_after: u64,
+    /// Notification for long executions (`auto`, `osc9`, `osc777`, `bell`
+    /// or `none`).
+    pub notification: Notification,
+    /// Also print code and output from the kernel's other clients, such as
+    /// the editor; toggled with `:follow`.
+    pub follow: bool,
+    /// Ask Ark for `?topic` help pages and show them in the console. Ark
+    /// sends each page to a single help comm, so this takes the pages away
+    /// from the editor's help panel; off by default.
+    pub help_pages: bool,
+    /// Keep the console open when the kernel goes away and reattach once
+    /// it is reachable again, instead of exiting.
+    pub auto_reconnect: bool,
+    /// Listen on a Unix socket next to the connection file for code sent
+    /// by the editor. Anyone who can connect runs code in the session, so
+    /// this is opt-in and the socket is only accessible to the user.
+    pub inject_socket: bool,
+    /// File every session appends a transcript to.
+    pub transcript: Option<String>,
+    /// Transcript format (`log` or `jsonl`); by default `.jsonl` files get
+    /// JSON lines.
+    pub transcript_format: Option<TranscriptFormat>,
+}
+
+impl Default for ConsoleConfig {
+    fn default() -> Self {
+        Self {
+            edit_mode: ConsoleEditMode::default(),
+            prompt: DEFAULT_PROMPT_TEMPLATE.to_string(),
+            right_prompt: true,
+            completion_matching: CompletionMatching::default(),
+            history_hints: true,
+            indent_width: 2,
+            image_protocol: ImageProtocol::default(),
+            rich_output: false,
+            long_output: LongOutput::default(),
+            max_output_lines: 1000,
+            spinner: true,
+            notify_after: 30,
+            notification: Notification::default(),
+            follow: false,
+            help_pages: false,
+            auto_reconnect: false,
+            inject_socket: false,
+            transcript: None,
+            transcript_format: None,
+        }
+    }
+}
+
+impl ConsoleConfig {
+    /// Apply command-line overrides on top of the file settings.
+    fn apply_args(mut self, args: &ConsoleArgs) -> Self {
+        if let Some(edit_mode) = args.edit_mode {
+            self.edit_mode = edit_mode;
+        }
+        if let Some(prompt) = &args.prompt {
+            self.prompt = prompt.clone();
+        }
+        if let Some(completion_matching) = args.completion_matching {
+            self.completion_matching = completion_matching;
+        }
+        if args.no_history_hints {
+            self.history_hints = false;
+        }
+        if let Some(image_protocol) = args.image_protocol {
+            self.image_protocol = image_protocol;
+        }
+        if args.rich_output {
+            self.rich_output = true;
+        }
+        if let Some(long_output) = args.long_output {
+            self.long_output = long_output;
+        }
+        if let Some(max_output_lines) = args.max_output_lines {
+            self.max_output_lines = max_output_lines;
+        }
+        if args.no_spinner {
+            self.spinner = false;
+        }
+        if let Some(notify_after) = args.notify_after {
+            self.notify_after = notify_after;
+        }
+        if let Some(notification) = args.notification {
+            self.notification = notification;
+        }
+        if args.follow {
+            self.follow = true;
+        }
+        if args.help_pages {
+            self.help_pages = true;
+        }
+        if args.auto_reconnect {
+            self.auto_reconnect = true;
+        }
+        if args.inject_socket {
+            self.inject_socket = true;
+        }
+        if let Some(transcript) = &args.transcript {
+            self.transcript = Some(transcript.clone());
+        }
+        if let Some(transcript_format) = args.transcript_format {
+            self.transcript_format = Some(transcript_format);
+        }
+        self
+    }
+}
+
+/// Resolve the console config file path.
+///
+/// Returns `$XDG_CONFIG_HOME/krarkode/console.json`
+/// (defaults to `~/.config/krarkode/console.json` on Linux).
+pub(crate) fn config_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|p| p.join(APP_NAME).join("console.json"))
+}
+
+fn read_config_file(path: &Path) -> Result<ConsoleConfig> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Invalid console config {}", path.display()))
+}
+
+/// Load the console configuration file.
+///
+/// A missing config file yields the defaults; an unreadable or invalid
+/// one is logged and ignored so the console still starts.
+pub(crate) fn load_config_file() -> ConsoleConfig {
+    match config_path() {
+        Some(path) if path.exists() => match read_config_file(&path) {
+            Ok(config) => {
+                debug!(path = %path.display(), "Console: loaded config file");
+                config
+            }
+            Err(err) => {
+                warn!(error = ?err, "Console: ignoring invalid config file");
+                ConsoleConfig::default()
+            }
+        },
+        _ => ConsoleConfig::default(),
+    }
+}
+
+/// Load the console configuration and apply command-line overrides.
+pub(crate) fn load_console_config(args: &ConsoleArgs) -> ConsoleConfig {
+    load_config_file().apply_args(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(edit_mode: Option<ConsoleEditMode>) -> ConsoleArgs {
+        ConsoleArgs {
+            connection_file: "connection.json".to_string(),
+            r_binary_path: None,
+            edit_mode,
+            prompt: None,
+            completion_matching: None,
+            no_history_hints: false,
+            image_protocol: None,
+            rich_output: false,
+            long_output: None,
+            max_output_lines: None,
+            no_spinner: false,
+            notify_after: None,
+            notification: None,
+            follow: false,
+            help_pages: false,
+            auto_reconnect: false,
+            inject_socket: false,
+            transcript: None,
+            transcript_format: None,
+        }
+    }
+
+    #[test]
+    fn empty_config_uses_defaults() {
+        let config: ConsoleConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, ConsoleConfig::default());
+        assert_eq!(config.edit_mode, ConsoleEditMode::Emacs);
+    }
+
+    #[test]
+    fn config_parses_prompt_settings() {
+        let config: ConsoleConfig =
+            serde_json::from_str(r#"{"prompt": "{cwd} >", "right_prompt": false}"#).unwrap();
+        assert_eq!(config.prompt, "{cwd} >");
+        assert!(!config.right_prompt);
+        assert_eq!(config.edit_mode, ConsoleEditMode::Emacs);
+    }
+
+    #[test]
+    fn config_parses_completion_matching() {
+        let config: ConsoleConfig =
+            serde_json::from_str(r#"{"completion_matching": "fuzzy"}"#).unwrap();
+        assert_eq!(config.completion_matching, CompletionMatching::Fuzzy);
+        assert_eq!(
+            ConsoleConfig::default().completion_matching,
+            CompletionMatching::Prefix
+        );
+    }
+
+    #[test]
+    fn history_hints_can_be_turned_off() {
+        assert!(ConsoleConfig::default().history_hints);
+        let config: ConsoleConfig = serde_json::from_str(r#"{"history_hints": false}"#).unwrap();
+        assert!(!config.history_hints);
+        let args = ConsoleArgs {
+            no_history_hints: true,
+            ..args(None)
+        };
+        assert!(!ConsoleConfig::default().apply_args(&args).history_hints);
+    }
+
+    #[test]
+    fn config_parses_indent_width() {
+        let config: ConsoleConfig = serde_json::from_str(r#"{"indent_width": 4}"#).unwrap();
+        assert_eq!(config.indent_width, 4);
+        assert_eq!(ConsoleConfig::default().indent_width, 2);
+    }
+
+    #[test]
+    fn image_protocol_flag_overrides_config_file() {
+        let config: ConsoleConfig = serde_json::from_str(r#"{"image_protocol": "sixel"}"#).unwrap();
+        assert_eq!(config.image_protocol, ImageProtocol::Sixel);
+        let args = ConsoleArgs {
+            image_protocol: Some(ImageProtocol::None),
+            ..args(None)
+        };
+        assert_eq!(config.apply_args(&args).image_protocol, ImageProtocol::None);
+    }
+
+    #[test]
+    fn rich_output_from_config_or_flag() {
+        let config: ConsoleConfig = serde_json::from_str(r#"{"rich_output": true}"#).unwrap();
+        assert!(config.apply_args(&args(None)).rich_output);
+        let args = ConsoleArgs {
+            rich_output: true,
+            ..args(None)
+        };
+        assert!(!ConsoleConfig::default().rich_output);
+        assert!(ConsoleConfig::default().apply_args(&args).rich_output);
+    }
+
+    #[test]
+    fn output_limit_settings() {
+        let config: ConsoleConfig =
+            serde_json::from_str(r#"{"long_output": "page", "max_output_lines": 50}"#).unwrap();
+        assert_eq!(config.long_output, LongOutput::Page);
+        assert_eq!(config.max_output_lines, 50);
+        let args = ConsoleArgs {
+            long_output: Some(LongOutput::Print),
+            ..args(None)
+        };
+        assert_eq!(config.apply_args(&args).long_output, LongOutput::Print);
+        assert_eq!(ConsoleConfig::default().long_output, LongOutput::Print);
+    }
+
+    #[test]
+    fn busy_feedback_settings() {
+        let config: ConsoleConfig =
+            serde_json::from_str(r#"{"notify_after": 120, "notification": "osc777"}"#).unwrap();
+        assert_eq!(config.notify_after, 120);
+        assert_eq!(config.notification, Notification::Osc777);
+        assert!(config.spinner);
+        let args = ConsoleArgs {
+            no_spinner: true,
+            notify_after: Some(0),
+            ..args(None)
+        };
+        let config = config.apply_args(&args);
+        assert!(!config.spinner);
+        assert_eq!(config.notify_after, 0);
+    }
+
+    #[test]
+    fn follow_from_config_or_flag() {
+        assert!(!ConsoleConfig::default().follow);
+        let config: ConsoleConfig = serde_json::from_str(r#"{"follow": true}"#).unwrap();
+        assert!(config.apply_args(&args(None)).follow);
+        let args = ConsoleArgs {
+            follow: true,
+            ..args(None)
+        };
+        assert!(ConsoleConfig::default().apply_args(&args).follow);
+    }
+
+    #[test]
+    fn auto_reconnect_is_opt_in() {
+        assert!(!ConsoleConfig::default().auto_reconnect);
+        let config: ConsoleConfig = serde_json::from_str(r#"{"auto_reconnect": true}"#).unwrap();
+        assert!(config.auto_reconnect);
+        let args = ConsoleArgs {
+            auto_reconnect: true,
+            ..args(None)
+        };
+        assert!(ConsoleConfig::default().apply_args(&args).auto_reconnect);
+    }
+
+    #[test]
+    fn help_pages_are_opt_in() {
+        assert!(!ConsoleConfig::default().help_pages);
+        let args = ConsoleArgs {
+            help_pages: true,
+            ..args(None)
+        };
+        assert!(ConsoleConfig::default().apply_args(&args).help_pages);
+    }
+
+    #[test]
+    fn inject_socket_is_opt_in() {
+        assert!(!ConsoleConfig::default().inject_socket);
+        let config: ConsoleConfig = serde_json::from_str(r#"{"inject_socket": true}"#).unwrap();
+        assert!(config.apply_args(&args(None)).inject_socket);
+        let args = ConsoleArgs {
+            inject_socket: true,
+            ..args(None)
+        };
+        assert!(ConsoleConfig::default().apply_args(&args).inject_socket);
+    }
+
+    #[test]
+    fn transcript_flag_overrides_config_file() {
+        let config: ConsoleConfig =
+            serde_json::from_str(r#"{"transcript": "~/r.log", "transcript_format": "log"}"#)
+                .unwrap();
+        let args = ConsoleArgs {
+            transcript: Some("session.jsonl".to_string()),
+            ..args(None)
+        };
+        let config = config.apply_args(&args);
+        assert_eq!(config.transcript.as_deref(), Some("session.jsonl"));
+        assert_eq!(config.transcript_format, Some(TranscriptFormat::Log));
+        assert_eq!(ConsoleConfig::default().transcript, None);
+    }
+
+    #[test]
+    fn config_parses_vi_edit_mode() {
+        let config: ConsoleConfig = serde_json::from_str(r#"{"edit_mode": "vi"}"#).unwrap();
+        assert_eq!(config.edit_mode, ConsoleEditMode::Vi);
+    }
+
+    #[test]
+    fn cli_flag_overrides_config_file() {
+        let config = ConsoleConfig {
+            edit_mode: ConsoleEditMode::Vi,
+            ..ConsoleConfig::default()
+        };
+        assert_eq!(
+            config
+                .clone()
+                .apply_args(&args(Some(ConsoleEditMode::Emacs)))
+                .edit_mode,
+            ConsoleEditMode::Emacs
+        );
+        assert_eq!(
+            config.apply_args(&args(None)).edit_mode,
+            ConsoleEditMode::Vi
+        );
+    }
+
+    #[test]
+    fn config_path_is_under_krarkode() {
+        if let Some(path) = config_path() {
+            assert!(path.ends_with("krarkode/console.json"));
+        }
+    }
+}
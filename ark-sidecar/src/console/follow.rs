@@ -0,0 +1,269 @@
+// Mirroring other clients' executions.
+//
+// The kernel loop only prints output whose parent is one of its own
+// execute requests, so code sent from the editor (through the watch-plot
+// sidecar) or from another console never shows up. In follow mode their
+// `execute_input` and output are printed too, behind a dimmed gutter that
+// marks them as foreign. `monitor` does the same without a prompt. The
+// queries a console runs for its own prompt are left out.
+
+use std::io::Write;
+
+use anyhow::{Context, Result};
+use nu_ansi_term::Color;
+use runtimelib::{
+    create_client_iopub_connection, ConnectionInfo, ExecuteRequest, ExecutionState, JupyterMessage,
+    JupyterMessageContent,
+};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{debug, error, info, warn};
+
+use super::image::resolve_image_protocol;
+use super::kernel_loop::is_comm_close_missing_data;
+use super::output::{format_iopub_content, LineBuffer, OutputOptions};
+use super::paste::echo_with_prompts;
+use crate::heartbeat::{spawn_heartbeat_monitor, stop_heartbeat_monitor};
+use crate::types::MonitorArgs;
+
+const FOREIGN_GUTTER: &str = "│ ";
+
+/// First line of the code a console runs to query the kernel, such as its
+/// startup session query. Other consoles don't mirror these executions.
+pub(crate) const CONSOLE_QUERY_MARKER: &str = "# ark-sidecar console query";
+
+/// An execute request for a console query. It is kept out of R's history,
+/// and marked so other consoles don't mirror it.
+pub(crate) fn console_query(code: &str) -> ExecuteRequest {
+    let mut request = ExecuteRequest::new(format!("{CONSOLE_QUERY_MARKER}\n{code}"));
+    request.store_history = false;
+    request
+}
+
+/// Whether `message` answers a request from another client's session.
+pub(crate) fn is_foreign(message: &JupyterMessage, session_id: &str) -> bool {
+    message
+        .parent_header
+        .as_ref()
+        .is_some_and(|parent| parent.session != session_id)
+}
+
+/// Formats foreign messages a whole line at a time, so stream chunks from
+/// another execution never land in the middle of the console's own lines.
+#[derive(Debug, Default)]
+pub(crate) struct ForeignOutput {
+    lines: LineBuffer,
+    /// The request whose execution is another console's query, hidden
+    /// until the kernel is idle again.
+    query: Option<String>,
+}
+
+impl ForeignOutput {
+    /// Text to print for a foreign message, if any.
+    pub fn mirror(&mut self, message: &JupyterMessage, options: OutputOptions) -> Option<String> {
+        let parent_msg_id = message.parent_header.as_ref().map(|h| h.msg_id.as_str());
+        if self.query.is_some() && self.query.as_deref() == parent_msg_id {
+            if is_idle(&message.content) {
+                self.query = None;
+            }
+            return None;
+        }
+        match &message.content {
+            JupyterMessageContent::ExecuteInput(input)
+                if input.code.starts_with(CONSOLE_QUERY_MARKER) =>
+            {
+                self.query = parent_msg_id.map(str::to_string);
+                None
+            }
+            JupyterMessageContent::ExecuteInput(input) => Some(self.input(&input.code)),
+            JupyterMessageContent::Status(status)
+                if status.execution_state == ExecutionState::Idle =>
+            {
+                self.finish()
+            }
+            content => self.output(&format_iopub_content(content, options)?),
+        }
+    }
+
+    /// Echo the code another client runs.
+    pub fn input(&mut self, code: &str) -> String {
+        let mut text = self.finish().unwrap_or_default();
+        text.push_str(&mark_lines(&echo_with_prompts(code, false)));
+        text
+    }
+
+    /// Complete lines of output; the rest waits for its newline.
+    pub fn output(&mut self, text: &str) -> Option<String> {
+        Some(mark_lines(&self.lines.push(text)?))
+    }
+
+    /// Flush an unfinished line once the execution is over.
+    pub fn finish(&mut self) -> Option<String> {
+        Some(mark_lines(&self.lines.flush()?))
+    }
+}
+
+fn is_idle(content: &JupyterMessageContent) -> bool {
+    match content {
+        JupyterMessageContent::Status(status) => status.execution_state == ExecutionState::Idle,
+        _ => false,
+    }
+}
+
+/// Put the foreign gutter before every line of `text`.
+fn mark_lines(text: &str) -> String {
+    let gutter = Color::DarkGray.paint(FOREIGN_GUTTER).to_string();
+    text.split_inclusive('\n')
+        .map(|line| format!("{gutter}{line}"))
+        .collect()
+}
+
+/// Mirror every client's executions until Ctrl+C or the kernel goes away.
+pub(crate) async fn run_monitor(
+    connection_info: &ConnectionInfo,
+    session_id: &str,
+    args: &MonitorArgs,
+) -> Result<()> {
+    info!(mode = "monitor", "Sidecar: starting monitor mode");
+
+    let config = super::config::load_config_file();
+    let output_options = OutputOptions {
+        image_protocol: resolve_image_protocol(
+            args.image_protocol.unwrap_or(config.image_protocol),
+        ),
+        rich_output: args.rich_output || config.rich_output,
+    };
+
+    let mut iopub = create_client_iopub_connection(connection_info, "", session_id)
+        .await
+        .context("Failed to connect iopub")?;
+    let (mut heartbeat_disconnect_rx, heartbeat_handle) =
+        spawn_heartbeat_monitor(connection_info.clone());
+    let mut sigint =
+        signal(SignalKind::interrupt()).context("Failed to register SIGINT handler")?;
+
+    println!(
+        "{}",
+        Color::DarkGray.paint("Mirroring executions in the Ark kernel. Press Ctrl+C to stop.")
+    );
+
+    let mut foreign = ForeignOutput::default();
+    loop {
+        tokio::select! {
+            _ = sigint.recv() => {
+                debug!("Console monitor: Ctrl+C, exiting");
+                break;
+            }
+            disconnect_reason = heartbeat_disconnect_rx.recv() => {
+                if let Some(disconnect_reason) = disconnect_reason {
+                    warn!(reason = %disconnect_reason, "Console monitor: kernel disconnected");
+                    println!("{}", Color::DarkGray.paint("Lost connection to the Ark kernel."));
+                    break;
+                }
+            }
+            iopub_result = iopub.read() => {
+                match iopub_result {
+                    Ok(message) => {
+                        if !is_foreign(&message, session_id) {
+                            continue;
+                        }
+                        if let Some(text) = foreign.mirror(&message, output_options) {
+                            print!("{text}");
+                            let _ = std::io::stdout().flush();
+                        }
+                    }
+                    Err(err) => {
+                        if is_comm_close_missing_data(&format!("{err:?}")) {
+                            debug!(error = ?err, "Console monitor: ignoring comm_close without data");
+                            continue;
+                        }
+                        error!(error = ?err, "Console monitor: iopub read error");
+                        stop_heartbeat_monitor(heartbeat_handle);
+                        return Err(err).context("Failed to read iopub");
+                    }
+                }
+            }
+        }
+    }
+
+    stop_heartbeat_monitor(heartbeat_handle);
+    debug!("Console monitor: exiting");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtimelib::{ExecuteInput, ExecutionCount, Status, StreamContent};
+
+    fn plain(text: &str) -> String {
+        crate::console::transcript::strip_ansi(text)
+    }
+
+    #[test]
+    fn foreign_input_is_echoed_with_a_gutter() {
+        let mut foreign = ForeignOutput::default();
+        assert_eq!(
+            plain(&foreign.input("f <- function() {\n  1\n}\n")),
+            "│ > f <- function() {\n│ +   1\n│ + }\n"
+        );
+    }
+
+    #[test]
+    fn partial_lines_wait_for_their_newline() {
+        let mut foreign = ForeignOutput::default();
+        assert_eq!(foreign.output("Iteration 1"), None);
+        assert_eq!(
+            plain(&foreign.output(" done\nIteration 2").unwrap()),
+            "│ Iteration 1 done\n"
+        );
+        assert_eq!(plain(&foreign.finish().unwrap()), "│ Iteration 2\n");
+        assert_eq!(foreign.finish(), None);
+    }
+
+    /// `message` as the kernel sends it for `request`.
+    fn reply_to(request: &JupyterMessage, content: JupyterMessageContent) -> JupyterMessage {
+        JupyterMessage::new(content, Some(request))
+    }
+
+    fn execution(request: &JupyterMessage, code: &str, output: &str) -> Vec<JupyterMessage> {
+        vec![
+            reply_to(request, Status::busy().into()),
+            reply_to(
+                request,
+                ExecuteInput {
+                    code: code.to_string(),
+                    execution_count: ExecutionCount::new(1),
+                }
+                .into(),
+            ),
+            reply_to(request, StreamContent::stdout(output).into()),
+            reply_to(request, Status::idle().into()),
+        ]
+    }
+
+    #[test]
+    fn other_consoles_queries_are_not_mirrored() {
+        let mut foreign = ForeignOutput::default();
+        let options = OutputOptions::default();
+        let query = console_query("cat(getwd())");
+        let code = query.code.clone();
+        let request = JupyterMessage::new(query, None);
+        for message in execution(&request, &code, "/srv/analysis\n") {
+            assert_eq!(foreign.mirror(&message, options), None);
+        }
+
+        let request = JupyterMessage::new(ExecuteRequest::new("1".to_string()), None);
+        let mirrored: String = execution(&request, "1", "[1] 1\n")
+            .iter()
+            .filter_map(|message| foreign.mirror(message, options))
+            .collect();
+        assert_eq!(plain(&mirrored), "│ > 1\n│ [1] 1\n");
+    }
+
+    #[test]
+    fn new_input_flushes_the_previous_output() {
+        let mut foreign = ForeignOutput::default();
+        foreign.output("[1] 1");
+        assert_eq!(plain(&foreign.input("x")), "│ [1] 1\n│ > x\n");
+    }
+}
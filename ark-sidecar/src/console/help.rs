@@ -0,0 +1,331 @@
+// Help pages for console mode.
+//
+// Ark answers `?topic` and `help(topic)` with a `show_help` event on the
+// `positron.help` comm. For URL help, the page is served by Ark's help
+// proxy on the loopback interface; we fetch it with a minimal HTTP/1.0
+// GET so the console can render it in the terminal.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tracing::debug;
+
+const HELP_FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_REDIRECTS: usize = 3;
+
+/// Help content announced by a `show_help` event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum HelpContent {
+    /// A help page URL to fetch.
+    Url(String),
+    /// Inline HTML.
+    Html(String),
+}
+
+/// Extract the help content from a `positron.help` `show_help` event.
+pub(crate) fn help_content_from_comm_data(data: &Map<String, Value>) -> Option<HelpContent> {
+    if data.get("method").and_then(Value::as_str) != Some("show_help") {
+        return None;
+    }
+    let params = data.get("params")?;
+    let content = params.get("content").and_then(Value::as_str)?.to_string();
+    match params.get("kind").and_then(Value::as_str) {
+        Some("url") => Some(HelpContent::Url(content)),
+        Some("html") => Some(HelpContent::Html(content)),
+        kind => {
+            debug!(kind = ?kind, "Console help: unsupported show_help kind");
+            None
+        }
+    }
+}
+
+/// Resolve help content to the HTML to display.
+pub(crate) async fn load_help_html(content: HelpContent) -> Result<String> {
+    match content {
+        HelpContent::Html(html) => Ok(html),
+        HelpContent::Url(url) => tokio::time::timeout(HELP_FETCH_TIMEOUT, fetch_help_html(&url))
+            .await
+            .map_err(|_| anyhow!("Timed out fetching help page {url}"))?,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LoopbackUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+/// Parse an `http://` URL, accepting only loopback hosts.
+fn parse_loopback_url(url: &str) -> Result<LoopbackUrl> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Unsupported help URL scheme: {url}"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+
+    let (host, port) = if let Some(bracketed) = authority.strip_prefix('[') {
+        let (host, after) = bracketed
+            .split_once(']')
+            .ok_or_else(|| anyhow!("Invalid help URL host: {url}"))?;
+        (host, after.strip_prefix(':'))
+    } else {
+        match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        }
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("Invalid help URL port: {url}"))?,
+        None => 80,
+    };
+
+    let is_loopback = host == "localhost"
+        || host
+            .parse::<IpAddr>()
+            .is_ok_and(|address| address.is_loopback());
+    if !is_loopback {
+        bail!("Refusing to fetch help from non-loopback host {host}");
+    }
+
+    Ok(LoopbackUrl {
+        host: host.to_string(),
+        port,
+        path: path.to_string(),
+    })
+}
+
+#[derive(Debug)]
+struct HttpResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn parse_http_response(raw: &[u8]) -> Result<HttpResponse> {
+    let header_end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("Malformed HTTP response: no header terminator"))?;
+    let head = String::from_utf8_lossy(&raw[..header_end]);
+    let mut lines = head.split("\r\n");
+
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("Malformed HTTP status line: {status_line}"))?;
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+
+    let mut response = HttpResponse {
+        status,
+        headers,
+        body: raw[header_end + 4..].to_vec(),
+    };
+    if response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = decode_chunked(&response.body)?;
+    }
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow!("Malformed chunked body"))?;
+        let size_line = String::from_utf8_lossy(&body[..line_end]);
+        let size_text = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_text, 16)
+            .with_context(|| format!("Invalid chunk size: {size_text}"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        if body.len() < size {
+            bail!("Truncated chunked body");
+        }
+        decoded.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+async fn http_get(url: &LoopbackUrl) -> Result<HttpResponse> {
+    debug!(host = %url.host, port = url.port, path = %url.path, "Console help: fetching page");
+    let mut stream = TcpStream::connect((url.host.as_str(), url.port))
+        .await
+        .with_context(|| format!("Failed to connect to help server {}:{}", url.host, url.port))?;
+
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}:{}\r\nConnection: close\r\n\r\n",
+        url.path, url.host, url.port
+    );
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .context("Failed to send help request")?;
+
+    let mut raw = Vec::new();
+    stream
+        .read_to_end(&mut raw)
+        .await
+        .context("Failed to read help response")?;
+    parse_http_response(&raw)
+}
+
+/// Fetch a help page from Ark's help server, following local redirects.
+async fn fetch_help_html(url: &str) -> Result<String> {
+    let mut url = parse_loopback_url(url)?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let response = http_get(&url).await?;
+        match response.status {
+            200 => return Ok(String::from_utf8_lossy(&response.body).into_owned()),
+            301 | 302 | 303 | 307 | 308 => {
+                let location = response
+                    .header("Location")
+                    .ok_or_else(|| anyhow!("Help redirect without Location"))?;
+                url = if location.starts_with('/') {
+                    LoopbackUrl {
+                        path: location.to_string(),
+                        ..url
+                    }
+                } else {
+                    parse_loopback_url(location)?
+                };
+                debug!(path = %url.path, "Console help: following redirect");
+            }
+            status => bail!("Help server returned HTTP {status}"),
+        }
+    }
+
+    bail!("Too many help redirects")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_help_url_is_parsed() {
+        let data = serde_json::json!({
+            "method": "show_help",
+            "params": {
+                "content": "http://127.0.0.1:54321/library/base/html/mean.html",
+                "kind": "url",
+                "focus": true
+            }
+        });
+        assert_eq!(
+            help_content_from_comm_data(data.as_object().unwrap()),
+            Some(HelpContent::Url(
+                "http://127.0.0.1:54321/library/base/html/mean.html".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn other_help_events_are_ignored() {
+        let data = serde_json::json!({ "method": "working_directory", "params": {} });
+        assert_eq!(help_content_from_comm_data(data.as_object().unwrap()), None);
+
+        let data = serde_json::json!({
+            "method": "show_help",
+            "params": { "content": "# mean", "kind": "markdown" }
+        });
+        assert_eq!(help_content_from_comm_data(data.as_object().unwrap()), None);
+    }
+
+    #[test]
+    fn loopback_urls_are_accepted() {
+        assert_eq!(
+            parse_loopback_url("http://127.0.0.1:54321/library/base/html/mean.html").unwrap(),
+            LoopbackUrl {
+                host: "127.0.0.1".to_string(),
+                port: 54321,
+                path: "/library/base/html/mean.html".to_string(),
+            }
+        );
+        assert_eq!(parse_loopback_url("http://localhost").unwrap().port, 80);
+        assert_eq!(
+            parse_loopback_url("http://[::1]:8080/doc").unwrap().host,
+            "::1"
+        );
+    }
+
+    #[test]
+    fn remote_and_https_urls_are_rejected() {
+        assert!(parse_loopback_url("http://example.com/help.html").is_err());
+        assert!(parse_loopback_url("http://10.0.0.2:8080/help.html").is_err());
+        assert!(parse_loopback_url("https://127.0.0.1/help.html").is_err());
+    }
+
+    #[test]
+    fn http_response_is_parsed() {
+        let raw = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<h2>Mean</h2>";
+        let response = parse_http_response(raw).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("content-type"), Some("text/html"));
+        assert_eq!(response.body, b"<h2>Mean</h2>");
+    }
+
+    #[test]
+    fn chunked_response_is_decoded() {
+        let raw = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\n<h2>\r\n9\r\nMean</h2>\r\n0\r\n\r\n";
+        let response = parse_http_response(raw).unwrap();
+        assert_eq!(response.body, b"<h2>Mean</h2>");
+    }
+
+    #[tokio::test]
+    async fn help_page_is_fetched_over_loopback() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = tokio::spawn(async move {
+            for response in [
+                "HTTP/1.0 302 Found\r\nLocation: /library/base/html/mean.html\r\n\r\n",
+                "HTTP/1.0 200 OK\r\n\r\n<h2>Arithmetic Mean</h2>",
+            ] {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = vec![0; 1024];
+                let _ = socket.read(&mut request).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        let html = load_help_html(HelpContent::Url(format!(
+            "http://127.0.0.1:{port}/library/base/help/mean"
+        )))
+        .await
+        .unwrap();
+        assert_eq!(html, "<h2>Arithmetic Mean</h2>");
+        server.await.unwrap();
+    }
+}
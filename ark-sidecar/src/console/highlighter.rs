@@ -1,16 +1,21 @@
 // R syntax highlighting using tree-sitter-r.
 //
-// Simplified adaptation of arf's r_tree_sitter.rs highlighter.
-// No bracket matching, no config system, no editor state sync.
+// Simplified adaptation of arf's r_tree_sitter.rs highlighter, with
+// rainbow brackets, matching-bracket highlighting at the cursor, and
+// underlines for LSP diagnostics. Identifiers take their color from LSP
+// semantic tokens where the server has computed them. No editor state sync.
 
 use nu_ansi_term::{Color, Style};
 use once_cell::sync::Lazy;
 use reedline::{Highlighter, StyledText};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tree_sitter::Node;
 
+use super::indent::{record_buffer, SharedBufferView};
 use super::r_parser::{is_atomic_node, parse_r};
+use crate::lsp_client::diagnostics::ConsoleDiagnostic;
+use crate::lsp_client::semantic_tokens::{ConsoleSemanticToken, SemanticKind};
 use crate::lsp_client::virtual_document::DebouncedVirtualDocument;
 
 /// Reserved keywords in R.
@@ -41,16 +46,35 @@ static CONSTANTS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
 });
 
 /// Token types for R syntax elements.
+///
+/// Identifiers are classified by where they sit in the parse tree; see
+/// `identifier_token_type`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum TokenType {
     Comment,
+    /// `#'` documentation comment.
+    RoxygenComment,
     String,
     Number,
     Keyword,
+    /// The `\` of a `\(x) x + 1` lambda.
+    Lambda,
     Constant,
     Operator,
+    /// `~` in a formula.
+    FormulaOperator,
     Punctuation,
     Identifier,
+    /// The name of the function in a call, `f` in `f(x)` and `pkg::f(x)`.
+    FunctionCall,
+    /// The package prefix of `pkg::name`.
+    Namespace,
+    /// The name of a named argument, `na.rm` in `mean(x, na.rm = TRUE)`.
+    ArgumentName,
+    /// A parameter declared by `function(x, y = 1)`.
+    Parameter,
+    /// A variable on either side of a formula.
+    FormulaTerm,
     Whitespace,
     Other,
 }
@@ -59,17 +83,50 @@ impl TokenType {
     fn style(self) -> Style {
         match self {
             TokenType::Keyword => Style::new().fg(Color::LightBlue),
+            TokenType::Lambda => Style::new().fg(Color::LightBlue).bold(),
             TokenType::String => Style::new().fg(Color::Green),
             TokenType::Number => Style::new().fg(Color::LightMagenta),
             TokenType::Comment => Style::new().fg(Color::DarkGray),
+            TokenType::RoxygenComment => Style::new().fg(Color::DarkGray).italic(),
             TokenType::Constant => Style::new().fg(Color::LightCyan),
             TokenType::Operator => Style::new().fg(Color::Yellow),
+            TokenType::FormulaOperator => Style::new().fg(Color::Yellow).bold(),
+            TokenType::FunctionCall => Style::new().fg(Color::LightYellow),
+            TokenType::Namespace => Style::new().fg(Color::Cyan),
+            TokenType::ArgumentName => Style::new().fg(Color::LightRed),
+            TokenType::Parameter => Style::new().italic(),
+            TokenType::FormulaTerm => Style::new().fg(Color::LightGreen).italic(),
             TokenType::Punctuation | TokenType::Identifier => Style::new(),
             TokenType::Whitespace | TokenType::Other => Style::new(),
         }
     }
 }
 
+/// Rotating colors for nested brackets, indexed by nesting depth.
+const RAINBOW_COLORS: [Color; 4] = [Color::Yellow, Color::Magenta, Color::Cyan, Color::Blue];
+
+/// Style for closing brackets with no matching opener.
+fn unmatched_bracket_style() -> Style {
+    Style::new().fg(Color::Red).bold()
+}
+
+/// Style for a bracket color at the given nesting depth.
+fn rainbow_style(depth: usize) -> Style {
+    Style::new().fg(RAINBOW_COLORS[depth % RAINBOW_COLORS.len()])
+}
+
+/// Style for an identifier the LSP server classified, if it differs from a
+/// plain identifier.
+fn semantic_style(kind: SemanticKind) -> Option<Style> {
+    match kind {
+        SemanticKind::Namespace => Some(TokenType::Namespace.style()),
+        SemanticKind::Function => Some(TokenType::FunctionCall.style()),
+        SemanticKind::Parameter => Some(TokenType::Parameter.style()),
+        SemanticKind::Type => Some(Style::new().fg(Color::LightGreen)),
+        SemanticKind::Variable => None,
+    }
+}
+
 /// A token with its byte range and type.
 #[derive(Debug, Clone)]
 struct Token {
@@ -86,7 +143,14 @@ fn node_to_token_type(node: &Node, source: &[u8]) -> TokenType {
         "string" | "string_content" | "escape_sequence" => TokenType::String,
 
         // Comments
-        "comment" => TokenType::Comment,
+        "comment" => {
+            let text = node.utf8_text(source).unwrap_or("");
+            if text.starts_with("#'") {
+                TokenType::RoxygenComment
+            } else {
+                TokenType::Comment
+            }
+        }
 
         // Constants
         "true" | "false" | "null" | "inf" | "nan" | "na" => TokenType::Constant,
@@ -96,10 +160,14 @@ fn node_to_token_type(node: &Node, source: &[u8]) -> TokenType {
         "function" | "if" | "else" | "for" | "while" | "repeat" | "in" | "next" | "break"
         | "return" => TokenType::Keyword,
 
+        // The lambda shorthand for `function`
+        "\\" => TokenType::Lambda,
+
         // Operators
-        "?" | ":=" | "=" | "<-" | "<<-" | "->" | "->>" | "~" | "|>" | "||" | "|" | "&&" | "&"
-        | "<" | "<=" | ">" | ">=" | "==" | "!=" | "+" | "-" | "*" | "/" | "::" | ":::" | "**"
-        | "^" | "$" | "@" | ":" | "!" | "\\" | "special" => TokenType::Operator,
+        "~" => TokenType::FormulaOperator,
+        "?" | ":=" | "=" | "<-" | "<<-" | "->" | "->>" | "|>" | "||" | "|" | "&&" | "&" | "<"
+        | "<=" | ">" | ">=" | "==" | "!=" | "+" | "-" | "*" | "/" | "::" | ":::" | "**" | "^"
+        | "$" | "@" | ":" | "!" | "special" => TokenType::Operator,
 
         // Punctuation
         "(" | ")" | "{" | "}" | "[" | "]" | "[[" | "]]" | "comma" | ";" => TokenType::Punctuation,
@@ -112,7 +180,7 @@ fn node_to_token_type(node: &Node, source: &[u8]) -> TokenType {
             } else if CONSTANTS.contains(text) {
                 TokenType::Constant
             } else {
-                TokenType::Identifier
+                identifier_token_type(node)
             }
         }
 
@@ -120,6 +188,52 @@ fn node_to_token_type(node: &Node, source: &[u8]) -> TokenType {
     }
 }
 
+/// Classify an identifier by its place in the parse tree.
+fn identifier_token_type(node: &Node) -> TokenType {
+    let Some(parent) = node.parent() else {
+        return TokenType::Identifier;
+    };
+    let role = match parent.kind() {
+        "call" if is_field(&parent, "function", node) => Some(TokenType::FunctionCall),
+        "namespace_operator" if is_field(&parent, "lhs", node) => Some(TokenType::Namespace),
+        "namespace_operator" if is_field(&parent, "rhs", node) => parent
+            .parent()
+            .filter(|call| call.kind() == "call" && is_field(call, "function", &parent))
+            .map(|_| TokenType::FunctionCall),
+        "argument" if is_field(&parent, "name", node) => Some(TokenType::ArgumentName),
+        "parameter" if is_field(&parent, "name", node) => Some(TokenType::Parameter),
+        _ => None,
+    };
+    role.unwrap_or_else(|| {
+        if in_formula(node) {
+            TokenType::FormulaTerm
+        } else {
+            TokenType::Identifier
+        }
+    })
+}
+
+/// Whether `node` is the `field` child of `parent`.
+fn is_field(parent: &Node, field: &str, node: &Node) -> bool {
+    parent.child_by_field_name(field) == Some(*node)
+}
+
+/// Whether `node` is part of a formula, one- or two-sided.
+fn in_formula(node: &Node) -> bool {
+    let mut ancestor = node.parent();
+    while let Some(current) = ancestor {
+        let is_formula = matches!(current.kind(), "binary_operator" | "unary_operator")
+            && current
+                .child_by_field_name("operator")
+                .is_some_and(|operator| operator.kind() == "~");
+        if is_formula {
+            return true;
+        }
+        ancestor = current.parent();
+    }
+    false
+}
+
 /// Recursively visit nodes and collect leaf tokens.
 fn visit_node(cursor: &mut tree_sitter::TreeCursor, source: &[u8], tokens: &mut Vec<Token>) {
     let node = cursor.node();
@@ -134,6 +248,13 @@ fn visit_node(cursor: &mut tree_sitter::TreeCursor, source: &[u8], tokens: &mut
         return;
     }
 
+    // MISSING nodes are zero-width placeholders tree-sitter inserts for
+    // incomplete input (e.g. an unclosed `(`); they have no text to style
+    // and must not be mistaken for real brackets.
+    if node.is_missing() {
+        return;
+    }
+
     // If this is a leaf node (no children), add it as a token
     if node.child_count() == 0 {
         let token_type = node_to_token_type(&node, source);
@@ -158,6 +279,136 @@ fn visit_node(cursor: &mut tree_sitter::TreeCursor, source: &[u8], tokens: &mut
     }
 }
 
+/// A bracket token with its nesting depth and matching partner.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bracket {
+    start: usize,
+    end: usize,
+    /// Nesting depth; a closing bracket shares the depth of its opener.
+    depth: usize,
+    /// Index of the matching bracket in the bracket list.
+    partner: Option<usize>,
+    is_open: bool,
+}
+
+/// The closing bracket text for an opening bracket, if `text` is one.
+fn closing_bracket(text: &str) -> Option<&'static str> {
+    match text {
+        "(" => Some(")"),
+        "[" => Some("]"),
+        "[[" => Some("]]"),
+        "{" => Some("}"),
+        _ => None,
+    }
+}
+
+fn is_closing_bracket(text: &str) -> bool {
+    matches!(text, ")" | "]" | "]]" | "}")
+}
+
+/// Pair up the bracket tokens of a parsed buffer.
+///
+/// Only punctuation tokens from the tree-sitter parse are considered, so
+/// brackets inside strings and comments (which are atomic tokens) are
+/// ignored. Depth is the number of enclosing open brackets, which follows
+/// the parse tree's nesting of calls, subsets, and braced expressions.
+fn match_brackets(tokens: &[Token], source: &str) -> Vec<Bracket> {
+    let mut brackets: Vec<Bracket> = Vec::new();
+    let mut open_stack: Vec<usize> = Vec::new();
+
+    for token in tokens {
+        if token.token_type != TokenType::Punctuation {
+            continue;
+        }
+        let text = &source[token.start..token.end];
+
+        if closing_bracket(text).is_some() {
+            open_stack.push(brackets.len());
+            brackets.push(Bracket {
+                start: token.start,
+                end: token.end,
+                depth: open_stack.len() - 1,
+                partner: None,
+                is_open: true,
+            });
+        } else if is_closing_bracket(text) {
+            let index = brackets.len();
+            let opener = open_stack.last().copied().filter(|&open_index| {
+                let open = &brackets[open_index];
+                closing_bracket(&source[open.start..open.end]) == Some(text)
+            });
+            match opener {
+                Some(open_index) => {
+                    open_stack.pop();
+                    brackets[open_index].partner = Some(index);
+                    brackets.push(Bracket {
+                        start: token.start,
+                        end: token.end,
+                        depth: brackets[open_index].depth,
+                        partner: Some(open_index),
+                        is_open: false,
+                    });
+                }
+                None => brackets.push(Bracket {
+                    start: token.start,
+                    end: token.end,
+                    depth: open_stack.len(),
+                    partner: None,
+                    is_open: false,
+                }),
+            }
+        }
+    }
+
+    brackets
+}
+
+/// Find the bracket pair to highlight for the given cursor position.
+///
+/// A closer just before the cursor wins, so a freshly typed `)` lights up
+/// its opener; then the bracket under the cursor, then an opener just
+/// before it.
+fn bracket_pair_at_cursor(brackets: &[Bracket], cursor: usize) -> Option<(usize, usize)> {
+    let index = brackets
+        .iter()
+        .position(|bracket| !bracket.is_open && bracket.end == cursor)
+        .or_else(|| brackets.iter().position(|bracket| bracket.start == cursor))
+        .or_else(|| brackets.iter().position(|bracket| bracket.end == cursor))?;
+    brackets[index].partner.map(|partner| (index, partner))
+}
+
+/// Compute the style of each bracket token, keyed by start byte.
+fn bracket_styles(brackets: &[Bracket], cursor: usize) -> HashMap<usize, Style> {
+    let active_pair = bracket_pair_at_cursor(brackets, cursor);
+
+    brackets
+        .iter()
+        .enumerate()
+        .map(|(index, bracket)| {
+            let style = if bracket.partner.is_none() && !bracket.is_open {
+                unmatched_bracket_style()
+            } else if active_pair.is_some_and(|(a, b)| index == a || index == b) {
+                rainbow_style(bracket.depth).bold().underline()
+            } else {
+                rainbow_style(bracket.depth)
+            };
+            (bracket.start, style)
+        })
+        .collect()
+}
+
+/// Semantic style for a plain identifier token covered by a semantic token.
+/// Identifiers the parse tree already classifies keep their own style.
+fn semantic_style_for(token: &Token, semantic_tokens: &[ConsoleSemanticToken]) -> Option<Style> {
+    if token.token_type != TokenType::Identifier {
+        return None;
+    }
+    semantic_tokens
+        .iter()
+        .find(|semantic| semantic.start <= token.start && token.end <= semantic.end)
+        .and_then(|semantic| semantic_style(semantic.kind))
+}
+
 /// Fill gaps between tokens with whitespace.
 fn fill_gaps(tokens: &[Token], total_len: usize) -> Vec<Token> {
     let mut result = Vec::new();
@@ -189,35 +440,59 @@ fn fill_gaps(tokens: &[Token], total_len: usize) -> Vec<Token> {
 /// Tree-sitter based R syntax highlighter for reedline.
 pub(crate) struct RHighlighter {
     virtual_document: Option<Arc<DebouncedVirtualDocument>>,
+    /// Receives the painted buffer for auto-indentation.
+    buffer_view: Option<SharedBufferView>,
 }
 
 impl RHighlighter {
     pub fn new(virtual_document: Option<Arc<DebouncedVirtualDocument>>) -> Self {
-        Self { virtual_document }
+        Self {
+            virtual_document,
+            buffer_view: None,
+        }
+    }
+
+    pub fn with_buffer_view(mut self, buffer_view: SharedBufferView) -> Self {
+        self.buffer_view = Some(buffer_view);
+        self
     }
 }
 
 impl Highlighter for RHighlighter {
-    fn highlight(&self, line: &str, _cursor: usize) -> StyledText {
+    fn highlight(&self, line: &str, cursor: usize) -> StyledText {
         if let Some(virtual_document) = &self.virtual_document {
             virtual_document.schedule_sync(line);
         }
+        if let Some(buffer_view) = &self.buffer_view {
+            record_buffer(buffer_view, line, cursor);
+        }
 
         let mut styled = StyledText::new();
 
         if let Some(tree) = parse_r(line) {
             let source = line.as_bytes();
             let mut tokens = Vec::new();
-            let mut cursor = tree.walk();
+            let mut tree_cursor = tree.walk();
 
-            visit_node(&mut cursor, source, &mut tokens);
+            visit_node(&mut tree_cursor, source, &mut tokens);
             tokens.sort_by_key(|t| t.start);
             let tokens = fill_gaps(&tokens, source.len());
+            let bracket_styles = bracket_styles(&match_brackets(&tokens, line), cursor);
+            let semantic_tokens = self
+                .virtual_document
+                .as_ref()
+                .map(|virtual_document| virtual_document.semantic_tokens().for_buffer(line))
+                .unwrap_or_default();
 
             for token in tokens {
                 if token.start < line.len() && token.end <= line.len() {
                     let text = &line[token.start..token.end];
-                    styled.push((token.token_type.style(), text.to_string()));
+                    let style = bracket_styles
+                        .get(&token.start)
+                        .copied()
+                        .or_else(|| semantic_style_for(&token, &semantic_tokens))
+                        .unwrap_or_else(|| token.token_type.style());
+                    styled.push((style, text.to_string()));
                 }
             }
         } else {
@@ -228,8 +503,94 @@ impl Highlighter for RHighlighter {
             styled.push((Style::new(), String::new()));
         }
 
-        styled
+        match &self.virtual_document {
+            Some(virtual_document) => {
+                underline_diagnostics(styled, &virtual_document.diagnostics().for_buffer(line))
+            }
+            None => styled,
+        }
+    }
+}
+
+/// Byte range to underline for a diagnostic.
+///
+/// Zero-width diagnostics (e.g. "unexpected end of input") are widened to
+/// the neighbouring character so they stay visible.
+fn underline_range(diagnostic: &ConsoleDiagnostic, line: &str) -> Option<(usize, usize)> {
+    if diagnostic.start < diagnostic.end {
+        return Some((diagnostic.start, diagnostic.end.min(line.len())));
+    }
+    let at = diagnostic.start.min(line.len());
+    if let Some(next) = line[at..].chars().next() {
+        return Some((at, at + next.len_utf8()));
+    }
+    line[..at]
+        .chars()
+        .next_back()
+        .map(|prev| (at - prev.len_utf8(), at))
+}
+
+/// Underline the spans covered by diagnostics, splitting styled segments
+/// at span boundaries.
+fn underline_diagnostics(styled: StyledText, diagnostics: &[ConsoleDiagnostic]) -> StyledText {
+    if diagnostics.is_empty() {
+        return styled;
+    }
+
+    let line = styled.raw_string();
+    let ranges: Vec<(usize, usize)> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| underline_range(diagnostic, &line))
+        .collect();
+
+    let mut result = StyledText::new();
+    let mut pos = 0;
+    for (style, text) in styled.buffer {
+        let segment_end = pos + text.len();
+        let mut cuts: Vec<usize> = ranges
+            .iter()
+            .flat_map(|&(start, end)| [start, end])
+            .filter(|&cut| pos < cut && cut < segment_end)
+            .collect();
+        cuts.push(segment_end);
+        cuts.sort_unstable();
+        cuts.dedup();
+
+        let mut piece_start = pos;
+        for cut in cuts {
+            let underlined = ranges
+                .iter()
+                .any(|&(start, end)| start <= piece_start && cut <= end);
+            let piece = text[piece_start - pos..cut - pos].to_string();
+            let piece_style = if underlined { style.underline() } else { style };
+            result.push((piece_style, piece));
+            piece_start = cut;
+        }
+        pos = segment_end;
+    }
+
+    result
+}
+
+/// Highlight `code` outside the editor, one painted string per line.
+///
+/// No bracket is matched at a cursor, and styles are closed at each line
+/// break so every line can be printed on its own.
+pub(crate) fn highlight_lines(code: &str) -> Vec<String> {
+    let styled = RHighlighter::new(None).highlight(code, usize::MAX);
+    let mut lines = vec![String::new()];
+    for (style, text) in &styled.buffer {
+        for (index, piece) in text.split('\n').enumerate() {
+            if index > 0 {
+                lines.push(String::new());
+            }
+            if !piece.is_empty() {
+                let line = lines.last_mut().expect("lines start non-empty");
+                line.push_str(&style.paint(piece).to_string());
+            }
+        }
     }
+    lines
 }
 
 #[cfg(test)]
@@ -311,6 +672,69 @@ mod tests {
         assert_eq!(tokens[2], ("42".to_string(), TokenType::Number));
     }
 
+    fn token_type_of(input: &str, text: &str) -> TokenType {
+        get_token_types(input)
+            .into_iter()
+            .find(|(token, _)| token == text)
+            .unwrap_or_else(|| panic!("no token {text:?} in {input:?}"))
+            .1
+    }
+
+    #[test]
+    fn call_names_and_namespaces() {
+        assert_eq!(token_type_of("mean(x)", "mean"), TokenType::FunctionCall);
+        assert_eq!(token_type_of("mean(x)", "x"), TokenType::Identifier);
+        let input = "dplyr::filter(df)";
+        assert_eq!(token_type_of(input, "dplyr"), TokenType::Namespace);
+        assert_eq!(token_type_of(input, "filter"), TokenType::FunctionCall);
+        // A namespaced value that isn't called
+        assert_eq!(
+            token_type_of("x <- pkg::data", "data"),
+            TokenType::Identifier
+        );
+    }
+
+    #[test]
+    fn argument_names_and_parameters() {
+        let input = "mean(x, na.rm = TRUE)";
+        assert_eq!(token_type_of(input, "na.rm"), TokenType::ArgumentName);
+        assert_eq!(token_type_of(input, "x"), TokenType::Identifier);
+        let input = "f <- function(x, y = 2) x + y";
+        let parameters: Vec<_> = get_token_types(input)
+            .into_iter()
+            .filter(|(_, t)| *t == TokenType::Parameter)
+            .map(|(token, _)| token)
+            .collect();
+        assert_eq!(parameters, ["x", "y"]);
+    }
+
+    #[test]
+    fn backslash_lambda() {
+        let input = "sapply(xs, \\(x) x + 1)";
+        assert_eq!(token_type_of(input, "\\"), TokenType::Lambda);
+        assert_eq!(token_type_of(input, "x"), TokenType::Parameter);
+    }
+
+    #[test]
+    fn formulas() {
+        let input = "lm(y ~ log(x) + z, data = df)";
+        assert_eq!(token_type_of(input, "~"), TokenType::FormulaOperator);
+        assert_eq!(token_type_of(input, "y"), TokenType::FormulaTerm);
+        assert_eq!(token_type_of(input, "log"), TokenType::FunctionCall);
+        assert_eq!(token_type_of(input, "x"), TokenType::FormulaTerm);
+        assert_eq!(token_type_of(input, "df"), TokenType::Identifier);
+        assert_eq!(token_type_of("~ a + b", "b"), TokenType::FormulaTerm);
+    }
+
+    #[test]
+    fn roxygen_comments() {
+        assert_eq!(
+            token_type_of("#' @param x A vector", "#' @param x A vector"),
+            TokenType::RoxygenComment
+        );
+        assert_eq!(token_type_of("# note", "# note"), TokenType::Comment);
+    }
+
     #[test]
     fn test_highlight_preserves_text() {
         let highlighter = RHighlighter::new(None);
@@ -319,10 +743,218 @@ mod tests {
         assert_eq!(styled.raw_string(), input);
     }
 
+    fn brackets_of(input: &str) -> Vec<Bracket> {
+        let tree = parse_r(input).unwrap();
+        let mut tokens = Vec::new();
+        let mut cursor = tree.walk();
+        visit_node(&mut cursor, input.as_bytes(), &mut tokens);
+        tokens.sort_by_key(|t| t.start);
+        match_brackets(&fill_gaps(&tokens, input.len()), input)
+    }
+
+    fn bracket_summary(input: &str) -> Vec<(String, usize, bool)> {
+        brackets_of(input)
+            .into_iter()
+            .map(|b| {
+                (
+                    input[b.start..b.end].to_string(),
+                    b.depth,
+                    b.partner.is_some(),
+                )
+            })
+            .collect()
+    }
+
+    fn style_at(styled: &StyledText, byte: usize) -> Style {
+        let mut pos = 0;
+        for (style, text) in &styled.buffer {
+            if byte < pos + text.len() {
+                return *style;
+            }
+            pos += text.len();
+        }
+        panic!("byte {byte} out of range");
+    }
+
+    #[test]
+    fn brackets_get_nesting_depth() {
+        assert_eq!(
+            bracket_summary("f(x[[1]], {g(y)})"),
+            vec![
+                ("(".to_string(), 0, true),
+                ("[[".to_string(), 1, true),
+                ("]]".to_string(), 1, true),
+                ("{".to_string(), 1, true),
+                ("(".to_string(), 2, true),
+                (")".to_string(), 2, true),
+                ("}".to_string(), 1, true),
+                (")".to_string(), 0, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn brackets_match_across_lines() {
+        let input = "f <- function(x) {\n  lapply(x, function(y) {\n    y[1]\n  })\n}";
+        let brackets = brackets_of(input);
+        assert!(brackets.iter().all(|b| b.partner.is_some()));
+
+        let outer_open = input.find('{').unwrap();
+        let outer_close = input.rfind('}').unwrap();
+        let open_index = brackets.iter().position(|b| b.start == outer_open).unwrap();
+        let partner = brackets[open_index].partner.unwrap();
+        assert_eq!(brackets[partner].start, outer_close);
+        assert_eq!(brackets[open_index].depth, 0);
+    }
+
+    #[test]
+    fn brackets_inside_strings_and_comments_are_ignored() {
+        assert_eq!(
+            bracket_summary("paste(\")(\", x) # ) ] }"),
+            vec![("(".to_string(), 0, true), (")".to_string(), 0, true)]
+        );
+        assert_eq!(
+            bracket_summary("x <- \"[\"\n# {\ny[1]"),
+            vec![("[".to_string(), 0, true), ("]".to_string(), 0, true)]
+        );
+    }
+
+    #[test]
+    fn unmatched_closing_bracket_is_red() {
+        let input = "x)";
+        let brackets = brackets_of(input);
+        assert_eq!(brackets.len(), 1);
+        assert!(brackets[0].partner.is_none());
+
+        let highlighter = RHighlighter::new(None);
+        let styled = highlighter.highlight(input, 0);
+        assert_eq!(style_at(&styled, 1), unmatched_bracket_style());
+    }
+
+    #[test]
+    fn unclosed_opening_bracket_keeps_rainbow_color() {
+        let highlighter = RHighlighter::new(None);
+        let styled = highlighter.highlight("mean(", 0);
+        assert_eq!(styled.raw_string(), "mean(");
+        assert_eq!(style_at(&styled, 4), rainbow_style(0));
+    }
+
+    #[test]
+    fn bracket_at_cursor_highlights_partner() {
+        let input = "f(g(x))";
+        let highlighter = RHighlighter::new(None);
+        let active = |depth| rainbow_style(depth).bold().underline();
+
+        // Cursor just after the inner `)` highlights `g(`'s opener.
+        let styled = highlighter.highlight(input, 6);
+        assert_eq!(style_at(&styled, 3), active(1));
+        assert_eq!(style_at(&styled, 5), active(1));
+        assert_eq!(style_at(&styled, 1), rainbow_style(0));
+
+        // Cursor on the outer `(` highlights the final `)`.
+        let styled = highlighter.highlight(input, 1);
+        assert_eq!(style_at(&styled, 1), active(0));
+        assert_eq!(style_at(&styled, 6), active(0));
+    }
+
+    fn diagnostic(start: usize, end: usize) -> ConsoleDiagnostic {
+        ConsoleDiagnostic {
+            start,
+            end,
+            severity: lsp_types::DiagnosticSeverity::WARNING,
+            message: "No symbol named 'bar' in scope.".to_string(),
+        }
+    }
+
+    #[test]
+    fn diagnostics_underline_their_span() {
+        let input = "foo(bar, baz)";
+        let styled = RHighlighter::new(None).highlight(input, 0);
+        let styled = underline_diagnostics(styled, &[diagnostic(4, 7)]);
+
+        assert_eq!(styled.raw_string(), input);
+        assert!(style_at(&styled, 4).is_underline);
+        assert!(style_at(&styled, 6).is_underline);
+        assert!(!style_at(&styled, 3).is_underline);
+        assert!(!style_at(&styled, 9).is_underline);
+    }
+
+    #[test]
+    fn diagnostics_split_styled_segments() {
+        let input = "x <- \"abcdef\"";
+        let styled = RHighlighter::new(None).highlight(input, 0);
+        let string_style = style_at(&styled, 6);
+        let styled = underline_diagnostics(styled, &[diagnostic(7, 9)]);
+
+        assert_eq!(styled.raw_string(), input);
+        assert_eq!(style_at(&styled, 6), string_style);
+        assert_eq!(style_at(&styled, 7), string_style.underline());
+        assert_eq!(style_at(&styled, 9), string_style);
+    }
+
+    #[test]
+    fn zero_width_diagnostic_underlines_neighbour() {
+        let input = "f(x";
+        let styled = RHighlighter::new(None).highlight(input, 0);
+        let styled = underline_diagnostics(styled, &[diagnostic(3, 3)]);
+        assert!(style_at(&styled, 2).is_underline);
+        assert!(!style_at(&styled, 1).is_underline);
+    }
+
     #[test]
     fn test_highlight_empty() {
         let highlighter = RHighlighter::new(None);
         let styled = highlighter.highlight("", 0);
         assert_eq!(styled.raw_string(), "");
     }
+
+    #[test]
+    fn highlighted_lines_close_styles_at_line_breaks() {
+        let lines = highlight_lines("x <- \"a\nb\"\nf(x)");
+        let plain: Vec<String> = lines
+            .iter()
+            .map(|line| crate::console::transcript::strip_ansi(line))
+            .collect();
+        assert_eq!(plain, ["x <- \"a", "b\"", "f(x)"]);
+        assert!(lines.iter().all(|line| line.ends_with("\u{1b}[0m")));
+    }
+
+    #[test]
+    fn semantic_tokens_recolor_plain_identifiers_only() {
+        let input = "lapply(xs, fn, \"x\")";
+        let tree = parse_r(input).unwrap();
+        let mut tokens = Vec::new();
+        let mut cursor = tree.walk();
+        visit_node(&mut cursor, input.as_bytes(), &mut tokens);
+        let semantic_tokens = [
+            ConsoleSemanticToken {
+                start: 0,
+                end: 6,
+                kind: SemanticKind::Function,
+            },
+            ConsoleSemanticToken {
+                start: 11,
+                end: 13,
+                kind: SemanticKind::Function,
+            },
+            // A server classifying the string's content is ignored
+            ConsoleSemanticToken {
+                start: 15,
+                end: 18,
+                kind: SemanticKind::Variable,
+            },
+        ];
+        let style_of = |text: &str| {
+            let token = tokens
+                .iter()
+                .find(|token| &input[token.start..token.end] == text)
+                .unwrap();
+            semantic_style_for(token, &semantic_tokens)
+        };
+        // Tree-sitter already knows `lapply` is called
+        assert_eq!(style_of("lapply"), None);
+        assert_eq!(style_of("fn"), semantic_style(SemanticKind::Function));
+        assert_eq!(style_of("xs"), None);
+        assert_eq!(style_of("\"x\""), None);
+    }
 }
@@ -0,0 +1,297 @@
+// Hint lines for console mode.
+//
+// Suggests the rest of the line from the current project's history, greyed
+// out after the cursor (Right-arrow accepts it, Alt+F one word of it), and
+// shows the signature of the call at the cursor and the LSP diagnostic at
+// the cursor on lines below the input, so arguments, syntax errors, and
+// undefined symbols are visible before pressing Enter.
+
+use std::sync::Arc;
+
+use lsp_types::DiagnosticSeverity;
+use nu_ansi_term::{Color, Style};
+use reedline::{
+    CommandLineSearch, Hinter, History, HistoryItem, SearchDirection, SearchFilter, SearchQuery,
+};
+use tracing::debug;
+
+use super::history::session_project;
+use super::indent::{menu_active, SharedBufferView};
+use super::session_state::SharedSessionState;
+use super::signature_help::SignatureHelpSource;
+use crate::lsp_client::diagnostics::{diagnostic_at, ConsoleDiagnostic, DiagnosticsStore};
+
+/// Recent entries with the typed prefix checked for a longer command.
+const SUGGESTION_CANDIDATES: i64 = 20;
+
+/// Hinter that renders history suggestions, signature help and diagnostics.
+///
+/// Reedline paints hints after the whole buffer, so the history suggestion
+/// and signature help only show while the cursor is at the end of it. The
+/// diagnostic line shows wherever the cursor is, and like every hint is
+/// hidden in the final repaint when the input is submitted.
+#[derive(Default)]
+pub(crate) struct ConsoleHinter {
+    diagnostics: Option<Arc<DiagnosticsStore>>,
+    signature_help: Option<SignatureHelpSource>,
+    /// Suggest from the history of this session's project.
+    history_session: Option<SharedSessionState>,
+    /// Suggestions are hidden while the completion menu is open.
+    buffer_view: Option<SharedBufferView>,
+    /// The history suggestion currently shown.
+    suggestion: String,
+}
+
+impl ConsoleHinter {
+    pub fn with_diagnostics(mut self, diagnostics: Arc<DiagnosticsStore>) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    pub fn with_signature_help(mut self, signature_help: SignatureHelpSource) -> Self {
+        self.signature_help = Some(signature_help);
+        self
+    }
+
+    pub fn with_history_suggestions(mut self, session_state: SharedSessionState) -> Self {
+        self.history_session = Some(session_state);
+        self
+    }
+
+    pub fn with_buffer_view(mut self, buffer_view: SharedBufferView) -> Self {
+        self.buffer_view = Some(buffer_view);
+        self
+    }
+
+    /// The rest of the most recent project entry starting with `line`.
+    fn history_suggestion(&self, line: &str, history: &dyn History) -> String {
+        let Some(session_state) = &self.history_session else {
+            return String::new();
+        };
+        if line.trim().is_empty() || self.buffer_view.as_ref().is_some_and(menu_active) {
+            return String::new();
+        }
+        let mut filter = SearchFilter::anything(history.session());
+        filter.command_line = Some(CommandLineSearch::Prefix(line.to_string()));
+        filter.cwd_exact = session_project(session_state);
+        let query = SearchQuery {
+            direction: SearchDirection::Backward,
+            start_time: None,
+            end_time: None,
+            start_id: None,
+            end_id: None,
+            limit: Some(SUGGESTION_CANDIDATES),
+            filter,
+        };
+        match history.search(query) {
+            Ok(items) => suggestion_suffix(line, &items),
+            Err(err) => {
+                debug!(error = %err, "Console hinter: history search failed");
+                String::new()
+            }
+        }
+    }
+}
+
+/// The rest of the first entry that extends `line`.
+fn suggestion_suffix(line: &str, items: &[HistoryItem]) -> String {
+    items
+        .iter()
+        .filter_map(|item| item.command_line.strip_prefix(line))
+        .find(|rest| !rest.is_empty())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The next word of a suggestion, with the whitespace before it: an R
+/// name, or a run of operators and brackets.
+fn first_word(suggestion: &str) -> &str {
+    let is_name = |ch: char| ch.is_alphanumeric() || ch == '.' || ch == '_';
+    let start = suggestion.len() - suggestion.trim_start().len();
+    let Some(first) = suggestion[start..].chars().next() else {
+        return suggestion;
+    };
+    let end = suggestion[start..]
+        .find(|ch: char| ch.is_whitespace() || is_name(ch) != is_name(first))
+        .map_or(suggestion.len(), |offset| start + offset);
+    &suggestion[..end]
+}
+
+fn severity_marker(severity: DiagnosticSeverity) -> (&'static str, Color) {
+    match severity {
+        DiagnosticSeverity::ERROR => ("✗", Color::Red),
+        DiagnosticSeverity::WARNING => ("⚠", Color::Yellow),
+        _ => ("ℹ", Color::DarkGray),
+    }
+}
+
+/// Format a diagnostic as a hint line, using its first message line.
+fn format_diagnostic_hint(diagnostic: &ConsoleDiagnostic, use_ansi_coloring: bool) -> String {
+    let (marker, color) = severity_marker(diagnostic.severity);
+    let message = diagnostic.message.lines().next().unwrap_or_default();
+    let text = format!("{marker} {message}");
+    if use_ansi_coloring {
+        Style::new().fg(color).italic().paint(text).to_string()
+    } else {
+        text
+    }
+}
+
+/// Render hint lines below the input.
+fn hint_lines(lines: impl IntoIterator<Item = String>) -> String {
+    lines.into_iter().map(|line| format!("\n{line}")).collect()
+}
+
+impl Hinter for ConsoleHinter {
+    fn handle(
+        &mut self,
+        line: &str,
+        pos: usize,
+        history: &dyn History,
+        use_ansi_coloring: bool,
+        _cwd: &str,
+    ) -> String {
+        let at_end = pos == line.len();
+        self.suggestion = if at_end {
+            self.history_suggestion(line, history)
+        } else {
+            String::new()
+        };
+        let suggestion = if use_ansi_coloring && !self.suggestion.is_empty() {
+            Style::new()
+                .fg(Color::DarkGray)
+                .paint(&self.suggestion)
+                .to_string()
+        } else {
+            self.suggestion.clone()
+        };
+        let signature = self
+            .signature_help
+            .as_ref()
+            .filter(|_| at_end)
+            .and_then(|signature_help| signature_help.hint(line, pos, use_ansi_coloring));
+        let diagnostic = self.diagnostics.as_ref().and_then(|diagnostics| {
+            let diagnostics = diagnostics.for_buffer(line);
+            diagnostic_at(&diagnostics, pos)
+                .map(|diagnostic| format_diagnostic_hint(diagnostic, use_ansi_coloring))
+        });
+        suggestion + &hint_lines(signature.into_iter().chain(diagnostic))
+    }
+
+    // Only the history suggestion is inserted; the hint lines below the
+    // input are informational.
+    fn complete_hint(&self) -> String {
+        self.suggestion.clone()
+    }
+
+    fn next_hint_token(&self) -> String {
+        first_word(&self.suggestion).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(severity: DiagnosticSeverity, message: &str) -> ConsoleDiagnostic {
+        ConsoleDiagnostic {
+            start: 4,
+            end: 7,
+            severity,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn hint_shows_first_message_line_below_input() {
+        let hint = format_diagnostic_hint(
+            &diagnostic(
+                DiagnosticSeverity::ERROR,
+                "Unexpected ')'.\nExpected an expression.",
+            ),
+            false,
+        );
+        assert_eq!(hint, "✗ Unexpected ')'.");
+    }
+
+    #[test]
+    fn hint_marks_severity() {
+        let hint = format_diagnostic_hint(
+            &diagnostic(
+                DiagnosticSeverity::WARNING,
+                "No symbol named 'bar' in scope.",
+            ),
+            false,
+        );
+        assert_eq!(hint, "⚠ No symbol named 'bar' in scope.");
+
+        let colored = format_diagnostic_hint(&diagnostic(DiagnosticSeverity::HINT, "x"), true);
+        assert!(colored.contains("\u{1b}["));
+    }
+
+    #[test]
+    fn hint_lines_go_below_the_input() {
+        assert_eq!(hint_lines(Vec::new()), "");
+        assert_eq!(
+            hint_lines(vec!["mean(x, ...)".to_string(), "✗ oops".to_string()]),
+            "\nmean(x, ...)\n✗ oops"
+        );
+    }
+
+    #[test]
+    fn accepting_the_hint_lines_inserts_nothing() {
+        let hinter =
+            ConsoleHinter::default().with_diagnostics(Arc::new(DiagnosticsStore::default()));
+        assert_eq!(hinter.complete_hint(), "");
+        assert_eq!(hinter.next_hint_token(), "");
+    }
+
+    #[test]
+    fn diagnostic_under_a_cursor_inside_the_input_is_hinted_below_it() {
+        let store = Arc::new(DiagnosticsStore::default());
+        let range = lsp_types::Range::new(
+            lsp_types::Position::new(0, 4),
+            lsp_types::Position::new(0, 7),
+        );
+        store.publish(
+            "foo(bar, baz)",
+            &[lsp_types::Diagnostic {
+                range,
+                severity: Some(DiagnosticSeverity::WARNING),
+                message: "No symbol named 'bar' in scope.".to_string(),
+                ..Default::default()
+            }],
+        );
+        let history = reedline::FileBackedHistory::new(10).unwrap();
+        let mut hinter = ConsoleHinter::default().with_diagnostics(store);
+
+        assert_eq!(
+            hinter.handle("foo(bar, baz)", 5, &history, false, ""),
+            "\n⚠ No symbol named 'bar' in scope."
+        );
+        assert_eq!(hinter.handle("foo(bar, baz)", 10, &history, false, ""), "");
+        assert_eq!(hinter.complete_hint(), "");
+    }
+
+    #[test]
+    fn suggestion_completes_the_newest_longer_entry() {
+        let items: Vec<HistoryItem> = ["summary(fit)", "summary(fit, digits = 3)", "summary"]
+            .into_iter()
+            .map(HistoryItem::from_command_line)
+            .collect();
+        assert_eq!(suggestion_suffix("summary(fit)", &items), ", digits = 3)");
+        assert_eq!(suggestion_suffix("sum", &items), "mary(fit)");
+        assert_eq!(suggestion_suffix("plot", &items), "");
+    }
+
+    #[test]
+    fn suggestion_is_accepted_word_by_word() {
+        assert_eq!(first_word("mary(fit, digits = 3)"), "mary");
+        assert_eq!(first_word("(fit, digits = 3)"), "(");
+        assert_eq!(first_word(", digits = 3)"), ",");
+        assert_eq!(first_word(" digits = 3)"), " digits");
+        assert_eq!(first_word(" |> head()"), " |>");
+        assert_eq!(first_word("  "), "  ");
+        assert_eq!(first_word(""), "");
+    }
+}
@@ -2,13 +2,31 @@
 //
 // Uses reedline's SqliteBackedHistory for persistent, timestamped
 // command history stored at $XDG_DATA_HOME/krarkode/history.db.
+//
+// Entries are tagged with the project they were run in (the kernel's
+// working directory, or the connection file when it is unknown) via the
+// `cwd` column. Up-arrow and Ctrl+R walk the current project's entries
+// before falling back to the rest of the history. The `history`
+// subcommand imports `.Rhistory` files and exports the database.
 
-use anyhow::{Context, Result};
-use reedline::SqliteBackedHistory;
 use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use reedline::{
+    CommandLineSearch, History, HistoryItem, HistoryItemId, HistorySessionId, SearchDirection,
+    SearchFilter, SearchQuery, SqliteBackedHistory,
+};
+use serde::Serialize;
 use tracing::debug;
 
-const APP_NAME: &str = "krarkode";
+use super::path_completion::expand_tilde;
+use super::session_state::{lock_session_state, SharedSessionState};
+use crate::types::{HistoryAction, HistoryExportFormat};
+
+pub(super) const APP_NAME: &str = "krarkode";
+
+/// Entries fetched per query while looking for the next navigation step.
+const SEARCH_BATCH: i64 = 50;
 
 /// Resolve the XDG data directory for krarkode.
 fn data_dir() -> Option<PathBuf> {
@@ -23,11 +41,7 @@ pub(crate) fn history_db_path() -> Option<PathBuf> {
     data_dir().map(|p| p.join("history.db"))
 }
 
-/// Create a SqliteBackedHistory instance with XDG-compliant path.
-///
-/// Creates the parent directory if it doesn't exist.
-/// Reedline automatically stores timestamps and session metadata.
-pub(crate) fn create_history() -> Result<SqliteBackedHistory> {
+fn open_history_db() -> Result<SqliteBackedHistory> {
     let path = history_db_path().context("Could not determine XDG data directory")?;
 
     debug!(path = %path.display(), "Console: opening history database");
@@ -35,15 +49,309 @@ pub(crate) fn create_history() -> Result<SqliteBackedHistory> {
     // SqliteBackedHistory::with_file creates parent dirs and handles
     // session/timestamp internally. Pass None for session and timestamp
     // to use reedline's defaults (same pattern as arf).
-    let history = SqliteBackedHistory::with_file(path, None, None)
-        .context("Failed to create SQLite history")?;
+    SqliteBackedHistory::with_file(path, None, None).context("Failed to create SQLite history")
+}
 
-    Ok(history)
+/// Create the console history, scoped to the session's project.
+///
+/// Creates the parent directory if it doesn't exist.
+/// Reedline automatically stores timestamps and session metadata.
+pub(crate) fn create_history(session_state: SharedSessionState) -> Result<ProjectHistory> {
+    Ok(ProjectHistory::new(
+        Box::new(open_history_db()?),
+        session_state,
+    ))
+}
+
+/// Normalize a directory to the key stored in the `cwd` column.
+///
+/// Ark reports the working directory with the home directory shortened
+/// to `~`; expand it so both spellings map to the same project.
+pub(crate) fn project_key(directory: &str) -> String {
+    let expanded = expand_tilde(directory);
+    match expanded.trim_end_matches('/') {
+        "" => "/".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// The project history entries of this session are tagged with.
+pub(crate) fn session_project(session_state: &SharedSessionState) -> Option<String> {
+    let state = lock_session_state(session_state);
+    state
+        .working_directory
+        .as_deref()
+        .map(project_key)
+        .or_else(|| state.connection_file.clone())
+}
+
+/// History that prefers entries from the current project.
+///
+/// Reedline navigates history one entry at a time (`limit: Some(1)`), for
+/// both up-arrow and Ctrl+R. Those queries walk the current project's
+/// entries first and then the rest of the history, newest first. All other
+/// queries pass through unchanged.
+pub(crate) struct ProjectHistory {
+    inner: Box<dyn History>,
+    session_state: SharedSessionState,
+}
+
+/// Which part of the history a navigation step searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Project,
+    Elsewhere,
+}
+
+impl ProjectHistory {
+    pub fn new(inner: Box<dyn History>, session_state: SharedSessionState) -> Self {
+        Self {
+            inner,
+            session_state,
+        }
+    }
+
+    fn prefers_project(query: &SearchQuery) -> bool {
+        query.limit == Some(1)
+            && query.filter.cwd_exact.is_none()
+            && query.filter.cwd_prefix.is_none()
+    }
+
+    /// Take one navigation step.
+    ///
+    /// Going back, the project's entries come first and the rest of the
+    /// history follows; going forward retraces the same order.
+    fn navigate(&self, query: &SearchQuery, project: &str) -> reedline::Result<Vec<HistoryItem>> {
+        let current = query.start_id.and_then(|id| self.inner.load(id).ok());
+        let in_project = match &current {
+            Some(item) => item.cwd.as_deref() == Some(project),
+            None => true,
+        };
+        // Reedline skips entries repeating the current one; its filter for
+        // that is private, so the skipping is redone here.
+        let skip = current.map(|item| item.command_line);
+        let skip = skip.as_deref();
+
+        let next = match (query.direction, in_project) {
+            (SearchDirection::Backward, true) => {
+                match self.find(query, query.start_id, Scope::Project, project, skip)? {
+                    Some(item) => Some(item),
+                    None => self.find(query, None, Scope::Elsewhere, project, skip)?,
+                }
+            }
+            (SearchDirection::Forward, false) => {
+                match self.find(query, query.start_id, Scope::Elsewhere, project, skip)? {
+                    Some(item) => Some(item),
+                    None => self.find(query, None, Scope::Project, project, skip)?,
+                }
+            }
+            (_, true) => self.find(query, query.start_id, Scope::Project, project, skip)?,
+            (_, false) => self.find(query, query.start_id, Scope::Elsewhere, project, skip)?,
+        };
+        Ok(next.into_iter().collect())
+    }
+
+    /// Find the first entry after `start_id` in `scope` that isn't `skip`.
+    fn find(
+        &self,
+        query: &SearchQuery,
+        mut start_id: Option<HistoryItemId>,
+        scope: Scope,
+        project: &str,
+        skip: Option<&str>,
+    ) -> reedline::Result<Option<HistoryItem>> {
+        loop {
+            let items = self
+                .inner
+                .search(scoped_query(query, start_id, scope, project))?;
+            let Some(last) = items.last() else {
+                return Ok(None);
+            };
+            start_id = last.id;
+            let found = items.into_iter().find(|item| {
+                skip != Some(item.command_line.as_str())
+                    && (scope == Scope::Project || item.cwd.as_deref() != Some(project))
+            });
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+    }
+}
+
+fn clone_command_line_search(search: &CommandLineSearch) -> CommandLineSearch {
+    match search {
+        CommandLineSearch::Prefix(text) => CommandLineSearch::Prefix(text.clone()),
+        CommandLineSearch::Substring(text) => CommandLineSearch::Substring(text.clone()),
+        CommandLineSearch::Exact(text) => CommandLineSearch::Exact(text.clone()),
+    }
+}
+
+/// Copy a navigation `query` as a batched search of `scope`, starting
+/// after `start_id`.
+fn scoped_query(
+    query: &SearchQuery,
+    start_id: Option<HistoryItemId>,
+    scope: Scope,
+    project: &str,
+) -> SearchQuery {
+    let mut filter = SearchFilter::anything(query.filter.session);
+    filter.command_line = query
+        .filter
+        .command_line
+        .as_ref()
+        .map(clone_command_line_search);
+    filter.hostname = query.filter.hostname.clone();
+    filter.exit_successful = query.filter.exit_successful;
+    if scope == Scope::Project {
+        filter.cwd_exact = Some(project.to_string());
+    }
+    SearchQuery {
+        direction: query.direction,
+        start_time: query.start_time,
+        end_time: query.end_time,
+        start_id,
+        end_id: query.end_id,
+        limit: Some(SEARCH_BATCH),
+        filter,
+    }
+}
+
+impl History for ProjectHistory {
+    fn save(&mut self, h: HistoryItem) -> reedline::Result<HistoryItem> {
+        self.inner.save(h)
+    }
+
+    fn load(&self, id: HistoryItemId) -> reedline::Result<HistoryItem> {
+        self.inner.load(id)
+    }
+
+    fn count(&self, query: SearchQuery) -> reedline::Result<i64> {
+        self.inner.count(query)
+    }
+
+    fn search(&self, query: SearchQuery) -> reedline::Result<Vec<HistoryItem>> {
+        match session_project(&self.session_state) {
+            Some(project) if Self::prefers_project(&query) => self.navigate(&query, &project),
+            _ => self.inner.search(query),
+        }
+    }
+
+    fn update(
+        &mut self,
+        id: HistoryItemId,
+        updater: &dyn Fn(HistoryItem) -> HistoryItem,
+    ) -> reedline::Result<()> {
+        self.inner.update(id, updater)
+    }
+
+    fn clear(&mut self) -> reedline::Result<()> {
+        self.inner.clear()
+    }
+
+    fn delete(&mut self, h: HistoryItemId) -> reedline::Result<()> {
+        self.inner.delete(h)
+    }
+
+    fn sync(&mut self) -> std::io::Result<()> {
+        self.inner.sync()
+    }
+
+    fn session(&self) -> Option<HistorySessionId> {
+        self.inner.session()
+    }
+}
+
+/// Import the commands of an `.Rhistory` file, one entry per line.
+///
+/// R writes multi-line commands as separate lines, so they are imported
+/// line by line as well.
+fn import_rhistory(history: &mut dyn History, content: &str, project: &str) -> Result<usize> {
+    let mut imported = 0;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let mut item = HistoryItem::from_command_line(line);
+        item.cwd = Some(project.to_string());
+        history
+            .save(item)
+            .context("Failed to save imported history entry")?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// A history entry in the JSON export.
+#[derive(Debug, Serialize)]
+struct ExportedEntry {
+    command: String,
+    timestamp: Option<String>,
+    project: Option<String>,
+    duration_ms: Option<u128>,
+    exit_status: Option<i64>,
+}
+
+/// Export history entries, oldest first, optionally limited to a project.
+fn export_history(
+    history: &dyn History,
+    format: HistoryExportFormat,
+    project: Option<&str>,
+) -> Result<String> {
+    let mut query = SearchQuery::everything(SearchDirection::Forward, None);
+    query.filter.cwd_exact = project.map(project_key);
+    let items = history.search(query).context("Failed to read history")?;
+
+    match format {
+        HistoryExportFormat::Rhistory => Ok(items
+            .iter()
+            .map(|item| format!("{}\n", item.command_line))
+            .collect()),
+        HistoryExportFormat::Json => {
+            let entries: Vec<ExportedEntry> = items
+                .into_iter()
+                .map(|item| ExportedEntry {
+                    command: item.command_line,
+                    timestamp: item.start_timestamp.map(|time| time.to_rfc3339()),
+                    project: item.cwd,
+                    duration_ms: item.duration.map(|duration| duration.as_millis()),
+                    exit_status: item.exit_status,
+                })
+                .collect();
+            let mut json = serde_json::to_string_pretty(&entries)?;
+            json.push('\n');
+            Ok(json)
+        }
+    }
+}
+
+fn current_dir_project() -> Result<String> {
+    let directory = std::env::current_dir().context("Failed to read the current directory")?;
+    Ok(project_key(&directory.to_string_lossy()))
+}
+
+/// Run a `history` subcommand against the history database.
+pub(crate) fn run_history_command(action: &HistoryAction) -> Result<()> {
+    let mut history = open_history_db()?;
+    match action {
+        HistoryAction::Import { path, project } => {
+            let content =
+                std::fs::read_to_string(path).with_context(|| format!("Failed to read {path}"))?;
+            let project = match project {
+                Some(project) => project_key(project),
+                None => current_dir_project()?,
+            };
+            let imported = import_rhistory(&mut history, &content, &project)?;
+            println!("Imported {imported} entries from {path} into project {project}");
+        }
+        HistoryAction::Export { format, project } => {
+            print!("{}", export_history(&history, *format, project.as_deref())?);
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::console::session_state::SessionState;
 
     #[test]
     fn history_db_path_returns_some() {
@@ -53,4 +361,165 @@ mod tests {
             assert!(p.ends_with("krarkode/history.db"));
         }
     }
+
+    fn session_in(directory: &str) -> SharedSessionState {
+        SessionState {
+            working_directory: Some(directory.to_string()),
+            ..SessionState::default()
+        }
+        .shared()
+    }
+
+    fn save(history: &mut dyn History, command: &str, project: &str) {
+        let mut item = HistoryItem::from_command_line(command);
+        item.cwd = Some(project.to_string());
+        history.save(item).unwrap();
+    }
+
+    /// Step through history like reedline's cursor, starting after `start_id`.
+    fn navigate(
+        history: &dyn History,
+        direction: SearchDirection,
+        mut start_id: Option<HistoryItemId>,
+        steps: usize,
+    ) -> Vec<String> {
+        let mut commands = Vec::new();
+        for _ in 0..steps {
+            let mut query = SearchQuery::everything(direction, None);
+            query.start_id = start_id;
+            query.limit = Some(1);
+            let Some(item) = history.search(query).unwrap().into_iter().next() else {
+                break;
+            };
+            start_id = item.id;
+            commands.push(item.command_line);
+        }
+        commands
+    }
+
+    #[test]
+    fn project_key_normalizes_directories() {
+        assert_eq!(project_key("/work/proj/"), "/work/proj");
+        assert_eq!(project_key("/"), "/");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(project_key("~/proj"), format!("{}/proj", home.display()));
+        }
+        assert_eq!(project_key("~other/proj"), "~other/proj");
+    }
+
+    #[test]
+    fn session_project_falls_back_to_connection_file() {
+        let state = SessionState {
+            connection_file: Some("/tmp/kernel-1.json".to_string()),
+            ..SessionState::default()
+        }
+        .shared();
+        assert_eq!(
+            session_project(&state),
+            Some("/tmp/kernel-1.json".to_string())
+        );
+        assert_eq!(
+            session_project(&session_in("/work/a/")),
+            Some("/work/a".to_string())
+        );
+    }
+
+    #[test]
+    fn navigation_prefers_project_entries() {
+        let mut history = ProjectHistory::new(
+            Box::new(SqliteBackedHistory::in_memory().unwrap()),
+            session_in("/work/a"),
+        );
+        save(&mut history, "a1", "/work/a");
+        save(&mut history, "b1", "/work/b");
+        save(&mut history, "a2", "/work/a");
+        save(&mut history, "b2", "/work/b");
+
+        assert_eq!(
+            navigate(&history, SearchDirection::Backward, None, 5),
+            vec!["a2", "a1", "b2", "b1"]
+        );
+    }
+
+    #[test]
+    fn forward_navigation_retraces_the_way_back() {
+        let mut history = ProjectHistory::new(
+            Box::new(SqliteBackedHistory::in_memory().unwrap()),
+            session_in("/work/a"),
+        );
+        save(&mut history, "a1", "/work/a");
+        save(&mut history, "b1", "/work/b");
+        save(&mut history, "a2", "/work/a");
+
+        // "b1" is the last entry on the way back.
+        let b1 = history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))
+            .unwrap()
+            .into_iter()
+            .find(|item| item.command_line == "b1")
+            .unwrap();
+        assert_eq!(
+            navigate(&history, SearchDirection::Forward, b1.id, 5),
+            vec!["a1", "a2"]
+        );
+    }
+
+    #[test]
+    fn navigation_skips_repeated_project_entries() {
+        let mut history = ProjectHistory::new(
+            Box::new(SqliteBackedHistory::in_memory().unwrap()),
+            session_in("/work/a"),
+        );
+        save(&mut history, "x <- 1", "/work/a");
+        save(&mut history, "plot(x)", "/work/a");
+        save(&mut history, "plot(x)", "/work/a");
+
+        assert_eq!(
+            navigate(&history, SearchDirection::Backward, None, 2),
+            vec!["plot(x)", "x <- 1"]
+        );
+    }
+
+    #[test]
+    fn listing_queries_are_not_scoped() {
+        let mut history = ProjectHistory::new(
+            Box::new(SqliteBackedHistory::in_memory().unwrap()),
+            session_in("/work/a"),
+        );
+        save(&mut history, "a1", "/work/a");
+        save(&mut history, "b1", "/work/b");
+
+        let items = history
+            .search(SearchQuery::everything(SearchDirection::Forward, None))
+            .unwrap();
+        assert_eq!(items.len(), 2);
+    }
+
+    #[test]
+    fn rhistory_import_and_export_round_trip() {
+        let mut history = SqliteBackedHistory::in_memory().unwrap();
+        let imported = import_rhistory(&mut history, "x <- 1\n\nplot(x)\n", "/work/a").unwrap();
+        assert_eq!(imported, 2);
+        save(&mut history, "y <- 2", "/work/b");
+
+        assert_eq!(
+            export_history(&history, HistoryExportFormat::Rhistory, None).unwrap(),
+            "x <- 1\nplot(x)\ny <- 2\n"
+        );
+        assert_eq!(
+            export_history(&history, HistoryExportFormat::Rhistory, Some("/work/a/")).unwrap(),
+            "x <- 1\nplot(x)\n"
+        );
+    }
+
+    #[test]
+    fn json_export_includes_project() {
+        let mut history = SqliteBackedHistory::in_memory().unwrap();
+        save(&mut history, "mean(x)", "/work/a");
+
+        let json = export_history(&history, HistoryExportFormat::Json, None).unwrap();
+        let entries: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(entries[0]["command"], "mean(x)");
+        assert_eq!(entries[0]["project"], "/work/a");
+    }
 }
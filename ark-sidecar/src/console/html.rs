@@ -0,0 +1,754 @@
+// HTML to styled terminal text.
+//
+// A small renderer for R help pages: headings, paragraphs wrapped to the
+// terminal width, `<pre>` code blocks, argument tables, lists, and inline
+// code/bold/italic. Anything else is rendered as plain text; `<head>`,
+// `<style>` and `<script>` content is dropped. Rich kernel output such as
+// a kable or gt table renders the same way, but with tables drawn as grids.
+
+use nu_ansi_term::{Color, Style};
+
+use super::table::render_grid;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HtmlToken {
+    Text(String),
+    Open { name: String, self_closing: bool },
+    Close(String),
+}
+
+/// Split HTML into text and tag tokens. Attributes, comments and doctype
+/// declarations are discarded.
+fn tokenize(html: &str) -> Vec<HtmlToken> {
+    let mut tokens = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(lt) = rest.find('<') else {
+            tokens.push(HtmlToken::Text(rest.to_string()));
+            break;
+        };
+        if lt > 0 {
+            tokens.push(HtmlToken::Text(rest[..lt].to_string()));
+        }
+        rest = &rest[lt..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let starts_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || matches!(ch, '/' | '!' | '?'));
+        let Some(gt) = rest.find('>').filter(|_| starts_tag) else {
+            // A stray `<` in text, e.g. `x < y` left unescaped.
+            tokens.push(HtmlToken::Text("<".to_string()));
+            rest = &rest[1..];
+            continue;
+        };
+        let tag = &rest[1..gt];
+        rest = &rest[gt + 1..];
+
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(HtmlToken::Close(tag_name(name)));
+        } else {
+            tokens.push(HtmlToken::Open {
+                name: tag_name(tag),
+                self_closing: tag.trim_end().ends_with('/'),
+            });
+        }
+    }
+
+    tokens
+}
+
+fn tag_name(tag: &str) -> String {
+    tag.trim_start()
+        .split(|ch: char| ch.is_whitespace() || ch == '/')
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+/// Decode HTML character references.
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let Some(semi) = rest.find(';').filter(|&semi| semi <= 10) else {
+            decoded.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..semi];
+        let replacement = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            "ndash" => Some('–'),
+            "mdash" => Some('—'),
+            "hellip" => Some('…'),
+            "lsquo" => Some('‘'),
+            "rsquo" => Some('’'),
+            "ldquo" => Some('“'),
+            "rdquo" => Some('”'),
+            "times" => Some('×'),
+            "copy" => Some('©'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match replacement {
+            Some(ch) => {
+                decoded.push(ch);
+                rest = &rest[semi + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// A `<table>` collected for drawing as a grid.
+#[derive(Debug, Default)]
+struct GridTable {
+    rows: Vec<Vec<String>>,
+    /// Leading rows inside `<thead>` or made only of `<th>` cells.
+    header_rows: usize,
+    in_head: bool,
+    row_open: bool,
+    /// Every cell of the current row so far is a `<th>`.
+    row_is_header: bool,
+    cell: Option<String>,
+    in_caption: bool,
+    caption: Option<String>,
+    /// Tables nested in a cell are flattened into its text.
+    nested: usize,
+}
+
+impl GridTable {
+    fn text(&mut self, text: &str) {
+        if let Some(cell) = &mut self.cell {
+            cell.push_str(text);
+        }
+    }
+
+    fn start_row(&mut self) {
+        self.end_row();
+        self.rows.push(Vec::new());
+        self.row_open = true;
+        self.row_is_header = true;
+    }
+
+    fn end_row(&mut self) {
+        self.end_cell();
+        if !std::mem::take(&mut self.row_open) {
+            return;
+        }
+        if self.rows.last().is_some_and(Vec::is_empty) {
+            self.rows.pop();
+        } else if self.header_rows + 1 == self.rows.len() && (self.in_head || self.row_is_header) {
+            self.header_rows += 1;
+        }
+    }
+
+    fn start_cell(&mut self, header: bool) {
+        self.end_cell();
+        if !self.row_open {
+            self.start_row();
+        }
+        self.row_is_header &= header;
+        self.cell = Some(String::new());
+    }
+
+    fn end_cell(&mut self) {
+        let Some(cell) = self.cell.take() else {
+            return;
+        };
+        let text = cell.split_whitespace().collect::<Vec<_>>().join(" ");
+        if std::mem::take(&mut self.in_caption) {
+            self.caption = Some(text);
+        } else if let Some(row) = self.rows.last_mut() {
+            row.push(text);
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        match name {
+            "table" => {
+                self.nested += 1;
+                self.text(" ");
+            }
+            _ if self.nested > 0 => self.text(" "),
+            "thead" => {
+                self.end_row();
+                self.in_head = true;
+            }
+            "tbody" | "tfoot" => {
+                self.end_row();
+                self.in_head = false;
+            }
+            "tr" => self.start_row(),
+            "td" => self.start_cell(false),
+            "th" => self.start_cell(true),
+            "caption" => {
+                self.end_cell();
+                self.in_caption = true;
+                self.cell = Some(String::new());
+            }
+            "br" | "p" | "div" | "li" => self.text(" "),
+            _ => {}
+        }
+    }
+
+    /// Returns true when the table itself closes.
+    fn close(&mut self, name: &str) -> bool {
+        match name {
+            "table" if self.nested > 0 => self.nested -= 1,
+            "table" => {
+                self.end_row();
+                return true;
+            }
+            _ if self.nested > 0 => {}
+            "td" | "th" | "caption" => self.end_cell(),
+            "tr" => self.end_row(),
+            "thead" => {
+                self.end_row();
+                self.in_head = false;
+            }
+            _ => {}
+        }
+        false
+    }
+}
+
+/// A word made of differently styled pieces, e.g. `` `x`, `` → `x` + `,`.
+type Word = Vec<(Style, String)>;
+
+struct Renderer {
+    width: usize,
+    use_ansi: bool,
+    lines: Vec<String>,
+    words: Vec<Word>,
+    word: Word,
+    indent: usize,
+    skip_depth: usize,
+    pre: Option<String>,
+    heading: Option<u8>,
+    bold: usize,
+    italic: usize,
+    code: usize,
+    table_depth: usize,
+    /// Indent to restore when the current table cell or `<dd>` closes.
+    indent_stack: Vec<usize>,
+    cell_index: usize,
+    /// Draw tables as grids instead of argument lists.
+    grid_tables: bool,
+    grid: Option<GridTable>,
+}
+
+impl Renderer {
+    fn new(width: usize, use_ansi: bool) -> Self {
+        Self {
+            width,
+            use_ansi,
+            lines: Vec::new(),
+            words: Vec::new(),
+            word: Vec::new(),
+            indent: 0,
+            skip_depth: 0,
+            pre: None,
+            heading: None,
+            bold: 0,
+            italic: 0,
+            code: 0,
+            table_depth: 0,
+            indent_stack: Vec::new(),
+            cell_index: 0,
+            grid_tables: false,
+            grid: None,
+        }
+    }
+
+    fn inline_style(&self) -> Style {
+        let mut style = Style::new();
+        match self.heading {
+            Some(level) if level <= 2 => style = style.fg(Color::Yellow).bold().underline(),
+            Some(_) => style = style.fg(Color::Yellow).bold(),
+            None => {}
+        }
+        if self.code > 0 {
+            style = style.fg(Color::Cyan);
+        }
+        if self.bold > 0 {
+            style = style.bold();
+        }
+        if self.italic > 0 {
+            style = style.italic();
+        }
+        style
+    }
+
+    fn paint(&self, style: Style, text: &str) -> String {
+        if self.use_ansi && style != Style::new() {
+            style.paint(text).to_string()
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        let text = decode_entities(text);
+        if let Some(grid) = &mut self.grid {
+            grid.text(&text);
+            return;
+        }
+        if let Some(pre) = &mut self.pre {
+            pre.push_str(&text);
+            return;
+        }
+
+        let style = self.inline_style();
+        for ch in text.chars() {
+            if matches!(ch, ' ' | '\t' | '\n' | '\r') {
+                self.end_word();
+                continue;
+            }
+            match self.word.last_mut() {
+                Some((last_style, piece)) if *last_style == style => piece.push(ch),
+                _ => self.word.push((style, ch.to_string())),
+            }
+        }
+    }
+
+    fn end_word(&mut self) {
+        if !self.word.is_empty() {
+            self.words.push(std::mem::take(&mut self.word));
+        }
+    }
+
+    /// Wrap the pending words into lines at the current indent.
+    fn flush(&mut self) {
+        self.end_word();
+        if self.words.is_empty() {
+            return;
+        }
+
+        let indent = " ".repeat(self.indent);
+        let mut line = indent.clone();
+        let mut line_len = self.indent;
+        for word in std::mem::take(&mut self.words) {
+            let word_len: usize = word.iter().map(|(_, piece)| piece.chars().count()).sum();
+            if line_len > self.indent && line_len + 1 + word_len > self.width {
+                self.lines
+                    .push(std::mem::replace(&mut line, indent.clone()));
+                line_len = self.indent;
+            }
+            if line_len > self.indent {
+                line.push(' ');
+                line_len += 1;
+            }
+            for (style, piece) in &word {
+                line.push_str(&self.paint(*style, piece));
+            }
+            line_len += word_len;
+        }
+        self.lines.push(line);
+    }
+
+    /// Flush and separate the next block with one blank line.
+    fn block_break(&mut self) {
+        self.flush();
+        if self.lines.last().is_some_and(|line| !line.is_empty()) {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn open(&mut self, name: &str) {
+        match name {
+            "head" | "style" | "script" | "title" => self.skip_depth += 1,
+            _ if self.skip_depth > 0 => {}
+            _ if self.grid.is_some() => {
+                if let Some(grid) = &mut self.grid {
+                    grid.open(name);
+                }
+            }
+            "table" if self.grid_tables => {
+                self.block_break();
+                self.grid = Some(GridTable::default());
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.block_break();
+                self.heading = name[1..].parse().ok();
+            }
+            "p" | "div" | "blockquote" if self.table_depth > 0 => self.flush(),
+            "p" | "div" | "blockquote" | "ul" | "ol" | "dl" => self.block_break(),
+            "pre" => {
+                self.block_break();
+                self.pre = Some(String::new());
+            }
+            "table" => {
+                self.block_break();
+                self.table_depth += 1;
+            }
+            "tr" => {
+                self.flush();
+                self.cell_index = 0;
+            }
+            "td" | "th" => {
+                self.flush();
+                self.indent_stack.push(self.indent);
+                if self.cell_index > 0 {
+                    self.indent += 4;
+                }
+                self.cell_index += 1;
+            }
+            "dt" => {
+                self.flush();
+                self.bold += 1;
+            }
+            "dd" => {
+                self.flush();
+                self.indent_stack.push(self.indent);
+                self.indent += 4;
+            }
+            "li" => {
+                self.flush();
+                self.word.push((Style::new(), "•".to_string()));
+                self.end_word();
+            }
+            "br" => self.flush(),
+            "hr" => {
+                self.block_break();
+                self.lines.push("─".repeat(self.width.min(40)));
+            }
+            "code" | "kbd" | "samp" | "tt" => self.code += 1,
+            "b" | "strong" => self.bold += 1,
+            "i" | "em" | "var" => self.italic += 1,
+            _ => {}
+        }
+    }
+
+    fn close(&mut self, name: &str) {
+        match name {
+            "head" | "style" | "script" | "title" => {
+                self.skip_depth = self.skip_depth.saturating_sub(1)
+            }
+            _ if self.skip_depth > 0 => {}
+            _ if self.grid.is_some() => {
+                if self.grid.as_mut().is_some_and(|grid| grid.close(name)) {
+                    self.push_grid();
+                    self.block_break();
+                }
+            }
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                self.flush();
+                self.heading = None;
+                self.block_break();
+            }
+            "p" | "div" | "blockquote" if self.table_depth > 0 => self.flush(),
+            "p" | "div" | "blockquote" | "ul" | "ol" | "dl" => self.block_break(),
+            "pre" => {
+                if let Some(pre) = self.pre.take() {
+                    self.push_code_block(&pre);
+                }
+                self.block_break();
+            }
+            "table" => {
+                self.table_depth = self.table_depth.saturating_sub(1);
+                self.block_break();
+            }
+            "td" | "th" | "dd" => {
+                self.flush();
+                if let Some(indent) = self.indent_stack.pop() {
+                    self.indent = indent;
+                }
+            }
+            "dt" => {
+                self.flush();
+                self.bold = self.bold.saturating_sub(1);
+            }
+            "li" | "tr" => self.flush(),
+            "code" | "kbd" | "samp" | "tt" => self.code = self.code.saturating_sub(1),
+            "b" | "strong" => self.bold = self.bold.saturating_sub(1),
+            "i" | "em" | "var" => self.italic = self.italic.saturating_sub(1),
+            _ => {}
+        }
+    }
+
+    fn push_code_block(&mut self, code: &str) {
+        let code = code.strip_prefix('\n').unwrap_or(code).trim_end();
+        let indent = " ".repeat(self.indent + 2);
+        let style = Style::new().fg(Color::Green);
+        for line in code.lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                self.lines.push(String::new());
+                continue;
+            }
+            let painted = self.paint(style, line);
+            self.lines.push(format!("{indent}{painted}"));
+        }
+    }
+
+    fn push_grid(&mut self) {
+        let Some(mut grid) = self.grid.take() else {
+            return;
+        };
+        grid.end_row();
+        let indent = " ".repeat(self.indent);
+        if let Some(caption) = grid.caption.filter(|caption| !caption.is_empty()) {
+            let painted = self.paint(Style::new().italic(), &caption);
+            self.lines.push(format!("{indent}{painted}"));
+        }
+        let width = self.width.saturating_sub(self.indent);
+        for line in render_grid(&grid.rows, grid.header_rows, width, self.use_ansi) {
+            self.lines.push(format!("{indent}{line}"));
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        self.push_grid();
+        if let Some(pre) = self.pre.take() {
+            self.push_code_block(&pre);
+        }
+        self.flush();
+        while self.lines.last().is_some_and(|line| line.is_empty()) {
+            self.lines.pop();
+        }
+        self.lines
+    }
+}
+
+/// Render HTML as terminal lines wrapped to `width` columns.
+///
+/// With `use_ansi`, headings, code, and emphasis are styled with ANSI
+/// escapes; otherwise the output is plain text.
+pub(crate) fn html_to_terminal(html: &str, width: usize, use_ansi: bool) -> Vec<String> {
+    render(html, Renderer::new(width.max(20), use_ansi))
+}
+
+/// Render HTML kernel output, such as a kable or gt table, drawing each
+/// `<table>` as a grid fitted to `width` columns.
+pub(crate) fn html_output_to_terminal(html: &str, width: usize, use_ansi: bool) -> Vec<String> {
+    let mut renderer = Renderer::new(width.max(20), use_ansi);
+    renderer.grid_tables = true;
+    render(html, renderer)
+}
+
+fn render(html: &str, mut renderer: Renderer) -> Vec<String> {
+    for token in tokenize(html) {
+        match token {
+            HtmlToken::Text(text) => renderer.text(&text),
+            HtmlToken::Open { name, self_closing } => {
+                renderer.open(&name);
+                if self_closing {
+                    renderer.close(&name);
+                }
+            }
+            HtmlToken::Close(name) => renderer.close(&name),
+        }
+    }
+    renderer.finish()
+}
+
+/// The document `<title>`, e.g. `R: Arithmetic Mean`.
+pub(crate) fn html_title(html: &str) -> Option<String> {
+    let mut tokens = tokenize(html).into_iter();
+    tokens.find(|token| matches!(token, HtmlToken::Open { name, .. } if name == "title"))?;
+    let mut title = String::new();
+    for token in tokens {
+        match token {
+            HtmlToken::Text(text) => title.push_str(&text),
+            _ => break,
+        }
+    }
+    let title = decode_entities(
+        title
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .as_str(),
+    );
+    (!title.is_empty()).then_some(title)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(html: &str, width: usize) -> Vec<String> {
+        html_to_terminal(html, width, false)
+    }
+
+    #[test]
+    fn headings_and_paragraphs_are_separated() {
+        let html = "<html><head><title>R: Mean</title><style>p { x }</style></head>\
+            <body><h2>Arithmetic Mean</h2><p>Generic function for the mean.</p>\
+            <h3>Usage</h3></body></html>";
+        assert_eq!(
+            plain(html, 80),
+            vec![
+                "Arithmetic Mean",
+                "",
+                "Generic function for the mean.",
+                "",
+                "Usage",
+            ]
+        );
+    }
+
+    #[test]
+    fn paragraphs_wrap_to_width() {
+        let html = "<p>The arithmetic mean of the values in x is computed.</p>";
+        assert_eq!(
+            plain(html, 24),
+            vec!["The arithmetic mean of", "the values in x is", "computed."]
+        );
+    }
+
+    #[test]
+    fn inline_tags_do_not_split_words() {
+        let html = "<p>Use <code>na.rm</code>, then <em>trim</em>.</p>";
+        assert_eq!(plain(html, 80), vec!["Use na.rm, then trim."]);
+    }
+
+    #[test]
+    fn code_blocks_keep_their_layout() {
+        let html = "<h3>Examples</h3><pre>\nx &lt;- c(0:10, 50)\nxm &lt;- mean(x)\n\n  c(xm, mean(x, trim = 0.10))\n</pre>";
+        assert_eq!(
+            plain(html, 20),
+            vec![
+                "Examples",
+                "",
+                "  x <- c(0:10, 50)",
+                "  xm <- mean(x)",
+                "",
+                "    c(xm, mean(x, trim = 0.10))",
+            ]
+        );
+    }
+
+    #[test]
+    fn argument_tables_indent_descriptions() {
+        let html = "<table><tr><td><code>x</code></td>\
+            <td><p>An R object.</p></td></tr>\
+            <tr><td><code>trim</code></td><td><p>the fraction of observations to be trimmed.</p></td></tr></table>";
+        assert_eq!(
+            plain(html, 30),
+            vec![
+                "x",
+                "    An R object.",
+                "trim",
+                "    the fraction of",
+                "    observations to be",
+                "    trimmed.",
+            ]
+        );
+    }
+
+    #[test]
+    fn lists_get_bullets() {
+        let html = "<ul><li>first</li><li>second item</li></ul>";
+        assert_eq!(plain(html, 80), vec!["• first", "• second item"]);
+    }
+
+    #[test]
+    fn title_is_extracted() {
+        assert_eq!(
+            html_title("<html><head><title>R: Arithmetic\n  Mean</title></head></html>"),
+            Some("R: Arithmetic Mean".to_string())
+        );
+        assert_eq!(html_title("<h2>Mean</h2>"), None);
+    }
+
+    #[test]
+    fn entities_are_decoded() {
+        assert_eq!(
+            decode_entities("a &lt;- b &amp;&amp; c &#x3E; d &#39;e&#39; &unknown; & f"),
+            "a <- b && c > d 'e' &unknown; & f"
+        );
+    }
+
+    #[test]
+    fn styles_are_applied_with_ansi() {
+        let lines = html_to_terminal("<h3>Usage</h3><p>Call <code>mean</code>.</p>", 80, true);
+        assert_eq!(
+            lines[0],
+            Style::new()
+                .fg(Color::Yellow)
+                .bold()
+                .paint("Usage")
+                .to_string()
+        );
+        assert!(lines[2].contains(&Style::new().fg(Color::Cyan).paint("mean").to_string()));
+    }
+
+    #[test]
+    fn output_tables_are_drawn_as_grids() {
+        let html = "<p>Cars</p><table class=\"dataframe\"><caption>mtcars</caption>\
+            <thead><tr><th>model</th><th>mpg</th></tr></thead>\
+            <tbody><tr><td>Mazda RX4</td><td>21.0</td></tr>\
+            <tr><td>Datsun&nbsp;710</td><td>22.8</td></tr></tbody></table><p>2 rows</p>";
+        assert_eq!(
+            html_output_to_terminal(html, 80, false),
+            vec![
+                "Cars",
+                "",
+                "mtcars",
+                "┌────────────┬──────┐",
+                "│ model      │  mpg │",
+                "├────────────┼──────┤",
+                "│ Mazda RX4  │ 21.0 │",
+                "│ Datsun 710 │ 22.8 │",
+                "└────────────┴──────┘",
+                "",
+                "2 rows",
+            ]
+        );
+    }
+
+    #[test]
+    fn output_tables_without_thead_use_th_rows() {
+        let html = "<table><tr><th>a</th><th>b</th></tr><tr><td>x</td><td>y</td></tr>\
+            <tr><td><table><tr><td>in</td><td>ner</td></tr></table></td><td>z</td></tr></table>";
+        assert_eq!(
+            html_output_to_terminal(html, 80, false),
+            vec![
+                "┌────────┬───┐",
+                "│ a      │ b │",
+                "├────────┼───┤",
+                "│ x      │ y │",
+                "│ in ner │ z │",
+                "└────────┴───┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_stray_brackets_are_handled() {
+        assert_eq!(plain("<!-- hidden --><p>x < y</p>", 80), vec!["x < y"]);
+    }
+}
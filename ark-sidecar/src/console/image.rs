@@ -0,0 +1,472 @@
+// Inline image output for console mode.
+//
+// Draws PNG display data (plots) in terminals that speak the kitty
+// graphics, iTerm2 inline image or sixel protocol. Images are fitted to
+// the terminal, never upscaled, using its pixel size per cell. Kitty and
+// iTerm2 terminals scale the PNG themselves; sixel needs the pixels, so
+// the image is decoded, resized and mapped to a 6x6x6 color cube.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use anyhow::{bail, Context, Result};
+use base64::Engine;
+
+use super::terminal::TerminalInfo;
+use crate::types::ImageProtocol;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Base64 bytes per kitty graphics escape; larger payloads are chunked.
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Cell size assumed when the terminal doesn't report its pixel size.
+const DEFAULT_CELL_SIZE: (u32, u32) = (10, 20);
+
+/// Rows left free below an image for the prompt.
+const PROMPT_ROWS: u32 = 2;
+
+/// Pick a protocol for the terminal.
+///
+/// Multiplexers pass graphics escapes through unreliably, so images are
+/// off inside tmux and screen unless a protocol is set explicitly. VS
+/// Code's terminal only draws images with `terminal.integrated.enableImages`
+/// on, which the environment doesn't reveal, so it gets placeholders too.
+pub(crate) fn detect_image_protocol(terminal: &TerminalInfo) -> ImageProtocol {
+    if terminal.multiplexed {
+        return ImageProtocol::None;
+    }
+    if terminal.is_kitty() || terminal.is_ghostty() {
+        ImageProtocol::Kitty
+    } else if terminal.is_iterm2() || terminal.is_wezterm() {
+        ImageProtocol::Iterm2
+    } else if terminal.term.starts_with("foot") || terminal.term.starts_with("mlterm") {
+        ImageProtocol::Sixel
+    } else {
+        ImageProtocol::None
+    }
+}
+
+/// Resolve `auto` against the current environment.
+pub(crate) fn resolve_image_protocol(setting: ImageProtocol) -> ImageProtocol {
+    match setting {
+        ImageProtocol::Auto => detect_image_protocol(&TerminalInfo::current()),
+        protocol => protocol,
+    }
+}
+
+/// Width and height from a PNG's header.
+fn png_dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(PNG_SIGNATURE) || png.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(png.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(png.get(20..24)?.try_into().ok()?);
+    (width > 0 && height > 0).then_some((width, height))
+}
+
+/// Where an image goes on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ImageLayout {
+    columns: u32,
+    rows: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Scale an image down to fit `max_cells`, keeping its aspect ratio.
+fn fit_image(image: (u32, u32), cell: (u32, u32), max_cells: (u32, u32)) -> ImageLayout {
+    let (image_width, image_height) = (image.0 as f64, image.1 as f64);
+    let max_width = (max_cells.0 * cell.0) as f64;
+    let max_height = (max_cells.1 * cell.1) as f64;
+    let scale = (max_width / image_width)
+        .min(max_height / image_height)
+        .min(1.0);
+    let width = ((image_width * scale).round() as u32).max(1);
+    let height = ((image_height * scale).round() as u32).max(1);
+    ImageLayout {
+        columns: width.div_ceil(cell.0),
+        rows: height.div_ceil(cell.1),
+        width,
+        height,
+    }
+}
+
+/// Cell size in pixels and the cells available for an image.
+fn terminal_geometry() -> ((u32, u32), (u32, u32)) {
+    let Ok(size) = crossterm::terminal::window_size() else {
+        return (DEFAULT_CELL_SIZE, (80, 24 - PROMPT_ROWS));
+    };
+    let (columns, rows) = (u32::from(size.columns.max(1)), u32::from(size.rows.max(1)));
+    let cell = if size.width > 0 && size.height > 0 {
+        (
+            (u32::from(size.width) / columns).max(1),
+            (u32::from(size.height) / rows).max(1),
+        )
+    } else {
+        DEFAULT_CELL_SIZE
+    };
+    (cell, (columns, rows.saturating_sub(PROMPT_ROWS).max(1)))
+}
+
+fn kitty_sequence(png: &[u8], layout: ImageLayout) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+    let mut sequence = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(index + 1 < chunks.len());
+        // Transmit and display a PNG in a cell box; q=2 keeps the terminal
+        // from answering on stdin.
+        if index == 0 {
+            let _ = write!(
+                sequence,
+                "\x1b_Ga=T,f=100,q=2,c={},r={},m={more};",
+                layout.columns, layout.rows
+            );
+        } else {
+            let _ = write!(sequence, "\x1b_Gm={more};");
+        }
+        sequence.push_str(std::str::from_utf8(chunk).expect("base64 is ASCII"));
+        sequence.push_str("\x1b\\");
+    }
+    sequence
+}
+
+fn iterm2_sequence(png: &[u8], layout: ImageLayout) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+        png.len(),
+        layout.columns,
+        layout.rows,
+        base64::engine::general_purpose::STANDARD.encode(png)
+    )
+}
+
+/// Decoded RGBA pixels.
+struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+fn decode_png(png: &[u8]) -> Result<RgbaImage> {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().context("Failed to read PNG header")?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buffer)
+        .context("Failed to decode PNG")?;
+    let data = &buffer[..frame.buffer_size()];
+    let pixels = match frame.color_type {
+        png::ColorType::Rgba => data
+            .chunks_exact(4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+            .collect(),
+        png::ColorType::Rgb => data
+            .chunks_exact(3)
+            .map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => data
+            .chunks_exact(2)
+            .map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => data.iter().map(|&v| [v, v, v, 255]).collect(),
+        other => bail!("Unsupported PNG color type {other:?}"),
+    };
+    Ok(RgbaImage {
+        width: frame.width,
+        height: frame.height,
+        pixels,
+    })
+}
+
+/// Resize by averaging the source pixels under each target pixel.
+fn resize(image: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if (width, height) == (image.width, image.height) {
+        return RgbaImage {
+            width,
+            height,
+            pixels: image.pixels.clone(),
+        };
+    }
+    let span = |target: u32, target_size: u32, source_size: u32| {
+        let start = (u64::from(target) * u64::from(source_size) / u64::from(target_size)) as u32;
+        let end = (u64::from(target + 1) * u64::from(source_size)).div_ceil(u64::from(target_size))
+            as u32;
+        start..end.max(start + 1).min(source_size)
+    };
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        let rows = span(y, height, image.height);
+        for x in 0..width {
+            let columns = span(x, width, image.width);
+            let mut sum = [0u32; 4];
+            let mut count = 0;
+            for source_y in rows.clone() {
+                for source_x in columns.clone() {
+                    let pixel = image.pixels[(source_y * image.width + source_x) as usize];
+                    for (total, channel) in sum.iter_mut().zip(pixel) {
+                        *total += u32::from(channel);
+                    }
+                    count += 1;
+                }
+            }
+            pixels.push(sum.map(|total| (total / count.max(1)) as u8));
+        }
+    }
+    RgbaImage {
+        width,
+        height,
+        pixels,
+    }
+}
+
+/// Index in the 6x6x6 color cube, or `None` for transparent pixels.
+fn cube_color(pixel: [u8; 4]) -> Option<usize> {
+    if pixel[3] < 128 {
+        return None;
+    }
+    let level = |channel: u8| (usize::from(channel) * 5 + 127) / 255;
+    Some(level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]))
+}
+
+fn push_sixel_run(sequence: &mut String, sixel: char, count: usize) {
+    if count >= 4 {
+        let _ = write!(sequence, "!{count}{sixel}");
+    } else {
+        sequence.extend(std::iter::repeat_n(sixel, count));
+    }
+}
+
+fn sixel_sequence(image: &RgbaImage) -> String {
+    let (width, height) = (image.width as usize, image.height as usize);
+    let colors: Vec<Option<usize>> = image.pixels.iter().map(|&p| cube_color(p)).collect();
+
+    // Transparent pixels keep the terminal background (P2=1)
+    let mut sequence = format!("\x1bP0;1;0q\"1;1;{width};{height}");
+    let used: BTreeSet<usize> = colors.iter().flatten().copied().collect();
+    for &color in &used {
+        let percent = |level: usize| level * 100 / 5;
+        let _ = write!(
+            sequence,
+            "#{color};2;{};{};{}",
+            percent(color / 36),
+            percent(color / 6 % 6),
+            percent(color % 6)
+        );
+    }
+
+    for band in (0..height).step_by(6) {
+        if band > 0 {
+            sequence.push('-');
+        }
+        let rows = band..(band + 6).min(height);
+        let band_colors: BTreeSet<usize> = rows
+            .clone()
+            .flat_map(|y| colors[y * width..(y + 1) * width].iter().flatten().copied())
+            .collect();
+        for (index, &color) in band_colors.iter().enumerate() {
+            if index > 0 {
+                sequence.push('$');
+            }
+            let _ = write!(sequence, "#{color}");
+            let mut run: Option<(char, usize)> = None;
+            for x in 0..width {
+                let bits = rows
+                    .clone()
+                    .filter(|&y| colors[y * width + x] == Some(color))
+                    .fold(0u8, |bits, y| bits | 1 << (y - band));
+                let sixel = char::from(63 + bits);
+                run = match run {
+                    Some((previous, count)) if previous == sixel => Some((sixel, count + 1)),
+                    Some((previous, count)) => {
+                        push_sixel_run(&mut sequence, previous, count);
+                        Some((sixel, 1))
+                    }
+                    None => Some((sixel, 1)),
+                };
+            }
+            if let Some((sixel, count)) = run {
+                push_sixel_run(&mut sequence, sixel, count);
+            }
+        }
+    }
+    sequence.push_str("\x1b\\");
+    sequence
+}
+
+/// Escape sequences drawing a PNG inline, followed by a newline.
+pub(crate) fn render_png(png: &[u8], protocol: ImageProtocol) -> Result<String> {
+    let dimensions = png_dimensions(png).context("Image data is not a PNG")?;
+    let (cell, max_cells) = terminal_geometry();
+    let layout = fit_image(dimensions, cell, max_cells);
+    let mut sequence = match protocol {
+        ImageProtocol::Kitty => kitty_sequence(png, layout),
+        ImageProtocol::Iterm2 => iterm2_sequence(png, layout),
+        ImageProtocol::Sixel => {
+            sixel_sequence(&resize(&decode_png(png)?, layout.width, layout.height))
+        }
+        ImageProtocol::Auto | ImageProtocol::None => bail!("No inline image protocol"),
+    };
+    sequence.push('\n');
+    Ok(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::terminal::test_terminal;
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(b"IHDR");
+        png.extend_from_slice(&width.to_be_bytes());
+        png.extend_from_slice(&height.to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn detects_protocol_from_environment() {
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[("TERM", "xterm-kitty")])),
+            ImageProtocol::Kitty
+        );
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[("TERM_PROGRAM", "WezTerm")])),
+            ImageProtocol::Iterm2
+        );
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[("TERM_PROGRAM", "vscode")])),
+            ImageProtocol::None
+        );
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[("TERM", "foot")])),
+            ImageProtocol::Sixel
+        );
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[
+                ("TERM", "xterm-kitty"),
+                ("TMUX", "/tmp/tmux")
+            ])),
+            ImageProtocol::None
+        );
+        assert_eq!(
+            detect_image_protocol(&test_terminal(&[("TERM", "xterm-256color")])),
+            ImageProtocol::None
+        );
+        assert_eq!(
+            resolve_image_protocol(ImageProtocol::Sixel),
+            ImageProtocol::Sixel
+        );
+    }
+
+    #[test]
+    fn reads_png_dimensions() {
+        assert_eq!(png_dimensions(&png_header(640, 480)), Some((640, 480)));
+        assert_eq!(png_dimensions(b"GIF89a"), None);
+        assert_eq!(png_dimensions(&png_header(0, 480)), None);
+    }
+
+    #[test]
+    fn fits_images_to_terminal_without_upscaling() {
+        assert_eq!(
+            fit_image((1000, 500), (10, 20), (80, 20)),
+            ImageLayout {
+                columns: 80,
+                rows: 20,
+                width: 800,
+                height: 400
+            }
+        );
+        assert_eq!(
+            fit_image((100, 50), (10, 20), (80, 20)),
+            ImageLayout {
+                columns: 10,
+                rows: 3,
+                width: 100,
+                height: 50
+            }
+        );
+    }
+
+    #[test]
+    fn kitty_payload_is_chunked() {
+        let mut png = png_header(10, 10);
+        png.resize(5000, 0);
+        let layout = fit_image((10, 10), (10, 20), (80, 20));
+        let sequence = kitty_sequence(&png, layout);
+
+        assert!(sequence.starts_with("\x1b_Ga=T,f=100,q=2,c=1,r=1,m=1;"));
+        assert!(sequence.contains("\x1b\\\x1b_Gm=0;"));
+        assert!(sequence.ends_with("\x1b\\"));
+        let payload: String = sequence
+            .split("\x1b\\")
+            .filter_map(|chunk| chunk.split_once(';').map(|(_, data)| data))
+            .collect();
+        assert_eq!(
+            base64::engine::general_purpose::STANDARD
+                .decode(payload)
+                .unwrap(),
+            png
+        );
+    }
+
+    #[test]
+    fn iterm2_sequence_carries_size_and_cells() {
+        let png = png_header(200, 100);
+        let layout = fit_image((200, 100), (10, 20), (80, 20));
+        let sequence = iterm2_sequence(&png, layout);
+        assert!(sequence.starts_with(
+            "\x1b]1337;File=inline=1;size=24;width=20;height=5;preserveAspectRatio=1:"
+        ));
+        assert!(sequence.ends_with('\x07'));
+    }
+
+    #[test]
+    fn sixel_encodes_palette_and_bands() {
+        let red = [255, 0, 0, 255];
+        let blue = [0, 0, 255, 255];
+        let image = RgbaImage {
+            width: 2,
+            height: 1,
+            pixels: vec![red, blue],
+        };
+        assert_eq!(
+            sixel_sequence(&image),
+            "\x1bP0;1;0q\"1;1;2;1#5;2;0;0;100#180;2;100;0;0#5?@$#180@?\x1b\\"
+        );
+
+        let image = RgbaImage {
+            width: 5,
+            height: 7,
+            pixels: vec![red; 35],
+        };
+        assert_eq!(
+            sixel_sequence(&image),
+            "\x1bP0;1;0q\"1;1;5;7#180;2;100;0;0#180!5~-#180!5@\x1b\\"
+        );
+    }
+
+    #[test]
+    fn transparent_pixels_are_skipped() {
+        let image = RgbaImage {
+            width: 1,
+            height: 1,
+            pixels: vec![[0, 0, 0, 0]],
+        };
+        assert_eq!(sixel_sequence(&image), "\x1bP0;1;0q\"1;1;1;1\x1b\\");
+    }
+
+    #[test]
+    fn resize_averages_pixels() {
+        let image = RgbaImage {
+            width: 2,
+            height: 1,
+            pixels: vec![[0, 0, 0, 255], [200, 100, 50, 255]],
+        };
+        let resized = resize(&image, 1, 1);
+        assert_eq!(resized.pixels, vec![[100, 50, 25, 255]]);
+    }
+}
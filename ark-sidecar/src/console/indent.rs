@@ -0,0 +1,348 @@
+// Auto-indentation for multi-line console input.
+//
+// When the validator reports incomplete input, reedline inserts a bare
+// newline and gives edit modes no view of the buffer. The highlighter
+// records the buffer on every repaint, and `AutoIndent` wraps the edit mode
+// to follow Enter with the new line's indentation, and to snap a closing
+// bracket typed on a blank line back to its opening line's indentation.
+//
+// Indentation follows the unclosed brackets tree-sitter finds before the
+// cursor: one level past the line holding the innermost one, plus a level
+// after a trailing operator or pipe.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use reedline::{
+    EditCommand, EditMode, EventStatus, PromptEditMode, ReedlineEvent, ReedlineRawEvent,
+    ValidationResult, Validator,
+};
+
+use super::r_parser::parse_r;
+use super::validator::RValidator;
+
+/// The input buffer as of the last repaint.
+#[derive(Debug, Default)]
+pub(crate) struct BufferView {
+    buffer: String,
+    cursor: usize,
+    /// Key events parsed since the buffer was recorded. Events arriving in
+    /// one batch are parsed before any is applied, so only the first sees
+    /// the buffer as recorded.
+    pending_events: usize,
+    /// Enter accepts the selected completion while the menu is open.
+    menu_active: bool,
+}
+
+pub(crate) type SharedBufferView = Arc<Mutex<BufferView>>;
+
+fn lock_view(view: &SharedBufferView) -> MutexGuard<'_, BufferView> {
+    view.lock().expect("buffer view mutex poisoned")
+}
+
+/// Record the buffer being painted.
+pub(crate) fn record_buffer(view: &SharedBufferView, buffer: &str, cursor: usize) {
+    let mut view = lock_view(view);
+    view.buffer.clear();
+    view.buffer.push_str(buffer);
+    view.cursor = cursor;
+    view.pending_events = 0;
+}
+
+/// Record whether the completion menu is open.
+pub(crate) fn record_menu_active(view: &SharedBufferView, active: bool) {
+    lock_view(view).menu_active = active;
+}
+
+/// Whether the completion menu is open.
+pub(crate) fn menu_active(view: &SharedBufferView) -> bool {
+    lock_view(view).menu_active
+}
+
+/// Binary operators that continue an expression onto the next line.
+fn is_continuation_operator(kind: &str) -> bool {
+    matches!(
+        kind,
+        "|>" | "special"
+            | "+"
+            | "-"
+            | "*"
+            | "/"
+            | "^"
+            | "<-"
+            | "<<-"
+            | "="
+            | "->"
+            | "->>"
+            | "~"
+            | "&"
+            | "&&"
+            | "|"
+            | "||"
+            | "=="
+            | "!="
+            | "<"
+            | ">"
+            | "<="
+            | ">="
+    )
+}
+
+/// What the tokens before the cursor leave open.
+#[derive(Debug, Default)]
+struct Nesting {
+    /// Byte offsets of unclosed brackets, innermost last.
+    open_brackets: Vec<usize>,
+    /// Kind of the last token, skipping comments.
+    last_token: Option<&'static str>,
+}
+
+fn collect_nesting(cursor: &mut tree_sitter::TreeCursor, nesting: &mut Nesting) {
+    let node = cursor.node();
+    if node.child_count() == 0 {
+        if node.is_missing() || node.kind() == "comment" {
+            return;
+        }
+        match node.kind() {
+            "{" | "(" | "[" | "[[" => nesting.open_brackets.push(node.start_byte()),
+            "}" | ")" | "]" | "]]" => {
+                nesting.open_brackets.pop();
+            }
+            _ => {}
+        }
+        nesting.last_token = Some(node.kind());
+        return;
+    }
+    if cursor.goto_first_child() {
+        loop {
+            collect_nesting(cursor, nesting);
+            if !cursor.goto_next_sibling() {
+                break;
+            }
+        }
+        cursor.goto_parent();
+    }
+}
+
+fn nesting_before(source: &str, pos: usize) -> Nesting {
+    let mut nesting = Nesting::default();
+    if let Some(tree) = source.get(..pos).and_then(parse_r) {
+        collect_nesting(&mut tree.walk(), &mut nesting);
+    }
+    nesting
+}
+
+/// Leading whitespace of the line containing `offset`.
+fn line_indent(source: &str, offset: usize) -> &str {
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &source[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Indentation for a line started by a newline inserted at `pos`.
+pub(crate) fn new_line_indent(source: &str, pos: usize, width: usize) -> String {
+    let nesting = nesting_before(source, pos);
+    let mut indent = match nesting.open_brackets.last() {
+        Some(&bracket) => format!("{}{}", line_indent(source, bracket), " ".repeat(width)),
+        None => String::new(),
+    };
+    if nesting.last_token.is_some_and(is_continuation_operator) {
+        indent.push_str(&" ".repeat(width));
+    }
+    indent
+}
+
+/// Indentation for a closing bracket typed at `pos`, when everything before
+/// it on the line is whitespace.
+pub(crate) fn closing_bracket_indent(source: &str, pos: usize) -> Option<String> {
+    let before = source.get(..pos)?;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    if !before[line_start..].trim().is_empty() {
+        return None;
+    }
+    let bracket = *nesting_before(source, pos).open_brackets.last()?;
+    Some(line_indent(source, bracket).to_string())
+}
+
+/// Edit mode wrapper adding R-aware indentation to multi-line input.
+pub(crate) struct AutoIndent {
+    inner: Box<dyn EditMode>,
+    view: SharedBufferView,
+    width: usize,
+}
+
+impl AutoIndent {
+    pub fn new(inner: Box<dyn EditMode>, view: SharedBufferView, width: usize) -> Self {
+        Self { inner, view, width }
+    }
+
+    fn indent_event(&self, event: ReedlineEvent) -> ReedlineEvent {
+        let mut view = lock_view(&self.view);
+        view.pending_events += 1;
+        if view.pending_events > 1 || view.menu_active {
+            return event;
+        }
+
+        match event {
+            // Enter inserts a newline exactly when the buffer is incomplete
+            ReedlineEvent::Enter
+                if matches!(
+                    RValidator.validate(&view.buffer),
+                    ValidationResult::Incomplete
+                ) =>
+            {
+                let indent = new_line_indent(&view.buffer, view.cursor, self.width);
+                if indent.is_empty() {
+                    return ReedlineEvent::Enter;
+                }
+                ReedlineEvent::Multiple(vec![
+                    ReedlineEvent::Enter,
+                    ReedlineEvent::Edit(vec![EditCommand::InsertString(indent)]),
+                ])
+            }
+            ReedlineEvent::Edit(commands) => {
+                let [EditCommand::InsertChar(bracket @ ('}' | ')' | ']'))] = commands[..] else {
+                    return ReedlineEvent::Edit(commands);
+                };
+                let Some(indent) = closing_bracket_indent(&view.buffer, view.cursor) else {
+                    return ReedlineEvent::Edit(commands);
+                };
+                let line_start = view.buffer[..view.cursor].rfind('\n').map_or(0, |i| i + 1);
+                let current = view.buffer[line_start..view.cursor].chars().count();
+                let mut edits = vec![EditCommand::Backspace; current];
+                if !indent.is_empty() {
+                    edits.push(EditCommand::InsertString(indent));
+                }
+                edits.push(EditCommand::InsertChar(bracket));
+                ReedlineEvent::Edit(edits)
+            }
+            event => event,
+        }
+    }
+}
+
+impl EditMode for AutoIndent {
+    fn parse_event(&mut self, event: ReedlineRawEvent) -> ReedlineEvent {
+        let event = self.inner.parse_event(event);
+        self.indent_event(event)
+    }
+
+    fn edit_mode(&self) -> PromptEditMode {
+        self.inner.edit_mode()
+    }
+
+    fn handle_mode_specific_event(&mut self, event: ReedlineEvent) -> EventStatus {
+        self.inner.handle_mode_specific_event(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use reedline::{default_emacs_keybindings, Emacs};
+
+    fn auto_indent(buffer: &str) -> AutoIndent {
+        let view = SharedBufferView::default();
+        record_buffer(&view, buffer, buffer.len());
+        AutoIndent::new(Box::new(Emacs::new(default_emacs_keybindings())), view, 2)
+    }
+
+    fn key(code: KeyCode) -> ReedlineRawEvent {
+        ReedlineRawEvent::try_from(Event::Key(KeyEvent::new(code, KeyModifiers::NONE))).unwrap()
+    }
+
+    fn indent_after(source: &str) -> String {
+        new_line_indent(source, source.len(), 2)
+    }
+
+    #[test]
+    fn indents_inside_open_brackets() {
+        assert_eq!(indent_after("f <- function(x) {"), "  ");
+        assert_eq!(indent_after("f <- function(x) {\n  if (x) {"), "    ");
+        assert_eq!(indent_after("list(a = 1,"), "  ");
+        assert_eq!(indent_after("x[[1"), "  ");
+        assert_eq!(indent_after("tryCatch({"), "  ");
+        assert_eq!(indent_after("{\n  x <- 1\n}"), "");
+    }
+
+    #[test]
+    fn ignores_brackets_in_strings_and_comments() {
+        assert_eq!(indent_after("paste(\"{\""), "  ");
+        assert_eq!(indent_after("{ # )"), "  ");
+        assert_eq!(indent_after("x <- \"(\""), "");
+    }
+
+    #[test]
+    fn indents_after_trailing_operators() {
+        assert_eq!(indent_after("mtcars |>"), "  ");
+        assert_eq!(indent_after("mtcars %>%"), "  ");
+        assert_eq!(indent_after("x <-"), "  ");
+        assert_eq!(indent_after("mtcars |>\n  head() |>"), "  ");
+        assert_eq!(indent_after("{\n  y <- 1 +"), "    ");
+        assert_eq!(indent_after("1 + # sum"), "  ");
+    }
+
+    #[test]
+    fn closing_bracket_returns_to_opening_line() {
+        let source = "f <- function(x) {\n  if (x) {\n    ";
+        assert_eq!(
+            closing_bracket_indent(source, source.len()).as_deref(),
+            Some("  ")
+        );
+        let source = "f <- function(x) {\n  1\n  ";
+        assert_eq!(
+            closing_bracket_indent(source, source.len()).as_deref(),
+            Some("")
+        );
+        assert_eq!(closing_bracket_indent("{\n  x", 5), None);
+        assert_eq!(closing_bracket_indent("  ", 2), None);
+    }
+
+    #[test]
+    fn enter_on_incomplete_input_adds_indent() {
+        let mut edit_mode = auto_indent("f <- function(x) {");
+        assert_eq!(
+            edit_mode.parse_event(key(KeyCode::Enter)),
+            ReedlineEvent::Multiple(vec![
+                ReedlineEvent::Enter,
+                ReedlineEvent::Edit(vec![EditCommand::InsertString("  ".to_string())]),
+            ])
+        );
+
+        let mut edit_mode = auto_indent("x <- 1");
+        assert_eq!(
+            edit_mode.parse_event(key(KeyCode::Enter)),
+            ReedlineEvent::Enter
+        );
+    }
+
+    #[test]
+    fn closing_bracket_removes_indent() {
+        let mut edit_mode = auto_indent("f <- function(x) {\n  1\n  ");
+        assert_eq!(
+            edit_mode.parse_event(key(KeyCode::Char('}'))),
+            ReedlineEvent::Edit(vec![
+                EditCommand::Backspace,
+                EditCommand::Backspace,
+                EditCommand::InsertChar('}'),
+            ])
+        );
+    }
+
+    #[test]
+    fn stale_buffer_and_open_menu_leave_events_alone() {
+        let mut edit_mode = auto_indent("f <- function(x) {");
+        edit_mode.parse_event(key(KeyCode::Char('a')));
+        assert_eq!(
+            edit_mode.parse_event(key(KeyCode::Enter)),
+            ReedlineEvent::Enter
+        );
+
+        let mut edit_mode = auto_indent("f <- function(x) {");
+        record_menu_active(&edit_mode.view, true);
+        assert_eq!(
+            edit_mode.parse_event(key(KeyCode::Enter)),
+            ReedlineEvent::Enter
+        );
+    }
+}
@@ -0,0 +1,434 @@
+// Code sent into the console by the editor.
+//
+// Typing "run selection" into the terminal as keystrokes breaks on long
+// code and special characters. Instead the console listens on a Unix
+// socket in a directory next to the connection file and accepts one JSON
+// command per line, e.g. `{"command":"execute","code":"x <- 1","echo":true}`.
+// The code runs as if typed: it is echoed with highlighting, its output is
+// printed, and the command is answered once the kernel is idle again, with
+// `{"status":"ok","failed":false,"duration_ms":12}` or
+// `{"status":"error","message":"..."}`.
+//
+// Whoever can connect runs arbitrary R code in the session, so the socket
+// is opt-in (`--inject-socket`) and bound inside a directory only the user
+// can enter: the socket file itself is created with umask permissions.
+
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use runtimelib::JupyterMessageContent;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use super::kernel_loop::ConsoleRequest;
+use super::output::{format_iopub_content, LineBuffer, OutputOptions};
+use super::paste::echo_with_prompts;
+use super::session_state::ExecutionRecord;
+
+/// Replaces the connection file's extension to name the socket directory.
+const SOCKET_DIR_EXTENSION: &str = "console";
+
+/// The socket's name in its directory.
+const SOCKET_NAME: &str = "inject.sock";
+
+/// A command sent over the injection socket.
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum InjectCommand {
+    /// Run R code in the console.
+    Execute {
+        code: String,
+        /// Echo the code into the console before its output.
+        #[serde(default = "echo_by_default")]
+        echo: bool,
+    },
+}
+
+fn echo_by_default() -> bool {
+    true
+}
+
+/// The answer to a command, sent once it is complete.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum InjectReply {
+    /// The code ran; `failed` when R signalled an error.
+    Ok { failed: bool, duration_ms: u64 },
+    /// The code could not be run.
+    Error { message: String },
+}
+
+impl InjectReply {
+    pub fn error(message: impl Into<String>) -> Self {
+        InjectReply::Error {
+            message: message.into(),
+        }
+    }
+}
+
+/// Code to run for the editor, handed to the kernel loop.
+#[derive(Debug)]
+pub(crate) struct Injection {
+    pub code: String,
+    pub echo: bool,
+    /// Receives the outcome once the execution is complete.
+    pub reply: oneshot::Sender<InjectReply>,
+}
+
+/// The socket for `connection_file`, in a directory next to it.
+pub(crate) fn socket_path(connection_file: &str) -> PathBuf {
+    Path::new(connection_file)
+        .with_extension(SOCKET_DIR_EXTENSION)
+        .join(SOCKET_NAME)
+}
+
+/// Create `dir` accessible to the user only, or check that an existing one
+/// is: the socket is connectable by whoever can enter it.
+fn create_private_dir(dir: &Path) -> Result<()> {
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("Failed to create {}", dir.display()));
+        }
+    }
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect {}", dir.display()))?;
+    // SAFETY: geteuid() takes no arguments and cannot fail.
+    let uid = unsafe { libc::geteuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        anyhow::bail!(
+            "{} is not a directory private to the current user",
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+/// The listening socket. Dropping it stops accepting commands and removes
+/// the socket file.
+pub(crate) struct InjectionSocket {
+    path: PathBuf,
+    accept_task: JoinHandle<()>,
+}
+
+impl InjectionSocket {
+    /// Listen on `path`, forwarding commands to the kernel loop. Its
+    /// directory is created first, accessible to the user only.
+    ///
+    /// A leftover socket from a console that exited uncleanly is replaced;
+    /// one another console still listens on is left alone. The socket
+    /// doesn't keep the kernel loop's request channel open.
+    pub async fn bind(path: PathBuf, request_tx: &mpsc::Sender<ConsoleRequest>) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        if path.exists() {
+            if UnixStream::connect(&path).await.is_ok() {
+                anyhow::bail!("Another console is listening on {}", path.display());
+            }
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(&path)
+            .with_context(|| format!("Failed to listen on {}", path.display()))?;
+        if let Err(err) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)) {
+            let _ = std::fs::remove_file(&path);
+            return Err(err)
+                .with_context(|| format!("Failed to restrict access to {}", path.display()));
+        }
+        info!(path = %path.display(), "Console inject: listening for code");
+
+        let request_tx = request_tx.downgrade();
+        let accept_task = tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        debug!("Console inject: client connected");
+                        tokio::spawn(serve_client(stream, request_tx.clone()));
+                    }
+                    Err(err) => {
+                        warn!(error = ?err, "Console inject: accept failed, closing socket");
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self { path, accept_task })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InjectionSocket {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            debug!(error = ?err, "Console inject: failed to remove socket file");
+        }
+        if let Some(dir) = self.path.parent() {
+            let _ = std::fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Answer one client's commands in order until it disconnects.
+async fn serve_client(stream: UnixStream, request_tx: mpsc::WeakSender<ConsoleRequest>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                debug!(error = ?err, "Console inject: read failed");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = run_command(&line, &request_tx).await;
+        let mut text = serde_json::to_string(&reply).expect("replies serialize");
+        text.push('\n');
+        if let Err(err) = writer.write_all(text.as_bytes()).await {
+            debug!(error = ?err, "Console inject: write failed");
+            break;
+        }
+    }
+    debug!("Console inject: client disconnected");
+}
+
+async fn run_command(line: &str, request_tx: &mpsc::WeakSender<ConsoleRequest>) -> InjectReply {
+    let command = match serde_json::from_str::<InjectCommand>(line) {
+        Ok(command) => command,
+        Err(err) => {
+            debug!(error = %err, "Console inject: invalid command");
+            return InjectReply::error(format!("Invalid command: {err}"));
+        }
+    };
+    let InjectCommand::Execute { code, echo } = command;
+    debug!(
+        code_len = code.len(),
+        echo, "Console inject: execute command"
+    );
+
+    let (reply_tx, reply_rx) = oneshot::channel();
+    let injection = Injection {
+        code,
+        echo,
+        reply: reply_tx,
+    };
+    let Some(request_tx) = request_tx.upgrade() else {
+        return InjectReply::error("The console is closing");
+    };
+    if request_tx
+        .send(ConsoleRequest::Inject(injection))
+        .await
+        .is_err()
+    {
+        return InjectReply::error("The console is closing");
+    }
+    drop(request_tx);
+    reply_rx
+        .await
+        .unwrap_or_else(|_| InjectReply::error("The console closed before the code finished"))
+}
+
+/// An injected execution the kernel is running or has queued.
+pub(crate) struct RunningInjection {
+    echo: bool,
+    reply: oneshot::Sender<InjectReply>,
+    started_at: Instant,
+    failed: bool,
+    lines: LineBuffer,
+}
+
+impl RunningInjection {
+    pub fn new(echo: bool, reply: oneshot::Sender<InjectReply>) -> Self {
+        Self {
+            echo,
+            reply,
+            started_at: Instant::now(),
+            failed: false,
+            lines: LineBuffer::default(),
+        }
+    }
+
+    /// The kernel started the code: its echo, if wanted. The duration is
+    /// timed from here, not from when it was queued.
+    pub fn input(&mut self, code: &str) -> Option<String> {
+        self.started_at = Instant::now();
+        self.echo.then(|| echo_with_prompts(code, true))
+    }
+
+    /// Complete lines of output to print for `content`; the rest waits for
+    /// its newline, so printing above the prompt never splits a line.
+    pub fn output(
+        &mut self,
+        content: &JupyterMessageContent,
+        options: OutputOptions,
+    ) -> Option<String> {
+        if matches!(content, JupyterMessageContent::ErrorOutput(_)) {
+            self.failed = true;
+        }
+        self.lines.push(&format_iopub_content(content, options)?)
+    }
+
+    /// The kernel is idle again: answer the editor, and return what is left
+    /// to print with the execution's record.
+    pub fn finish(mut self) -> (Option<String>, ExecutionRecord) {
+        let record = ExecutionRecord {
+            duration: self.started_at.elapsed(),
+            failed: self.failed,
+        };
+        let _ = self.reply.send(InjectReply::Ok {
+            failed: record.failed,
+            duration_ms: record.duration.as_millis() as u64,
+        });
+        let rest = self.lines.flush();
+        (rest, record)
+    }
+
+    /// The execution will never finish, e.g. the kernel restarted.
+    pub fn abandon(self, message: &str) {
+        let _ = self.reply.send(InjectReply::error(message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::test_dir::TestDir;
+    use runtimelib::StreamContent;
+
+    fn plain(text: &str) -> String {
+        crate::console::transcript::strip_ansi(text)
+    }
+
+    fn stdout(text: &str) -> JupyterMessageContent {
+        JupyterMessageContent::StreamContent(StreamContent::stdout(text))
+    }
+
+    #[test]
+    fn execute_commands_echo_by_default() {
+        assert_eq!(
+            serde_json::from_str::<InjectCommand>(r#"{"command":"execute","code":"1 + 1"}"#)
+                .unwrap(),
+            InjectCommand::Execute {
+                code: "1 + 1".to_string(),
+                echo: true
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<InjectCommand>(
+                r#"{"command":"execute","code":"x","echo":false}"#
+            )
+            .unwrap(),
+            InjectCommand::Execute {
+                code: "x".to_string(),
+                echo: false
+            }
+        );
+        assert!(serde_json::from_str::<InjectCommand>(r#"{"command":"shutdown"}"#).is_err());
+    }
+
+    #[test]
+    fn replies_are_tagged_by_status() {
+        assert_eq!(
+            serde_json::to_string(&InjectReply::Ok {
+                failed: false,
+                duration_ms: 12
+            })
+            .unwrap(),
+            r#"{"status":"ok","failed":false,"duration_ms":12}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&InjectReply::error("no kernel")).unwrap(),
+            r#"{"status":"error","message":"no kernel"}"#
+        );
+    }
+
+    #[test]
+    fn socket_sits_next_to_the_connection_file() {
+        assert_eq!(
+            socket_path("/tmp/kernel-1234.json"),
+            PathBuf::from("/tmp/kernel-1234.console/inject.sock")
+        );
+    }
+
+    #[test]
+    fn injected_code_is_echoed_with_prompts() {
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        let mut run = RunningInjection::new(true, reply_tx);
+        let echo = run.input("f <- function() {\n  1\n}\n").unwrap();
+        assert_eq!(plain(&echo), "> f <- function() {\n+   1\n+ }\n");
+
+        let (reply_tx, _reply_rx) = oneshot::channel();
+        assert_eq!(RunningInjection::new(false, reply_tx).input("x"), None);
+    }
+
+    #[test]
+    fn finished_injection_replies_with_the_outcome() {
+        let (reply_tx, mut reply_rx) = oneshot::channel();
+        let mut run = RunningInjection::new(true, reply_tx);
+        let options = OutputOptions::default();
+        assert_eq!(run.output(&stdout("Iteration 1"), options), None);
+        assert_eq!(
+            run.output(&stdout(" done\nIteration 2"), options)
+                .as_deref(),
+            Some("Iteration 1 done\n")
+        );
+        let (rest, record) = run.finish();
+        assert_eq!(rest.as_deref(), Some("Iteration 2\n"));
+        assert!(!record.failed);
+        assert!(matches!(
+            reply_rx.try_recv().unwrap(),
+            InjectReply::Ok { failed: false, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn socket_is_private_to_the_user() {
+        let directory = TestDir::new("inject");
+        let connection_file = directory.join("kernel.json");
+        let path = socket_path(connection_file.to_str().unwrap());
+        let dir = path.parent().unwrap().to_path_buf();
+        let (request_tx, _request_rx) = mpsc::channel(1);
+
+        let socket = InjectionSocket::bind(path.clone(), &request_tx)
+            .await
+            .unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&dir), 0o700);
+        assert_eq!(mode(&path), 0o600);
+        assert!(InjectionSocket::bind(path.clone(), &request_tx)
+            .await
+            .is_err());
+
+        drop(socket);
+        assert!(!path.exists());
+        assert!(!dir.exists());
+    }
+
+    #[tokio::test]
+    async fn socket_is_not_bound_in_a_shared_directory() {
+        let directory = TestDir::new("inject-shared");
+        let dir = directory.join("kernel.console");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let (request_tx, _request_rx) = mpsc::channel(1);
+
+        let result = InjectionSocket::bind(dir.join(SOCKET_NAME), &request_tx).await;
+        assert!(result.is_err());
+        assert!(!dir.join(SOCKET_NAME).exists());
+    }
+}
@@ -4,72 +4,258 @@
 // tokio::select! over shell, iopub, heartbeat, and request channels.
 // Completion is handled directly by LspCompleter over TCP,
 // so this loop manages execute requests, output, and disconnect detection.
+// With `auto_reconnect`, a lost kernel is retried in the background while
+// the prompt stays open, and the console reattaches once it answers again.
 
 use anyhow::{Context, Result};
+use nu_ansi_term::Color;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::mpsc as std_mpsc;
-use std::time::Duration;
-use tracing::{debug, error, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
 use runtimelib::{
-    ClientControlConnection, ClientIoPubConnection,
-    ExecuteRequest, ExecutionState, InterruptRequest, JupyterMessage, JupyterMessageContent,
+    create_client_iopub_connection, ClientControlConnection, ClientIoPubConnection,
+    ClientShellConnection, ExecuteRequest, ExecutionState, InterruptRequest, JupyterMessage,
+    JupyterMessageContent, ShutdownRequest,
 };
 
-use crate::connection::create_shell_connection;
-use crate::heartbeat::{spawn_heartbeat_monitor, stop_heartbeat_monitor};
+use crate::connection::{
+    create_control_connection, create_shell_connection, read_connection, send_comm_close,
+};
+use crate::heartbeat::{probe_heartbeat, spawn_heartbeat_monitor, stop_heartbeat_monitor};
+use crate::lsp_client::LspClient;
+use crate::types::TranscriptFormat;
 
-use super::output::{format_iopub_content, kernel_disconnect_message, ConsoleUiEvent};
+use super::follow::{console_query, is_foreign, ForeignOutput};
+use super::help::{help_content_from_comm_data, load_help_html, HelpContent};
+use super::inject::{InjectReply, Injection, RunningInjection};
+use super::output::{
+    format_duration, format_iopub_content, kernel_disconnect_message, ConsoleUiEvent, OutputOptions,
+};
+use super::session_state::{
+    lock_session_state, working_directory_from_comm_data, DirectoryQuery, ExecutionRecord,
+    SharedSessionState, WORKING_DIRECTORY_QUERY,
+};
+use super::transcript::Transcript;
+use super::{refresh_after_reconnect, reopen_comms};
 
 const EXIT_AFTER_EXEC_TIMEOUT: Duration = Duration::from_secs(5);
 
+/// A second Ctrl+C this soon after an interrupt offers a restart or kill.
+const INTERRUPT_ESCALATION_WINDOW: Duration = Duration::from_secs(5);
+
+/// How long closing a comm may take before the console moves on.
+const COMM_CLOSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How often a lost kernel is retried.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long the prompt waits for the working directory after an execution,
+/// in case the query is queued behind other clients' code.
+const DIRECTORY_QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+const DISCONNECTED_REPLY: &str =
+    "The kernel is disconnected; reconnecting in the background. Use :reconnect to retry now.";
+
 /// Request sent from the reedline loop to the kernel loop.
 pub(crate) enum ConsoleRequest {
     /// Execute R code.
     Execute(String),
     /// Execute R code and then exit the console (used for confirmed q()/quit()).
     ExecuteAndExit(String),
+    /// Interrupt the kernel, whether or not this console started the execution.
+    Interrupt,
+    /// Ask the kernel to restart.
+    Restart,
+    /// Kill the kernel process, which ignores interrupts.
+    KillKernel,
+    /// Reopen the shell, iopub, and control sockets.
+    Reconnect,
+    /// Record executions to a transcript file in the given format,
+    /// replacing any current one.
+    StartTranscript(PathBuf, TranscriptFormat),
+    /// Stop recording the transcript.
+    StopTranscript,
+    /// Turn mirroring of other clients' executions on or off, or toggle it.
+    Follow(Option<bool>),
+    /// Run code sent by the editor over the injection socket, answering
+    /// once it completes.
+    Inject(Injection),
     /// Exit the console.
     Exit,
 }
 
+impl ConsoleRequest {
+    /// Whether the request can only be served by a connected kernel.
+    fn needs_kernel(&self) -> bool {
+        matches!(
+            self,
+            ConsoleRequest::Execute(_)
+                | ConsoleRequest::ExecuteAndExit(_)
+                | ConsoleRequest::Interrupt
+                | ConsoleRequest::Restart
+                | ConsoleRequest::KillKernel
+        )
+    }
+}
+
+/// Channels connecting the kernel loop to the reedline loop and the SIGINT handler.
+pub(crate) struct KernelLoopChannels {
+    /// Execute/exit requests from the reedline loop.
+    pub request_rx: tokio::sync::mpsc::Receiver<ConsoleRequest>,
+    /// Real-time execution output and disconnect events back to the reedline loop.
+    pub ui_event_tx: std_mpsc::Sender<ConsoleUiEvent>,
+    /// Ctrl+C presses forwarded by the SIGINT handler.
+    pub interrupt_rx: tokio::sync::mpsc::Receiver<()>,
+}
+
+/// Comms the console opened in the kernel. They are closed when the
+/// console exits or replaces them after the kernel restarted.
+#[derive(Debug)]
+pub(crate) struct ConsoleComms {
+    /// The `positron.help` comm, whose help pages are fetched and forwarded
+    /// to the reedline loop.
+    pub help: Option<String>,
+    /// The comm behind the LSP client's server.
+    pub lsp: Option<String>,
+}
+
+/// Console features the kernel loop serves besides executing code.
+pub(crate) struct KernelLoopOptions {
+    /// Comms opened at startup.
+    pub comms: ConsoleComms,
+    /// Open a new help comm after the kernel restarted (`--help-pages`).
+    pub help_pages: bool,
+    /// How plots and rich output are drawn in the terminal.
+    pub output_options: OutputOptions,
+    /// Transcript recorded from startup (`--transcript`).
+    pub transcript: Option<Transcript>,
+    /// Mirror executions from other clients of the kernel (`--follow`).
+    pub follow: bool,
+    /// Keep retrying a lost kernel instead of closing the console.
+    pub auto_reconnect: bool,
+    /// LSP client to point at the new LSP server after the kernel restarted.
+    pub lsp_client: Option<Arc<LspClient>>,
+}
+
 /// Run the async kernel event loop.
 ///
 /// This function coordinates between:
-/// - `request_rx`: execute/exit requests from the reedline loop
-/// - `ui_event_tx`: real-time execution output and disconnect events back to the reedline loop
+/// - the reedline loop and SIGINT handler, via `channels`
 /// - Jupyter shell/iopub/control sockets
+/// - `session_state`, updated with working directory changes and execution results
+/// - help pages, output rendering and transcripts, per `options`
 pub(crate) async fn run_kernel_loop(
     connection_info: &runtimelib::ConnectionInfo,
     session_id: &str,
     mut iopub: ClientIoPubConnection,
-    mut request_rx: tokio::sync::mpsc::Receiver<ConsoleRequest>,
-    ui_event_tx: std_mpsc::Sender<ConsoleUiEvent>,
     mut control: ClientControlConnection,
-    mut interrupt_rx: tokio::sync::mpsc::Receiver<()>,
+    channels: KernelLoopChannels,
+    session_state: SharedSessionState,
+    options: KernelLoopOptions,
 ) -> Result<()> {
+    let KernelLoopChannels {
+        mut request_rx,
+        ui_event_tx,
+        mut interrupt_rx,
+    } = channels;
+    let KernelLoopOptions {
+        mut comms,
+        help_pages,
+        output_options,
+        mut transcript,
+        mut follow,
+        auto_reconnect,
+        lsp_client,
+    } = options;
+    // A restarted kernel may listen on new ports, so the connection file
+    // is read again before each reconnect
+    let mut connection_info = connection_info.clone();
+    let connection_file = lock_session_state(&session_state).connection_file.clone();
+
     debug!("Console kernel_loop: connecting to kernel");
 
-    let mut shell = create_shell_connection(connection_info, session_id)
+    let mut shell = create_shell_connection(&connection_info, session_id)
         .await
         .context("Failed to connect shell")?;
 
-    let (mut heartbeat_disconnect_rx, heartbeat_handle) =
+    let (mut heartbeat_disconnect_rx, mut heartbeat_handle) =
         spawn_heartbeat_monitor(connection_info.clone());
 
     debug!("Console kernel_loop: connected, entering event loop");
 
     // Track the current execute_request msg_id to correlate iopub output
     let mut current_exec_msg_id: Option<String> = None;
+    // When the current execution was sent, and whether it produced an error
+    let mut exec_started_at: Option<Instant> = None;
+    let mut exec_failed = false;
+    // When the current execution was last interrupted with Ctrl+C
+    let mut last_interrupt: Option<Instant> = None;
     // Whether to exit after the current execution completes (for q()/quit())
     let mut exit_after_exec = false;
+    // Partial lines of other clients' output, when following them
+    let mut foreign = ForeignOutput::default();
+    // Code sent by the editor, by execute_request msg_id. The kernel queues
+    // it behind any running execution.
+    let mut injections: HashMap<String, RunningInjection> = HashMap::new();
     // Deadline for exit timeout — when q() kills Ark, ZMQ SUB sockets hang
     // forever (no close notification), so we need a safety timeout.
     let mut exit_deadline: Option<tokio::time::Instant> = None;
+    // Why the kernel was found unreachable, until that is acted on
+    let mut lost_connection: Option<String> = None;
+    // The kernel is lost and retried until it answers (auto_reconnect)
+    let mut kernel_lost = false;
+    // When to try reattaching to the kernel, and whether :reconnect waits
+    // for the outcome
+    let mut reconnect_at: Option<tokio::time::Instant> = None;
+    let mut reconnect_requested = false;
+    // Asks R for its working directory when no UI comm reports it
+    let mut directory_query = DirectoryQuery::default();
+    // The finished execution's outcome, held back until the working
+    // directory query is done so the prompt shows the new directory
+    let mut prompt_after_query: Option<(Option<ExecutionRecord>, tokio::time::Instant)> = None;
 
     loop {
+        if let Some(reason) = lost_connection.take() {
+            // Ark is gone after q(), so there is nothing to reconnect to
+            if !auto_reconnect || exit_after_exec {
+                let _ = ui_event_tx.send(ConsoleUiEvent::KernelDisconnected(
+                    kernel_disconnect_message(),
+                ));
+                break;
+            }
+            warn!(reason = %reason, "Console kernel_loop: kernel lost, reconnecting in the background");
+            kernel_lost = true;
+            reconnect_at = Some(tokio::time::Instant::now() + RECONNECT_INTERVAL);
+            lock_session_state(&session_state).disconnected = true;
+            let _ = ui_event_tx.send(ConsoleUiEvent::Notice(format!(
+                "Lost connection to the kernel ({reason}). Reconnecting in the background."
+            )));
+            // Release the prompt; a running execution's remaining output
+            // went with the connection
+            current_exec_msg_id = None;
+            exec_started_at = None;
+            last_interrupt = None;
+            abandon_injections(&mut injections, "Lost connection to the kernel");
+            directory_query = DirectoryQuery::default();
+            prompt_after_query = None;
+            let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
+        }
+
         tokio::select! {
             // Handle execute/exit requests from the reedline loop
             request = request_rx.recv() => {
+                if kernel_lost && request.as_ref().is_some_and(ConsoleRequest::needs_kernel) {
+                    debug!("Console kernel_loop: request while disconnected");
+                    send_meta_reply(&ui_event_tx, DISCONNECTED_REPLY);
+                    if matches!(request, Some(ConsoleRequest::ExecuteAndExit(_))) {
+                        break;
+                    }
+                    continue;
+                }
                 match request {
                     Some(ConsoleRequest::ExecuteAndExit(code)) => {
                         exit_after_exec = true;
@@ -77,48 +263,176 @@ pub(crate) async fn run_kernel_loop(
                             tokio::time::Instant::now() + EXIT_AFTER_EXEC_TIMEOUT,
                         );
                         debug!(code_len = code.len(), exit_after = true, "Console kernel_loop: execute-and-exit request");
+                        record_transcript(&mut transcript, &ui_event_tx, |t| t.record_input(&code));
                         let execute_request = ExecuteRequest::new(code);
                         let message = JupyterMessage::new(execute_request, None);
                         let msg_id = message.header.msg_id.clone();
                         current_exec_msg_id = Some(msg_id);
+                        exec_started_at = Some(Instant::now());
+                        last_interrupt = None;
+                        exec_failed = false;
                         if let Err(err) = shell.send(message).await {
                             error!(error = ?err, "Console kernel_loop: failed to send execute_request");
                             if handle_transport_disconnect(
-                                &ui_event_tx,
+                                &mut lost_connection,
                                 "execute_request send failed",
                                 &err,
                                 current_exec_msg_id.is_some(),
                             ) {
-                                break;
+                                continue;
                             }
                             let _ = ui_event_tx.send(ConsoleUiEvent::Output(
                                 format!("Error sending execute request: {}\n", err),
                             ));
-                            let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone);
+                            let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
                             break;
                         }
                     }
                     Some(ConsoleRequest::Execute(code)) => {
                         debug!(code_len = code.len(), "Console kernel_loop: execute request");
+                        record_transcript(&mut transcript, &ui_event_tx, |t| t.record_input(&code));
                         let execute_request = ExecuteRequest::new(code);
                         let message = JupyterMessage::new(execute_request, None);
                         let msg_id = message.header.msg_id.clone();
                         current_exec_msg_id = Some(msg_id);
+                        exec_started_at = Some(Instant::now());
+                        last_interrupt = None;
+                        exec_failed = false;
                         if let Err(err) = shell.send(message).await {
                             error!(error = ?err, "Console kernel_loop: failed to send execute_request");
                             if handle_transport_disconnect(
-                                &ui_event_tx,
+                                &mut lost_connection,
                                 "execute_request send failed",
                                 &err,
                                 current_exec_msg_id.is_some(),
                             ) {
-                                break;
+                                continue;
                             }
                             let _ = ui_event_tx.send(ConsoleUiEvent::Output(
                                 format!("Error sending execute request: {}\n", err),
                             ));
-                            let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone);
+                            let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
+                        }
+                    }
+                    Some(ConsoleRequest::Interrupt) => {
+                        debug!("Console kernel_loop: :interrupt requested");
+                        let message = JupyterMessage::new(InterruptRequest {}, None);
+                        let reply = match control.send(message).await {
+                            Ok(()) => "Interrupt sent.".to_string(),
+                            Err(err) => {
+                                warn!(error = ?err, "Console kernel_loop: failed to send InterruptRequest");
+                                if handle_transport_disconnect(&mut lost_connection, "interrupt send failed", &err, false) {
+                                    continue;
+                                }
+                                format!("Failed to send interrupt: {err}")
+                            }
+                        };
+                        send_meta_reply(&ui_event_tx, &reply);
+                    }
+                    Some(ConsoleRequest::Restart) => {
+                        debug!("Console kernel_loop: :restart requested");
+                        let message = JupyterMessage::new(ShutdownRequest { restart: true }, None);
+                        let reply = match control.send(message).await {
+                            Ok(()) => {
+                                // A running execution never finishes in the restarted kernel
+                                current_exec_msg_id = None;
+                                exec_started_at = None;
+                                abandon_injections(&mut injections, "The kernel restarted");
+                                "Restart requested. Use :reconnect if the console loses the kernel.".to_string()
+                            }
+                            Err(err) => {
+                                warn!(error = ?err, "Console kernel_loop: failed to send restart request");
+                                if handle_transport_disconnect(&mut lost_connection, "restart send failed", &err, false) {
+                                    continue;
+                                }
+                                format!("Failed to request restart: {err}")
+                            }
+                        };
+                        send_meta_reply(&ui_event_tx, &reply);
+                    }
+                    Some(ConsoleRequest::KillKernel) => {
+                        let pid = lock_session_state(&session_state).kernel_pid;
+                        debug!(pid = ?pid, "Console kernel_loop: kill requested");
+                        let reply = match pid {
+                            Some(pid) => match kill_kernel_process(pid) {
+                                Ok(()) => {
+                                    info!(pid, "Console kernel_loop: killed kernel process");
+                                    current_exec_msg_id = None;
+                                    exec_started_at = None;
+                                    abandon_injections(&mut injections, "The kernel was killed");
+                                    format!("Killed the kernel process ({pid}).")
+                                }
+                                Err(err) => {
+                                    warn!(error = ?err, "Console kernel_loop: failed to kill kernel");
+                                    format!("Failed to kill the kernel: {err:#}")
+                                }
+                            },
+                            None => "The kernel's process is unknown; it may not run on this machine.".to_string(),
+                        };
+                        send_meta_reply(&ui_event_tx, &reply);
+                    }
+                    Some(ConsoleRequest::Reconnect) => {
+                        debug!("Console kernel_loop: :reconnect requested");
+                        reconnect_at = Some(tokio::time::Instant::now());
+                        reconnect_requested = true;
+                    }
+                    Some(ConsoleRequest::StartTranscript(path, format)) => {
+                        debug!(path = %path.display(), format = ?format, "Console kernel_loop: :transcript start requested");
+                        let reply = match Transcript::open(&path, format) {
+                            Ok(started) => {
+                                info!(path = %path.display(), "Console kernel_loop: transcript started");
+                                transcript = Some(started);
+                                format!("Recording transcript to {}", path.display())
+                            }
+                            Err(err) => {
+                                warn!(error = ?err, "Console kernel_loop: failed to start transcript");
+                                format!("Failed to start transcript: {err:#}")
+                            }
+                        };
+                        send_meta_reply(&ui_event_tx, &reply);
+                    }
+                    Some(ConsoleRequest::StopTranscript) => {
+                        debug!("Console kernel_loop: :transcript stop requested");
+                        let reply = match transcript.take() {
+                            Some(stopped) => format!("Transcript saved to {}", stopped.path().display()),
+                            None => "No transcript is being recorded.".to_string(),
+                        };
+                        send_meta_reply(&ui_event_tx, &reply);
+                    }
+                    Some(ConsoleRequest::Follow(enabled)) => {
+                        follow = enabled.unwrap_or(!follow);
+                        debug!(follow, "Console kernel_loop: :follow requested");
+                        foreign = ForeignOutput::default();
+                        let reply = if follow {
+                            "Following executions from other clients."
+                        } else {
+                            "Stopped following other clients."
+                        };
+                        send_meta_reply(&ui_event_tx, reply);
+                    }
+                    Some(ConsoleRequest::Inject(injection)) => {
+                        // The prompt isn't waiting, so only the editor hears back
+                        if kernel_lost {
+                            let _ = injection.reply.send(InjectReply::error(DISCONNECTED_REPLY));
+                            continue;
                         }
+                        debug!(code_len = injection.code.len(), "Console kernel_loop: injected execute request");
+                        let message = JupyterMessage::new(ExecuteRequest::new(injection.code), None);
+                        let msg_id = message.header.msg_id.clone();
+                        if let Err(err) = shell.send(message).await {
+                            error!(error = ?err, "Console kernel_loop: failed to send injected execute_request");
+                            let _ = injection.reply.send(InjectReply::error(format!(
+                                "Failed to send execute request: {err}"
+                            )));
+                            handle_transport_disconnect(
+                                &mut lost_connection,
+                                "execute_request send failed",
+                                &err,
+                                current_exec_msg_id.is_some(),
+                            );
+                            continue;
+                        }
+                        injections.insert(msg_id, RunningInjection::new(injection.echo, injection.reply));
                     }
                     Some(ConsoleRequest::Exit) | None => {
                         debug!("Console kernel_loop: exit requested");
@@ -127,17 +441,82 @@ pub(crate) async fn run_kernel_loop(
                 }
             }
 
-            disconnect_reason = heartbeat_disconnect_rx.recv() => {
+            disconnect_reason = heartbeat_disconnect_rx.recv(), if !kernel_lost => {
                 if let Some(disconnect_reason) = disconnect_reason {
                     warn!(
                         reason = %disconnect_reason,
                         execution_in_progress = current_exec_msg_id.is_some(),
                         "Console kernel_loop: heartbeat confirmed kernel disconnect"
                     );
-                    let _ = ui_event_tx.send(ConsoleUiEvent::KernelDisconnected(
-                        kernel_disconnect_message(),
-                    ));
-                    break;
+                    lost_connection = Some(disconnect_reason);
+                    continue;
+                }
+            }
+
+            // Reattach to the kernel, after it was lost or on :reconnect
+            _ = async {
+                match reconnect_at {
+                    Some(at) => tokio::time::sleep_until(at).await,
+                    None => std::future::pending::<()>().await,
+                }
+            } => {
+                reconnect_at = None;
+                let previous_connection = connection_info.clone();
+                match reattach_kernel(&mut connection_info, session_id, connection_file.as_deref()).await {
+                    Ok((new_shell, new_iopub, new_control)) => {
+                        shell = new_shell;
+                        iopub = new_iopub;
+                        control = new_control;
+                        stop_heartbeat_monitor(heartbeat_handle);
+                        (heartbeat_disconnect_rx, heartbeat_handle) =
+                            spawn_heartbeat_monitor(connection_info.clone());
+                        current_exec_msg_id = None;
+                        exec_started_at = None;
+                        foreign = ForeignOutput::default();
+                        directory_query = DirectoryQuery::default();
+                        abandon_injections(&mut injections, "Reconnected to the kernel");
+                        info!("Console kernel_loop: reconnected to kernel");
+                        let restarted = refresh_after_reconnect(
+                            &connection_info,
+                            session_id,
+                            &mut iopub,
+                            connection_changed(&previous_connection, &connection_info),
+                            &session_state,
+                        )
+                        .await;
+                        // A kernel that kept running still has its help comm
+                        // and LSP server, which keep working
+                        if restarted {
+                            info!("Console kernel_loop: kernel restarted, reopening comms");
+                            close_comms(&mut shell, &mut comms).await;
+                            comms = reopen_comms(
+                                &connection_info,
+                                session_id,
+                                &mut iopub,
+                                help_pages,
+                                lsp_client.as_deref(),
+                            )
+                            .await;
+                        }
+                        kernel_lost = false;
+                        lock_session_state(&session_state).disconnected = false;
+                        if std::mem::take(&mut reconnect_requested) {
+                            send_meta_reply(&ui_event_tx, "Reconnected to the kernel.");
+                        } else {
+                            let _ = ui_event_tx.send(ConsoleUiEvent::Notice(
+                                "Reconnected to the kernel.".to_string(),
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        debug!(error = ?err, "Console kernel_loop: kernel unreachable");
+                        if std::mem::take(&mut reconnect_requested) {
+                            send_meta_reply(&ui_event_tx, &format!("Reconnect failed: {err:#}"));
+                        }
+                        if kernel_lost {
+                            reconnect_at = Some(tokio::time::Instant::now() + RECONNECT_INTERVAL);
+                        }
+                    }
                 }
             }
 
@@ -146,22 +525,38 @@ pub(crate) async fn run_kernel_loop(
                 if exit_after_exec {
                     // Ark is likely dead after q(), no point sending interrupt
                     debug!("Console kernel_loop: interrupt during exit-after-exec, breaking immediately");
-                    let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone);
+                    let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
                     break;
                 }
+                let busy_for = exec_started_at.map(|started_at| started_at.elapsed());
+                if let (Some(busy_for), Some(interrupted_at)) = (busy_for, last_interrupt) {
+                    if interrupted_at.elapsed() < INTERRUPT_ESCALATION_WINDOW {
+                        debug!("Console kernel_loop: repeated interrupt, offering restart or kill");
+                        last_interrupt = None;
+                        let _ = ui_event_tx.send(ConsoleUiEvent::InterruptEscalation(busy_for));
+                        continue;
+                    }
+                }
                 if current_exec_msg_id.is_some() {
                     debug!("Console kernel_loop: sending InterruptRequest to kernel");
+                    last_interrupt = Some(Instant::now());
+                    if let Some(busy_for) = busy_for {
+                        let _ = ui_event_tx.send(ConsoleUiEvent::Notice(format!(
+                            "Interrupting R (busy for {}). Press Ctrl+C again to restart or kill it.",
+                            format_duration(busy_for)
+                        )));
+                    }
                     let interrupt = InterruptRequest {};
                     let message = JupyterMessage::new(interrupt, None);
                     if let Err(err) = control.send(message).await {
                         warn!(error = ?err, "Console kernel_loop: failed to send InterruptRequest");
                         if handle_transport_disconnect(
-                            &ui_event_tx,
+                            &mut lost_connection,
                             "interrupt send failed",
                             &err,
                             true,
                         ) {
-                            break;
+                            continue;
                         }
                     }
                 } else {
@@ -170,7 +565,7 @@ pub(crate) async fn run_kernel_loop(
             }
 
             // Handle iopub messages from the kernel
-            iopub_result = iopub.read() => {
+            iopub_result = iopub.read(), if !kernel_lost => {
                 match iopub_result {
                     Ok(message) => {
                         let parent_msg_id = message
@@ -188,12 +583,121 @@ pub(crate) async fn run_kernel_loop(
                         let is_our_exec = current_exec_msg_id.is_some()
                             && current_exec_msg_id.as_deref() == parent_msg_id;
 
+                        // Track working directory changes announced on the UI comm
+                        if let JupyterMessageContent::CommMsg(comm_msg) = &message.content {
+                            if let Some(directory) = working_directory_from_comm_data(&comm_msg.data) {
+                                debug!(directory = %directory, "Console kernel_loop: working directory changed");
+                                lock_session_state(&session_state).working_directory = Some(directory);
+                                directory_query.reported_by_ui_comm();
+                            }
+                            // The page is fetched in the background. It opens in the
+                            // pager if it arrives before `?topic` completes, and is
+                            // printed at the prompt otherwise.
+                            if comms.help.as_deref() == Some(comm_msg.comm_id.0.as_str()) {
+                                if let Some(content) = help_content_from_comm_data(&comm_msg.data) {
+                                    debug!(content = ?content, "Console kernel_loop: show_help received");
+                                    spawn_help_fetch(content, ui_event_tx.clone());
+                                }
+                            }
+                            continue;
+                        }
+
+                        // The working directory queried after an execution
+                        if directory_query.is_running(parent_msg_id) {
+                            if let Some(directory) = directory_query.collect(&message.content) {
+                                if !directory.is_empty() {
+                                    lock_session_state(&session_state).working_directory = Some(directory);
+                                }
+                                if let Some((record, _)) = prompt_after_query.take() {
+                                    let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(record));
+                                }
+                            }
+                            continue;
+                        }
+
+                        // Code from the editor prints as if typed at the prompt
+                        if let Some(msg_id) = parent_msg_id
+                            .filter(|id| injections.contains_key(*id))
+                            .map(str::to_string)
+                        {
+                            match &message.content {
+                                JupyterMessageContent::ExecuteInput(input) => {
+                                    record_transcript(&mut transcript, &ui_event_tx, |t| t.record_input(&input.code));
+                                    if let Some(echo) = injections
+                                        .get_mut(&msg_id)
+                                        .and_then(|run| run.input(&input.code))
+                                    {
+                                        let _ = ui_event_tx.send(ConsoleUiEvent::Injected(echo));
+                                    }
+                                }
+                                JupyterMessageContent::Status(status)
+                                    if status.execution_state == ExecutionState::Idle =>
+                                {
+                                    debug!("Console kernel_loop: injected execution idle");
+                                    if let Some(run) = injections.remove(&msg_id) {
+                                        let (rest, record) = run.finish();
+                                        if let Some(rest) = rest {
+                                            let _ = ui_event_tx.send(ConsoleUiEvent::Injected(rest));
+                                        }
+                                        record_transcript(&mut transcript, &ui_event_tx, |t| {
+                                            t.record_done(record.duration, record.failed)
+                                        });
+                                        lock_session_state(&session_state).last_execution = Some(record);
+                                        query_working_directory(&mut shell, &mut directory_query).await;
+                                    }
+                                }
+                                content => {
+                                    record_transcript(&mut transcript, &ui_event_tx, |t| {
+                                        t.record_output(content)
+                                    });
+                                    if let Some(text) = injections
+                                        .get_mut(&msg_id)
+                                        .and_then(|run| run.output(content, output_options))
+                                    {
+                                        let _ = ui_event_tx.send(ConsoleUiEvent::Injected(text));
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+
+                        if is_our_exec && matches!(message.content, JupyterMessageContent::ErrorOutput(_)) {
+                            exec_failed = true;
+                        }
+                        if is_our_exec {
+                            record_transcript(&mut transcript, &ui_event_tx, |t| {
+                                t.record_output(&message.content)
+                            });
+                        }
+
                         // Handle status changes
                         if let JupyterMessageContent::Status(status) = &message.content {
                             if is_our_exec && status.execution_state == ExecutionState::Idle {
                                 debug!("Console kernel_loop: execution idle");
                                 current_exec_msg_id = None;
-                                let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone);
+                                last_interrupt = None;
+                                let record =
+                                    exec_started_at.take().map(|started_at| ExecutionRecord {
+                                        duration: started_at.elapsed(),
+                                        failed: exec_failed,
+                                    });
+                                if let Some(record) = record {
+                                    record_transcript(&mut transcript, &ui_event_tx, |t| {
+                                        t.record_done(record.duration, record.failed)
+                                    });
+                                    lock_session_state(&session_state).last_execution =
+                                        Some(record);
+                                }
+                                if !exit_after_exec
+                                    && query_working_directory(&mut shell, &mut directory_query).await
+                                {
+                                    prompt_after_query = Some((
+                                        record,
+                                        tokio::time::Instant::now() + DIRECTORY_QUERY_TIMEOUT,
+                                    ));
+                                    continue;
+                                }
+                                let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(record));
                                 if exit_after_exec {
                                     debug!("Console kernel_loop: exit_after_exec set, breaking");
                                     break;
@@ -204,8 +708,20 @@ pub(crate) async fn run_kernel_loop(
 
                         // Format and send displayable output
                         if is_our_exec {
-                            if let Some(text) = format_iopub_content(&message.content) {
-                                let _ = ui_event_tx.send(ConsoleUiEvent::Output(text));
+                            if let Some(text) =
+                                format_iopub_content(&message.content, output_options)
+                            {
+                                let event = match message.content {
+                                    JupyterMessageContent::ErrorOutput(_) => {
+                                        ConsoleUiEvent::ErrorOutput(text)
+                                    }
+                                    _ => ConsoleUiEvent::Output(text),
+                                };
+                                let _ = ui_event_tx.send(event);
+                            }
+                        } else if follow && is_foreign(&message, session_id) {
+                            if let Some(text) = foreign.mirror(&message, output_options) {
+                                let _ = ui_event_tx.send(ConsoleUiEvent::Foreign(text));
                             }
                         }
                     }
@@ -218,12 +734,12 @@ pub(crate) async fn run_kernel_loop(
                         }
                         error!(error = ?err, "Console kernel_loop: iopub read error");
                         if handle_transport_disconnect(
-                            &ui_event_tx,
+                            &mut lost_connection,
                             "iopub read failed",
                             &err,
                             current_exec_msg_id.is_some(),
                         ) {
-                            break;
+                            continue;
                         }
                         break;
                     }
@@ -231,7 +747,7 @@ pub(crate) async fn run_kernel_loop(
             }
 
             // Handle shell replies (execute_reply, etc.)
-            shell_result = shell.read() => {
+            shell_result = shell.read(), if !kernel_lost => {
                 match shell_result {
                     Ok(message) => {
                         debug!(
@@ -255,18 +771,31 @@ pub(crate) async fn run_kernel_loop(
                     Err(err) => {
                         warn!(error = ?err, "Console kernel_loop: shell read error");
                         if handle_transport_disconnect(
-                            &ui_event_tx,
+                            &mut lost_connection,
                             "shell read failed",
                             &err,
                             current_exec_msg_id.is_some(),
                         ) {
-                            break;
+                            continue;
                         }
                         // Shell decode errors can be non-fatal with Ark replies, so continue.
                     }
                 }
             }
 
+            // Release the prompt when the working directory query is slow
+            _ = async {
+                match prompt_after_query {
+                    Some((_, deadline)) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending::<()>().await,
+                }
+            } => {
+                debug!("Console kernel_loop: working directory query timed out");
+                if let Some((record, _)) = prompt_after_query.take() {
+                    let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(record));
+                }
+            }
+
             // Safety timeout after q()/quit() — when Ark dies, ZMQ PUB/SUB
             // sockets never signal disconnection, so iopub.read() hangs forever.
             // This timeout ensures the console exits cleanly.
@@ -277,19 +806,195 @@ pub(crate) async fn run_kernel_loop(
                 }
             } => {
                 warn!("Console kernel_loop: exit timeout after q(), kernel likely terminated");
-                let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone);
+                let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
                 break;
             }
         }
     }
 
+    // After q() or a lost connection there is no kernel to tell
+    if !kernel_lost && !exit_after_exec {
+        close_comms(&mut shell, &mut comms).await;
+    }
     stop_heartbeat_monitor(heartbeat_handle);
     debug!("Console kernel_loop: exiting");
     Ok(())
 }
 
-fn handle_transport_disconnect<E: std::fmt::Debug>(
+/// Close the comms this console opened, so the kernel stops serving them.
+/// Closing the LSP comm stops its LSP server.
+///
+/// Best-effort: failures are logged, and a kernel that doesn't take the
+/// message within `COMM_CLOSE_TIMEOUT` is not waited for.
+async fn close_comms(shell: &mut ClientShellConnection, comms: &mut ConsoleComms) {
+    for comm_id in [comms.help.take(), comms.lsp.take()].into_iter().flatten() {
+        close_comm(shell, &comm_id).await;
+    }
+}
+
+async fn close_comm(shell: &mut ClientShellConnection, comm_id: &str) {
+    match tokio::time::timeout(COMM_CLOSE_TIMEOUT, send_comm_close(shell, comm_id)).await {
+        Ok(Ok(())) => debug!(comm_id = %comm_id, "Console kernel_loop: comm closed"),
+        Ok(Err(err)) => {
+            warn!(error = ?err, comm_id = %comm_id, "Console kernel_loop: failed to close comm")
+        }
+        Err(_) => warn!(comm_id = %comm_id, "Console kernel_loop: comm_close timed out"),
+    }
+}
+
+/// Fetch a help page without holding up the kernel loop, and forward it
+/// to the reedline loop.
+fn spawn_help_fetch(content: HelpContent, ui_event_tx: std_mpsc::Sender<ConsoleUiEvent>) {
+    tokio::spawn(async move {
+        match load_help_html(content).await {
+            Ok(html) => {
+                let _ = ui_event_tx.send(ConsoleUiEvent::Help(html));
+            }
+            Err(err) => {
+                warn!(error = ?err, "Console kernel_loop: failed to load help page");
+                let _ = ui_event_tx.send(ConsoleUiEvent::Output(format!(
+                    "Failed to load help page: {err:#}\n"
+                )));
+            }
+        }
+    });
+}
+
+/// Write to the transcript, if one is being recorded. A failed write
+/// stops the recording.
+fn record_transcript(
+    transcript: &mut Option<Transcript>,
     ui_event_tx: &std_mpsc::Sender<ConsoleUiEvent>,
+    record: impl FnOnce(&mut Transcript) -> Result<()>,
+) {
+    let Some(active) = transcript else {
+        return;
+    };
+    if let Err(err) = record(active) {
+        warn!(error = ?err, "Console kernel_loop: transcript write failed, stopping");
+        let message = format!("Transcript stopped: {err:#}");
+        let _ = ui_event_tx.send(ConsoleUiEvent::Output(format!(
+            "{}\n",
+            Color::DarkGray.paint(message)
+        )));
+        *transcript = None;
+    }
+}
+
+/// Answer the editor for injected code that will never finish.
+fn abandon_injections(injections: &mut HashMap<String, RunningInjection>, message: &str) {
+    for (_, run) in injections.drain() {
+        run.abandon(message);
+    }
+}
+
+/// Ask R for its working directory unless a UI comm reports it. Returns
+/// whether the query was sent.
+async fn query_working_directory(
+    shell: &mut ClientShellConnection,
+    directory_query: &mut DirectoryQuery,
+) -> bool {
+    if !directory_query.needed() {
+        return false;
+    }
+    let message = JupyterMessage::new(console_query(WORKING_DIRECTORY_QUERY), None);
+    let msg_id = message.header.msg_id.clone();
+    match shell.send(message).await {
+        Ok(()) => {
+            directory_query.started(msg_id);
+            true
+        }
+        Err(err) => {
+            warn!(error = ?err, "Console kernel_loop: failed to send working directory query");
+            false
+        }
+    }
+}
+
+/// Report the result of a meta-command handled here and release the prompt.
+fn send_meta_reply(ui_event_tx: &std_mpsc::Sender<ConsoleUiEvent>, message: &str) {
+    let _ = ui_event_tx.send(ConsoleUiEvent::Output(format!(
+        "{}\n",
+        Color::DarkGray.paint(message)
+    )));
+    let _ = ui_event_tx.send(ConsoleUiEvent::ExecutionDone(None));
+}
+
+/// Send SIGKILL to the kernel process.
+fn kill_kernel_process(pid: u32) -> Result<()> {
+    let pid = libc::pid_t::try_from(pid)
+        .ok()
+        .filter(|pid| *pid > 1)
+        .with_context(|| format!("Refusing to signal process {pid}"))?;
+    // SAFETY: kill() takes no pointers; a positive pid targets one process.
+    if unsafe { libc::kill(pid, libc::SIGKILL) } != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to kill process {pid}"));
+    }
+    Ok(())
+}
+
+/// Reconnect once the kernel answers heartbeats again.
+///
+/// The connection file is read again first: a kernel restarted by its
+/// supervisor may have been given new ports.
+async fn reattach_kernel(
+    connection_info: &mut runtimelib::ConnectionInfo,
+    session_id: &str,
+    connection_file: Option<&str>,
+) -> Result<(
+    ClientShellConnection,
+    ClientIoPubConnection,
+    ClientControlConnection,
+)> {
+    if let Some(path) = connection_file {
+        match read_connection(path) {
+            Ok(updated) => {
+                if connection_changed(connection_info, &updated) {
+                    info!(path = %path, "Console kernel_loop: connection file changed");
+                }
+                *connection_info = updated;
+            }
+            Err(err) => debug!(error = ?err, "Console kernel_loop: failed to read connection file"),
+        }
+    }
+    probe_heartbeat(connection_info).await?;
+    connect_kernel_sockets(connection_info, session_id).await
+}
+
+/// Whether the kernel moved to other ports or another key.
+fn connection_changed(
+    before: &runtimelib::ConnectionInfo,
+    after: &runtimelib::ConnectionInfo,
+) -> bool {
+    serde_json::to_value(before).ok() != serde_json::to_value(after).ok()
+}
+
+/// Open fresh shell, iopub, and control connections to the kernel.
+async fn connect_kernel_sockets(
+    connection_info: &runtimelib::ConnectionInfo,
+    session_id: &str,
+) -> Result<(
+    ClientShellConnection,
+    ClientIoPubConnection,
+    ClientControlConnection,
+)> {
+    let shell = create_shell_connection(connection_info, session_id)
+        .await
+        .context("Failed to connect shell")?;
+    let iopub = create_client_iopub_connection(connection_info, "", session_id)
+        .await
+        .context("Failed to connect iopub")?;
+    let control = create_control_connection(connection_info, session_id)
+        .await
+        .context("Failed to connect control socket")?;
+    Ok((shell, iopub, control))
+}
+
+/// Record a transport error that shows the kernel is gone; returns whether
+/// it did.
+fn handle_transport_disconnect<E: std::fmt::Debug>(
+    lost_connection: &mut Option<String>,
     operation: &str,
     err: &E,
     execution_in_progress: bool,
@@ -305,13 +1010,11 @@ fn handle_transport_disconnect<E: std::fmt::Debug>(
         error = %err_text,
         "Console kernel_loop: transport error confirms kernel disconnect"
     );
-    let _ = ui_event_tx.send(ConsoleUiEvent::KernelDisconnected(
-        kernel_disconnect_message(),
-    ));
+    *lost_connection = Some(operation.to_string());
     true
 }
 
-fn is_comm_close_missing_data(err_text: &str) -> bool {
+pub(crate) fn is_comm_close_missing_data(err_text: &str) -> bool {
     err_text.contains("comm_close") && err_text.contains("missing field `data`")
 }
 
@@ -376,4 +1079,32 @@ mod tests {
             "missing field `execution_count`"
         ));
     }
+
+    #[test]
+    fn transport_disconnect_records_the_failed_operation() {
+        let mut lost_connection = None;
+        assert!(!handle_transport_disconnect(
+            &mut lost_connection,
+            "shell read failed",
+            &"missing field `data`",
+            false,
+        ));
+        assert_eq!(lost_connection, None);
+        assert!(handle_transport_disconnect(
+            &mut lost_connection,
+            "iopub read failed",
+            &"Connection reset by peer",
+            true,
+        ));
+        assert_eq!(lost_connection.as_deref(), Some("iopub read failed"));
+    }
+
+    #[test]
+    fn only_kernel_requests_wait_for_a_reconnect() {
+        assert!(ConsoleRequest::Execute("1".to_string()).needs_kernel());
+        assert!(ConsoleRequest::Interrupt.needs_kernel());
+        assert!(!ConsoleRequest::Reconnect.needs_kernel());
+        assert!(!ConsoleRequest::StopTranscript.needs_kernel());
+        assert!(!ConsoleRequest::Exit.needs_kernel());
+    }
 }
@@ -0,0 +1,264 @@
+// Markdown to styled terminal text.
+//
+// Kernel output may carry `text/markdown`, e.g. from `knitr::kable()`.
+// The common subset (headings, paragraphs, lists, fenced code, rules and
+// pipe tables, with inline code, emphasis and links) is translated to HTML
+// and drawn by the HTML output renderer, so both formats share one layout.
+
+use super::html::html_output_to_terminal;
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// `[label](url)` at the start of `text`: the label and the length consumed.
+fn parse_link(text: &str) -> Option<(&str, usize)> {
+    let close = text.find("](")?;
+    let label = &text[1..close];
+    let end = text[close..].find(')')? + close;
+    Some((label, end + 1))
+}
+
+/// Translate inline markdown: code spans, `**bold**`, `*italic*` and links.
+fn inline_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+    let mut previous = ' ';
+    let (mut bold, mut italic) = (false, false);
+
+    while let Some(ch) = rest.chars().next() {
+        if ch == '`' {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let fence = &rest[..ticks];
+            if let Some(end) = rest[ticks..].find(fence) {
+                let code = rest[ticks..ticks + end].trim();
+                html.push_str(&format!("<code>{}</code>", escape_html(code)));
+                rest = &rest[2 * ticks + end..];
+                previous = '`';
+                continue;
+            }
+        } else if rest.starts_with("**") {
+            html.push_str(if bold { "</strong>" } else { "<strong>" });
+            bold = !bold;
+            rest = &rest[2..];
+            continue;
+        } else if ch == '*' {
+            let next = rest[1..].chars().next();
+            // `2 * 3` and `x*y` are not emphasis
+            if italic || (!previous.is_alphanumeric() && next.is_some_and(|c| !c.is_whitespace())) {
+                html.push_str(if italic { "</em>" } else { "<em>" });
+                italic = !italic;
+                rest = &rest[1..];
+                continue;
+            }
+        } else if ch == '[' {
+            if let Some((label, consumed)) = parse_link(rest) {
+                html.push_str(&inline_html(label));
+                rest = &rest[consumed..];
+                continue;
+            }
+        } else if ch == '\\' {
+            if let Some(escaped) = rest[1..].chars().next().filter(char::is_ascii_punctuation) {
+                html.push_str(&escape_html(&escaped.to_string()));
+                rest = &rest[1 + escaped.len_utf8()..];
+                previous = escaped;
+                continue;
+            }
+        }
+        html.push_str(&escape_html(&ch.to_string()));
+        rest = &rest[ch.len_utf8()..];
+        previous = ch;
+    }
+
+    if italic {
+        html.push_str("</em>");
+    }
+    if bold {
+        html.push_str("</strong>");
+    }
+    html
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.len() - line.trim_start_matches('#').len();
+    let after = &line[level..];
+    ((1..=6).contains(&level) && (after.is_empty() || after.starts_with(' '))).then_some(level)
+}
+
+fn list_item(line: &str) -> Option<&str> {
+    if let Some(item) = ["- ", "* ", "+ "]
+        .iter()
+        .find_map(|bullet| line.strip_prefix(bullet))
+    {
+        return Some(item);
+    }
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let after = &line[digits..];
+    (digits > 0)
+        .then(|| {
+            after
+                .strip_prefix(". ")
+                .or_else(|| after.strip_prefix(") "))
+        })
+        .flatten()
+}
+
+fn is_rule(line: &str) -> bool {
+    let compact: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    compact.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|&mark| compact.chars().all(|c| c == mark))
+}
+
+fn table_cells(line: &str) -> Vec<&str> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    line.split('|').map(str::trim).collect()
+}
+
+/// The `|---|:--:|` row under a pipe table's header.
+fn is_delimiter_row(line: &str) -> bool {
+    line.contains('-')
+        && table_cells(line).iter().all(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+fn table_row_html(cells: &[&str], tag: &str) -> String {
+    let cells: String = cells
+        .iter()
+        .map(|cell| format!("<{tag}>{}</{tag}>", inline_html(cell)))
+        .collect();
+    format!("<tr>{cells}</tr>")
+}
+
+/// Close the open paragraph or list.
+fn end_blocks(html: &mut String, paragraph: &mut Vec<&str>, in_list: &mut bool) {
+    if !paragraph.is_empty() {
+        html.push_str(&format!("<p>{}</p>", inline_html(&paragraph.join(" "))));
+        paragraph.clear();
+    }
+    if std::mem::take(in_list) {
+        html.push_str("</ul>");
+    }
+}
+
+/// Translate the supported markdown subset to HTML.
+pub(crate) fn markdown_to_html(markdown: &str) -> String {
+    let lines: Vec<&str> = markdown.lines().collect();
+    let mut html = String::new();
+    let mut paragraph: Vec<&str> = Vec::new();
+    let mut in_list = false;
+
+    let mut index = 0;
+    while index < lines.len() {
+        let line = lines[index].trim();
+        index += 1;
+
+        if let Some(fence) = ["```", "~~~"].into_iter().find(|f| line.starts_with(f)) {
+            end_blocks(&mut html, &mut paragraph, &mut in_list);
+            let start = index;
+            while index < lines.len() && !lines[index].trim_start().starts_with(fence) {
+                index += 1;
+            }
+            let code = lines[start..index].join("\n");
+            html.push_str(&format!("<pre>\n{}</pre>", escape_html(&code)));
+            index += 1;
+        } else if let Some(level) = heading_level(line) {
+            end_blocks(&mut html, &mut paragraph, &mut in_list);
+            let text = line[level..].trim().trim_end_matches('#').trim_end();
+            html.push_str(&format!("<h{level}>{}</h{level}>", inline_html(text)));
+        } else if line.contains('|') && lines.get(index).is_some_and(|next| is_delimiter_row(next))
+        {
+            end_blocks(&mut html, &mut paragraph, &mut in_list);
+            html.push_str("<table><thead>");
+            html.push_str(&table_row_html(&table_cells(line), "th"));
+            html.push_str("</thead><tbody>");
+            index += 1;
+            while index < lines.len() && lines[index].contains('|') {
+                html.push_str(&table_row_html(&table_cells(lines[index]), "td"));
+                index += 1;
+            }
+            html.push_str("</tbody></table>");
+        } else if is_rule(line) {
+            end_blocks(&mut html, &mut paragraph, &mut in_list);
+            html.push_str("<hr>");
+        } else if let Some(item) = list_item(line) {
+            if !in_list {
+                end_blocks(&mut html, &mut paragraph, &mut in_list);
+                html.push_str("<ul>");
+                in_list = true;
+            }
+            html.push_str(&format!("<li>{}</li>", inline_html(item)));
+        } else if line.is_empty() {
+            end_blocks(&mut html, &mut paragraph, &mut in_list);
+        } else {
+            if in_list {
+                end_blocks(&mut html, &mut paragraph, &mut in_list);
+            }
+            paragraph.push(line);
+        }
+    }
+    end_blocks(&mut html, &mut paragraph, &mut in_list);
+    html
+}
+
+/// Render markdown as terminal lines fitted to `width` columns.
+pub(crate) fn markdown_to_terminal(markdown: &str, width: usize, use_ansi: bool) -> Vec<String> {
+    html_output_to_terminal(&markdown_to_html(markdown), width, use_ansi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_markup_is_translated() {
+        assert_eq!(
+            inline_html("Use `x <- 1`, **bold**, *it* and [docs](https://r-project.org)."),
+            "Use <code>x &lt;- 1</code>, <strong>bold</strong>, <em>it</em> and docs."
+        );
+        assert_eq!(inline_html("2 * 3 and x*y"), "2 * 3 and x*y");
+        assert_eq!(inline_html("\\*not\\* `` a`b ``"), "*not* <code>a`b</code>");
+    }
+
+    #[test]
+    fn pipe_tables_are_drawn_as_grids() {
+        let markdown = "\n\n|                  |  mpg| cyl|\n|:-----------------|----:|---:|\n|Mazda RX4         | 21.0|   6|\n|Datsun 710        | 22.8|   4|";
+        assert_eq!(
+            markdown_to_terminal(markdown, 80, false),
+            vec![
+                "┌────────────┬──────┬─────┐",
+                "│            │  mpg │ cyl │",
+                "├────────────┼──────┼─────┤",
+                "│ Mazda RX4  │ 21.0 │   6 │",
+                "│ Datsun 710 │ 22.8 │   4 │",
+                "└────────────┴──────┴─────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn blocks_are_rendered() {
+        let markdown =
+            "# Summary\n\nThe model\nfits *well*.\n\n- one\n- two\n\n```r\nfit <- lm(y ~ x)\n```";
+        assert_eq!(
+            markdown_to_terminal(markdown, 80, false),
+            vec![
+                "Summary",
+                "",
+                "The model fits well.",
+                "",
+                "• one",
+                "• two",
+                "",
+                "  fit <- lm(y ~ x)",
+            ]
+        );
+    }
+}
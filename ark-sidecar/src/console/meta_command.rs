@@ -0,0 +1,329 @@
+// Console meta-commands.
+//
+// Lines starting with `:` control the console session instead of being
+// sent to R. A leading `:` is never valid R, so no R input is shadowed.
+
+use reedline::{Span, Suggestion};
+
+/// Number of history entries `:history` shows by default.
+const DEFAULT_HISTORY_COUNT: usize = 20;
+
+const LOG_LEVELS: &[&str] = &["error", "warn", "info", "debug", "trace"];
+
+/// Meta-command names with their usage and description, for `:help` and
+/// completion.
+const META_COMMANDS: &[(&str, &str, &str)] = &[
+    ("interrupt", ":interrupt", "Interrupt the R session"),
+    (
+        "restart",
+        ":restart",
+        "Ask the kernel to restart the R session",
+    ),
+    (
+        "reconnect",
+        ":reconnect",
+        "Reattach to the kernel, re-reading its connection file",
+    ),
+    ("history", ":history [n]", "Show the last n history entries"),
+    (
+        "more",
+        ":more",
+        "Page through the full output of the last execution",
+    ),
+    (
+        "log-level",
+        ":log-level <level>",
+        "Set the sidecar log level (error, warn, info, debug, trace)",
+    ),
+    (
+        "time",
+        ":time <expr>",
+        "Evaluate an expression and report how long it took",
+    ),
+    (
+        "transcript",
+        ":transcript start [file] | stop",
+        "Record executions and their output to a transcript file",
+    ),
+    (
+        "follow",
+        ":follow [on|off]",
+        "Also show executions from other clients, such as the editor",
+    ),
+    (
+        "lsp",
+        ":lsp status",
+        "Show the language server connection state",
+    ),
+    ("help", ":help", "List console meta-commands"),
+];
+
+/// A parsed meta-command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum MetaCommand {
+    Interrupt,
+    Restart,
+    Reconnect,
+    History(usize),
+    More,
+    LogLevel(String),
+    Time(String),
+    TranscriptStart(Option<String>),
+    TranscriptStop,
+    /// Turn follow mode on or off; `None` toggles it.
+    Follow(Option<bool>),
+    LspStatus,
+    Help,
+}
+
+/// Parse a meta-command line.
+///
+/// Returns `None` for regular R input, and `Some(Err(message))` for a
+/// malformed or unknown meta-command.
+pub(crate) fn parse_meta_command(input: &str) -> Option<Result<MetaCommand, String>> {
+    let command_line = input.trim().strip_prefix(':')?;
+    let (name, argument) = match command_line.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command_line, ""),
+    };
+
+    let command = match (name, argument) {
+        ("interrupt", "") => Ok(MetaCommand::Interrupt),
+        ("restart", "") => Ok(MetaCommand::Restart),
+        ("reconnect", "") => Ok(MetaCommand::Reconnect),
+        ("history", "") => Ok(MetaCommand::History(DEFAULT_HISTORY_COUNT)),
+        ("history", count) => count
+            .parse()
+            .map(MetaCommand::History)
+            .map_err(|_| format!("Invalid history count: {count}")),
+        ("more", "") => Ok(MetaCommand::More),
+        ("log-level", level) if LOG_LEVELS.contains(&level) => {
+            Ok(MetaCommand::LogLevel(level.to_string()))
+        }
+        ("log-level", level) => Err(format!(
+            "Invalid log level: '{level}'. Expected one of: {}",
+            LOG_LEVELS.join(", ")
+        )),
+        ("time", "") => Err("Usage: :time <expr>".to_string()),
+        ("time", expr) => Ok(MetaCommand::Time(expr.to_string())),
+        ("transcript", "stop") => Ok(MetaCommand::TranscriptStop),
+        ("transcript", argument) if argument.split_whitespace().next() == Some("start") => {
+            let file = argument["start".len()..].trim();
+            Ok(MetaCommand::TranscriptStart(
+                (!file.is_empty()).then(|| file.to_string()),
+            ))
+        }
+        ("transcript", _) => Err("Usage: :transcript start [file] | :transcript stop".to_string()),
+        ("follow", "") => Ok(MetaCommand::Follow(None)),
+        ("follow", "on") => Ok(MetaCommand::Follow(Some(true))),
+        ("follow", "off") => Ok(MetaCommand::Follow(Some(false))),
+        ("follow", _) => Err("Usage: :follow [on|off]".to_string()),
+        ("lsp", "status") => Ok(MetaCommand::LspStatus),
+        ("lsp", _) => Err("Usage: :lsp status".to_string()),
+        ("help", "") => Ok(MetaCommand::Help),
+        (name, _) if META_COMMANDS.iter().any(|(known, _, _)| *known == name) => {
+            Err(format!(":{name} takes no arguments"))
+        }
+        (name, _) => Err(format!("Unknown command ':{name}'. Type :help for a list.")),
+    };
+    Some(command)
+}
+
+/// Help text listing all meta-commands.
+pub(crate) fn meta_command_help() -> String {
+    let width = META_COMMANDS
+        .iter()
+        .map(|(_, usage, _)| usage.len())
+        .max()
+        .unwrap_or_default();
+    META_COMMANDS
+        .iter()
+        .map(|(_, usage, description)| format!("  {usage:<width$}  {description}\n"))
+        .collect()
+}
+
+fn suggestion(value: &str, description: Option<&str>, span: Span) -> Suggestion {
+    Suggestion {
+        value: value.to_string(),
+        description: description.map(str::to_string),
+        style: None,
+        extra: None,
+        span,
+        append_whitespace: true,
+        display_override: None,
+        match_indices: None,
+    }
+}
+
+/// Completion candidates for a meta-command line.
+///
+/// Returns `None` when the line is regular R input, so the caller can fall
+/// back to LSP completion.
+pub(crate) fn meta_command_suggestions(line: &str, pos: usize) -> Option<Vec<Suggestion>> {
+    let before_cursor = &line[..pos];
+    let leading = before_cursor.len() - before_cursor.trim_start().len();
+    let command_line = before_cursor.trim_start().strip_prefix(':')?;
+
+    let Some((name, argument)) = command_line.split_once(' ') else {
+        // Completing the command name, including the `:`.
+        let span = Span::new(leading, pos);
+        return Some(
+            META_COMMANDS
+                .iter()
+                .filter(|(name, _, _)| name.starts_with(command_line))
+                .map(|(name, _, description)| {
+                    suggestion(&format!(":{name}"), Some(description), span)
+                })
+                .collect(),
+        );
+    };
+
+    let argument = argument.trim_start();
+    let span = Span::new(pos - argument.len(), pos);
+    let candidates: &[&str] = match name {
+        "log-level" => LOG_LEVELS,
+        "lsp" => &["status"],
+        "transcript" => &["start", "stop"],
+        "follow" => &["on", "off"],
+        _ => &[],
+    };
+    Some(
+        candidates
+            .iter()
+            .filter(|candidate| candidate.starts_with(argument))
+            .map(|candidate| suggestion(candidate, None, span))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regular_input_is_not_a_meta_command() {
+        assert_eq!(parse_meta_command("mean(x)"), None);
+        assert_eq!(parse_meta_command("1:10"), None);
+    }
+
+    #[test]
+    fn commands_are_parsed() {
+        assert_eq!(
+            parse_meta_command(":interrupt"),
+            Some(Ok(MetaCommand::Interrupt))
+        );
+        assert_eq!(
+            parse_meta_command("  :restart  "),
+            Some(Ok(MetaCommand::Restart))
+        );
+        assert_eq!(
+            parse_meta_command(":history"),
+            Some(Ok(MetaCommand::History(DEFAULT_HISTORY_COUNT)))
+        );
+        assert_eq!(
+            parse_meta_command(":history 5"),
+            Some(Ok(MetaCommand::History(5)))
+        );
+        assert_eq!(
+            parse_meta_command(":log-level debug"),
+            Some(Ok(MetaCommand::LogLevel("debug".to_string())))
+        );
+        assert_eq!(
+            parse_meta_command(":time Sys.sleep(1)"),
+            Some(Ok(MetaCommand::Time("Sys.sleep(1)".to_string())))
+        );
+        assert_eq!(
+            parse_meta_command(":lsp status"),
+            Some(Ok(MetaCommand::LspStatus))
+        );
+        assert_eq!(parse_meta_command(":more"), Some(Ok(MetaCommand::More)));
+        assert_eq!(
+            parse_meta_command(":transcript start"),
+            Some(Ok(MetaCommand::TranscriptStart(None)))
+        );
+        assert_eq!(
+            parse_meta_command(":transcript start ~/notes/run.jsonl"),
+            Some(Ok(MetaCommand::TranscriptStart(Some(
+                "~/notes/run.jsonl".to_string()
+            ))))
+        );
+        assert_eq!(
+            parse_meta_command(":transcript stop"),
+            Some(Ok(MetaCommand::TranscriptStop))
+        );
+        assert_eq!(
+            parse_meta_command(":follow"),
+            Some(Ok(MetaCommand::Follow(None)))
+        );
+        assert_eq!(
+            parse_meta_command(":follow off"),
+            Some(Ok(MetaCommand::Follow(Some(false))))
+        );
+        assert_eq!(parse_meta_command(":help"), Some(Ok(MetaCommand::Help)));
+    }
+
+    #[test]
+    fn malformed_commands_report_errors() {
+        assert!(matches!(parse_meta_command(":history ten"), Some(Err(_))));
+        assert!(matches!(
+            parse_meta_command(":log-level loud"),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse_meta_command(":time"), Some(Err(_))));
+        assert!(matches!(parse_meta_command(":transcript"), Some(Err(_))));
+        assert!(matches!(
+            parse_meta_command(":transcript starting"),
+            Some(Err(_))
+        ));
+        assert!(matches!(parse_meta_command(":restart now"), Some(Err(_))));
+        assert!(matches!(parse_meta_command(":follow yes"), Some(Err(_))));
+        assert_eq!(
+            parse_meta_command(":frobnicate"),
+            Some(Err(
+                "Unknown command ':frobnicate'. Type :help for a list.".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn help_lists_every_command() {
+        let help = meta_command_help();
+        for (_, usage, _) in META_COMMANDS {
+            assert!(help.contains(usage));
+        }
+    }
+
+    fn values(suggestions: Option<Vec<Suggestion>>) -> Option<Vec<String>> {
+        suggestions.map(|suggestions| suggestions.into_iter().map(|s| s.value).collect())
+    }
+
+    #[test]
+    fn command_names_complete() {
+        assert_eq!(
+            values(meta_command_suggestions(":re", 3)),
+            Some(vec![":restart".to_string(), ":reconnect".to_string()])
+        );
+        let suggestions = meta_command_suggestions(":h", 2).unwrap();
+        assert_eq!(suggestions[0].span, Span::new(0, 2));
+    }
+
+    #[test]
+    fn arguments_complete() {
+        assert_eq!(
+            values(meta_command_suggestions(":log-level d", 12)),
+            Some(vec!["debug".to_string()])
+        );
+        let suggestions = meta_command_suggestions(":lsp ", 5).unwrap();
+        assert_eq!(suggestions[0].value, "status");
+        assert_eq!(suggestions[0].span, Span::new(5, 5));
+        assert_eq!(
+            values(meta_command_suggestions(":time me", 8)),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn r_input_falls_back_to_lsp() {
+        assert!(meta_command_suggestions("mean(x", 6).is_none());
+    }
+}
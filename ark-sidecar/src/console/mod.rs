@@ -1,10 +1,34 @@
+mod busy;
 mod completer;
+mod completion_menu;
+mod config;
+mod follow;
+mod help;
 mod highlighter;
+mod hinter;
 mod history;
+mod html;
+mod image;
+mod indent;
+mod inject;
 mod kernel_loop;
+mod markdown;
+mod meta_command;
 mod output;
+mod output_limit;
+mod pager;
+mod paste;
+mod path_completion;
+mod prompt;
 mod r_parser;
 mod reedline_loop;
+mod session_state;
+mod signature_help;
+mod table;
+mod terminal;
+#[cfg(test)]
+mod test_dir;
+mod transcript;
 mod validator;
 
 use std::sync::Arc;
@@ -12,8 +36,8 @@ use std::time::Duration;
 
 use anyhow::{Context, Result};
 use runtimelib::{
-    create_client_iopub_connection, ClientIoPubConnection, ConnectionInfo, ExecuteRequest,
-    ExecutionState, JupyterMessage, JupyterMessageContent,
+    create_client_iopub_connection, ClientIoPubConnection, ConnectionInfo, ExecutionState,
+    JupyterMessage, JupyterMessageContent,
 };
 use std::sync::mpsc as std_mpsc;
 use tokio::signal::unix::{signal, SignalKind};
@@ -21,10 +45,33 @@ use tracing::{debug, info, warn};
 use uuid::Uuid;
 
 use crate::connection::{
-    create_control_connection, create_shell_connection, send_comm_open, wait_for_comm_port,
+    create_control_connection, create_shell_connection, send_comm_open, send_help_comm_open,
+    wait_for_comm_port,
 };
+use crate::logging::LogReloadHandle;
 use crate::lsp_client::LspClient;
-use kernel_loop::{run_kernel_loop, ConsoleRequest};
+pub(crate) use config::load_console_config;
+use config::ConsoleConfig;
+use follow::console_query;
+pub(crate) use follow::run_monitor;
+pub(crate) use history::run_history_command;
+use image::resolve_image_protocol;
+use inject::{socket_path, InjectionSocket};
+use kernel_loop::{
+    run_kernel_loop, ConsoleComms, ConsoleRequest, KernelLoopChannels, KernelLoopOptions,
+};
+use output::{ConsoleUiEvent, OutputOptions};
+use reedline_loop::ReedlineLoopChannels;
+use session_state::{environment_label, lock_session_state, SessionState, SharedSessionState};
+use transcript::{format_for_path, transcript_path, Transcript};
+
+/// R code printing the R version, process ID, host name and working
+/// directory followed by the pixi project, pixi environment, and conda
+/// environment names, one per line.
+const SESSION_QUERY: &str = "cat(R.version.string, Sys.getpid(), Sys.info()[['nodename']], \
+    getwd(), \
+    Sys.getenv(c('PIXI_PROJECT_NAME', 'PIXI_ENVIRONMENT_NAME', 'CONDA_DEFAULT_ENV')), \
+    sep = '\\n')";
 
 /// Run the interactive R console.
 ///
@@ -33,8 +80,11 @@ use kernel_loop::{run_kernel_loop, ConsoleRequest};
 /// an LSP client for tab completion.
 pub(crate) async fn run_console(
     connection_info: &ConnectionInfo,
+    connection_file: &str,
     session_id: &str,
     r_binary_path: Option<&str>,
+    config: ConsoleConfig,
+    log_handle: LogReloadHandle,
 ) -> Result<()> {
     info!(mode = "console", "Sidecar: starting console mode");
 
@@ -47,28 +97,57 @@ pub(crate) async fn run_console(
         .context("Failed to connect iopub")?;
 
     // --- LSP Initialization (best-effort) ---
-    let lsp_client = match init_lsp(connection_info, session_id, &mut iopub).await {
-        Ok(client) => {
+    let (lsp_client, lsp_comm_id) = match init_lsp(connection_info, session_id, &mut iopub).await {
+        Ok((client, comm_id)) => {
             info!("Console: LSP client initialized, completion enabled");
-            Some(Arc::new(client))
+            (Some(Arc::new(client)), Some(comm_id))
         }
         Err(err) => {
             warn!(error = ?err, "Console: failed to initialize LSP, completion disabled");
-            None
+            (None, None)
+        }
+    };
+
+    // --- Help comm (best-effort, opt-in) ---
+    // Ark only sends `show_help` events for `?topic` while a help comm is
+    // open, and to just one of them; unless asked, the editor keeps them.
+    let help_comm_id = if config.help_pages {
+        match open_help_comm(connection_info, session_id).await {
+            Ok(comm_id) => {
+                info!(comm_id = %comm_id, "Console: help comm opened");
+                Some(comm_id)
+            }
+            Err(err) => {
+                warn!(error = ?err, "Console: failed to open help comm, help pages disabled");
+                None
+            }
         }
+    } else {
+        None
     };
 
-    // --- Query R version (best-effort) ---
-    let r_version = match query_r_version(connection_info, session_id, &mut iopub).await {
-        Ok(version) => {
-            info!(version = %version, "Console: R version queried");
-            Some(version)
+    // --- Query R version, process and prompt context (best-effort) ---
+    let mut session_state = SessionState {
+        connection_file: Some(connection_file.to_string()),
+        ..SessionState::default()
+    };
+    match query_r_session(connection_info, session_id, &mut iopub).await {
+        Ok(session) => {
+            info!(
+                version = %session.version,
+                pid = ?session.pid,
+                nodename = ?session.nodename,
+                working_directory = ?session.working_directory,
+                environment = ?session.environment,
+                "Console: R session queried"
+            );
+            session.apply(connection_info, &mut session_state);
         }
         Err(err) => {
-            warn!(error = ?err, "Console: failed to query R version");
-            None
+            warn!(error = ?err, "Console: failed to query R session");
         }
-    };
+    }
+    let session_state = session_state.shared();
 
     // --- Control channel for interrupt/shutdown ---
     let control = create_control_connection(connection_info, session_id)
@@ -102,21 +181,80 @@ pub(crate) async fn run_console(
     // Channel: kernel -> reedline (execution output + disconnect events)
     let (ui_event_tx, ui_event_rx) = std_mpsc::channel();
 
+    // --- Injection socket for code sent by the editor (best-effort) ---
+    let inject_socket = if config.inject_socket {
+        match InjectionSocket::bind(socket_path(connection_file), &request_tx).await {
+            Ok(socket) => {
+                let _ = ui_event_tx.send(ConsoleUiEvent::Notice(format!(
+                    "Accepting code from the editor on {}",
+                    socket.path().display()
+                )));
+                Some(socket)
+            }
+            Err(err) => {
+                warn!(error = ?err, "Console: failed to open injection socket");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
     // Clone connection info for the blocking task
     let conn_info = connection_info.clone();
     let sess_id = session_id.to_string();
     let runtime_handle = tokio::runtime::Handle::current();
     let r_binary_path_owned = r_binary_path.map(|s| s.to_string());
+    let reedline_session_state = Arc::clone(&session_state);
+    let output_options = OutputOptions {
+        image_protocol: resolve_image_protocol(config.image_protocol),
+        rich_output: config.rich_output,
+    };
+    info!(
+        image_protocol = ?output_options.image_protocol,
+        rich_output = output_options.rich_output,
+        "Console: output rendering"
+    );
+
+    let follow = config.follow;
+    let help_pages = config.help_pages;
+    let auto_reconnect = config.auto_reconnect;
+    let kernel_lsp_client = lsp_client.clone();
+
+    // --- Transcript (best-effort) ---
+    let transcript = config.transcript.as_deref().and_then(|file| {
+        let path = transcript_path(Some(file), None);
+        let format = config
+            .transcript_format
+            .unwrap_or_else(|| format_for_path(&path));
+        match Transcript::open(&path, format) {
+            Ok(transcript) => {
+                info!(path = %path.display(), format = ?format, "Console: recording transcript");
+                Some(transcript)
+            }
+            Err(err) => {
+                warn!(error = ?err, "Console: failed to open transcript");
+                let _ = ui_event_tx.send(ConsoleUiEvent::Output(format!(
+                    "Failed to start transcript: {err:#}\n"
+                )));
+                None
+            }
+        }
+    });
 
     // Spawn the blocking reedline loop
     let reedline_handle = tokio::task::spawn_blocking(move || {
         reedline_loop::run_reedline_loop(
-            request_tx,
-            ui_event_rx,
+            ReedlineLoopChannels {
+                request_tx,
+                ui_event_rx,
+            },
             lsp_client,
             runtime_handle,
-            r_version,
             r_binary_path_owned,
+            config,
+            reedline_session_state,
+            log_handle,
         );
     });
 
@@ -126,45 +264,156 @@ pub(crate) async fn run_console(
         &conn_info,
         &sess_id,
         iopub,
-        request_rx,
-        ui_event_tx,
         control,
-        interrupt_rx,
+        KernelLoopChannels {
+            request_rx,
+            ui_event_tx,
+            interrupt_rx,
+        },
+        session_state,
+        KernelLoopOptions {
+            comms: ConsoleComms {
+                help: help_comm_id,
+                lsp: lsp_comm_id,
+            },
+            help_pages,
+            output_options,
+            transcript,
+            follow,
+            auto_reconnect,
+            lsp_client: kernel_lsp_client,
+        },
     )
     .await;
 
     // Wait for reedline to finish
     let _ = reedline_handle.await;
+    drop(inject_socket);
 
     debug!("Console mode: finished");
 
     kernel_result
 }
 
-/// Query the R version string from the kernel by executing `cat(R.version.string)`.
+/// What R reports about its session: version, process ID, host name,
+/// working directory and active pixi/conda environment.
+#[derive(Debug, PartialEq, Eq)]
+struct RSession {
+    version: String,
+    pid: Option<u32>,
+    nodename: Option<String>,
+    working_directory: Option<String>,
+    environment: Option<String>,
+}
+
+impl RSession {
+    /// Parse the output of `SESSION_QUERY`.
+    fn parse(output: &str) -> Result<Self> {
+        let mut lines = output.lines().map(str::trim);
+        let version = lines.next().unwrap_or_default().to_string();
+        if version.is_empty() {
+            anyhow::bail!("Empty R version response");
+        }
+        let pid = lines.next().and_then(|pid| pid.parse().ok());
+        let nodename = lines.next().map(str::to_string);
+        let working_directory = lines
+            .next()
+            .filter(|working_directory| !working_directory.is_empty())
+            .map(str::to_string);
+        let pixi_project = lines.next().unwrap_or_default();
+        let pixi_environment = lines.next().unwrap_or_default();
+        let conda_environment = lines.next().unwrap_or_default();
+        Ok(Self {
+            version,
+            pid,
+            nodename,
+            working_directory,
+            environment: environment_label(pixi_project, pixi_environment, conda_environment),
+        })
+    }
+
+    /// Record the session in the console's state. Later working directory
+    /// changes arrive as `positron.ui` events and are tracked by the kernel
+    /// loop.
+    fn apply(self, connection_info: &ConnectionInfo, state: &mut SessionState) {
+        state.kernel_pid = self.local_pid(connection_info);
+        state.r_version = Some(self.version);
+        state.r_pid = self.pid;
+        state.working_directory = self.working_directory;
+        state.environment = self.environment;
+    }
+
+    /// The kernel's process ID, if it can be signalled from here.
+    ///
+    /// A loopback address alone doesn't prove the kernel is local: a
+    /// port-forwarded remote kernel listens on 127.0.0.1 too, and its pid
+    /// could name an unrelated local process. R must also run on a host
+    /// with this machine's name.
+    fn local_pid(&self, connection_info: &ConnectionInfo) -> Option<u32> {
+        let local_address = matches!(
+            connection_info.ip.as_str(),
+            "127.0.0.1" | "localhost" | "::1" | "0.0.0.0"
+        );
+        let hostname = gethostname::gethostname();
+        let same_host = self
+            .nodename
+            .as_deref()
+            .is_some_and(|nodename| is_same_host(nodename, &hostname.to_string_lossy()));
+        if !(local_address && same_host) {
+            debug!(
+                nodename = ?self.nodename,
+                hostname = ?hostname,
+                "Console: kernel may run on another host, not signalling its pid"
+            );
+            return None;
+        }
+        self.pid
+    }
+}
+
+/// Whether R's `nodename` names the machine with host name `hostname`.
+fn is_same_host(nodename: &str, hostname: &str) -> bool {
+    !nodename.is_empty() && nodename.eq_ignore_ascii_case(hostname)
+}
+
+/// Query the R version, the kernel's process, the host R runs on, and the
+/// working directory and environment shown in the prompt.
+async fn query_r_session(
+    connection_info: &ConnectionInfo,
+    session_id: &str,
+    iopub: &mut ClientIoPubConnection,
+) -> Result<RSession> {
+    debug!("Console: querying R session from kernel");
+
+    let output = query_kernel_stdout(connection_info, session_id, iopub, SESSION_QUERY).await?;
+    RSession::parse(&output)
+}
+
+/// Execute `code` in the kernel and collect what it writes to stdout.
 ///
-/// Uses the shared iopub connection and a temporary shell connection.
-async fn query_r_version(
+/// Uses the shared iopub connection and a temporary shell connection. The
+/// query is kept out of R's history and doesn't advance the execution
+/// count. It isn't sent `silent`, which would let the kernel drop the very
+/// stdout being collected; it is marked as a console query instead, so
+/// consoles following the session don't echo it.
+async fn query_kernel_stdout(
     connection_info: &ConnectionInfo,
     session_id: &str,
     iopub: &mut ClientIoPubConnection,
+    code: &str,
 ) -> Result<String> {
-    debug!("Console: querying R version from kernel");
-
     let mut shell = create_shell_connection(connection_info, session_id)
         .await
-        .context("Failed to connect shell for R version query")?;
+        .context("Failed to connect shell for kernel query")?;
 
-    // Execute cat(R.version.string) to get clean stdout output
-    let execute_request = ExecuteRequest::new("cat(R.version.string)".to_string());
-    let message = JupyterMessage::new(execute_request, None);
+    let message = JupyterMessage::new(console_query(code), None);
     let msg_id = message.header.msg_id.clone();
     shell
         .send(message)
         .await
-        .context("Failed to send R version execute request")?;
+        .context("Failed to send kernel query execute request")?;
 
-    let mut version_output = String::new();
+    let mut output = String::new();
     let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
 
     loop {
@@ -187,7 +436,7 @@ async fn query_r_version(
 
                 match &message.content {
                     JupyterMessageContent::StreamContent(stream) => {
-                        version_output.push_str(&stream.text);
+                        output.push_str(&stream.text);
                     }
                     JupyterMessageContent::Status(status) => {
                         if status.execution_state == ExecutionState::Idle {
@@ -203,35 +452,61 @@ async fn query_r_version(
                 if err_str.contains("comm_close") && err_str.contains("missing field `data`") {
                     continue;
                 }
-                debug!(error = ?err, "Console: iopub error during R version query");
+                debug!(error = ?err, "Console: iopub error during kernel query");
                 break;
             }
             Err(_) => {
-                debug!("Console: timeout waiting for R version");
+                debug!("Console: timeout waiting for kernel query output");
                 break;
             }
         }
     }
 
-    let version = version_output.trim().to_string();
-    if version.is_empty() {
-        anyhow::bail!("Empty R version response");
-    }
-    Ok(version)
+    Ok(output)
     // shell connection dropped here; iopub is borrowed, not owned
 }
 
+/// Open a `positron.help` comm so Ark forwards help requests to the console.
+async fn open_help_comm(connection_info: &ConnectionInfo, session_id: &str) -> Result<String> {
+    let mut shell = create_shell_connection(connection_info, session_id)
+        .await
+        .context("Failed to connect shell for help comm")?;
+    let comm_id = Uuid::new_v4().to_string();
+    send_help_comm_open(&mut shell, &comm_id).await?;
+    Ok(comm_id)
+    // shell connection dropped here; the comm stays open in the kernel
+}
+
 /// Initialize the LSP client by negotiating with Ark via the Jupyter comm protocol.
 ///
-/// Uses the shared iopub connection and a temporary shell connection for the comm
-/// handshake, then connects to the LSP TCP server and performs LSP initialization.
+/// Opens the LSP comm, then connects to the LSP TCP server and performs LSP
+/// initialization. Returns the client and the comm ID.
 async fn init_lsp(
     connection_info: &ConnectionInfo,
     session_id: &str,
     iopub: &mut ClientIoPubConnection,
-) -> Result<LspClient> {
+) -> Result<(LspClient, String)> {
+    debug!(ip = %connection_info.ip, "Console: initializing LSP client");
+    let (comm_id, port) = open_lsp_comm(connection_info, session_id, iopub).await?;
+
+    // Connect to LSP server and initialize
+    let client = LspClient::connect(&connection_info.ip, port).await?;
+    client.initialize().await?;
+
+    info!("Console: LSP client ready");
+    Ok((client, comm_id))
+}
+
+/// Ask Ark to start an LSP server and return the comm ID and the port.
+///
+/// Uses the shared iopub connection and a temporary shell connection for the comm
+/// handshake.
+async fn open_lsp_comm(
+    connection_info: &ConnectionInfo,
+    session_id: &str,
+    iopub: &mut ClientIoPubConnection,
+) -> Result<(String, u16)> {
     let ip_address = &connection_info.ip;
-    debug!(ip = %ip_address, "Console: initializing LSP client");
 
     // Create temporary shell connection for the comm handshake
     let mut shell = create_shell_connection(connection_info, session_id)
@@ -246,12 +521,127 @@ async fn init_lsp(
     // Wait for port from kernel
     let port = wait_for_comm_port(iopub, &comm_id, Duration::from_millis(10_000)).await?;
     info!(port = port, "Console: received LSP port");
+    Ok((comm_id, port))
+    // shell connection dropped here; iopub is borrowed, not owned
+}
 
-    // Connect to LSP server and initialize
-    let client = LspClient::connect(ip_address, port).await?;
-    client.initialize().await?;
+/// Bring the console up to date after it reattached to the kernel.
+///
+/// Queries the R version, process and working directory again,
+/// best-effort, and returns whether the kernel restarted.
+async fn refresh_after_reconnect(
+    connection_info: &ConnectionInfo,
+    session_id: &str,
+    iopub: &mut ClientIoPubConnection,
+    connection_changed: bool,
+    session_state: &SharedSessionState,
+) -> bool {
+    let previous_pid = lock_session_state(session_state).r_pid;
+    let pid = match query_r_session(connection_info, session_id, iopub).await {
+        Ok(session) => {
+            let pid = session.pid;
+            session.apply(connection_info, &mut lock_session_state(session_state));
+            pid
+        }
+        Err(err) => {
+            warn!(error = ?err, "Console: failed to query R session");
+            None
+        }
+    };
 
-    info!("Console: LSP client ready");
-    Ok(client)
-    // shell connection dropped here; iopub is borrowed, not owned
+    kernel_restarted(connection_changed, previous_pid, pid)
+}
+
+/// Whether the kernel the console reattached to is a new process.
+///
+/// A changed connection file or R process ID says so. When the process IDs
+/// can't be compared, the kernel is assumed to have restarted.
+fn kernel_restarted(connection_changed: bool, previous_pid: Option<u32>, pid: Option<u32>) -> bool {
+    connection_changed || previous_pid.is_none() || previous_pid != pid
+}
+
+/// Open the comms a restarted kernel needs: the help comm when `help_pages`
+/// is on, and an LSP comm whose new server the LSP client switches to.
+/// Each step is best-effort.
+async fn reopen_comms(
+    connection_info: &ConnectionInfo,
+    session_id: &str,
+    iopub: &mut ClientIoPubConnection,
+    help_pages: bool,
+    lsp_client: Option<&LspClient>,
+) -> ConsoleComms {
+    let help = if help_pages {
+        match open_help_comm(connection_info, session_id).await {
+            Ok(comm_id) => Some(comm_id),
+            Err(err) => {
+                warn!(error = ?err, "Console: failed to reopen help comm");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let Some(client) = lsp_client else {
+        return ConsoleComms { help, lsp: None };
+    };
+    let lsp = match open_lsp_comm(connection_info, session_id, iopub).await {
+        Ok((comm_id, port)) => {
+            match client.reconnect(&connection_info.ip, port).await {
+                Ok(_) => info!("Console: LSP client reconnected"),
+                Err(err) => warn!(error = ?err, "Console: failed to reconnect LSP"),
+            }
+            Some(comm_id)
+        }
+        Err(err) => {
+            warn!(error = ?err, "Console: failed to reopen LSP comm");
+            None
+        }
+    };
+    ConsoleComms { help, lsp }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_names_must_match() {
+        assert!(is_same_host("workstation", "workstation"));
+        assert!(is_same_host("Workstation", "workstation"));
+        assert!(!is_same_host("hpc-node-12", "workstation"));
+        assert!(!is_same_host("", ""));
+    }
+
+    #[test]
+    fn session_query_output_is_parsed() {
+        let session = RSession::parse(
+            "R version 4.4.1\n4242\nworkstation\n/home/user/analysis\nanalysis\ndev\n\n",
+        )
+        .unwrap();
+        assert_eq!(
+            session,
+            RSession {
+                version: "R version 4.4.1".to_string(),
+                pid: Some(4242),
+                nodename: Some("workstation".to_string()),
+                working_directory: Some("/home/user/analysis".to_string()),
+                environment: Some("analysis:dev".to_string()),
+            }
+        );
+
+        let session = RSession::parse("R version 4.4.1\n").unwrap();
+        assert_eq!(session.working_directory, None);
+        assert_eq!(session.environment, None);
+        assert!(RSession::parse("").is_err());
+    }
+
+    #[test]
+    fn kernel_restarts_when_its_process_or_connection_changes() {
+        assert!(!kernel_restarted(false, Some(42), Some(42)));
+        assert!(kernel_restarted(false, Some(42), Some(43)));
+        assert!(kernel_restarted(true, Some(42), Some(42)));
+        assert!(kernel_restarted(false, None, Some(42)));
+        assert!(kernel_restarted(false, Some(42), None));
+    }
 }
@@ -2,21 +2,86 @@
 //
 // Formats Jupyter iopub messages for terminal display using ANSI colors.
 
+use std::time::Duration;
+
+use base64::Engine;
 use nu_ansi_term::{Color, Style};
 use runtimelib::{media::MediaType, JupyterMessageContent};
-use tracing::debug;
+use tracing::{debug, warn};
+
+use super::html::html_output_to_terminal;
+use super::image::render_png;
+use super::markdown::markdown_to_terminal;
+use super::session_state::ExecutionRecord;
+use crate::types::ImageProtocol;
+
+/// How rich media in kernel output is drawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct OutputOptions {
+    /// Graphics protocol for PNG display data.
+    pub image_protocol: ImageProtocol,
+    /// Prefer HTML and markdown renderings over text/plain.
+    pub rich_output: bool,
+}
 
 /// UI event sent from kernel_loop to reedline_loop.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(crate) enum ConsoleUiEvent {
     /// A line of formatted output to print to stdout.
     Output(String),
-    /// Execution is complete (kernel returned to idle).
-    ExecutionDone,
+    /// Formatted error output of the awaited execution, printed even past
+    /// the output limit.
+    ErrorOutput(String),
+    /// A help page (HTML) to show in the pager.
+    Help(String),
+    /// A status message from the console, printed at once and not counted
+    /// as execution output.
+    Notice(String),
+    /// Code or output from another client's execution, in follow mode.
+    /// Printed at once and not counted as execution output.
+    Foreign(String),
+    /// Echoed code or output of code sent by the editor over the
+    /// injection socket. Printed at once and not counted as execution
+    /// output, since the prompt may be waiting on another execution.
+    Injected(String),
+    /// Ctrl+C was pressed again soon after an interrupt; ask whether to
+    /// wait, restart, or kill the kernel, which has been busy this long.
+    InterruptEscalation(Duration),
+    /// Execution is complete (kernel returned to idle), with its outcome.
+    /// `None` when it ended without one, e.g. the kernel restarted or the
+    /// connection was lost.
+    ExecutionDone(Option<ExecutionRecord>),
     /// The kernel is no longer reachable and the console should exit.
     KernelDisconnected(String),
 }
 
+/// Holds output back until its line is complete, so text printed above
+/// the prompt or among other output never splits a line.
+#[derive(Debug, Default)]
+pub(crate) struct LineBuffer {
+    /// Output after the last newline.
+    pending: String,
+}
+
+impl LineBuffer {
+    /// Add `text`, returning the lines it completes.
+    pub fn push(&mut self, text: &str) -> Option<String> {
+        self.pending.push_str(text);
+        let end = self.pending.rfind('\n')? + 1;
+        Some(self.pending.drain(..end).collect())
+    }
+
+    /// The unfinished line, ended with a newline, once no more output is
+    /// coming.
+    pub fn flush(&mut self) -> Option<String> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let line = std::mem::take(&mut self.pending);
+        Some(format!("{line}\n"))
+    }
+}
+
 const DISCONNECT_MESSAGE: &str = "Lost connection to the Ark kernel. Closing console.\n\
      The R session may still be running. Reconnect with:\n  \
      vscode-r-ark-sidecar console --connection-file <path>\n";
@@ -25,11 +90,32 @@ pub(crate) fn kernel_disconnect_message() -> String {
     DISCONNECT_MESSAGE.to_string()
 }
 
+/// Format a duration compactly: `850ms`, `4.2s`, `3m12s`, `1h05m`.
+pub(crate) fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        return format!("{millis}ms");
+    }
+    let secs = duration.as_secs();
+    if secs < 60 {
+        return format!("{:.1}s", duration.as_secs_f64());
+    }
+    if secs < 3600 {
+        return format!("{}m{:02}s", secs / 60, secs % 60);
+    }
+    format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+}
+
 /// Format an iopub message content for terminal display.
 ///
 /// Returns `Some(formatted_string)` for displayable messages,
-/// `None` for messages that should be silently ignored.
-pub(crate) fn format_iopub_content(content: &JupyterMessageContent) -> Option<String> {
+/// `None` for messages that should be silently ignored. PNG display data
+/// is drawn inline when the terminal has an image protocol, and HTML or
+/// markdown stand in for missing text/plain.
+pub(crate) fn format_iopub_content(
+    content: &JupyterMessageContent,
+    options: OutputOptions,
+) -> Option<String> {
     match content {
         JupyterMessageContent::StreamContent(stream) => {
             debug!(stream_name = ?stream.name, "Console: stream output");
@@ -40,11 +126,15 @@ pub(crate) fn format_iopub_content(content: &JupyterMessageContent) -> Option<St
         }
         JupyterMessageContent::ExecuteResult(result) => {
             debug!("Console: execute_result");
-            extract_text_plain(&result.data.content)
+            extract_text(&result.data.content, options)
         }
         JupyterMessageContent::DisplayData(display) => {
             debug!("Console: display_data");
-            let text = extract_text_plain(&display.data.content);
+            if let Some(image) = render_inline_image(&display.data.content, options.image_protocol)
+            {
+                return Some(image);
+            }
+            let text = extract_text(&display.data.content, options);
             if text.is_some() {
                 return text;
             }
@@ -83,6 +173,65 @@ fn extract_text_plain(content: &[MediaType]) -> Option<String> {
     None
 }
 
+/// Text for media content: text/plain, or rendered HTML or markdown when
+/// there is none or `rich_output` prefers them.
+fn extract_text(content: &[MediaType], options: OutputOptions) -> Option<String> {
+    if options.rich_output {
+        render_rich_text(content).or_else(|| extract_text_plain(content))
+    } else {
+        extract_text_plain(content).or_else(|| render_rich_text(content))
+    }
+}
+
+/// Render text/html, else text/markdown, fitted to the terminal width.
+fn render_rich_text(content: &[MediaType]) -> Option<String> {
+    let width = crossterm::terminal::size()
+        .map(|(cols, _)| cols as usize)
+        .unwrap_or(80);
+    let html = content.iter().find_map(|media| match media {
+        MediaType::Html(html) => Some(html_output_to_terminal(html, width, true)),
+        _ => None,
+    });
+    let lines = html.or_else(|| {
+        content.iter().find_map(|media| match media {
+            MediaType::Markdown(markdown) => Some(markdown_to_terminal(markdown, width, true)),
+            _ => None,
+        })
+    })?;
+    if lines.is_empty() {
+        return None;
+    }
+    debug!(lines = lines.len(), "Console: rendered rich output");
+    Some(lines.join("\n") + "\n")
+}
+
+/// Draw the PNG in media content as terminal graphics.
+fn render_inline_image(content: &[MediaType], image_protocol: ImageProtocol) -> Option<String> {
+    if matches!(image_protocol, ImageProtocol::Auto | ImageProtocol::None) {
+        return None;
+    }
+    let data = content.iter().find_map(|media| match media {
+        MediaType::Png(data) => Some(data),
+        _ => None,
+    })?;
+    // Base64 in display data may be wrapped
+    let data: String = data.split_whitespace().collect();
+    let png = match base64::engine::general_purpose::STANDARD.decode(data) {
+        Ok(png) => png,
+        Err(err) => {
+            warn!(error = ?err, "Console: invalid base64 in image/png output");
+            return None;
+        }
+    };
+    match render_png(&png, image_protocol) {
+        Ok(image) => Some(image),
+        Err(err) => {
+            warn!(error = ?err, "Console: failed to render image inline");
+            None
+        }
+    }
+}
+
 /// Check if media content contains an image type.
 fn has_image(content: &[MediaType]) -> bool {
     content.iter().any(|m| {
@@ -115,12 +264,32 @@ fn format_error(error: &runtimelib::ErrorOutput) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use runtimelib::{ExecuteResult, ExecutionCount, StreamContent};
+    use runtimelib::{DisplayData, ExecuteResult, ExecutionCount, StreamContent};
+
+    #[test]
+    fn format_duration_picks_unit() {
+        assert_eq!(format_duration(Duration::from_millis(850)), "850ms");
+        assert_eq!(format_duration(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(format_duration(Duration::from_secs(192)), "3m12s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h05m");
+    }
+
+    #[test]
+    fn line_buffer_holds_partial_lines() {
+        let mut lines = LineBuffer::default();
+        assert_eq!(lines.push("Iteration 1"), None);
+        assert_eq!(
+            lines.push(" done\nIteration 2").as_deref(),
+            Some("Iteration 1 done\n")
+        );
+        assert_eq!(lines.flush().as_deref(), Some("Iteration 2\n"));
+        assert_eq!(lines.flush(), None);
+    }
 
     #[test]
     fn format_stdout_stream() {
         let content = JupyterMessageContent::StreamContent(StreamContent::stdout("hello\n"));
-        let result = format_iopub_content(&content);
+        let result = format_iopub_content(&content, OutputOptions::default());
         assert_eq!(result, Some("hello\n".to_string()));
     }
 
@@ -131,7 +300,7 @@ mod tests {
             vec![MediaType::Plain("[1] 42".to_string())].into(),
         );
         let content = JupyterMessageContent::ExecuteResult(result);
-        let formatted = format_iopub_content(&content);
+        let formatted = format_iopub_content(&content, OutputOptions::default());
         assert_eq!(formatted, Some("[1] 42\n".to_string()));
     }
 
@@ -143,11 +312,66 @@ mod tests {
             traceback: vec!["line 1".to_string(), "line 2".to_string()],
         };
         let content = JupyterMessageContent::ErrorOutput(error);
-        let result = format_iopub_content(&content);
+        let result = format_iopub_content(&content, OutputOptions::default());
         assert!(result.is_some());
         // Contains the error text (with ANSI codes)
         let text = result.unwrap();
         assert!(text.contains("simpleError"));
         assert!(text.contains("oops"));
     }
+
+    #[test]
+    fn format_display_data_image() {
+        // A PNG signature and header are enough for the kitty protocol
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+        png.extend_from_slice(&[0, 0, 0, 64, 0, 0, 0, 32]);
+        let data = base64::engine::general_purpose::STANDARD.encode(&png);
+        let content =
+            JupyterMessageContent::DisplayData(DisplayData::new(vec![MediaType::Png(data)].into()));
+
+        let kitty = OutputOptions {
+            image_protocol: ImageProtocol::Kitty,
+            ..OutputOptions::default()
+        };
+        let image = format_iopub_content(&content, kitty).unwrap();
+        assert!(image.starts_with("\x1b_Ga=T,f=100"));
+        assert!(image.ends_with("\x1b\\\n"));
+
+        let placeholder = format_iopub_content(&content, OutputOptions::default()).unwrap();
+        assert!(placeholder.contains("[Image output not displayable in terminal]"));
+    }
+
+    #[test]
+    fn format_rich_output_without_text_plain() {
+        let table = "<table><tr><th>x</th></tr><tr><td>1</td></tr></table>";
+        let result = ExecuteResult::new(
+            ExecutionCount::new(1),
+            vec![MediaType::Html(table.to_string())].into(),
+        );
+        let content = JupyterMessageContent::ExecuteResult(result);
+        let formatted = format_iopub_content(&content, OutputOptions::default()).unwrap();
+        assert!(formatted.starts_with("┌───┐\n"));
+        assert!(formatted.contains("│ 1 │"));
+    }
+
+    #[test]
+    fn format_rich_output_when_preferred() {
+        let display = DisplayData::new(
+            vec![
+                MediaType::Plain("  x\n1 1".to_string()),
+                MediaType::Markdown("| x |\n|--:|\n| 1 |".to_string()),
+            ]
+            .into(),
+        );
+        let content = JupyterMessageContent::DisplayData(display);
+        let plain = format_iopub_content(&content, OutputOptions::default());
+        assert_eq!(plain, Some("  x\n1 1\n".to_string()));
+
+        let rich = OutputOptions {
+            rich_output: true,
+            ..OutputOptions::default()
+        };
+        let formatted = format_iopub_content(&content, rich).unwrap();
+        assert!(formatted.contains("│ 1 │"));
+    }
 }
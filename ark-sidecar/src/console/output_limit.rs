@@ -0,0 +1,191 @@
+// Output limits for console executions.
+//
+// Output is printed as it arrives until an execution passes the line
+// limit. The rest is held back: summarized with a `:more` hint, or shown
+// in the pager once the execution finishes. Errors are always printed, so
+// a failure at the end of a long print isn't hidden. The full output of
+// the last execution is kept so `:more` can page through it.
+
+use crate::types::LongOutput;
+
+/// Output of the current (or last) execution.
+#[derive(Debug)]
+pub(crate) struct ExecutionOutput {
+    mode: LongOutput,
+    max_lines: usize,
+    /// Everything the execution printed.
+    text: String,
+    /// Output past the limit that was not printed.
+    held_back: String,
+    printed_lines: usize,
+}
+
+impl ExecutionOutput {
+    pub fn new(mode: LongOutput, max_lines: usize) -> Self {
+        Self {
+            mode,
+            max_lines,
+            text: String::new(),
+            held_back: String::new(),
+            printed_lines: 0,
+        }
+    }
+
+    /// An empty output with the same limits.
+    pub fn fresh(&self) -> Self {
+        Self::new(self.mode, self.max_lines)
+    }
+
+    pub fn mode(&self) -> LongOutput {
+        self.mode
+    }
+
+    /// Start collecting a new execution's output.
+    pub fn reset(&mut self) {
+        self.text.clear();
+        self.held_back.clear();
+        self.printed_lines = 0;
+    }
+
+    /// Make `next` the last output, unless it would replace truncated
+    /// output with output that wasn't: `:more` then still shows what was
+    /// held back earlier in a block of expressions.
+    pub fn keep(&mut self, next: Self) {
+        if next.is_truncated() || !self.is_truncated() {
+            *self = next;
+        }
+    }
+
+    /// Record output, returning the part to print now.
+    pub fn push(&mut self, text: &str) -> &str {
+        let start = self.text.len();
+        self.text.push_str(text);
+        if !self.held_back.is_empty() {
+            // Already over the limit
+            self.held_back.push_str(text);
+            return "";
+        }
+        if self.mode == LongOutput::Print {
+            return &self.text[start..];
+        }
+        let mut shown = 0;
+        for piece in text.split_inclusive('\n') {
+            if self.printed_lines >= self.max_lines {
+                break;
+            }
+            shown += piece.len();
+            if piece.ends_with('\n') {
+                self.printed_lines += 1;
+            }
+        }
+        self.held_back.push_str(&text[shown..]);
+        &self.text[start..start + shown]
+    }
+
+    /// Record error output, returning it to print in full: errors neither
+    /// count against the limit nor are held back.
+    pub fn push_error(&mut self, text: &str) -> &str {
+        let start = self.text.len();
+        self.text.push_str(text);
+        &self.text[start..]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Whether some output was held back.
+    pub fn is_truncated(&self) -> bool {
+        !self.held_back.is_empty()
+    }
+
+    /// Hint printed after truncated output.
+    pub fn more_hint(&self) -> String {
+        let hidden = self.held_back.lines().count();
+        let noun = if hidden == 1 { "line" } else { "lines" };
+        format!("… {hidden} more {noun}, :more to view")
+    }
+
+    /// The full output, split into lines for the pager.
+    pub fn lines(&self) -> Vec<String> {
+        self.text.lines().map(str::to_string).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_within_limit_is_printed() {
+        let mut output = ExecutionOutput::new(LongOutput::Truncate, 3);
+        assert_eq!(output.push("a\nb\n"), "a\nb\n");
+        assert_eq!(output.push("c\n"), "c\n");
+        assert!(!output.is_truncated());
+    }
+
+    #[test]
+    fn output_past_limit_is_held_back() {
+        let mut output = ExecutionOutput::new(LongOutput::Truncate, 2);
+        assert_eq!(output.push("a\nb"), "a\nb");
+        assert_eq!(output.push("c\nd\ne\n"), "c\n");
+        assert_eq!(output.push("f\n"), "");
+        assert!(output.is_truncated());
+        assert_eq!(output.more_hint(), "… 3 more lines, :more to view");
+        assert_eq!(output.lines(), vec!["a", "bc", "d", "e", "f"]);
+
+        output.reset();
+        assert!(output.is_empty());
+        assert_eq!(output.push("g\n"), "g\n");
+    }
+
+    #[test]
+    fn truncated_output_is_kept_over_later_output() {
+        let mut output = ExecutionOutput::new(LongOutput::Truncate, 1);
+        let mut first = output.fresh();
+        first.push("a\nb\n");
+        output.keep(first);
+        let mut second = output.fresh();
+        second.push("c\n");
+        output.keep(second);
+        assert_eq!(output.lines(), vec!["a", "b"]);
+
+        let mut third = output.fresh();
+        third.push("d\ne\nf\n");
+        output.keep(third);
+        assert_eq!(output.more_hint(), "… 2 more lines, :more to view");
+        assert_eq!(output.lines(), vec!["d", "e", "f"]);
+    }
+
+    #[test]
+    fn print_mode_keeps_everything() {
+        let mut output = ExecutionOutput::new(LongOutput::Print, 1);
+        assert_eq!(output.push("a\nb\nc"), "a\nb\nc");
+        assert!(!output.is_truncated());
+        assert_eq!(output.lines().len(), 3);
+    }
+
+    #[test]
+    fn errors_are_printed_past_the_limit() {
+        let mut output = ExecutionOutput::new(LongOutput::Truncate, 1);
+        assert_eq!(output.push("a\nb\nc\n"), "a\n");
+        assert_eq!(
+            output.push_error("Error in f(): oops\n"),
+            "Error in f(): oops\n"
+        );
+        assert_eq!(output.push("d\n"), "");
+        assert_eq!(output.more_hint(), "… 3 more lines, :more to view");
+        assert_eq!(
+            output.lines(),
+            vec!["a", "b", "c", "Error in f(): oops", "d"]
+        );
+    }
+
+    #[test]
+    fn partial_lines_are_finished_before_holding_back() {
+        let mut output = ExecutionOutput::new(LongOutput::Page, 1);
+        assert_eq!(output.push("a"), "a");
+        assert_eq!(output.push("b\nc"), "b\n");
+        assert_eq!(output.more_hint(), "… 1 more line, :more to view");
+    }
+}
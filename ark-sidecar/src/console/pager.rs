@@ -0,0 +1,257 @@
+// Built-in pager for console mode.
+//
+// Shows pre-rendered lines (help pages) on the alternate screen with
+// less-style keys; `q` returns to the prompt. Text that fits in the
+// terminal is printed directly instead.
+
+use std::io::{self, IsTerminal, Write};
+
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{
+    self, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use tracing::debug;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PagerCommand {
+    Quit,
+    LineDown,
+    LineUp,
+    PageDown,
+    PageUp,
+    Top,
+    Bottom,
+}
+
+fn command_for_key(key: &KeyEvent) -> Option<PagerCommand> {
+    if key.kind == KeyEventKind::Release {
+        return None;
+    }
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('c') => Some(PagerCommand::Quit),
+            KeyCode::Char('f') => Some(PagerCommand::PageDown),
+            KeyCode::Char('b') => Some(PagerCommand::PageUp),
+            _ => None,
+        };
+    }
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc => Some(PagerCommand::Quit),
+        KeyCode::Char('j') | KeyCode::Down | KeyCode::Enter => Some(PagerCommand::LineDown),
+        KeyCode::Char('k') | KeyCode::Up => Some(PagerCommand::LineUp),
+        KeyCode::Char(' ') | KeyCode::Char('f') | KeyCode::PageDown => Some(PagerCommand::PageDown),
+        KeyCode::Char('b') | KeyCode::PageUp => Some(PagerCommand::PageUp),
+        KeyCode::Char('g') | KeyCode::Home => Some(PagerCommand::Top),
+        KeyCode::Char('G') | KeyCode::End => Some(PagerCommand::Bottom),
+        _ => None,
+    }
+}
+
+/// Scroll position over `total` lines shown `height` at a time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PagerView {
+    top: usize,
+    height: usize,
+    total: usize,
+}
+
+impl PagerView {
+    fn new(height: usize, total: usize) -> Self {
+        Self {
+            top: 0,
+            height: height.max(1),
+            total,
+        }
+    }
+
+    fn max_top(&self) -> usize {
+        self.total.saturating_sub(self.height)
+    }
+
+    fn apply(&mut self, command: PagerCommand) {
+        self.top = match command {
+            PagerCommand::Quit => self.top,
+            PagerCommand::LineDown => self.top + 1,
+            PagerCommand::LineUp => self.top.saturating_sub(1),
+            PagerCommand::PageDown => self.top + self.height,
+            PagerCommand::PageUp => self.top.saturating_sub(self.height),
+            PagerCommand::Top => 0,
+            PagerCommand::Bottom => self.max_top(),
+        }
+        .min(self.max_top());
+    }
+
+    fn resize(&mut self, height: usize) {
+        self.height = height.max(1);
+        self.top = self.top.min(self.max_top());
+    }
+
+    fn status(&self, title: &str) -> String {
+        let last = (self.top + self.height).min(self.total);
+        let position = if self.top >= self.max_top() {
+            "(END)".to_string()
+        } else {
+            format!("{}%", last * 100 / self.total.max(1))
+        };
+        format!(
+            " {title}  lines {}-{last}/{}  {position}  q: quit  space/b: page  j/k: line ",
+            self.top + 1,
+            self.total
+        )
+    }
+}
+
+/// Restores the terminal when the pager exits, including on error.
+struct ScreenGuard;
+
+impl ScreenGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = ScreenGuard;
+        execute!(io::stdout(), EnterAlternateScreen, DisableLineWrap, Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for ScreenGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, EnableLineWrap, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(out: &mut impl Write, lines: &[String], view: &PagerView, title: &str) -> Result<()> {
+    queue!(out, Clear(ClearType::All))?;
+    for (row, line) in lines[view.top..].iter().take(view.height).enumerate() {
+        queue!(
+            out,
+            MoveTo(0, row as u16),
+            Print(line),
+            SetAttribute(Attribute::Reset)
+        )?;
+    }
+    queue!(
+        out,
+        MoveTo(0, view.height as u16),
+        SetAttribute(Attribute::Reverse),
+        Print(view.status(title)),
+        SetAttribute(Attribute::Reset)
+    )?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Show `lines` in the pager until the user quits.
+///
+/// Falls back to printing when the lines fit on screen or stdout is not a
+/// terminal.
+pub(crate) fn page_lines(lines: &[String], title: &str) -> Result<()> {
+    let rows = terminal::size()
+        .ok()
+        .filter(|_| io::stdout().is_terminal())
+        .map(|(_, rows)| rows as usize)
+        .filter(|&rows| lines.len() >= rows);
+    let Some(rows) = rows else {
+        for line in lines {
+            println!("{line}");
+        }
+        return Ok(());
+    };
+
+    debug!(lines = lines.len(), rows, "Console pager: opening");
+    let _guard = ScreenGuard::enter()?;
+    let mut stdout = io::stdout();
+    // The last row holds the status line.
+    let mut view = PagerView::new(rows.saturating_sub(1), lines.len());
+    draw(&mut stdout, lines, &view, title)?;
+
+    loop {
+        match event::read()? {
+            Event::Key(key) => match command_for_key(&key) {
+                Some(PagerCommand::Quit) => break,
+                Some(command) => view.apply(command),
+                None => continue,
+            },
+            Event::Resize(_, rows) => view.resize((rows as usize).saturating_sub(1)),
+            _ => continue,
+        }
+        draw(&mut stdout, lines, &view, title)?;
+    }
+
+    debug!("Console pager: closed");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn keys_map_to_commands() {
+        assert_eq!(
+            command_for_key(&key(KeyCode::Char('q'))),
+            Some(PagerCommand::Quit)
+        );
+        assert_eq!(
+            command_for_key(&key(KeyCode::Esc)),
+            Some(PagerCommand::Quit)
+        );
+        assert_eq!(
+            command_for_key(&key(KeyCode::Char(' '))),
+            Some(PagerCommand::PageDown)
+        );
+        assert_eq!(
+            command_for_key(&key(KeyCode::Char('G'))),
+            Some(PagerCommand::Bottom)
+        );
+        assert_eq!(
+            command_for_key(&KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(PagerCommand::Quit)
+        );
+        assert_eq!(command_for_key(&key(KeyCode::Char('x'))), None);
+    }
+
+    #[test]
+    fn view_scrolls_within_bounds() {
+        let mut view = PagerView::new(10, 25);
+        view.apply(PagerCommand::LineUp);
+        assert_eq!(view.top, 0);
+        view.apply(PagerCommand::PageDown);
+        assert_eq!(view.top, 10);
+        view.apply(PagerCommand::PageDown);
+        assert_eq!(view.top, 15);
+        view.apply(PagerCommand::LineDown);
+        assert_eq!(view.top, 15);
+        view.apply(PagerCommand::PageUp);
+        assert_eq!(view.top, 5);
+        view.apply(PagerCommand::Bottom);
+        assert_eq!(view.top, 15);
+        view.apply(PagerCommand::Top);
+        assert_eq!(view.top, 0);
+    }
+
+    #[test]
+    fn resize_keeps_view_in_bounds() {
+        let mut view = PagerView::new(10, 25);
+        view.apply(PagerCommand::Bottom);
+        view.resize(20);
+        assert_eq!(view.top, 5);
+    }
+
+    #[test]
+    fn status_shows_position() {
+        let mut view = PagerView::new(10, 40);
+        assert!(view.status("mean").contains("lines 1-10/40  25%"));
+        view.apply(PagerCommand::Bottom);
+        assert!(view.status("mean").contains("lines 31-40/40  (END)"));
+    }
+}
@@ -0,0 +1,215 @@
+// Multi-expression input for console mode.
+//
+// A pasted block (or any submission spanning several lines) is run the way
+// R's own console runs it: split into top-level expressions, each echoed
+// with `> ` and `+ ` prompts and executed in turn, so output follows the
+// code that produced it and an error stops the rest of the block.
+// Expressions sharing a line, like `x <- 1; y <- 2`, run together, and
+// comments go with the expression that follows them.
+
+use std::io::{self, Write};
+
+use crossterm::cursor::{MoveToColumn, MoveUp};
+use crossterm::execute;
+use crossterm::terminal::{Clear, ClearType};
+use reedline::{Prompt, PromptEditMode, PromptViMode};
+use unicode_width::UnicodeWidthStr;
+
+use super::highlighter::highlight_lines;
+use super::r_parser::parse_r;
+use super::transcript::strip_ansi;
+use crate::types::ConsoleEditMode;
+
+/// Split `input` into the line ranges of its top-level expressions.
+///
+/// Returns `None` unless there are at least two, or when the input does
+/// not parse; R then sees the input as one execution and reports the
+/// syntax error itself.
+pub(crate) fn split_expressions(input: &str) -> Option<Vec<String>> {
+    let tree = parse_r(input)?;
+    let root = tree.root_node();
+    if root.has_error() {
+        return None;
+    }
+
+    // Last row of each group of expressions that share lines
+    let mut group_ends: Vec<usize> = Vec::new();
+    let mut cursor = root.walk();
+    for child in root.named_children(&mut cursor) {
+        if child.kind() == "comment" {
+            continue;
+        }
+        let start = child.start_position().row;
+        let end = child.end_position().row;
+        match group_ends.last_mut() {
+            Some(last) if start <= *last => *last = (*last).max(end),
+            _ => group_ends.push(end),
+        }
+    }
+    if group_ends.len() < 2 {
+        return None;
+    }
+
+    let lines: Vec<&str> = input.split('\n').collect();
+    if let Some(last) = group_ends.last_mut() {
+        // Trailing comments go with the last expression
+        *last = lines.len() - 1;
+    }
+    let mut start = 0;
+    let expressions = group_ends
+        .into_iter()
+        .map(|end| {
+            let expression = trim_blank_lines(&lines[start..=end]);
+            start = end + 1;
+            expression
+        })
+        .collect();
+    Some(expressions)
+}
+
+fn trim_blank_lines(lines: &[&str]) -> String {
+    let is_blank = |line: &&str| line.trim().is_empty();
+    let first = lines.iter().position(|line| !is_blank(line));
+    let last = lines.iter().rposition(|line| !is_blank(line));
+    match first.zip(last) {
+        Some((first, last)) => lines[first..=last].join("\n"),
+        None => String::new(),
+    }
+}
+
+/// Echo code the way R's console shows it, behind `> ` and `+ ` prompts,
+/// with syntax highlighting if `highlight` is set.
+pub(crate) fn echo_with_prompts(code: &str, highlight: bool) -> String {
+    let code = code.trim_end();
+    let lines = if highlight {
+        highlight_lines(code)
+    } else {
+        code.lines().map(str::to_string).collect()
+    };
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| {
+            let prompt = if index == 0 { "> " } else { "+ " };
+            format!("{prompt}{line}\n")
+        })
+        .collect()
+}
+
+/// Terminal rows taken by `text` at `columns` wide, counting wrapped lines
+/// and wide characters. `None` when control characters such as tabs make
+/// the width unknowable.
+fn painted_rows(text: &str, columns: usize) -> Option<usize> {
+    if text.chars().any(|ch| ch.is_control() && ch != '\n') {
+        return None;
+    }
+    let columns = columns.max(1);
+    Some(
+        text.split('\n')
+            .map(|line| line.width().div_ceil(columns).max(1))
+            .sum(),
+    )
+}
+
+/// The submitted input as reedline painted it: the prompt, then each line
+/// after the first behind the multi-line indicator.
+fn painted_input(prompt: &dyn Prompt, edit_mode: ConsoleEditMode, input: &str) -> String {
+    // Enter submits from vi insert mode
+    let edit_mode = match edit_mode {
+        ConsoleEditMode::Emacs => PromptEditMode::Emacs,
+        ConsoleEditMode::Vi => PromptEditMode::Vi(PromptViMode::Insert),
+    };
+    let continuation = strip_ansi(&prompt.render_prompt_multiline_indicator());
+    let mut painted = strip_ansi(&prompt.render_prompt_left());
+    painted.push_str(&strip_ansi(&prompt.render_prompt_indicator(edit_mode)));
+    for (index, line) in input.split('\n').enumerate() {
+        if index > 0 {
+            painted.push('\n');
+            painted.push_str(&continuation);
+        }
+        painted.push_str(line);
+    }
+    painted
+}
+
+/// Clear the submitted input from the screen so it can be echoed one
+/// expression at a time. Reedline leaves the cursor on the line below it.
+/// The right prompt is only drawn where it fits, so it adds no rows. When
+/// the rows can't be counted the input is left on screen: echoing it again
+/// beats erasing output above it.
+pub(crate) fn erase_submitted_input(
+    prompt: &dyn Prompt,
+    edit_mode: ConsoleEditMode,
+    input: &str,
+) -> io::Result<()> {
+    let columns = crossterm::terminal::size()
+        .map(|(cols, _)| cols as usize)
+        .unwrap_or(80);
+    let Some(rows) = painted_rows(&painted_input(prompt, edit_mode, input), columns) else {
+        return Ok(());
+    };
+    let mut stdout = io::stdout();
+    execute!(
+        stdout,
+        MoveUp(rows.min(u16::MAX as usize) as u16),
+        MoveToColumn(0),
+        Clear(ClearType::FromCursorDown)
+    )?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn block_splits_into_top_level_expressions() {
+        let input = "# setup\nx <- 1\n\nf <- function(y) {\n  y + x\n}\nf(2); f(3)\n# done\n";
+        assert_eq!(
+            split_expressions(input).unwrap(),
+            vec![
+                "# setup\nx <- 1",
+                "f <- function(y) {\n  y + x\n}",
+                "f(2); f(3)\n# done",
+            ]
+        );
+    }
+
+    #[test]
+    fn single_expressions_are_not_split() {
+        assert_eq!(split_expressions("x <- 1"), None);
+        assert_eq!(split_expressions("x <- 1; y <- 2"), None);
+        assert_eq!(split_expressions("mtcars |>\n  head()\n"), None);
+        assert_eq!(split_expressions("x <- (\ny <- 2"), None);
+    }
+
+    #[test]
+    fn expressions_are_echoed_with_r_prompts() {
+        assert_eq!(
+            echo_with_prompts("f <- function(y) {\n  y\n}\n", false),
+            "> f <- function(y) {\n+   y\n+ }\n"
+        );
+    }
+
+    #[test]
+    fn wrapped_lines_count_as_several_rows() {
+        assert_eq!(painted_rows("R〉x <- 1\n+ y", 80), Some(2));
+        assert_eq!(painted_rows(&"x".repeat(81), 80), Some(2));
+        assert_eq!(painted_rows(&"x".repeat(80), 80), Some(1));
+        assert_eq!(painted_rows("", 80), Some(1));
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        // `〉` and CJK characters are two columns wide
+        assert_eq!(
+            painted_rows(&format!("〉{}", "\u{4e2d}".repeat(39)), 80),
+            Some(1)
+        );
+        assert_eq!(
+            painted_rows(&format!("〉{}", "\u{4e2d}".repeat(40)), 80),
+            Some(2)
+        );
+        assert_eq!(painted_rows("〉x <- 1\tx", 80), None);
+    }
+}
@@ -0,0 +1,205 @@
+// File path completion inside R string literals.
+//
+// When the cursor sits in a string such as `read.csv("data/`, the typed
+// text is completed against the filesystem. Relative paths resolve against
+// the kernel's working directory, which follows `setwd()` through the
+// session state; `~` expands to the home directory.
+
+use std::path::{Path, PathBuf};
+
+use reedline::{Span, Suggestion};
+
+use super::r_parser::parse_r;
+
+/// Listing a huge directory shouldn't flood the menu.
+const MAX_PATH_SUGGESTIONS: usize = 200;
+
+/// Expand a leading `~` (alone or followed by `/`) to the home directory.
+pub(crate) fn expand_tilde(path: &str) -> String {
+    match (path.strip_prefix('~'), dirs::home_dir()) {
+        (Some(rest), Some(home)) if rest.is_empty() || rest.starts_with('/') => {
+            format!("{}{rest}", home.display())
+        }
+        _ => path.to_string(),
+    }
+}
+
+/// Byte offset where the content of the string literal around `pos` starts.
+///
+/// The string may still be unterminated, so the line up to the cursor is
+/// closed with each quote in turn; the cursor is inside a string when the
+/// added quote closes a string node that opened before it. Raw strings are
+/// left alone.
+pub(crate) fn string_content_start(line: &str, pos: usize) -> Option<usize> {
+    let before = line.get(..pos)?;
+    ['"', '\''].into_iter().find_map(|quote| {
+        let source = format!("{before}{quote}");
+        let tree = parse_r(&source)?;
+        let end = source.len();
+        let mut node = tree.root_node().descendant_for_byte_range(end - 1, end)?;
+        while node.kind() != "string" {
+            node = node.parent()?;
+        }
+        let start = node.start_byte();
+        (node.end_byte() == end && start < pos && source[start..].starts_with(quote))
+            .then_some(start + 1)
+    })
+}
+
+/// Directory listed for the directory part of a typed path.
+fn resolve_directory(directory: &str, working_directory: Option<&str>) -> PathBuf {
+    let expanded = expand_tilde(directory);
+    let path = Path::new(&expanded);
+    if path.is_absolute() {
+        return path.to_path_buf();
+    }
+    match working_directory {
+        Some(working_directory) => Path::new(&expand_tilde(working_directory)).join(path),
+        None => path.to_path_buf(),
+    }
+}
+
+/// Filesystem entries completing `typed`, replacing it within `span`.
+///
+/// Only the last path component is matched, by prefix. Hidden entries are
+/// offered once a `.` is typed, and directories end with `/` so completion
+/// can carry on into them.
+pub(crate) fn path_suggestions(
+    typed: &str,
+    span: Span,
+    working_directory: Option<&str>,
+) -> Vec<Suggestion> {
+    if typed == "~" {
+        return vec![path_suggestion("~/".to_string(), None, span)];
+    }
+    let (directory, prefix) = match typed.rfind('/') {
+        Some(index) => typed.split_at(index + 1),
+        None => ("", typed),
+    };
+    let Ok(entries) = std::fs::read_dir(resolve_directory(directory, working_directory)) else {
+        return vec![];
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Follows symlinks, so a link to a directory completes like one
+            Some(if entry.path().is_dir() {
+                format!("{name}/")
+            } else {
+                name
+            })
+        })
+        .collect();
+    names.sort();
+    names.truncate(MAX_PATH_SUGGESTIONS);
+
+    names
+        .into_iter()
+        .map(|name| {
+            // Show just the entry; the value keeps the typed directory
+            let display = (!directory.is_empty()).then(|| name.clone());
+            path_suggestion(format!("{directory}{name}"), display, span)
+        })
+        .collect()
+}
+
+fn path_suggestion(value: String, display_override: Option<String>, span: Span) -> Suggestion {
+    Suggestion {
+        value,
+        display_override,
+        description: None,
+        style: None,
+        extra: None,
+        span,
+        append_whitespace: false,
+        match_indices: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::test_dir::TestDir;
+
+    fn values(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions
+            .into_iter()
+            .map(|suggestion| suggestion.value)
+            .collect()
+    }
+
+    fn test_directory(name: &str) -> TestDir {
+        let directory = TestDir::new(name);
+        std::fs::create_dir_all(directory.join("data/raw")).unwrap();
+        std::fs::write(directory.join("data/iris.csv"), "").unwrap();
+        std::fs::write(directory.join("data/.hidden"), "").unwrap();
+        std::fs::write(directory.join("analysis.R"), "").unwrap();
+        directory
+    }
+
+    #[test]
+    fn detects_cursor_inside_strings() {
+        assert_eq!(string_content_start("read.csv(\"data/", 15), Some(10));
+        assert_eq!(string_content_start("read.csv('da", 12), Some(10));
+        assert_eq!(
+            string_content_start("read.csv(\"data/x.csv\")", 12),
+            Some(10)
+        );
+        assert_eq!(string_content_start("x <- \"a\"; y", 11), None);
+        assert_eq!(string_content_start("mean(x", 6), None);
+        assert_eq!(string_content_start("# \"data/", 8), None);
+    }
+
+    #[test]
+    fn completes_relative_to_working_directory() {
+        let directory = test_directory("relative");
+        let working_directory = directory.path().to_str();
+        let span = Span::new(10, 15);
+
+        assert_eq!(
+            values(path_suggestions("data/", span, working_directory)),
+            vec!["data/iris.csv", "data/raw/"]
+        );
+        assert_eq!(
+            values(path_suggestions("da", span, working_directory)),
+            vec!["data/"]
+        );
+        assert_eq!(
+            values(path_suggestions("data/.h", span, working_directory)),
+            vec!["data/.hidden"]
+        );
+
+        let suggestions = path_suggestions("data/r", span, working_directory);
+        assert_eq!(suggestions[0].display_override.as_deref(), Some("raw/"));
+        assert_eq!(suggestions[0].span, span);
+        assert!(!suggestions[0].append_whitespace);
+    }
+
+    #[test]
+    fn completes_absolute_paths() {
+        let directory = test_directory("absolute");
+        let typed = format!("{}/an", directory.path().display());
+        assert_eq!(
+            values(path_suggestions(&typed, Span::new(0, typed.len()), None)),
+            vec![format!("{}/analysis.R", directory.path().display())]
+        );
+    }
+
+    #[test]
+    fn expands_home_directory() {
+        assert_eq!(expand_tilde("/data"), "/data");
+        assert_eq!(expand_tilde("~other/data"), "~other/data");
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_tilde("~/data"), format!("{}/data", home.display()));
+        }
+        assert_eq!(
+            values(path_suggestions("~", Span::new(0, 1), None)),
+            vec!["~/"]
+        );
+    }
+}
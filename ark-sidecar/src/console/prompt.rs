@@ -0,0 +1,321 @@
+// Console prompt.
+//
+// Renders a configurable left prompt with kernel state segments (working
+// directory, pixi/conda environment), a right prompt with the duration and
+// status of the last execution, an R-style `+ ` continuation prompt, and an
+// edit-mode indicator so vi users can tell insert and normal mode apart.
+// While the kernel is unreachable the prompt starts with `(disconnected)`.
+
+use std::borrow::Cow;
+
+use reedline::{
+    Color, Prompt, PromptEditMode, PromptHistorySearch, PromptHistorySearchStatus, PromptViMode,
+};
+
+use super::output::format_duration;
+use super::session_state::{lock_session_state, SessionState, SharedSessionState};
+
+/// Default left prompt template.
+pub(crate) const DEFAULT_PROMPT_TEMPLATE: &str = "{env} {cwd} R";
+
+const EMACS_INDICATOR: &str = "〉";
+const VI_INSERT_INDICATOR: &str = " [I]〉";
+const VI_NORMAL_INDICATOR: &str = " [N]〉";
+const MULTILINE_INDICATOR: &str = "+ ";
+const DISCONNECTED_LABEL: &str = "(disconnected)";
+
+/// The R console prompt.
+pub(crate) struct RPrompt {
+    template: String,
+    show_right_prompt: bool,
+    state: SharedSessionState,
+}
+
+impl RPrompt {
+    pub fn new(template: &str, show_right_prompt: bool, state: SharedSessionState) -> Self {
+        Self {
+            template: template.to_string(),
+            show_right_prompt,
+            state,
+        }
+    }
+}
+
+/// Expand the `{cwd}` and `{env}` placeholders of a prompt template.
+///
+/// Unknown segments render as empty and take the whitespace gap around them
+/// along, so `"{env} {cwd} R"` becomes just `"R"` before the kernel state is
+/// known. Whitespace in the template and in the values is kept as is.
+fn render_left_prompt(template: &str, state: &SessionState) -> String {
+    let cwd = state
+        .working_directory
+        .as_deref()
+        .map(shorten_home)
+        .unwrap_or_default();
+    let env = state
+        .environment
+        .as_deref()
+        .map(|env| format!("({env})"))
+        .unwrap_or_default();
+
+    let mut rendered = template.to_string();
+    for (placeholder, value) in [("{env}", &env), ("{cwd}", &cwd)] {
+        if value.is_empty() {
+            rendered = drop_placeholder(&rendered, placeholder);
+        }
+    }
+    let rendered = rendered.replace("{env}", &env).replace("{cwd}", &cwd);
+
+    match (state.disconnected, rendered.is_empty()) {
+        (false, _) => rendered,
+        (true, true) => DISCONNECTED_LABEL.to_string(),
+        (true, false) => format!("{DISCONNECTED_LABEL} {rendered}"),
+    }
+}
+
+/// Remove every `placeholder` from `template` together with the whitespace
+/// around it, keeping a single gap between the text on either side.
+fn drop_placeholder(template: &str, placeholder: &str) -> String {
+    let mut template = template.to_string();
+    while let Some(start) = template.find(placeholder) {
+        let end = start + placeholder.len();
+        let before = template[..start].trim_end();
+        let after = template[end..].trim_start();
+        let gap = if before.is_empty() || after.is_empty() {
+            ""
+        } else if before.len() < start {
+            &template[before.len()..start]
+        } else {
+            &template[end..template.len() - after.len()]
+        };
+        template = format!("{before}{gap}{after}");
+    }
+    template
+}
+
+/// Replace the local home directory prefix with `~`.
+fn shorten_home(path: &str) -> String {
+    let Some(home) = dirs::home_dir() else {
+        return path.to_string();
+    };
+    let home = home.to_string_lossy();
+    match path.strip_prefix(home.as_ref()) {
+        Some("") => "~".to_string(),
+        Some(rest) if rest.starts_with('/') => format!("~{rest}"),
+        _ => path.to_string(),
+    }
+}
+
+fn render_right_prompt(state: &SessionState) -> String {
+    match state.last_execution {
+        Some(record) if record.failed => format!("✗ {}", format_duration(record.duration)),
+        Some(record) => format_duration(record.duration),
+        None => String::new(),
+    }
+}
+
+impl Prompt for RPrompt {
+    fn render_prompt_left(&self) -> Cow<'_, str> {
+        let state = lock_session_state(&self.state);
+        Cow::Owned(render_left_prompt(&self.template, &state))
+    }
+
+    fn render_prompt_right(&self) -> Cow<'_, str> {
+        if !self.show_right_prompt {
+            return Cow::Borrowed("");
+        }
+        let state = lock_session_state(&self.state);
+        Cow::Owned(render_right_prompt(&state))
+    }
+
+    fn render_prompt_indicator(&self, prompt_mode: PromptEditMode) -> Cow<'_, str> {
+        match prompt_mode {
+            PromptEditMode::Default | PromptEditMode::Emacs => Cow::Borrowed(EMACS_INDICATOR),
+            PromptEditMode::Vi(PromptViMode::Insert) => Cow::Borrowed(VI_INSERT_INDICATOR),
+            PromptEditMode::Vi(PromptViMode::Normal) => Cow::Borrowed(VI_NORMAL_INDICATOR),
+            PromptEditMode::Custom(mode) => Cow::Owned(format!(" ({mode})〉")),
+        }
+    }
+
+    fn render_prompt_multiline_indicator(&self) -> Cow<'_, str> {
+        Cow::Borrowed(MULTILINE_INDICATOR)
+    }
+
+    fn render_prompt_history_search_indicator(
+        &self,
+        history_search: PromptHistorySearch,
+    ) -> Cow<'_, str> {
+        let prefix = match history_search.status {
+            PromptHistorySearchStatus::Passing => "",
+            PromptHistorySearchStatus::Failing => "failing ",
+        };
+        Cow::Owned(format!(
+            "({}reverse-search: {}) ",
+            prefix, history_search.term
+        ))
+    }
+
+    fn get_prompt_right_color(&self) -> Color {
+        let state = lock_session_state(&self.state);
+        match state.last_execution {
+            Some(record) if record.failed => Color::Red,
+            _ => Color::DarkGrey,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::session_state::ExecutionRecord;
+    use std::time::Duration;
+
+    fn prompt_with(state: SessionState) -> RPrompt {
+        RPrompt::new(DEFAULT_PROMPT_TEMPLATE, true, state.shared())
+    }
+
+    #[test]
+    fn prompt_uses_r_with_default_indicator() {
+        let prompt = prompt_with(SessionState::default());
+        assert_eq!(prompt.render_prompt_left(), "R");
+        assert_eq!(prompt.render_prompt_right(), "");
+        assert_eq!(
+            prompt.render_prompt_indicator(PromptEditMode::Default),
+            "〉"
+        );
+        assert_eq!(prompt.render_prompt_indicator(PromptEditMode::Emacs), "〉");
+    }
+
+    #[test]
+    fn prompt_shows_vi_mode() {
+        let prompt = prompt_with(SessionState::default());
+        assert_eq!(
+            prompt.render_prompt_indicator(PromptEditMode::Vi(PromptViMode::Insert)),
+            " [I]〉"
+        );
+        assert_eq!(
+            prompt.render_prompt_indicator(PromptEditMode::Vi(PromptViMode::Normal)),
+            " [N]〉"
+        );
+    }
+
+    #[test]
+    fn multiline_indicator_is_r_continuation() {
+        let prompt = prompt_with(SessionState::default());
+        assert_eq!(prompt.render_prompt_multiline_indicator(), "+ ");
+    }
+
+    #[test]
+    fn left_prompt_shows_environment_and_directory() {
+        let prompt = prompt_with(SessionState {
+            working_directory: Some("/srv/analysis".to_string()),
+            environment: Some("analysis:dev".to_string()),
+            ..SessionState::default()
+        });
+        assert_eq!(
+            prompt.render_prompt_left(),
+            "(analysis:dev) /srv/analysis R"
+        );
+    }
+
+    #[test]
+    fn left_prompt_template_is_configurable() {
+        let state = SessionState {
+            working_directory: Some("/srv/analysis".to_string()),
+            ..SessionState::default()
+        };
+        let prompt = RPrompt::new("[{cwd}]", true, state.shared());
+        assert_eq!(prompt.render_prompt_left(), "[/srv/analysis]");
+    }
+
+    #[test]
+    fn left_prompt_keeps_whitespace_in_the_directory() {
+        let prompt = prompt_with(SessionState {
+            working_directory: Some("/srv/my  analysis".to_string()),
+            ..SessionState::default()
+        });
+        assert_eq!(prompt.render_prompt_left(), "/srv/my  analysis R");
+    }
+
+    #[test]
+    fn empty_segments_take_their_gap_along() {
+        let state = SessionState {
+            environment: Some("dev".to_string()),
+            ..SessionState::default()
+        };
+        let prompt = RPrompt::new("{env}  {cwd}  R", true, state.shared());
+        assert_eq!(prompt.render_prompt_left(), "(dev)  R");
+
+        let prompt = RPrompt::new("[{cwd}] {env}", true, state.shared());
+        assert_eq!(prompt.render_prompt_left(), "[] (dev)");
+    }
+
+    #[test]
+    fn left_prompt_marks_a_lost_kernel() {
+        let prompt = prompt_with(SessionState {
+            working_directory: Some("/srv/analysis".to_string()),
+            disconnected: true,
+            ..SessionState::default()
+        });
+        assert_eq!(
+            prompt.render_prompt_left(),
+            "(disconnected) /srv/analysis R"
+        );
+    }
+
+    #[test]
+    fn home_directory_is_shortened() {
+        if let Some(home) = dirs::home_dir() {
+            let path = home.join("project");
+            assert_eq!(shorten_home(&path.to_string_lossy()), "~/project");
+            assert_eq!(shorten_home(&home.to_string_lossy()), "~");
+        }
+        assert_eq!(shorten_home("~/already"), "~/already");
+    }
+
+    #[test]
+    fn right_prompt_shows_last_execution() {
+        let prompt = prompt_with(SessionState {
+            last_execution: Some(ExecutionRecord {
+                duration: Duration::from_millis(4200),
+                failed: false,
+            }),
+            ..SessionState::default()
+        });
+        assert_eq!(prompt.render_prompt_right(), "4.2s");
+        assert_eq!(prompt.get_prompt_right_color(), Color::DarkGrey);
+
+        let prompt = prompt_with(SessionState {
+            last_execution: Some(ExecutionRecord {
+                duration: Duration::from_millis(120),
+                failed: true,
+            }),
+            ..SessionState::default()
+        });
+        assert_eq!(prompt.render_prompt_right(), "✗ 120ms");
+        assert_eq!(prompt.get_prompt_right_color(), Color::Red);
+    }
+
+    #[test]
+    fn right_prompt_can_be_disabled() {
+        let state = SessionState {
+            last_execution: Some(ExecutionRecord {
+                duration: Duration::from_secs(1),
+                failed: false,
+            }),
+            ..SessionState::default()
+        };
+        let prompt = RPrompt::new(DEFAULT_PROMPT_TEMPLATE, false, state.shared());
+        assert_eq!(prompt.render_prompt_right(), "");
+    }
+
+    #[test]
+    fn history_search_indicator_reports_failure() {
+        let prompt = prompt_with(SessionState::default());
+        let search = PromptHistorySearch::new(PromptHistorySearchStatus::Failing, "lm".to_string());
+        assert_eq!(
+            prompt.render_prompt_history_search_indicator(search),
+            "(failing reverse-search: lm) "
+        );
+    }
+}
@@ -8,29 +8,61 @@ use std::io::Write;
 use std::panic::{catch_unwind, panic_any, resume_unwind, AssertUnwindSafe};
 use std::sync::mpsc as std_mpsc;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use nu_ansi_term::Color;
 use once_cell::sync::Lazy;
 use reedline::{
-    default_emacs_keybindings, ColumnarMenu, DefaultPrompt, DefaultPromptSegment, Emacs,
-    ExternalPrinter, KeyCode, KeyModifiers, MenuBuilder, Reedline, ReedlineEvent, ReedlineMenu,
-    Signal, TraversalDirection,
+    default_emacs_keybindings, default_vi_insert_keybindings, default_vi_normal_keybindings,
+    DescriptionMode, EditMode, Emacs, ExternalPrinter, IdeMenu, KeyCode, KeyModifiers,
+    Keybindings, MenuBuilder, Reedline, ReedlineEvent, ReedlineMenu, SearchDirection, SearchQuery,
+    Signal, Vi,
 };
 use tracing::{debug, error, info};
 
-use super::completer::LspCompleter;
+use super::busy::{notify, resolve_notification, BusyIndicator, BusyOptions, SPINNER_INTERVAL};
+use super::completer::{ConsoleCompleter, LspCompleter};
+use super::completion_menu::{description_width, CompletionDocs, CompletionMenu};
+use super::config::ConsoleConfig;
 use super::highlighter::RHighlighter;
-use super::history::create_history;
+use super::hinter::ConsoleHinter;
+use super::history::{create_history, session_project};
+use super::html::{html_title, html_to_terminal};
+use super::indent::{AutoIndent, SharedBufferView};
 use super::kernel_loop::ConsoleRequest;
-use super::output::{kernel_disconnect_message, ConsoleUiEvent};
+use super::meta_command::{meta_command_help, parse_meta_command, MetaCommand};
+use super::output::{format_duration, kernel_disconnect_message, ConsoleUiEvent};
+use super::output_limit::ExecutionOutput;
+use super::pager::page_lines;
+use super::paste::{echo_with_prompts, erase_submitted_input, split_expressions};
+use super::prompt::RPrompt;
 use super::r_parser::parse_r;
+use super::session_state::{lock_session_state, ExecutionRecord, SharedSessionState};
+use super::signature_help::SignatureHelpSource;
+use super::transcript::{format_for_path, transcript_path};
 use super::validator::RValidator;
+use crate::logging::LogReloadHandle;
 use crate::lsp_client::virtual_document::DebouncedVirtualDocument;
 use crate::lsp_client::LspClient;
+use crate::types::{ConsoleEditMode, LongOutput, TranscriptFormat};
 
 type SharedUiReceiver = Arc<Mutex<std_mpsc::Receiver<ConsoleUiEvent>>>;
 static DISCONNECT_PANIC_HOOK_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
 
+/// Help pages are wrapped to the terminal width, up to this many columns.
+const MAX_HELP_WIDTH: usize = 100;
+
+/// Lines of documentation shown beside the completion menu.
+const MAX_DESCRIPTION_HEIGHT: u16 = 12;
+
+/// Channels connecting the reedline loop to the kernel loop.
+pub(crate) struct ReedlineLoopChannels {
+    /// Execute/exit and meta-command requests to the kernel loop.
+    pub request_tx: tokio::sync::mpsc::Sender<ConsoleRequest>,
+    /// Real-time execution output and disconnect events from the kernel loop.
+    pub ui_event_rx: std_mpsc::Receiver<ConsoleUiEvent>,
+}
+
 #[derive(Debug)]
 struct DisconnectExitSentinel {
     message: String,
@@ -45,16 +77,130 @@ enum IdleUiAction {
 #[derive(Debug, PartialEq, Eq)]
 enum ExecutionUiAction {
     Print(String),
-    ExecutionDone,
+    PrintError(String),
+    Notice(String),
+    Foreign(String),
+    Escalate(Duration),
+    ShowHelp(String),
+    ExecutionDone(Option<ExecutionRecord>),
     Exit(String),
 }
 
-/// The R console prompt.
-fn make_prompt() -> DefaultPrompt {
-    DefaultPrompt::new(
-        DefaultPromptSegment::Basic("R".to_string()),
-        DefaultPromptSegment::Empty,
-    )
+/// Bind Tab to open the completion menu, or cycle it when already open.
+fn add_completion_keybindings(keybindings: &mut Keybindings) {
+    keybindings.add_binding(
+        KeyModifiers::NONE,
+        KeyCode::Tab,
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu("completion_menu".to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+    );
+}
+
+/// Build the line editing mode selected in the console config.
+///
+/// In vi mode, completion is bound in the insert keymap only; normal
+/// mode keeps reedline's default vi bindings.
+fn build_edit_mode(edit_mode: ConsoleEditMode) -> Box<dyn EditMode> {
+    match edit_mode {
+        ConsoleEditMode::Emacs => {
+            let mut keybindings = default_emacs_keybindings();
+            add_completion_keybindings(&mut keybindings);
+            Box::new(Emacs::new(keybindings))
+        }
+        ConsoleEditMode::Vi => {
+            let mut insert_keybindings = default_vi_insert_keybindings();
+            add_completion_keybindings(&mut insert_keybindings);
+            // Emacs binds Alt+F to accept a word of the history hint;
+            // Right-arrow accepts the whole hint in both keymaps.
+            insert_keybindings.add_binding(
+                KeyModifiers::ALT,
+                KeyCode::Char('f'),
+                ReedlineEvent::HistoryHintWordComplete,
+            );
+            Box::new(Vi::new(insert_keybindings, default_vi_normal_keybindings()))
+        }
+    }
+}
+
+/// Build the reedline editor with highlighter, validator, edit mode,
+/// completer, completion menu, and hinter. Multi-line input is auto-indented
+/// unless the indent width is 0, and pastes are bracketed so pasted code
+/// keeps its own indentation. History suggestions are shown unless turned
+/// off in the config. With an LSP client, completion covers R code with a
+/// documentation panel, and signature help and diagnostic hint lines are
+/// shown; without one, only meta-commands and file paths complete.
+fn build_editor(
+    config: &ConsoleConfig,
+    virtual_document: Option<Arc<DebouncedVirtualDocument>>,
+    runtime_handle: tokio::runtime::Handle,
+    session_state: SharedSessionState,
+) -> Reedline {
+    let docs = virtual_document
+        .as_ref()
+        .map(|document| CompletionDocs::new(Arc::clone(document), runtime_handle.clone()));
+    let terminal_columns = crossterm::terminal::size()
+        .map(|(cols, _)| cols)
+        .unwrap_or(80);
+    let buffer_view = SharedBufferView::default();
+    let completion_menu = Box::new(
+        CompletionMenu::new(
+            IdeMenu::default()
+                .with_name("completion_menu")
+                .with_default_border()
+                .with_description_mode(DescriptionMode::PreferRight)
+                .with_max_description_width(description_width(terminal_columns))
+                .with_max_description_height(MAX_DESCRIPTION_HEIGHT),
+            docs.clone(),
+        )
+        .with_buffer_view(Arc::clone(&buffer_view)),
+    );
+    let lsp_completer = virtual_document.as_ref().zip(docs).map(|(document, docs)| {
+        LspCompleter::new(
+            Arc::clone(document),
+            runtime_handle,
+            config.completion_matching,
+            docs,
+        )
+    });
+    let mut edit_mode = build_edit_mode(config.edit_mode);
+    if config.indent_width > 0 {
+        edit_mode = Box::new(AutoIndent::new(
+            edit_mode,
+            Arc::clone(&buffer_view),
+            config.indent_width,
+        ));
+    }
+    let mut hinter = ConsoleHinter::default().with_buffer_view(Arc::clone(&buffer_view));
+    if config.history_hints {
+        hinter = hinter.with_history_suggestions(Arc::clone(&session_state));
+    }
+    let editor = Reedline::create()
+        .with_highlighter(Box::new(
+            RHighlighter::new(virtual_document.clone()).with_buffer_view(buffer_view),
+        ))
+        .with_validator(Box::new(RValidator))
+        .with_edit_mode(edit_mode)
+        .use_bracketed_paste(true)
+        .with_completer(Box::new(ConsoleCompleter::new(
+            lsp_completer,
+            session_state,
+        )))
+        .with_menu(ReedlineMenu::EngineCompleter(completion_menu));
+
+    let Some(virtual_document) = virtual_document else {
+        info!(
+            "Console reedline_loop: no LSP client, completion limited to meta-commands and paths"
+        );
+        return editor.with_hinter(Box::new(hinter));
+    };
+
+    info!("Console reedline_loop: LSP completion enabled");
+    let hinter = hinter
+        .with_diagnostics(virtual_document.diagnostics())
+        .with_signature_help(SignatureHelpSource::new(virtual_document));
+    editor.with_hinter(Box::new(hinter))
 }
 
 /// Print the startup banner with R version and binary path info.
@@ -149,6 +295,17 @@ fn is_quit_call(input: &str) -> bool {
     }
 }
 
+/// The R code a submission runs: the input itself, or the expression a
+/// meta-command executes, such as `:time`'s. `None` for other
+/// meta-commands.
+fn submitted_code<'a>(input: &'a str, meta_request: Option<&'a ConsoleRequest>) -> Option<&'a str> {
+    match meta_request {
+        None => Some(input),
+        Some(ConsoleRequest::Execute(code)) => Some(code.trim()),
+        Some(_) => None,
+    }
+}
+
 /// Show red warning and ask for y/N confirmation when q()/quit() is detected.
 /// Returns true if user confirms (wants to quit R session).
 fn confirm_quit() -> bool {
@@ -167,6 +324,307 @@ fn confirm_quit() -> bool {
     }
 }
 
+/// What to do about an execution that ignores interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InterruptChoice {
+    Wait,
+    Restart,
+    Kill,
+}
+
+/// Parse the answer to the escalation prompt; anything else waits.
+fn parse_interrupt_choice(answer: &str, can_kill: bool) -> InterruptChoice {
+    match answer.trim().to_ascii_lowercase().as_str() {
+        "r" | "restart" => InterruptChoice::Restart,
+        "k" | "kill" if can_kill => InterruptChoice::Kill,
+        _ => InterruptChoice::Wait,
+    }
+}
+
+/// Ask whether to keep waiting for an execution that ignored an interrupt,
+/// restart the kernel, or kill its process (when it runs on this machine).
+fn ask_interrupt_escalation(busy_for: Duration, can_kill: bool) -> InterruptChoice {
+    let choices = if can_kill {
+        "[w]ait, [r]estart the kernel, or [k]ill its process? (W/r/k): "
+    } else {
+        "[w]ait or [r]estart the kernel? (W/r): "
+    };
+    let question = Color::Yellow.paint(format!(
+        "\nR has been busy for {} and has not responded to the interrupt.\n{choices}",
+        format_duration(busy_for)
+    ));
+    print!("{}", question);
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(_) => parse_interrupt_choice(&answer, can_kill),
+        Err(_) => InterruptChoice::Wait,
+    }
+}
+
+/// How an execution the console waited for ended.
+enum ExecutionEnd {
+    /// Back to the prompt, with the outcome if the execution completed.
+    Done(Option<ExecutionRecord>),
+    /// The console exits.
+    Exit,
+    /// The kernel loop is gone; it was asked to exit.
+    Closed,
+}
+
+/// Wait for the next execution event, spinning meanwhile.
+fn next_execution_event(
+    shared_ui_rx: &SharedUiReceiver,
+    busy: &mut BusyIndicator,
+) -> Option<ConsoleUiEvent> {
+    if !busy.is_enabled() {
+        return recv_ui_event(shared_ui_rx).ok();
+    }
+    loop {
+        match recv_ui_event_timeout(shared_ui_rx, SPINNER_INTERVAL) {
+            Ok(event) => {
+                busy.clear();
+                return Some(event);
+            }
+            Err(std_mpsc::RecvTimeoutError::Timeout) => busy.tick(),
+            Err(std_mpsc::RecvTimeoutError::Disconnected) => {
+                busy.clear();
+                return None;
+            }
+        }
+    }
+}
+
+/// Print an execution's output until it completes, holding back anything
+/// past the output limit. Long executions end with their duration and a
+/// terminal notification.
+fn await_execution(
+    shared_ui_rx: &SharedUiReceiver,
+    request_tx: &tokio::sync::mpsc::Sender<ConsoleRequest>,
+    output: &mut ExecutionOutput,
+    timed: bool,
+    session_state: &SharedSessionState,
+    busy_options: BusyOptions,
+) -> ExecutionEnd {
+    let mut busy = BusyIndicator::start(busy_options.spinner);
+    let mut help_page = None;
+    loop {
+        match next_execution_event(shared_ui_rx, &mut busy) {
+            Some(event) => match classify_execution_event(event) {
+                ExecutionUiAction::Print(text) => {
+                    let text = output.push(&text);
+                    busy.printed(text);
+                    print!("{}", text);
+                    let _ = std::io::stdout().flush();
+                }
+                ExecutionUiAction::PrintError(text) => {
+                    let text = output.push_error(&text);
+                    busy.printed(text);
+                    print!("{}", text);
+                    let _ = std::io::stdout().flush();
+                }
+                ExecutionUiAction::Notice(text) => {
+                    print_dimmed_message(&text);
+                    busy.printed("\n");
+                }
+                ExecutionUiAction::Foreign(text) => {
+                    busy.printed(&text);
+                    print!("{}", text);
+                    let _ = std::io::stdout().flush();
+                }
+                ExecutionUiAction::Escalate(busy_for) => {
+                    let can_kill = lock_session_state(session_state).kernel_pid.is_some();
+                    let request = match ask_interrupt_escalation(busy_for, can_kill) {
+                        InterruptChoice::Wait => {
+                            print_meta_output("Waiting for R. Press Ctrl+C to interrupt again.");
+                            continue;
+                        }
+                        InterruptChoice::Restart => ConsoleRequest::Restart,
+                        InterruptChoice::Kill => ConsoleRequest::KillKernel,
+                    };
+                    if request_tx.blocking_send(request).is_err() {
+                        debug!("Console reedline_loop: request channel closed, exiting");
+                        return ExecutionEnd::Exit;
+                    }
+                }
+                // Shown once the execution's own output is complete
+                ExecutionUiAction::ShowHelp(html) => help_page = Some(html),
+                ExecutionUiAction::ExecutionDone(record) => {
+                    let elapsed = busy.elapsed();
+                    if output.is_truncated() {
+                        if output.mode() == LongOutput::Page {
+                            page_output(output);
+                        }
+                        print_meta_output(&output.more_hint());
+                    }
+                    if let Some(html) = help_page.take() {
+                        show_help_page(&html);
+                    }
+                    if timed {
+                        print_elapsed(record);
+                    }
+                    if busy_options
+                        .notify_after
+                        .is_some_and(|threshold| elapsed >= threshold)
+                    {
+                        let message = format!("finished in {}", format_duration(elapsed));
+                        if !timed {
+                            print_meta_output(&message);
+                        }
+                        notify(busy_options.notification, &format!("R {message}"));
+                    }
+                    return ExecutionEnd::Done(record);
+                }
+                ExecutionUiAction::Exit(message) => {
+                    print_dimmed_message(&message);
+                    return ExecutionEnd::Exit;
+                }
+            },
+            None => {
+                debug!("Console reedline_loop: ui event channel closed");
+                let _ = request_tx.blocking_send(ConsoleRequest::Exit);
+                return ExecutionEnd::Closed;
+            }
+        }
+    }
+}
+
+/// Send a request to the kernel loop and wait for it to finish. Only code
+/// executions replace the output `:more` shows; meta-command replies are
+/// printed in full and not kept.
+fn run_request(
+    request: ConsoleRequest,
+    request_tx: &tokio::sync::mpsc::Sender<ConsoleRequest>,
+    shared_ui_rx: &SharedUiReceiver,
+    output: &mut ExecutionOutput,
+    timed: bool,
+    session_state: &SharedSessionState,
+    busy_options: BusyOptions,
+) -> ExecutionEnd {
+    let mut reply = ExecutionOutput::new(LongOutput::Print, 0);
+    let target = if matches!(request, ConsoleRequest::Execute(_)) {
+        output.reset();
+        output
+    } else {
+        &mut reply
+    };
+    if request_tx.blocking_send(request).is_err() {
+        debug!("Console reedline_loop: request channel closed, exiting");
+        return ExecutionEnd::Exit;
+    }
+    await_execution(
+        shared_ui_rx,
+        request_tx,
+        target,
+        timed,
+        session_state,
+        busy_options,
+    )
+}
+
+/// Run `line` and end the R session, printing its remaining output.
+fn execute_and_exit(
+    line: String,
+    request_tx: &tokio::sync::mpsc::Sender<ConsoleRequest>,
+    shared_ui_rx: &SharedUiReceiver,
+) {
+    debug!("Console reedline_loop: user confirmed q(), sending execute-and-exit");
+    if request_tx
+        .blocking_send(ConsoleRequest::ExecuteAndExit(line))
+        .is_err()
+    {
+        debug!("Console reedline_loop: request channel closed, exiting");
+        return;
+    }
+
+    // Drain remaining output before exiting
+    loop {
+        match recv_ui_event(shared_ui_rx) {
+            Ok(event) => match classify_execution_event(event) {
+                ExecutionUiAction::Print(text)
+                | ExecutionUiAction::PrintError(text)
+                | ExecutionUiAction::Foreign(text) => print!("{}", text),
+                ExecutionUiAction::Notice(text) => print_meta_output(&text),
+                ExecutionUiAction::Escalate(_) | ExecutionUiAction::ShowHelp(_) => {}
+                ExecutionUiAction::ExecutionDone(_) => break,
+                ExecutionUiAction::Exit(message) => {
+                    print_dimmed_message(&message);
+                    break;
+                }
+            },
+            Err(_) => {
+                debug!("Console reedline_loop: ui event channel closed during quit drain");
+                break;
+            }
+        }
+    }
+}
+
+/// Run top-level expressions in turn, each echoed with R's prompts. An
+/// error skips the rest, as in R's console, and so does an execution that
+/// never completed, e.g. because the kernel restarted.
+fn run_expressions(
+    expressions: &[String],
+    request_tx: &tokio::sync::mpsc::Sender<ConsoleRequest>,
+    shared_ui_rx: &SharedUiReceiver,
+    output: &mut ExecutionOutput,
+    session_state: &SharedSessionState,
+    busy_options: BusyOptions,
+) -> ExecutionEnd {
+    output.reset();
+    let mut last = None;
+    for (index, expression) in expressions.iter().enumerate() {
+        print!("{}", echo_with_prompts(expression, false));
+        let _ = std::io::stdout().flush();
+        let remaining = expressions.len() - index - 1;
+
+        if is_quit_call(expression) {
+            if confirm_quit() {
+                execute_and_exit(expression.clone(), request_tx, shared_ui_rx);
+                return ExecutionEnd::Exit;
+            }
+            print_skipped(remaining);
+            return ExecutionEnd::Done(last);
+        }
+
+        if request_tx
+            .blocking_send(ConsoleRequest::Execute(expression.clone()))
+            .is_err()
+        {
+            debug!("Console reedline_loop: request channel closed, exiting");
+            return ExecutionEnd::Exit;
+        }
+        let mut current = output.fresh();
+        let end = await_execution(
+            shared_ui_rx,
+            request_tx,
+            &mut current,
+            false,
+            session_state,
+            busy_options,
+        );
+        output.keep(current);
+        match end {
+            ExecutionEnd::Done(Some(record)) if !record.failed => last = Some(record),
+            ExecutionEnd::Done(record) => {
+                print_skipped(remaining);
+                return ExecutionEnd::Done(record);
+            }
+            end => return end,
+        }
+    }
+    ExecutionEnd::Done(last)
+}
+
+fn print_skipped(remaining: usize) {
+    match remaining {
+        0 => {}
+        1 => print_meta_output("Skipped the remaining expression"),
+        _ => print_meta_output(&format!("Skipped the remaining {remaining} expressions")),
+    }
+}
+
 fn print_dimmed_message(message: &str) {
     println!();
     print!("{}", Color::DarkGray.paint(message));
@@ -179,6 +637,16 @@ fn recv_ui_event(shared_ui_rx: &SharedUiReceiver) -> Result<ConsoleUiEvent, std_
     shared_ui_rx.lock().expect("ui event receiver mutex poisoned").recv()
 }
 
+fn recv_ui_event_timeout(
+    shared_ui_rx: &SharedUiReceiver,
+    timeout: Duration,
+) -> Result<ConsoleUiEvent, std_mpsc::RecvTimeoutError> {
+    shared_ui_rx
+        .lock()
+        .expect("ui event receiver mutex poisoned")
+        .recv_timeout(timeout)
+}
+
 fn try_recv_ui_event(
     shared_ui_rx: &SharedUiReceiver,
 ) -> Result<ConsoleUiEvent, std_mpsc::TryRecvError> {
@@ -188,7 +656,15 @@ fn try_recv_ui_event(
 fn classify_execution_event(event: ConsoleUiEvent) -> ExecutionUiAction {
     match event {
         ConsoleUiEvent::Output(text) => ExecutionUiAction::Print(text),
-        ConsoleUiEvent::ExecutionDone => ExecutionUiAction::ExecutionDone,
+        ConsoleUiEvent::ErrorOutput(text) => ExecutionUiAction::PrintError(text),
+        ConsoleUiEvent::Notice(text) => ExecutionUiAction::Notice(text),
+        // Both come from executions the prompt is not waiting on
+        ConsoleUiEvent::Foreign(text) | ConsoleUiEvent::Injected(text) => {
+            ExecutionUiAction::Foreign(text)
+        }
+        ConsoleUiEvent::InterruptEscalation(busy_for) => ExecutionUiAction::Escalate(busy_for),
+        ConsoleUiEvent::Help(html) => ExecutionUiAction::ShowHelp(html),
+        ConsoleUiEvent::ExecutionDone(record) => ExecutionUiAction::ExecutionDone(record),
         ConsoleUiEvent::KernelDisconnected(message) => ExecutionUiAction::Exit(message),
     }
 }
@@ -198,8 +674,22 @@ fn drain_idle_actions(shared_ui_rx: &SharedUiReceiver) -> Vec<IdleUiAction> {
 
     loop {
         match try_recv_ui_event(shared_ui_rx) {
-            Ok(ConsoleUiEvent::Output(text)) => actions.push(IdleUiAction::Print(text)),
-            Ok(ConsoleUiEvent::ExecutionDone) => {}
+            Ok(ConsoleUiEvent::Output(text) | ConsoleUiEvent::ErrorOutput(text)) => {
+                actions.push(IdleUiAction::Print(text))
+            }
+            Ok(ConsoleUiEvent::Notice(text)) => {
+                actions.push(IdleUiAction::Print(Color::DarkGray.paint(text).to_string()))
+            }
+            // The external printer ends each message with a line break
+            Ok(ConsoleUiEvent::Foreign(text) | ConsoleUiEvent::Injected(text)) => actions.extend(
+                text.lines()
+                    .map(|line| IdleUiAction::Print(line.to_string())),
+            ),
+            // The pager needs the terminal; while reedline owns it, print instead.
+            Ok(ConsoleUiEvent::Help(html)) => {
+                actions.push(IdleUiAction::Print(render_help_page(&html).join("\n")))
+            }
+            Ok(ConsoleUiEvent::ExecutionDone(_) | ConsoleUiEvent::InterruptEscalation(_)) => {}
             Ok(ConsoleUiEvent::KernelDisconnected(message)) => {
                 debug!("Console idle: received KernelDisconnected, triggering immediate exit");
                 actions.push(IdleUiAction::Exit(message));
@@ -217,16 +707,140 @@ fn drain_idle_actions(shared_ui_rx: &SharedUiReceiver) -> Vec<IdleUiAction> {
     actions
 }
 
+fn render_help_page(html: &str) -> Vec<String> {
+    let width = crossterm::terminal::size()
+        .map(|(cols, _)| cols as usize)
+        .unwrap_or(80)
+        .min(MAX_HELP_WIDTH);
+    html_to_terminal(html, width, true)
+}
+
+fn show_help_page(html: &str) {
+    let lines = render_help_page(html);
+    let title = html_title(html).unwrap_or_else(|| "Help".to_string());
+    if let Err(err) = page_lines(&lines, &title) {
+        error!(error = ?err, "Console reedline_loop: pager failed");
+    }
+}
+
+/// Show an execution's full output in the pager.
+fn page_output(output: &ExecutionOutput) {
+    if let Err(err) = page_lines(&output.lines(), "Output") {
+        error!(error = ?err, "Console reedline_loop: pager failed");
+    }
+}
+
+fn print_meta_output(message: &str) {
+    println!("{}", Color::DarkGray.paint(message.trim_end()));
+}
+
+/// Print how long an execution took, for `:time`.
+fn print_elapsed(record: Option<ExecutionRecord>) {
+    if let Some(record) = record {
+        print_meta_output(&format!("Elapsed: {}", format_duration(record.duration)));
+    }
+}
+
+/// Format history entries for `:history`, numbered oldest first.
+fn format_history_entries(commands: &[String]) -> String {
+    let width = commands.len().to_string().len();
+    commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| {
+            let indent = " ".repeat(width + 2);
+            let command = command.replace('\n', &format!("\n{indent}"));
+            format!("{:>width$}  {command}\n", index + 1)
+        })
+        .collect()
+}
+
+fn lsp_status(lsp_client: Option<&LspClient>) -> String {
+    match lsp_client {
+        None => "LSP: not available (completion limited to meta-commands)".to_string(),
+        Some(client) if client.is_connected() => format!(
+            "LSP: connected (document version {})",
+            client.document_version()
+        ),
+        Some(_) => "LSP: connection closed".to_string(),
+    }
+}
+
+/// Run a meta-command that the console handles itself, or return the
+/// request that carries it out in the kernel loop.
+fn run_meta_command(
+    command: MetaCommand,
+    editor: &Reedline,
+    lsp_client: Option<&LspClient>,
+    log_handle: &LogReloadHandle,
+    last_output: &ExecutionOutput,
+    session_state: &SharedSessionState,
+    transcript_format: Option<TranscriptFormat>,
+) -> Option<ConsoleRequest> {
+    match command {
+        MetaCommand::Interrupt => Some(ConsoleRequest::Interrupt),
+        MetaCommand::Restart => Some(ConsoleRequest::Restart),
+        MetaCommand::Reconnect => Some(ConsoleRequest::Reconnect),
+        MetaCommand::Time(expr) => Some(ConsoleRequest::Execute(expr)),
+        MetaCommand::TranscriptStart(file) => {
+            let working_directory = lock_session_state(session_state).working_directory.clone();
+            let path = transcript_path(file.as_deref(), working_directory.as_deref());
+            let format = transcript_format.unwrap_or_else(|| format_for_path(&path));
+            Some(ConsoleRequest::StartTranscript(path, format))
+        }
+        MetaCommand::TranscriptStop => Some(ConsoleRequest::StopTranscript),
+        MetaCommand::Follow(enabled) => Some(ConsoleRequest::Follow(enabled)),
+        MetaCommand::History(count) => {
+            let mut query = SearchQuery::everything(SearchDirection::Backward, None);
+            query.limit = Some(count as i64);
+            match editor.history().search(query) {
+                Ok(items) => {
+                    let commands: Vec<String> = items
+                        .into_iter()
+                        .rev()
+                        .map(|item| item.command_line)
+                        .collect();
+                    print!("{}", format_history_entries(&commands));
+                }
+                Err(err) => print_meta_output(&format!("Failed to read history: {err}")),
+            }
+            None
+        }
+        MetaCommand::More => {
+            if last_output.is_empty() {
+                print_meta_output("The last execution printed no output");
+            } else {
+                page_output(last_output);
+            }
+            None
+        }
+        MetaCommand::LogLevel(level) => {
+            log_handle.reload_with_level(&level);
+            print_meta_output(&format!("Log level set to {level}"));
+            None
+        }
+        MetaCommand::LspStatus => {
+            print_meta_output(&lsp_status(lsp_client));
+            None
+        }
+        MetaCommand::Help => {
+            print!("{}", meta_command_help());
+            None
+        }
+    }
+}
+
 /// Run the blocking reedline loop.
 ///
 /// This function should be called inside `tokio::task::spawn_blocking`.
 pub(crate) fn run_reedline_loop(
-    request_tx: tokio::sync::mpsc::Sender<ConsoleRequest>,
-    ui_event_rx: std_mpsc::Receiver<ConsoleUiEvent>,
+    channels: ReedlineLoopChannels,
     lsp_client: Option<Arc<LspClient>>,
     runtime_handle: tokio::runtime::Handle,
-    r_version: Option<String>,
     r_binary_path: Option<String>,
+    config: ConsoleConfig,
+    session_state: SharedSessionState,
+    log_handle: LogReloadHandle,
 ) {
     let _panic_hook_lock = DISCONNECT_PANIC_HOOK_LOCK.lock().expect("panic hook mutex poisoned");
     let previous_hook = Arc::new(std::panic::take_hook());
@@ -240,12 +854,13 @@ pub(crate) fn run_reedline_loop(
 
     let result = catch_unwind(AssertUnwindSafe(|| {
         run_reedline_loop_inner(
-            request_tx,
-            ui_event_rx,
+            channels,
             lsp_client,
             runtime_handle,
-            r_version,
             r_binary_path,
+            config,
+            session_state,
+            log_handle,
         );
     }));
 
@@ -265,16 +880,23 @@ pub(crate) fn run_reedline_loop(
 }
 
 fn run_reedline_loop_inner(
-    request_tx: tokio::sync::mpsc::Sender<ConsoleRequest>,
-    ui_event_rx: std_mpsc::Receiver<ConsoleUiEvent>,
+    channels: ReedlineLoopChannels,
     lsp_client: Option<Arc<LspClient>>,
     runtime_handle: tokio::runtime::Handle,
-    r_version: Option<String>,
     r_binary_path: Option<String>,
+    config: ConsoleConfig,
+    session_state: SharedSessionState,
+    log_handle: LogReloadHandle,
 ) {
-    debug!("Console reedline_loop: building editor");
+    let ReedlineLoopChannels {
+        request_tx,
+        ui_event_rx,
+    } = channels;
+
+    debug!(edit_mode = ?config.edit_mode, "Console reedline_loop: building editor");
 
     // Print startup banner
+    let r_version = lock_session_state(&session_state).r_version.clone();
     print_banner(r_version.as_deref(), r_binary_path.as_deref());
 
     let shared_ui_rx = Arc::new(Mutex::new(ui_event_rx));
@@ -282,35 +904,16 @@ fn run_reedline_loop_inner(
         .as_ref()
         .map(|client| DebouncedVirtualDocument::new(client.clone(), runtime_handle.clone()));
 
-    // Build keybindings with Tab completion
-    let mut keybindings = default_emacs_keybindings();
-    keybindings.add_binding(
-        KeyModifiers::NONE,
-        KeyCode::Tab,
-        ReedlineEvent::UntilFound(vec![
-            ReedlineEvent::Menu("completion_menu".to_string()),
-            ReedlineEvent::MenuNext,
-        ]),
-    );
-    let edit_mode = Box::new(Emacs::new(keybindings));
-
-    // Completion menu
-    let completion_menu = Box::new(
-        ColumnarMenu::default()
-            .with_name("completion_menu")
-            .with_columns(4)
-            .with_column_padding(2)
-            .with_traversal_direction(TraversalDirection::Vertical),
-    );
-
     let printer = ExternalPrinter::default();
     let printer_sender = printer.sender();
     let idle_ui_rx = Arc::clone(&shared_ui_rx);
 
-    // Build reedline editor
-    let mut editor = Reedline::create()
-        .with_highlighter(Box::new(RHighlighter::new(virtual_document.clone())))
-        .with_validator(Box::new(RValidator))
+    let mut editor = build_editor(
+        &config,
+        virtual_document,
+        runtime_handle,
+        Arc::clone(&session_state),
+    )
         .with_external_printer(printer)
         .with_idle_callback(Box::new(move || {
             for action in drain_idle_actions(&idle_ui_rx) {
@@ -325,23 +928,8 @@ fn run_reedline_loop_inner(
             }
         }));
 
-    // Attach LSP completer if available
-    if let Some(virtual_document) = virtual_document {
-        info!("Console reedline_loop: LSP completion enabled");
-        editor = editor
-            .with_completer(Box::new(LspCompleter::new(
-                virtual_document,
-                runtime_handle,
-            )))
-            .with_menu(ReedlineMenu::EngineCompleter(completion_menu))
-            .with_edit_mode(edit_mode);
-    } else {
-        info!("Console reedline_loop: no LSP client, completion disabled");
-        editor = editor.with_edit_mode(edit_mode);
-    }
-
     // Attach history (non-fatal if it fails)
-    match create_history() {
+    match create_history(Arc::clone(&session_state)) {
         Ok(history) => {
             debug!("Console reedline_loop: history loaded");
             editor = editor.with_history(Box::new(history));
@@ -351,11 +939,22 @@ fn run_reedline_loop_inner(
         }
     }
 
-    let prompt = make_prompt();
+    let prompt = RPrompt::new(
+        &config.prompt,
+        config.right_prompt,
+        Arc::clone(&session_state),
+    );
+
+    let mut output = ExecutionOutput::new(config.long_output, config.max_output_lines);
+    let busy_options = BusyOptions {
+        spinner: config.spinner,
+        notify_after: (config.notify_after > 0).then(|| Duration::from_secs(config.notify_after)),
+        notification: resolve_notification(config.notification),
+    };
 
     debug!("Console reedline_loop: entering main loop");
 
-    'console: loop {
+    loop {
         crate::logging::CONSOLE_ON_PROMPT.store(true, std::sync::atomic::Ordering::Relaxed);
         match editor.read_line(&prompt) {
             Ok(Signal::Success(line)) => {
@@ -366,69 +965,93 @@ fn run_reedline_loop_inner(
 
                 debug!(line_len = line.len(), "Console reedline_loop: user input");
 
-                // Intercept q()/quit() calls with confirmation
-                if is_quit_call(trimmed) {
-                    if confirm_quit() {
-                        debug!(
-                            "Console reedline_loop: user confirmed q(), sending execute-and-exit"
-                        );
-                        if request_tx
-                            .blocking_send(ConsoleRequest::ExecuteAndExit(line))
-                            .is_err()
-                        {
-                            debug!("Console reedline_loop: request channel closed, exiting");
-                            break;
-                        }
+                // Tag the saved entry with the project so navigation can prefer it
+                if let Some(project) = session_project(&session_state) {
+                    if let Err(err) = editor.update_last_command_context(&|mut item| {
+                        item.cwd = Some(project.clone());
+                        item
+                    }) {
+                        debug!(error = %err, "Console reedline_loop: failed to tag history entry");
+                    }
+                }
 
-                        // Drain remaining output before exiting
-                        loop {
-                            match recv_ui_event(&shared_ui_rx) {
-                                Ok(event) => match classify_execution_event(event) {
-                                    ExecutionUiAction::Print(text) => print!("{}", text),
-                                    ExecutionUiAction::ExecutionDone => break,
-                                    ExecutionUiAction::Exit(message) => {
-                                        print_dimmed_message(&message);
-                                        break 'console;
-                                    }
-                                },
-                                Err(_) => {
-                                    debug!("Console reedline_loop: ui event channel closed during quit drain");
-                                    break;
-                                }
-                            }
+                // Meta-commands are handled here or turned into kernel loop requests
+                let mut timed = false;
+                let meta_request = match parse_meta_command(trimmed) {
+                    None => None,
+                    Some(Err(message)) => {
+                        print_meta_output(&message);
+                        continue;
+                    }
+                    Some(Ok(command)) => {
+                        debug!(command = ?command, "Console reedline_loop: meta-command");
+                        timed = matches!(command, MetaCommand::Time(_));
+                        match run_meta_command(
+                            command,
+                            &editor,
+                            lsp_client.as_deref(),
+                            &log_handle,
+                            &output,
+                            &session_state,
+                            config.transcript_format,
+                        ) {
+                            Some(request) => Some(request),
+                            None => continue,
                         }
+                    }
+                };
+
+                // Intercept q()/quit() calls with confirmation, `:time q()` too
+                if let Some(code) =
+                    submitted_code(trimmed, meta_request.as_ref()).filter(|code| is_quit_call(code))
+                {
+                    if confirm_quit() {
+                        execute_and_exit(code.to_string(), &request_tx, &shared_ui_rx);
                         break;
                     }
                     debug!("Console reedline_loop: user declined q(), returning to prompt");
                     continue;
                 }
 
-                // Send code to kernel loop for execution
-                if request_tx
-                    .blocking_send(ConsoleRequest::Execute(line))
-                    .is_err()
+                // Several expressions run one at a time, echoed like R does
+                if let Some(expressions) =
+                    split_expressions(&line).filter(|_| meta_request.is_none())
                 {
-                    debug!("Console reedline_loop: request channel closed, exiting");
-                    break;
+                    debug!(
+                        count = expressions.len(),
+                        "Console reedline_loop: running multi-expression input"
+                    );
+                    if let Err(err) = erase_submitted_input(&prompt, config.edit_mode, &line) {
+                        debug!(error = %err, "Console reedline_loop: failed to erase input");
+                    }
+                    match run_expressions(
+                        &expressions,
+                        &request_tx,
+                        &shared_ui_rx,
+                        &mut output,
+                        &session_state,
+                        busy_options,
+                    ) {
+                        ExecutionEnd::Done(_) => continue,
+                        ExecutionEnd::Exit => break,
+                        ExecutionEnd::Closed => return,
+                    }
                 }
 
-                // Real-time output loop: receive and print output until execution completes
-                loop {
-                    match recv_ui_event(&shared_ui_rx) {
-                        Ok(event) => match classify_execution_event(event) {
-                            ExecutionUiAction::Print(text) => print!("{}", text),
-                            ExecutionUiAction::ExecutionDone => break,
-                            ExecutionUiAction::Exit(message) => {
-                                print_dimmed_message(&message);
-                                break 'console;
-                            }
-                        },
-                        Err(_) => {
-                            debug!("Console reedline_loop: ui event channel closed");
-                            let _ = request_tx.blocking_send(ConsoleRequest::Exit);
-                            return;
-                        }
-                    }
+                // Send code (or a meta-command request) to the kernel loop
+                let request = meta_request.unwrap_or(ConsoleRequest::Execute(line));
+                match run_request(
+                    request,
+                    &request_tx,
+                    &shared_ui_rx,
+                    &mut output,
+                    timed,
+                    &session_state,
+                    busy_options,
+                ) {
+                    ExecutionEnd::Done(_) => {}
+                    ExecutionEnd::Exit => break,
+                    ExecutionEnd::Closed => return,
                 }
             }
             Ok(Signal::CtrlC) => {
@@ -463,18 +1086,71 @@ fn run_reedline_loop_inner(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use reedline::{Prompt, PromptEditMode};
+    use crate::console::session_state::SessionState;
+    use reedline::{PromptEditMode, PromptViMode};
+
+    fn test_runtime() -> tokio::runtime::Runtime {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("build test runtime")
+    }
+
+    fn completion_event() -> ReedlineEvent {
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu("completion_menu".to_string()),
+            ReedlineEvent::MenuNext,
+        ])
+    }
 
     #[test]
-    fn prompt_uses_r_with_default_indicator() {
-        assert_eq!(make_prompt().render_prompt_left(), "R");
-        assert_eq!(make_prompt().render_prompt_right(), "");
+    fn emacs_edit_mode_reports_emacs() {
+        let edit_mode = build_edit_mode(ConsoleEditMode::Emacs);
+        assert!(matches!(edit_mode.edit_mode(), PromptEditMode::Emacs));
+    }
+
+    #[test]
+    fn vi_edit_mode_starts_in_insert_mode() {
+        let edit_mode = build_edit_mode(ConsoleEditMode::Vi);
+        assert!(matches!(
+            edit_mode.edit_mode(),
+            PromptEditMode::Vi(PromptViMode::Insert)
+        ));
+    }
+
+    #[test]
+    fn tab_opens_completion_menu_in_emacs_and_vi_insert() {
+        let mut emacs = default_emacs_keybindings();
+        add_completion_keybindings(&mut emacs);
         assert_eq!(
-            make_prompt().render_prompt_indicator(PromptEditMode::Default),
-            "〉"
+            emacs.find_binding(KeyModifiers::NONE, KeyCode::Tab),
+            Some(completion_event())
+        );
+
+        let mut vi_insert = default_vi_insert_keybindings();
+        add_completion_keybindings(&mut vi_insert);
+        assert_eq!(
+            vi_insert.find_binding(KeyModifiers::NONE, KeyCode::Tab),
+            Some(completion_event())
         );
     }
 
+    #[test]
+    fn editor_builds_in_both_modes() {
+        let runtime = test_runtime();
+        for edit_mode in [ConsoleEditMode::Emacs, ConsoleEditMode::Vi] {
+            let config = ConsoleConfig {
+                edit_mode,
+                ..ConsoleConfig::default()
+            };
+            let _editor = build_editor(
+                &config,
+                None,
+                runtime.handle().clone(),
+                SessionState::default().shared(),
+            );
+        }
+    }
+
     #[test]
     fn quit_call_simple() {
         assert!(is_quit_call("q()"));
@@ -502,6 +1178,17 @@ mod tests {
         assert!(is_quit_call("base::q(save = \"no\")"));
     }
 
+    #[test]
+    fn timed_quit_call_is_confirmed() {
+        let timed = ConsoleRequest::Execute("q()".to_string());
+        assert!(submitted_code(":time q()", Some(&timed)).is_some_and(is_quit_call));
+        assert!(submitted_code("q()", None).is_some_and(is_quit_call));
+        assert_eq!(
+            submitted_code(":restart", Some(&ConsoleRequest::Restart)),
+            None
+        );
+    }
+
     #[test]
     fn not_quit_call() {
         // Not a call (no parens)
@@ -534,6 +1221,70 @@ mod tests {
         assert_eq!(action, ExecutionUiAction::Exit("bye".to_string()));
     }
 
+    #[test]
+    fn history_entries_are_numbered() {
+        let commands = vec![
+            "x <- 1".to_string(),
+            "f <- function() {\n  x\n}".to_string(),
+        ];
+        assert_eq!(
+            format_history_entries(&commands),
+            "1  x <- 1\n2  f <- function() {\n     x\n   }\n"
+        );
+    }
+
+    #[test]
+    fn lsp_status_without_client() {
+        assert!(lsp_status(None).starts_with("LSP: not available"));
+    }
+
+    #[test]
+    fn execution_help_event_shows_help() {
+        let action = classify_execution_event(ConsoleUiEvent::Help("<h2>Mean</h2>".to_string()));
+        assert_eq!(
+            action,
+            ExecutionUiAction::ShowHelp("<h2>Mean</h2>".to_string())
+        );
+    }
+
+    #[test]
+    fn execution_escalation_event_asks_what_to_do() {
+        let busy_for = Duration::from_secs(42);
+        assert_eq!(
+            classify_execution_event(ConsoleUiEvent::InterruptEscalation(busy_for)),
+            ExecutionUiAction::Escalate(busy_for)
+        );
+    }
+
+    #[test]
+    fn interrupt_choice_defaults_to_waiting() {
+        assert_eq!(
+            parse_interrupt_choice("r\n", true),
+            InterruptChoice::Restart
+        );
+        assert_eq!(
+            parse_interrupt_choice("Kill\n", true),
+            InterruptChoice::Kill
+        );
+        assert_eq!(parse_interrupt_choice("k\n", false), InterruptChoice::Wait);
+        assert_eq!(parse_interrupt_choice("\n", true), InterruptChoice::Wait);
+        assert_eq!(parse_interrupt_choice("x", true), InterruptChoice::Wait);
+    }
+
+    #[test]
+    fn idle_help_is_printed() {
+        let (tx, rx) = std_mpsc::channel();
+        let shared_rx = Arc::new(Mutex::new(rx));
+
+        tx.send(ConsoleUiEvent::Help("<p>Arithmetic mean.</p>".to_string()))
+            .expect("send help");
+
+        assert_eq!(
+            drain_idle_actions(&shared_rx),
+            vec![IdleUiAction::Print("Arithmetic mean.".to_string())]
+        );
+    }
+
     #[test]
     fn idle_disconnect_exits_immediately() {
         let (tx, rx) = std_mpsc::channel();
@@ -575,4 +1326,124 @@ mod tests {
         );
         assert!(drain_idle_actions(&shared_rx).is_empty());
     }
+
+    #[test]
+    fn idle_foreign_output_is_printed_line_by_line() {
+        let (tx, rx) = std_mpsc::channel();
+        let shared_rx = Arc::new(Mutex::new(rx));
+
+        tx.send(ConsoleUiEvent::Foreign("> x\n[1] 1\n".to_string()))
+            .expect("send foreign output");
+
+        assert_eq!(
+            drain_idle_actions(&shared_rx),
+            vec![
+                IdleUiAction::Print("> x".to_string()),
+                IdleUiAction::Print("[1] 1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn injected_code_does_not_end_the_awaited_execution() {
+        assert_eq!(
+            classify_execution_event(ConsoleUiEvent::Injected("> x\n".to_string())),
+            ExecutionUiAction::Foreign("> x\n".to_string())
+        );
+    }
+
+    #[test]
+    fn truncated_output_survives_meta_replies_and_later_expressions() {
+        let (ui_tx, ui_rx) = std_mpsc::channel();
+        let shared_rx = Arc::new(Mutex::new(ui_rx));
+        let (request_tx, mut request_rx) = tokio::sync::mpsc::channel(4);
+        let session_state = Arc::new(Mutex::new(SessionState::default()));
+        let busy_options = BusyOptions {
+            spinner: false,
+            notify_after: None,
+            notification: crate::types::Notification::None,
+        };
+        let done = || {
+            ConsoleUiEvent::ExecutionDone(Some(ExecutionRecord {
+                duration: Duration::from_millis(1),
+                failed: false,
+            }))
+        };
+        let mut output = ExecutionOutput::new(LongOutput::Truncate, 1);
+
+        ui_tx
+            .send(ConsoleUiEvent::Output("a\nb\nc\n".to_string()))
+            .unwrap();
+        ui_tx.send(done()).unwrap();
+        ui_tx
+            .send(ConsoleUiEvent::Output("d\n".to_string()))
+            .unwrap();
+        ui_tx.send(done()).unwrap();
+        let end = run_expressions(
+            &["print(1:3)".to_string(), "print(4)".to_string()],
+            &request_tx,
+            &shared_rx,
+            &mut output,
+            &session_state,
+            busy_options,
+        );
+        assert!(matches!(end, ExecutionEnd::Done(Some(_))));
+        assert_eq!(output.lines(), vec!["a", "b", "c"]);
+
+        ui_tx
+            .send(ConsoleUiEvent::Output("Following\n".to_string()))
+            .unwrap();
+        ui_tx.send(ConsoleUiEvent::ExecutionDone(None)).unwrap();
+        let end = run_request(
+            ConsoleRequest::Follow(true),
+            &request_tx,
+            &shared_rx,
+            &mut output,
+            false,
+            &session_state,
+            busy_options,
+        );
+        assert!(matches!(end, ExecutionEnd::Done(None)));
+        assert!(output.is_truncated());
+        assert_eq!(output.lines(), vec!["a", "b", "c"]);
+
+        let sent: Vec<_> = std::iter::from_fn(|| request_rx.try_recv().ok()).collect();
+        assert_eq!(sent.len(), 3);
+        assert!(matches!(sent[2], ConsoleRequest::Follow(true)));
+    }
+
+    #[test]
+    fn expressions_stop_when_an_execution_ends_without_an_outcome() {
+        let (ui_tx, ui_rx) = std_mpsc::channel();
+        let shared_rx = Arc::new(Mutex::new(ui_rx));
+        let (request_tx, mut request_rx) = tokio::sync::mpsc::channel(4);
+        // A success from before the restart must not let the block go on
+        let session_state = Arc::new(Mutex::new(SessionState {
+            last_execution: Some(ExecutionRecord {
+                duration: Duration::from_secs(1),
+                failed: false,
+            }),
+            ..SessionState::default()
+        }));
+        ui_tx.send(ConsoleUiEvent::ExecutionDone(None)).unwrap();
+
+        let end = run_expressions(
+            &["Sys.sleep(60)".to_string(), "x <- 1".to_string()],
+            &request_tx,
+            &shared_rx,
+            &mut ExecutionOutput::new(LongOutput::Print, 10),
+            &session_state,
+            BusyOptions {
+                spinner: false,
+                notify_after: None,
+                notification: crate::types::Notification::None,
+            },
+        );
+        assert!(matches!(end, ExecutionEnd::Done(None)));
+        assert!(matches!(
+            request_rx.try_recv(),
+            Ok(ConsoleRequest::Execute(code)) if code == "Sys.sleep(60)"
+        ));
+        assert!(request_rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,228 @@
+// Kernel session state shared between the kernel loop and the reedline loop.
+//
+// The kernel loop records what it learns from iopub (working directory
+// changes, execution results); the reedline loop reads it when rendering
+// the prompt.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use runtimelib::{ExecutionState, JupyterMessageContent, Stdio};
+use serde_json::{Map, Value};
+
+/// R code printing the working directory.
+pub(crate) const WORKING_DIRECTORY_QUERY: &str = "cat(getwd())";
+
+/// Shared handle to the session state.
+pub(crate) type SharedSessionState = Arc<Mutex<SessionState>>;
+
+/// Outcome of the most recent execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ExecutionRecord {
+    pub duration: Duration,
+    pub failed: bool,
+}
+
+/// What the console knows about the attached R session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct SessionState {
+    /// `R.version.string`, queried at startup.
+    pub r_version: Option<String>,
+    /// Process ID R reports, wherever it runs. A different one after
+    /// reattaching means the kernel restarted.
+    pub r_pid: Option<u32>,
+    /// Process ID of a kernel running on this machine, for `kill` when it
+    /// stops responding to interrupts.
+    pub kernel_pid: Option<u32>,
+    /// The kernel's working directory, as reported by Ark.
+    pub working_directory: Option<String>,
+    /// Active pixi or conda environment of the kernel process.
+    pub environment: Option<String>,
+    /// Duration and status of the last execution from this console.
+    pub last_execution: Option<ExecutionRecord>,
+    /// Connection file of the kernel, used to scope history when the
+    /// working directory is unknown.
+    pub connection_file: Option<String>,
+    /// The kernel is unreachable and the console is trying to reconnect.
+    pub disconnected: bool,
+}
+
+impl SessionState {
+    pub fn shared(self) -> SharedSessionState {
+        Arc::new(Mutex::new(self))
+    }
+}
+
+/// Lock the shared session state.
+pub(crate) fn lock_session_state(
+    state: &SharedSessionState,
+) -> std::sync::MutexGuard<'_, SessionState> {
+    state.lock().expect("session state mutex poisoned")
+}
+
+/// Extract the new directory from a `positron.ui` `working_directory` event.
+///
+/// Ark broadcasts UI comm events on iopub, so the console sees them even
+/// though the UI comm itself is owned by the watch-plot sidecar.
+pub(crate) fn working_directory_from_comm_data(data: &Map<String, Value>) -> Option<String> {
+    if data.get("method").and_then(Value::as_str) != Some("working_directory") {
+        return None;
+    }
+    data.get("params")
+        .and_then(|params| params.get("directory"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+}
+
+/// Keeps the working directory current when no UI comm reports it.
+///
+/// Ark announces directory changes only on a `positron.ui` comm, which
+/// exists when another client such as the watch-plot sidecar opened one.
+/// Until such an announcement is seen, R is asked for `getwd()` after
+/// each execution.
+#[derive(Debug, Default)]
+pub(crate) struct DirectoryQuery {
+    /// A UI comm announced a change, so Ark reports them by itself.
+    reported_by_ui_comm: bool,
+    /// The running query's msg_id.
+    running: Option<String>,
+    /// What the running query printed so far.
+    output: String,
+}
+
+impl DirectoryQuery {
+    /// A `working_directory` event arrived on a UI comm.
+    pub fn reported_by_ui_comm(&mut self) {
+        self.reported_by_ui_comm = true;
+    }
+
+    /// Whether to ask R for its directory after an execution.
+    pub fn needed(&self) -> bool {
+        !self.reported_by_ui_comm
+    }
+
+    /// The query was sent as `msg_id`, replacing any unfinished one.
+    pub fn started(&mut self, msg_id: String) {
+        self.running = Some(msg_id);
+        self.output.clear();
+    }
+
+    /// Whether a message with this parent belongs to the running query.
+    pub fn is_running(&self, parent_msg_id: Option<&str>) -> bool {
+        self.running.is_some() && self.running.as_deref() == parent_msg_id
+    }
+
+    /// Collect a message of the running query. Once the kernel is idle
+    /// again, the query is over and what R printed is returned: the
+    /// directory, or an empty string if `getwd()` failed.
+    pub fn collect(&mut self, content: &JupyterMessageContent) -> Option<String> {
+        match content {
+            JupyterMessageContent::StreamContent(stream)
+                if matches!(stream.name, Stdio::Stdout) =>
+            {
+                self.output.push_str(&stream.text);
+                None
+            }
+            JupyterMessageContent::Status(status)
+                if status.execution_state == ExecutionState::Idle =>
+            {
+                self.running = None;
+                let output = std::mem::take(&mut self.output);
+                Some(output.trim_end_matches('\n').to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Build the environment label from pixi/conda environment variables.
+///
+/// Pixi wins over conda because pixi activation also sets `CONDA_PREFIX`.
+/// A pixi environment named `default` is shown by project name alone.
+pub(crate) fn environment_label(
+    pixi_project: &str,
+    pixi_environment: &str,
+    conda_environment: &str,
+) -> Option<String> {
+    let pixi_project = pixi_project.trim();
+    let pixi_environment = pixi_environment.trim();
+    let conda_environment = conda_environment.trim();
+
+    if !pixi_project.is_empty() {
+        if pixi_environment.is_empty() || pixi_environment == "default" {
+            return Some(pixi_project.to_string());
+        }
+        return Some(format!("{pixi_project}:{pixi_environment}"));
+    }
+    if !conda_environment.is_empty() {
+        return Some(conda_environment.to_string());
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use runtimelib::{Status, StreamContent};
+
+    #[test]
+    fn working_directory_event_is_parsed() {
+        let data = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "working_directory",
+            "params": { "directory": "~/projects/analysis" }
+        });
+        assert_eq!(
+            working_directory_from_comm_data(data.as_object().unwrap()),
+            Some("~/projects/analysis".to_string())
+        );
+    }
+
+    #[test]
+    fn other_ui_events_are_ignored() {
+        let data = serde_json::json!({
+            "method": "busy",
+            "params": { "busy": true }
+        });
+        assert_eq!(
+            working_directory_from_comm_data(data.as_object().unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn directory_is_queried_until_a_ui_comm_reports_it() {
+        let mut query = DirectoryQuery::default();
+        assert!(query.needed());
+        query.started("query-1".to_string());
+        assert!(query.is_running(Some("query-1")));
+        assert!(!query.is_running(Some("execution-1")));
+        assert!(!query.is_running(None));
+
+        let stdout = JupyterMessageContent::StreamContent(StreamContent::stdout("/srv/analysis"));
+        assert_eq!(query.collect(&stdout), None);
+        let idle = JupyterMessageContent::Status(Status::idle());
+        assert_eq!(query.collect(&idle).as_deref(), Some("/srv/analysis"));
+        assert!(!query.is_running(Some("query-1")));
+
+        query.reported_by_ui_comm();
+        assert!(!query.needed());
+    }
+
+    #[test]
+    fn environment_label_prefers_pixi() {
+        assert_eq!(
+            environment_label("analysis", "default", "analysis"),
+            Some("analysis".to_string())
+        );
+        assert_eq!(
+            environment_label("analysis", "dev", ""),
+            Some("analysis:dev".to_string())
+        );
+        assert_eq!(
+            environment_label("", "", "r-base"),
+            Some("r-base".to_string())
+        );
+        assert_eq!(environment_label("", "", " "), None);
+    }
+}
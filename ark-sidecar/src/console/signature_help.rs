@@ -0,0 +1,428 @@
+// Signature help for the call around the cursor.
+//
+// A lexical scan finds the innermost open call and which argument the
+// cursor is in. Ark's `textDocument/signatureHelp` is asked once per call,
+// in the background after the document syncs; moving between arguments
+// only re-emphasises the stored signature.
+
+use std::sync::Arc;
+
+use lsp_types::{ParameterLabel, SignatureHelp, SignatureInformation};
+use nu_ansi_term::{Color, Style};
+
+use crate::lsp_client::virtual_document::DebouncedVirtualDocument;
+
+/// The call enclosing the cursor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CallContext {
+    /// Name of the called function, e.g. `read.csv` or `utils::head`.
+    pub function: String,
+    /// Byte offset of the call's opening parenthesis.
+    pub open_paren: usize,
+    /// Zero-based index of the argument the cursor is in.
+    pub argument_index: usize,
+    /// Name of the current argument if written as `name = value`.
+    pub argument_name: Option<String>,
+}
+
+#[derive(Debug)]
+struct OpenBracket {
+    open: usize,
+    is_call: bool,
+    argument_start: usize,
+    argument_index: usize,
+}
+
+fn is_name_char(ch: char) -> bool {
+    ch.is_alphanumeric() || matches!(ch, '.' | '_' | ':' | '$' | '@')
+}
+
+/// Name of the function called by the `(` at `open`, if it is a call.
+fn function_name_before(line: &str, open: usize) -> Option<String> {
+    let before = line[..open].trim_end();
+    let start = before
+        .char_indices()
+        .rev()
+        .take_while(|&(_, ch)| is_name_char(ch))
+        .last()
+        .map(|(i, _)| i)?;
+    let name = &before[start..];
+    let first = name.chars().next()?;
+    if first.is_ascii_digit() || first == ':' || first == '$' || first == '@' {
+        return None;
+    }
+    // `function(` and `if (` are syntax, not calls.
+    if matches!(name, "function" | "if" | "for" | "while") {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// Name of an argument written as `name = value`.
+fn argument_name(argument: &str) -> Option<String> {
+    let (name, rest) = argument.split_once('=')?;
+    if rest.starts_with('=') {
+        return None;
+    }
+    let name = name.trim().trim_matches('`');
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|ch| ch.is_alphanumeric() || ch == '.' || ch == '_')
+    {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+/// Find the innermost call whose argument list contains `cursor`.
+///
+/// Strings and comments are skipped, so brackets and commas inside them
+/// don't count. Works on incomplete input such as `read.csv(file, `.
+pub(crate) fn call_context(line: &str, cursor: usize) -> Option<CallContext> {
+    let cursor = cursor.min(line.len());
+    let mut stack: Vec<OpenBracket> = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut in_comment = false;
+    let mut escaped = false;
+
+    for (i, ch) in line[..cursor].char_indices() {
+        if in_comment {
+            in_comment = ch != '\n';
+            continue;
+        }
+        if let Some(q) = quote {
+            if escaped {
+                escaped = false;
+            } else if ch == '\\' {
+                escaped = true;
+            } else if ch == q {
+                quote = None;
+            }
+            continue;
+        }
+        match ch {
+            '"' | '\'' | '`' => quote = Some(ch),
+            '#' => in_comment = true,
+            '(' | '[' | '{' => stack.push(OpenBracket {
+                open: i,
+                is_call: ch == '(' && function_name_before(line, i).is_some(),
+                argument_start: i + 1,
+                argument_index: 0,
+            }),
+            ')' | ']' | '}' => {
+                stack.pop();
+            }
+            ',' => {
+                if let Some(top) = stack.last_mut() {
+                    top.argument_index += 1;
+                    top.argument_start = i + 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if quote.is_some() || in_comment {
+        return None;
+    }
+
+    // Only the innermost bracket counts: inside `f(x[1` the subset is
+    // what the cursor is in, not `f`'s argument list.
+    let top = stack.last().filter(|top| top.is_call)?;
+    Some(CallContext {
+        function: function_name_before(line, top.open)?,
+        open_paren: top.open,
+        argument_index: top.argument_index,
+        argument_name: argument_name(&line[top.argument_start..cursor]),
+    })
+}
+
+/// Convert a UTF-16 offset within `text` to a byte offset.
+fn utf16_to_byte_offset(text: &str, utf16_offset: u32) -> usize {
+    let mut units = 0;
+    for (i, ch) in text.char_indices() {
+        if units >= utf16_offset {
+            return i;
+        }
+        units += ch.len_utf16() as u32;
+    }
+    text.len()
+}
+
+/// Byte range of each parameter within the signature label.
+fn parameter_ranges(signature: &SignatureInformation) -> Vec<(usize, usize)> {
+    let label = &signature.label;
+    let mut search_from = label.find('(').map_or(0, |i| i + 1);
+    let mut ranges = Vec::new();
+
+    for parameter in signature.parameters.iter().flatten() {
+        match &parameter.label {
+            ParameterLabel::LabelOffsets([start, end]) => {
+                ranges.push((
+                    utf16_to_byte_offset(label, *start),
+                    utf16_to_byte_offset(label, *end),
+                ));
+            }
+            ParameterLabel::Simple(text) => {
+                let Some(found) = label[search_from..].find(text.as_str()) else {
+                    ranges.push((0, 0));
+                    continue;
+                };
+                let start = search_from + found;
+                search_from = start + text.len();
+                ranges.push((start, start + text.len()));
+            }
+        }
+    }
+
+    ranges
+}
+
+/// Parameter name from its label text, e.g. `header = TRUE` -> `header`.
+fn parameter_name(label: &str) -> &str {
+    label.split('=').next().unwrap_or(label).trim()
+}
+
+/// Pick the parameter to emphasise for the cursor's argument.
+///
+/// A named argument selects the parameter of that name; a positional one
+/// selects by index, spilling into `...` when past the named parameters.
+fn active_parameter(
+    signature: &SignatureInformation,
+    ranges: &[(usize, usize)],
+    context: &CallContext,
+) -> Option<usize> {
+    let names: Vec<&str> = ranges
+        .iter()
+        .map(|&(start, end)| parameter_name(&signature.label[start..end]))
+        .collect();
+    let dots = names.iter().position(|name| *name == "...");
+
+    if let Some(argument_name) = &context.argument_name {
+        return names
+            .iter()
+            .position(|name| *name == argument_name.as_str())
+            .or(dots);
+    }
+    if context.argument_index < names.len() {
+        Some(context.argument_index)
+    } else {
+        dots
+    }
+}
+
+/// Render the active signature with the current parameter emphasised.
+pub(crate) fn format_signature(
+    help: &SignatureHelp,
+    context: &CallContext,
+    use_ansi_coloring: bool,
+) -> Option<String> {
+    let index = help.active_signature.unwrap_or(0) as usize;
+    let signature = help.signatures.get(index).or(help.signatures.first())?;
+    let label = signature.label.as_str();
+
+    if !use_ansi_coloring {
+        return Some(label.to_string());
+    }
+
+    let base = Style::new().fg(Color::DarkGray);
+    let ranges = parameter_ranges(signature);
+    let Some((start, end)) =
+        active_parameter(signature, &ranges, context).map(|active| ranges[active])
+    else {
+        return Some(base.paint(label).to_string());
+    };
+
+    Some(format!(
+        "{}{}{}",
+        base.paint(&label[..start]),
+        Style::new()
+            .fg(Color::Cyan)
+            .bold()
+            .paint(&label[start..end]),
+        base.paint(&label[end..])
+    ))
+}
+
+/// Signature help for the call at the cursor.
+pub(crate) struct SignatureHelpSource {
+    virtual_document: Arc<DebouncedVirtualDocument>,
+}
+
+impl SignatureHelpSource {
+    pub fn new(virtual_document: Arc<DebouncedVirtualDocument>) -> Self {
+        Self { virtual_document }
+    }
+
+    /// Signature line for the call at the cursor, if any.
+    ///
+    /// Only reads what the server already answered, so a call shows no
+    /// hint until its signature arrives, and none if the request fails.
+    pub fn hint(&self, line: &str, pos: usize, use_ansi_coloring: bool) -> Option<String> {
+        let context = call_context(line, pos)?;
+        // Ask at the opening parenthesis so Ark resolves the call itself
+        // rather than whatever argument the cursor is in.
+        let help = self
+            .virtual_document
+            .signature_help(line, context.open_paren + 1)?;
+        format_signature(&help, &context, use_ansi_coloring)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::ParameterInformation;
+
+    fn context(line: &str) -> Option<CallContext> {
+        call_context(line, line.len())
+    }
+
+    fn read_csv_help() -> SignatureHelp {
+        let label = "read.csv(file, header = TRUE, sep = \",\", ...)";
+        let parameters = ["file", "header = TRUE", "sep = \",\"", "..."]
+            .into_iter()
+            .map(|text| ParameterInformation {
+                label: ParameterLabel::Simple(text.to_string()),
+                documentation: None,
+            })
+            .collect();
+        SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label: label.to_string(),
+                documentation: None,
+                parameters: Some(parameters),
+                active_parameter: None,
+            }],
+            active_signature: Some(0),
+            active_parameter: Some(0),
+        }
+    }
+
+    fn emphasised(line: &str) -> Option<String> {
+        let help = read_csv_help();
+        let signature = &help.signatures[0];
+        let ranges = parameter_ranges(signature);
+        let context = context(line)?;
+        active_parameter(signature, &ranges, &context)
+            .map(|active| signature.label[ranges[active].0..ranges[active].1].to_string())
+    }
+
+    #[test]
+    fn cursor_after_open_paren_is_in_first_argument() {
+        assert_eq!(
+            context("read.csv("),
+            Some(CallContext {
+                function: "read.csv".to_string(),
+                open_paren: 8,
+                argument_index: 0,
+                argument_name: None,
+            })
+        );
+    }
+
+    #[test]
+    fn commas_advance_the_argument() {
+        let ctx = context("x <- read.csv(path, TRUE, ").unwrap();
+        assert_eq!(ctx.function, "read.csv");
+        assert_eq!(ctx.argument_index, 2);
+    }
+
+    #[test]
+    fn nested_calls_use_innermost() {
+        let ctx = context("lapply(xs, function(x) mean(x, ").unwrap();
+        assert_eq!(ctx.function, "mean");
+        assert_eq!(ctx.argument_index, 1);
+
+        let ctx = context("lapply(xs, f(1), ").unwrap();
+        assert_eq!(ctx.function, "lapply");
+        assert_eq!(ctx.argument_index, 2);
+    }
+
+    #[test]
+    fn namespaced_function_names_are_kept() {
+        assert_eq!(
+            context("utils::head(x, ").map(|ctx| ctx.function),
+            Some("utils::head".to_string())
+        );
+    }
+
+    #[test]
+    fn strings_and_comments_are_skipped() {
+        let ctx = context("paste(\"a, (b\", ").unwrap();
+        assert_eq!(ctx.function, "paste");
+        assert_eq!(ctx.argument_index, 1);
+
+        assert!(context("paste(\"a, ").is_none());
+        assert!(context("f(x) # g(").is_none());
+    }
+
+    #[test]
+    fn non_calls_have_no_context() {
+        assert!(context("x <- 1").is_none());
+        assert!(context("f(x)").is_none());
+        assert!(context("function(").is_none());
+        assert!(context("if (").is_none());
+        assert!(context("x[").is_none());
+        assert!(context("f(x[").is_none());
+        assert!(context("(").is_none());
+    }
+
+    #[test]
+    fn named_arguments_are_detected() {
+        assert_eq!(
+            context("read.csv(path, sep = ").and_then(|ctx| ctx.argument_name),
+            Some("sep".to_string())
+        );
+        assert_eq!(context("f(x == ").and_then(|ctx| ctx.argument_name), None);
+    }
+
+    #[test]
+    fn active_parameter_follows_position_and_name() {
+        assert_eq!(emphasised("read.csv(").as_deref(), Some("file"));
+        assert_eq!(
+            emphasised("read.csv(path, ").as_deref(),
+            Some("header = TRUE")
+        );
+        assert_eq!(
+            emphasised("read.csv(path, sep = ").as_deref(),
+            Some("sep = \",\"")
+        );
+        assert_eq!(
+            emphasised("read.csv(path, quote = ").as_deref(),
+            Some("...")
+        );
+        assert_eq!(emphasised("read.csv(a, b, c, d, ").as_deref(), Some("..."));
+    }
+
+    #[test]
+    fn label_offsets_are_utf16() {
+        let signature = SignatureInformation {
+            label: "f(\u{1F600}, y)".to_string(),
+            documentation: None,
+            parameters: Some(vec![ParameterInformation {
+                label: ParameterLabel::LabelOffsets([6, 7]),
+                documentation: None,
+            }]),
+            active_parameter: None,
+        };
+        let (start, end) = parameter_ranges(&signature)[0];
+        assert_eq!(&signature.label[start..end], "y");
+    }
+
+    #[test]
+    fn signature_is_rendered_with_emphasis() {
+        let help = read_csv_help();
+        let ctx = context("read.csv(path, ").unwrap();
+
+        assert_eq!(
+            format_signature(&help, &ctx, false).as_deref(),
+            Some("read.csv(file, header = TRUE, sep = \",\", ...)")
+        );
+
+        let colored = format_signature(&help, &ctx, true).unwrap();
+        let emphasis = Style::new().fg(Color::Cyan).bold().paint("header = TRUE");
+        assert!(colored.contains(&emphasis.to_string()));
+    }
+}
@@ -0,0 +1,155 @@
+// Box-drawn tables for rich console output.
+//
+// Lays out HTML and markdown tables as a grid that fits the terminal:
+// the widest columns give up space first and their cells are truncated
+// with an ellipsis. Numeric columns are right-aligned.
+
+use nu_ansi_term::Style;
+
+/// Columns never shrink below this many characters.
+const MIN_COLUMN_WIDTH: usize = 3;
+
+fn is_numeric(cell: &str) -> bool {
+    let cell = cell.trim().trim_end_matches('%').replace(',', "");
+    matches!(cell.as_str(), "NA" | "NaN" | "Inf" | "-Inf") || cell.parse::<f64>().is_ok()
+}
+
+/// Cut `text` to `width` characters, ending with `…` when shortened.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let kept: String = text.chars().take(width.saturating_sub(1)).collect();
+    let mut truncated = kept.trim_end().to_string();
+    truncated.push('…');
+    truncated
+}
+
+/// Shrink the widest columns until the table fits in `max_width`.
+fn fit_columns(widths: &mut [usize], max_width: usize) {
+    // Borders: `│ ` before each column, ` │` after the last
+    let borders = 3 * widths.len() + 1;
+    while widths.iter().sum::<usize>() + borders > max_width {
+        let Some(widest) = widths
+            .iter_mut()
+            .filter(|width| **width > MIN_COLUMN_WIDTH)
+            .max_by_key(|width| **width)
+        else {
+            break;
+        };
+        *widest -= 1;
+    }
+}
+
+fn cell(row: &[String], column: usize) -> &str {
+    row.get(column).map_or("", String::as_str)
+}
+
+fn border(widths: &[usize], left: char, middle: char, right: char) -> String {
+    let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+    format!("{left}{}{right}", segments.join(&middle.to_string()))
+}
+
+/// Draw `rows` as a grid at most `max_width` columns wide. The first
+/// `header_rows` rows are set apart, in bold with `use_ansi`.
+pub(crate) fn render_grid(
+    rows: &[Vec<String>],
+    header_rows: usize,
+    max_width: usize,
+    use_ansi: bool,
+) -> Vec<String> {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+    let header_rows = header_rows.min(rows.len());
+
+    let mut widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .map(|row| cell(row, column).chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(1)
+        })
+        .collect();
+    fit_columns(&mut widths, max_width);
+    let right_aligned: Vec<bool> = (0..columns)
+        .map(|column| {
+            let mut body = rows[header_rows..]
+                .iter()
+                .map(|row| cell(row, column))
+                .filter(|text| !text.is_empty())
+                .peekable();
+            body.peek().is_some() && body.all(is_numeric)
+        })
+        .collect();
+
+    let mut lines = vec![border(&widths, '┌', '┬', '┐')];
+    for (index, row) in rows.iter().enumerate() {
+        if index == header_rows && header_rows > 0 {
+            lines.push(border(&widths, '├', '┼', '┤'));
+        }
+        let mut line = String::from("│");
+        for (column, &width) in widths.iter().enumerate() {
+            let text = truncate(cell(row, column), width);
+            let padded = if right_aligned[column] {
+                format!("{text:>width$}")
+            } else {
+                format!("{text:<width$}")
+            };
+            let painted = if use_ansi && index < header_rows {
+                Style::new().bold().paint(padded).to_string()
+            } else {
+                padded
+            };
+            line.push_str(&format!(" {painted} │"));
+        }
+        lines.push(line);
+    }
+    lines.push(border(&widths, '└', '┴', '┘'));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(cells: &[&[&str]]) -> Vec<Vec<String>> {
+        cells
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn draws_header_and_aligns_numbers() {
+        let table = rows(&[&["name", "mpg"], &["Mazda RX4", "21"], &["Valiant", "18.1"]]);
+        assert_eq!(
+            render_grid(&table, 1, 80, false),
+            vec![
+                "┌───────────┬──────┐",
+                "│ name      │  mpg │",
+                "├───────────┼──────┤",
+                "│ Mazda RX4 │   21 │",
+                "│ Valiant   │ 18.1 │",
+                "└───────────┴──────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn wide_columns_shrink_to_fit() {
+        let table = rows(&[&["id", "description"], &["1", "a rather long description"]]);
+        let lines = render_grid(&table, 1, 24, false);
+        assert!(lines.iter().all(|line| line.chars().count() <= 24));
+        assert_eq!(lines[3], "│  1 │ a rather long…  │");
+    }
+
+    #[test]
+    fn ragged_rows_are_padded() {
+        let table = rows(&[&["a", "b"], &["1"]]);
+        assert_eq!(render_grid(&table, 0, 80, false)[2], "│ 1 │   │");
+        assert!(render_grid(&[], 0, 80, false).is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+// Terminal identification for console mode.
+//
+// Inline images and notifications depend on the terminal emulator, which
+// only announces itself through environment variables. They are read here
+// once, for both.
+
+/// What the environment says about the terminal.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct TerminalInfo {
+    /// `TERM`, e.g. `xterm-kitty` or `foot`.
+    pub term: String,
+    /// `TERM_PROGRAM`, e.g. `iTerm.app` or `vscode`.
+    pub program: String,
+    /// `LC_TERMINAL`, which iTerm2 sets and ssh forwards.
+    pub lc_terminal: String,
+    /// Inside tmux or screen, which pass escapes through unreliably.
+    pub multiplexed: bool,
+    /// `KITTY_WINDOW_ID` is set.
+    pub kitty_window: bool,
+    /// `VTE_VERSION` is set (GNOME Terminal, Tilix).
+    pub vte: bool,
+}
+
+impl TerminalInfo {
+    /// Identify the terminal from environment variables looked up by `env`.
+    pub fn from_env(env: impl Fn(&str) -> Option<String>) -> Self {
+        Self {
+            term: env("TERM").unwrap_or_default(),
+            program: env("TERM_PROGRAM").unwrap_or_default(),
+            lc_terminal: env("LC_TERMINAL").unwrap_or_default(),
+            multiplexed: env("TMUX").is_some() || env("STY").is_some(),
+            kitty_window: env("KITTY_WINDOW_ID").is_some(),
+            vte: env("VTE_VERSION").is_some(),
+        }
+    }
+
+    /// The terminal the console runs in.
+    pub fn current() -> Self {
+        Self::from_env(|name| std::env::var(name).ok())
+    }
+
+    pub fn is_kitty(&self) -> bool {
+        self.kitty_window || self.term == "xterm-kitty"
+    }
+
+    pub fn is_ghostty(&self) -> bool {
+        self.term == "xterm-ghostty" || self.program == "ghostty"
+    }
+
+    pub fn is_iterm2(&self) -> bool {
+        self.program == "iTerm.app" || self.lc_terminal == "iTerm2"
+    }
+
+    pub fn is_wezterm(&self) -> bool {
+        self.program == "WezTerm"
+    }
+}
+
+/// A terminal with the given environment variables set.
+#[cfg(test)]
+pub(crate) fn test_terminal(vars: &[(&str, &str)]) -> TerminalInfo {
+    TerminalInfo::from_env(|name| {
+        vars.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value.to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn terminal_is_identified_from_its_variables() {
+        let terminal = test_terminal(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux")]);
+        assert!(terminal.is_kitty());
+        assert!(terminal.multiplexed);
+
+        let terminal = test_terminal(&[("LC_TERMINAL", "iTerm2")]);
+        assert!(terminal.is_iterm2());
+        assert!(!terminal.multiplexed);
+
+        assert_eq!(test_terminal(&[]), TerminalInfo::default());
+    }
+}
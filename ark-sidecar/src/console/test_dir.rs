@@ -0,0 +1,35 @@
+// Scratch directories for tests that touch the file system.
+
+use std::path::{Path, PathBuf};
+
+/// A directory in the system temp directory, named after the test and the
+/// process, and removed with its contents when dropped, even if the test
+/// fails.
+pub(crate) struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Create the directory, replacing whatever an earlier run left behind.
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("ark-sidecar-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("failed to create test directory");
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `relative` inside the directory.
+    pub fn join(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.path.join(relative)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
@@ -0,0 +1,397 @@
+// Session transcripts for console mode.
+//
+// `--transcript <file>` or `:transcript start` records the input, output,
+// errors and timing of each execution, either as an R-style log with `> `
+// and `+ ` prompts or as JSON lines named after the Jupyter message types.
+// ANSI escapes are stripped. Files are appended to, so one transcript can
+// span several sessions.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::{Local, SecondsFormat};
+use runtimelib::{media::MediaType, JupyterMessageContent};
+use serde_json::{json, Map, Value};
+
+use super::output::format_duration;
+use super::paste::echo_with_prompts;
+use super::path_completion::expand_tilde;
+use crate::types::TranscriptFormat;
+
+/// Remove ANSI escape sequences: CSI (colors, cursor movement), and string
+/// commands such as OSC hyperlinks or DCS/APC graphics.
+pub(crate) fn strip_ansi(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch != '\x1b' {
+            stripped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            // Parameters, then a final byte in `@`..=`~`
+            Some('[') => {
+                for ch in chars.by_ref() {
+                    if ('@'..='~').contains(&ch) {
+                        break;
+                    }
+                }
+            }
+            // Terminated by BEL or ST (`ESC \`)
+            Some(']' | 'P' | '_' | '^' | 'X') => {
+                while let Some(ch) = chars.next() {
+                    if ch == '\x07' {
+                        break;
+                    }
+                    if ch == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+/// Where `:transcript start [file]` writes: the given file, or a
+/// timestamped log. Relative paths resolve against `working_directory`,
+/// the kernel's directory when known, else the current directory.
+pub(crate) fn transcript_path(file: Option<&str>, working_directory: Option<&str>) -> PathBuf {
+    let path = match file {
+        Some(file) => PathBuf::from(expand_tilde(file)),
+        None => PathBuf::from(format!(
+            "console-transcript-{}.log",
+            Local::now().format("%Y%m%d-%H%M%S")
+        )),
+    };
+    if path.is_absolute() {
+        return path;
+    }
+    match working_directory {
+        Some(directory) => Path::new(&expand_tilde(directory)).join(path),
+        None => match std::env::current_dir() {
+            Ok(directory) => directory.join(path),
+            Err(_) => path,
+        },
+    }
+}
+
+/// JSON lines for `.jsonl` files, the R-style log otherwise.
+pub(crate) fn format_for_path(path: &Path) -> TranscriptFormat {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("jsonl") => TranscriptFormat::Jsonl,
+        _ => TranscriptFormat::Log,
+    }
+}
+
+/// `text/*` media, which is what a transcript can show.
+fn text_media(content: &[MediaType]) -> Vec<(&'static str, String)> {
+    content
+        .iter()
+        .filter_map(|media| match media {
+            MediaType::Plain(text) => Some(("text/plain", strip_ansi(text))),
+            MediaType::Markdown(text) => Some(("text/markdown", text.clone())),
+            MediaType::Html(text) => Some(("text/html", text.clone())),
+            MediaType::Latex(text) => Some(("text/latex", text.clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// An open transcript file.
+pub(crate) struct Transcript {
+    path: PathBuf,
+    format: TranscriptFormat,
+    writer: BufWriter<File>,
+    /// The log ends in the middle of a line of streamed output.
+    line_open: bool,
+}
+
+impl Transcript {
+    /// Open `path` for appending.
+    pub fn open(path: &Path, format: TranscriptFormat) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            writer: BufWriter::new(file),
+            line_open: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn timestamp() -> String {
+        Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)
+    }
+
+    fn write_log(&mut self, text: &str) -> Result<()> {
+        self.writer.write_all(text.as_bytes())?;
+        self.writer.flush()?;
+        if !text.is_empty() {
+            self.line_open = !text.ends_with('\n');
+        }
+        Ok(())
+    }
+
+    /// Write whole lines, after finishing any partial line of output.
+    fn write_log_lines(&mut self, text: &str) -> Result<()> {
+        if self.line_open {
+            self.write_log("\n")?;
+        }
+        self.write_log(text)?;
+        if self.line_open {
+            self.write_log("\n")?;
+        }
+        Ok(())
+    }
+
+    fn write_json(
+        &mut self,
+        msg_type: &str,
+        content: Value,
+        extra: Option<(&str, Value)>,
+    ) -> Result<()> {
+        let mut record = Map::new();
+        record.insert("time".to_string(), json!(Self::timestamp()));
+        record.insert("msg_type".to_string(), json!(msg_type));
+        record.insert("content".to_string(), content);
+        if let Some((key, value)) = extra {
+            record.insert(key.to_string(), value);
+        }
+        serde_json::to_writer(&mut self.writer, &record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Record the code sent for execution.
+    pub fn record_input(&mut self, code: &str) -> Result<()> {
+        match self.format {
+            TranscriptFormat::Log => {
+                let mut text = format!("# {}\n", Self::timestamp());
+                text.push_str(&echo_with_prompts(code, false));
+                self.write_log_lines(&text)
+            }
+            TranscriptFormat::Jsonl => {
+                self.write_json("execute_input", json!({ "code": code }), None)
+            }
+        }
+    }
+
+    /// Record an iopub message of the execution; other messages are ignored.
+    pub fn record_output(&mut self, content: &JupyterMessageContent) -> Result<()> {
+        match (self.format, content) {
+            (TranscriptFormat::Log, JupyterMessageContent::StreamContent(stream)) => {
+                // Streams arrive in chunks that may split lines
+                self.write_log(&strip_ansi(&stream.text))
+            }
+            (TranscriptFormat::Jsonl, JupyterMessageContent::StreamContent(stream)) => {
+                let name = match stream.name {
+                    runtimelib::Stdio::Stdout => "stdout",
+                    runtimelib::Stdio::Stderr => "stderr",
+                };
+                let content = json!({ "name": name, "text": strip_ansi(&stream.text) });
+                self.write_json("stream", content, None)
+            }
+            (format, JupyterMessageContent::ExecuteResult(result)) => {
+                self.record_media(format, "execute_result", &result.data.content)
+            }
+            (format, JupyterMessageContent::DisplayData(display)) => {
+                self.record_media(format, "display_data", &display.data.content)
+            }
+            (TranscriptFormat::Log, JupyterMessageContent::ErrorOutput(error)) => {
+                let mut text = format!("Error: {}\n", strip_ansi(&error.evalue));
+                for line in &error.traceback {
+                    text.push_str(strip_ansi(line).trim_end_matches('\n'));
+                    text.push('\n');
+                }
+                self.write_log_lines(&text)
+            }
+            (TranscriptFormat::Jsonl, JupyterMessageContent::ErrorOutput(error)) => {
+                let traceback: Vec<String> = error
+                    .traceback
+                    .iter()
+                    .map(|line| strip_ansi(line))
+                    .collect();
+                let content = json!({
+                    "ename": error.ename,
+                    "evalue": strip_ansi(&error.evalue),
+                    "traceback": traceback,
+                });
+                self.write_json("error", content, None)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn record_media(
+        &mut self,
+        format: TranscriptFormat,
+        msg_type: &str,
+        content: &[MediaType],
+    ) -> Result<()> {
+        let media = text_media(content);
+        match format {
+            TranscriptFormat::Log => {
+                let text = match media.iter().find(|(mime, _)| *mime == "text/plain") {
+                    Some((_, text)) => text.clone(),
+                    None => "[output not shown in text]".to_string(),
+                };
+                self.write_log_lines(&text)
+            }
+            TranscriptFormat::Jsonl => {
+                let data: Map<String, Value> = media
+                    .into_iter()
+                    .map(|(mime, text)| (mime.to_string(), json!(text)))
+                    .collect();
+                self.write_json(msg_type, json!({ "data": data }), None)
+            }
+        }
+    }
+
+    /// Record that the execution finished.
+    pub fn record_done(&mut self, duration: Duration, failed: bool) -> Result<()> {
+        match self.format {
+            TranscriptFormat::Log => {
+                let outcome = if failed { "failed after" } else { "done in" };
+                self.write_log_lines(&format!("# {outcome} {}\n\n", format_duration(duration)))
+            }
+            TranscriptFormat::Jsonl => {
+                let status = if failed { "error" } else { "ok" };
+                let duration_ms = json!(duration.as_millis() as u64);
+                self.write_json(
+                    "execute_reply",
+                    json!({ "status": status }),
+                    Some(("duration_ms", duration_ms)),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::console::test_dir::TestDir;
+    use runtimelib::{ErrorOutput, StreamContent};
+
+    fn record_execution(transcript: &mut Transcript) {
+        transcript
+            .record_input("f <- function(x) {\n  x\n}\nf(1)")
+            .unwrap();
+        for chunk in ["\x1b[32m[1]", " 1\x1b[0m\n", "partial"] {
+            let stdout = StreamContent::stdout(chunk);
+            transcript
+                .record_output(&JupyterMessageContent::StreamContent(stdout))
+                .unwrap();
+        }
+        let error = ErrorOutput {
+            ename: "simpleError".to_string(),
+            evalue: "oops".to_string(),
+            traceback: vec!["\x1b[1mat f()\x1b[22m".to_string()],
+        };
+        transcript
+            .record_output(&JupyterMessageContent::ErrorOutput(error))
+            .unwrap();
+        transcript
+            .record_done(Duration::from_millis(850), true)
+            .unwrap();
+    }
+
+    #[test]
+    fn ansi_sequences_are_stripped() {
+        assert_eq!(strip_ansi("\x1b[1;31mError\x1b[0m: x"), "Error: x");
+        assert_eq!(
+            strip_ansi("\x1b]8;;https://r-project.org\x1b\\link\x1b]8;;\x07"),
+            "link"
+        );
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+
+    #[test]
+    fn log_transcript_uses_r_prompts() {
+        let directory = TestDir::new("transcript-log");
+        let path = directory.join("transcript.log");
+        let mut transcript = Transcript::open(&path, TranscriptFormat::Log).unwrap();
+        record_execution(&mut transcript);
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert!(lines[0].starts_with("# "));
+        assert_eq!(
+            lines[1..],
+            [
+                "> f <- function(x) {",
+                "+   x",
+                "+ }",
+                "+ f(1)",
+                "[1] 1",
+                "partial",
+                "Error: oops",
+                "at f()",
+                "# failed after 850ms",
+                "",
+            ]
+        );
+    }
+
+    #[test]
+    fn jsonl_transcript_uses_message_types() {
+        let directory = TestDir::new("transcript-jsonl");
+        let path = directory.join("transcript.jsonl");
+        assert_eq!(format_for_path(&path), TranscriptFormat::Jsonl);
+        let mut transcript = Transcript::open(&path, TranscriptFormat::Jsonl).unwrap();
+        record_execution(&mut transcript);
+
+        let records: Vec<Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let types: Vec<&str> = records
+            .iter()
+            .map(|record| record["msg_type"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "execute_input",
+                "stream",
+                "stream",
+                "stream",
+                "error",
+                "execute_reply"
+            ]
+        );
+        assert_eq!(records[2]["content"]["text"], " 1\n");
+        assert_eq!(records[4]["content"]["traceback"][0], "at f()");
+        assert_eq!(records[5]["content"]["status"], "error");
+        assert_eq!(records[5]["duration_ms"], 850);
+    }
+
+    #[test]
+    fn relative_transcripts_go_to_the_kernel_directory() {
+        assert_eq!(
+            transcript_path(Some("session.log"), Some("/srv/analysis")),
+            PathBuf::from("/srv/analysis/session.log")
+        );
+        assert_eq!(
+            transcript_path(Some("/tmp/session.log"), Some("/srv/analysis")),
+            PathBuf::from("/tmp/session.log")
+        );
+        let default = transcript_path(None, Some("/srv/analysis"));
+        assert_eq!(default.parent(), Some(Path::new("/srv/analysis")));
+    }
+}
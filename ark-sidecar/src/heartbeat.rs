@@ -4,6 +4,7 @@
 // Jupyter kernel has exited. ZMQ PUB/SUB sockets never signal peer
 // disconnection, so we must actively probe the kernel's heartbeat port.
 
+use anyhow::{Context, Result};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
@@ -50,6 +51,22 @@ pub(crate) fn spawn_heartbeat_monitor(
     (disconnect_rx, handle)
 }
 
+/// Probe the kernel's heartbeat once, e.g. before reconnecting to it.
+pub(crate) async fn probe_heartbeat(connection_info: &runtimelib::ConnectionInfo) -> Result<()> {
+    let mut connection = tokio::time::timeout(
+        HEARTBEAT_CONNECT_TIMEOUT,
+        create_client_heartbeat_connection(connection_info),
+    )
+    .await
+    .context("Heartbeat connection timed out")?
+    .context("Failed to connect heartbeat socket")?;
+    tokio::time::timeout(HEARTBEAT_TIMEOUT, connection.single_heartbeat())
+        .await
+        .context("Heartbeat probe timed out")?
+        .context("Heartbeat probe failed")?;
+    Ok(())
+}
+
 /// Abort the heartbeat monitor task.
 pub(crate) fn stop_heartbeat_monitor(handle: JoinHandle<()>) {
     handle.abort();
@@ -4,19 +4,28 @@
 // - Text extraction from textEdit, insertText, or label fallbacks
 // - Snippet syntax stripping ($0, ${1:default}, etc.)
 // - Span computation from textEdit ranges or word-start heuristics
+// - Prefix or fuzzy filtering and ranking of candidates
 
 use lsp_types::{CompletionItem, CompletionItemKind, CompletionResponse, Documentation, InsertTextFormat};
 use reedline::{Span, Suggestion};
 use tracing::debug;
 
+use super::fuzzy::{fuzzy_match, FuzzyMatch};
 use super::position::lsp_position_to_byte_offset;
+use crate::types::CompletionMatching;
 
-/// Convert an LSP CompletionResponse to reedline Suggestions.
-pub(crate) fn completion_response_to_suggestions(
+/// Fuzzy score given up per step of `completion_item_priority`, so a much
+/// better match still outranks a preferred item.
+const FUZZY_PRIORITY_WEIGHT: i64 = 6;
+
+/// Convert an LSP CompletionResponse to reedline Suggestions, each paired
+/// with its CompletionItem so it can be resolved later.
+pub(crate) fn completion_response_to_entries(
     response: Option<CompletionResponse>,
     buffer: &str,
     cursor_byte_offset: usize,
-) -> Vec<Suggestion> {
+    matching: CompletionMatching,
+) -> Vec<(CompletionItem, Suggestion)> {
     let items = match response {
         Some(CompletionResponse::Array(items)) => items,
         Some(CompletionResponse::List(list)) => list.items,
@@ -27,13 +36,25 @@ pub(crate) fn completion_response_to_suggestions(
     debug!(
         item_count = items.len(),
         token = token,
+        matching = ?matching,
         "completion: filtering CompletionItems for console suggestions"
     );
 
-    sort_completion_items(filter_completion_items(&items, token))
-        .iter()
-        .map(|item| completion_item_to_suggestion(item, buffer, cursor_byte_offset))
-        .collect()
+    let entry = |item: &CompletionItem, match_indices| {
+        let suggestion =
+            completion_item_to_suggestion(item, buffer, cursor_byte_offset, match_indices);
+        (item.clone(), suggestion)
+    };
+    match matching {
+        CompletionMatching::Prefix => sort_completion_items(filter_completion_items(&items, token))
+            .into_iter()
+            .map(|item| entry(item, None))
+            .collect(),
+        CompletionMatching::Fuzzy => rank_fuzzy_completion_items(&items, token)
+            .into_iter()
+            .map(|(item, fuzzy)| entry(item, Some(fuzzy.indices)))
+            .collect(),
+    }
 }
 
 /// Convert a single CompletionItem to a reedline Suggestion.
@@ -41,19 +62,20 @@ fn completion_item_to_suggestion(
     item: &CompletionItem,
     buffer: &str,
     cursor_byte_offset: usize,
+    match_indices: Option<Vec<usize>>,
 ) -> Suggestion {
     let clean_text = completion_item_replacement_text(item);
     let span = compute_span(item, buffer, cursor_byte_offset);
 
     Suggestion {
         value: clean_text,
-        description: item.detail.clone(),
+        description: completion_item_description(item),
         style: None,
         extra: None,
         span,
         append_whitespace: false,
         display_override: None,
-        match_indices: None,
+        match_indices,
     }
 }
 
@@ -108,6 +130,42 @@ fn sort_completion_items<'a>(mut items: Vec<&'a CompletionItem>) -> Vec<&'a Comp
     items
 }
 
+/// Keep the items `token` fuzzy-matches, best first.
+///
+/// Match quality and `completion_item_priority` are combined into one rank;
+/// equal ranks fall back to the same keys as the prefix ordering, so the
+/// result doesn't depend on the order the server sent the items in.
+fn rank_fuzzy_completion_items<'a>(
+    items: &'a [CompletionItem],
+    token: &str,
+) -> Vec<(&'a CompletionItem, FuzzyMatch)> {
+    let mut matches: Vec<_> = items
+        .iter()
+        .filter_map(|item| {
+            fuzzy_match(&completion_item_replacement_text(item), token).map(|fuzzy| (item, fuzzy))
+        })
+        .collect();
+
+    let rank = |(item, fuzzy): &(&CompletionItem, FuzzyMatch)| {
+        fuzzy.score - FUZZY_PRIORITY_WEIGHT * i64::from(completion_item_priority(item))
+    };
+    matches.sort_by(|left, right| {
+        rank(right)
+            .cmp(&rank(left))
+            .then_with(|| completion_item_sort_key(left.0).cmp(completion_item_sort_key(right.0)))
+            .then_with(|| left.0.label.cmp(&right.0.label))
+    });
+
+    debug!(
+        token = token,
+        original_count = items.len(),
+        returned_count = matches.len(),
+        "completion: ranked fuzzy completion candidates"
+    );
+
+    matches
+}
+
 fn completion_item_priority(item: &CompletionItem) -> u8 {
     if item.preselect.unwrap_or(false)
         || item
@@ -168,6 +226,37 @@ fn is_unscoped_variable_completion(item: &CompletionItem) -> bool {
         )
 }
 
+/// Text for the completion menu's documentation panel: the item's detail
+/// followed by its documentation.
+///
+/// The menu reflows the text to its width, so markdown markup is reduced
+/// to plain words.
+pub(crate) fn completion_item_description(item: &CompletionItem) -> Option<String> {
+    let documentation = completion_item_documentation_text(item)
+        .map(documentation_plain_text)
+        .filter(|text| !text.is_empty());
+    match (item.detail.as_deref(), documentation) {
+        (Some(detail), Some(documentation)) => Some(format!("{detail} — {documentation}")),
+        (Some(detail), None) => Some(detail.to_string()),
+        (None, documentation) => documentation,
+    }
+}
+
+/// Strip markdown fences, heading markers, and emphasis from documentation.
+fn documentation_plain_text(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with("```") && !line.chars().all(|ch| ch == '-'))
+        .map(|line| line.trim_start_matches('#').trim_start())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("**", "")
+        .replace('`', "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 fn completion_item_documentation_text(item: &CompletionItem) -> Option<&str> {
     match item.documentation.as_ref() {
         Some(Documentation::String(text)) => Some(text.as_str()),
@@ -337,6 +426,18 @@ mod tests {
         Range, TextEdit,
     };
 
+    fn completion_response_to_suggestions(
+        response: Option<CompletionResponse>,
+        buffer: &str,
+        cursor_byte_offset: usize,
+        matching: CompletionMatching,
+    ) -> Vec<Suggestion> {
+        completion_response_to_entries(response, buffer, cursor_byte_offset, matching)
+            .into_iter()
+            .map(|(_, suggestion)| suggestion)
+            .collect()
+    }
+
     // --- strip_snippet_syntax ---
 
     #[test]
@@ -525,11 +626,12 @@ mod tests {
         assert_eq!(span.end, 8);
     }
 
-    // --- completion_response_to_suggestions ---
+    // --- completion_response_to_entries ---
 
     #[test]
     fn empty_response() {
-        let suggestions = completion_response_to_suggestions(None, "Vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(None, "Vi", 2, CompletionMatching::Prefix);
         assert!(suggestions.is_empty());
     }
 
@@ -547,7 +649,8 @@ mod tests {
                 ..Default::default()
             },
         ]);
-        let suggestions = completion_response_to_suggestions(Some(response), "Vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "Vi", 2, CompletionMatching::Prefix);
         assert_eq!(suggestions.len(), 2);
         assert_eq!(suggestions[0].value, "View");
         assert_eq!(suggestions[0].description, Some("{utils}".to_string()));
@@ -575,7 +678,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "Vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "Vi", 2, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -601,7 +705,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "vi", 2, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -623,7 +728,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "Vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "Vi", 2, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -655,7 +761,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "Vi", 2);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "Vi", 2, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -694,7 +801,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "", 0);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "", 0, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -727,7 +835,8 @@ mod tests {
             },
         ]);
 
-        let suggestions = completion_response_to_suggestions(Some(response), "", 0);
+        let suggestions =
+            completion_response_to_suggestions(Some(response), "", 0, CompletionMatching::Prefix);
         let values: Vec<_> = suggestions
             .into_iter()
             .map(|suggestion| suggestion.value)
@@ -735,4 +844,167 @@ mod tests {
 
         assert_eq!(values, vec!["alpha".to_string(), "zebra".to_string()]);
     }
+
+    // --- fuzzy matching ---
+
+    fn fuzzy_values(items: Vec<CompletionItem>, buffer: &str) -> Vec<String> {
+        completion_response_to_suggestions(
+            Some(CompletionResponse::Array(items)),
+            buffer,
+            buffer.len(),
+            CompletionMatching::Fuzzy,
+        )
+        .into_iter()
+        .map(|suggestion| suggestion.value)
+        .collect()
+    }
+
+    fn labelled(labels: &[&str]) -> Vec<CompletionItem> {
+        labels
+            .iter()
+            .map(|label| CompletionItem {
+                label: label.to_string(),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fuzzy_matches_subsequences_with_indices() {
+        let response = CompletionResponse::Array(labelled(&["read.csv", "readline", "sd"]));
+        let suggestions = completion_response_to_suggestions(
+            Some(response),
+            "rdcsv",
+            5,
+            CompletionMatching::Fuzzy,
+        );
+
+        assert_eq!(suggestions.len(), 1);
+        assert_eq!(suggestions[0].value, "read.csv");
+        assert_eq!(suggestions[0].match_indices, Some(vec![0, 3, 5, 6, 7]));
+        assert_eq!(suggestions[0].span, Span::new(0, 5));
+    }
+
+    #[test]
+    fn fuzzy_ranks_better_matches_first() {
+        assert_eq!(
+            fuzzy_values(labelled(&["group_by", "ggplot", "geom_point"]), "gplot"),
+            vec!["ggplot".to_string()]
+        );
+        assert_eq!(
+            fuzzy_values(labelled(&["spread", "read.csv", "readRDS"]), "read"),
+            vec![
+                "readRDS".to_string(),
+                "read.csv".to_string(),
+                "spread".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn fuzzy_ranking_combines_match_quality_with_priority() {
+        let package_item = |label: &str| CompletionItem {
+            label: label.to_string(),
+            label_details: Some(CompletionItemLabelDetails {
+                detail: None,
+                description: Some("utils".to_string()),
+            }),
+            ..Default::default()
+        };
+        let variable = CompletionItem {
+            label: "my_data".to_string(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            ..Default::default()
+        };
+
+        // An equally good match from the global environment wins...
+        assert_eq!(
+            fuzzy_values(vec![package_item("my_dir"), variable], "my_d"),
+            vec!["my_data".to_string(), "my_dir".to_string()]
+        );
+        // ...but not over a much better one.
+        let scattered_variable = CompletionItem {
+            label: "price".to_string(),
+            kind: Some(CompletionItemKind::VARIABLE),
+            ..Default::default()
+        };
+        assert_eq!(
+            fuzzy_values(vec![scattered_variable, package_item("read.csv")], "rc"),
+            vec!["read.csv".to_string(), "price".to_string()]
+        );
+    }
+
+    #[test]
+    fn fuzzy_ranking_is_independent_of_response_order() {
+        let labels = ["data.frame", "data", "dat", "as.data.frame", "date", "Date"];
+        let forward = fuzzy_values(labelled(&labels), "dat");
+        let mut reversed_labels = labels;
+        reversed_labels.reverse();
+        let reversed = fuzzy_values(labelled(&reversed_labels), "dat");
+
+        assert_eq!(forward, reversed);
+        assert_eq!(
+            forward,
+            vec![
+                "dat".to_string(),
+                "data".to_string(),
+                "date".to_string(),
+                "Date".to_string(),
+                "data.frame".to_string(),
+                "as.data.frame".to_string()
+            ]
+        );
+    }
+
+    // --- documentation ---
+
+    #[test]
+    fn description_combines_detail_and_documentation() {
+        let item = CompletionItem {
+            label: "mean".to_string(),
+            detail: Some("mean(x, ...)".to_string()),
+            documentation: Some(Documentation::MarkupContent(lsp_types::MarkupContent {
+                kind: lsp_types::MarkupKind::Markdown,
+                value: "## Arithmetic Mean\n\n\
+                        Generic function for the **trimmed** `mean`.\n\n\
+                        ```r\nmean(x)\n```"
+                    .to_string(),
+            })),
+            ..Default::default()
+        };
+        assert_eq!(
+            completion_item_description(&item),
+            Some(
+                "mean(x, ...) — Arithmetic Mean Generic function for the trimmed mean. mean(x)"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn description_falls_back_to_detail_or_documentation() {
+        let detail_only = CompletionItem {
+            label: "sd".to_string(),
+            detail: Some("{stats}".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            completion_item_description(&detail_only),
+            Some("{stats}".to_string())
+        );
+
+        let documentation_only = CompletionItem {
+            label: "sd".to_string(),
+            documentation: Some(Documentation::String("Standard Deviation".to_string())),
+            ..Default::default()
+        };
+        assert_eq!(
+            completion_item_description(&documentation_only),
+            Some("Standard Deviation".to_string())
+        );
+        assert_eq!(
+            completion_item_description(&CompletionItem::default()),
+            None
+        );
+    }
 }
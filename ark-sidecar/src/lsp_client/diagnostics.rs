@@ -0,0 +1,211 @@
+// Diagnostics published by the LSP server for the console document.
+//
+// Ark sends `textDocument/publishDiagnostics` after each document change.
+// Ranges are converted to byte offsets against the document text they were
+// computed for. Diagnostics lag behind typing, so they are served back for
+// a newer buffer only while the text up to their span is unchanged; a
+// stale underline never lands on edited text.
+
+use std::sync::Mutex;
+
+use lsp_types::{Diagnostic, DiagnosticSeverity};
+
+use super::position::lsp_position_to_byte_offset;
+
+/// A diagnostic with its range mapped to byte offsets in the console buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConsoleDiagnostic {
+    pub start: usize,
+    pub end: usize,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+impl ConsoleDiagnostic {
+    fn from_lsp(text: &str, diagnostic: &Diagnostic) -> Self {
+        let start = lsp_position_to_byte_offset(text, &diagnostic.range.start);
+        let end = lsp_position_to_byte_offset(text, &diagnostic.range.end).max(start);
+        Self {
+            start,
+            end,
+            // The spec says a missing severity is up to the client; treat
+            // it as an error like VS Code does.
+            severity: diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR),
+            message: diagnostic.message.clone(),
+        }
+    }
+
+    /// Whether the cursor is on or right after this diagnostic's span.
+    pub fn contains(&self, cursor: usize) -> bool {
+        self.start <= cursor && cursor <= self.end
+    }
+}
+
+#[derive(Debug, Default)]
+struct DiagnosticSet {
+    text: String,
+    diagnostics: Vec<ConsoleDiagnostic>,
+}
+
+/// Latest diagnostics for the console document.
+#[derive(Debug, Default)]
+pub(crate) struct DiagnosticsStore {
+    latest: Mutex<DiagnosticSet>,
+}
+
+impl DiagnosticsStore {
+    /// Replace the stored diagnostics with a new publication for `text`.
+    pub fn publish(&self, text: &str, diagnostics: &[Diagnostic]) {
+        let diagnostics = diagnostics
+            .iter()
+            .map(|diagnostic| ConsoleDiagnostic::from_lsp(text, diagnostic))
+            .collect();
+        let mut latest = self.latest.lock().expect("diagnostics mutex poisoned");
+        *latest = DiagnosticSet {
+            text: text.to_string(),
+            diagnostics,
+        };
+    }
+
+    /// Diagnostics that still apply to `buffer`.
+    ///
+    /// A diagnostic applies while `buffer` shares the published text up to
+    /// the end of its span, e.g. when the user kept typing after it.
+    pub fn for_buffer(&self, buffer: &str) -> Vec<ConsoleDiagnostic> {
+        let latest = self.latest.lock().expect("diagnostics mutex poisoned");
+        let unchanged = common_prefix_len(&latest.text, buffer);
+        latest
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.end <= unchanged)
+            .cloned()
+            .collect()
+    }
+}
+
+pub(super) fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.bytes().zip(b.bytes()).take_while(|(a, b)| a == b).count()
+}
+
+/// Pick the diagnostic to describe for the cursor position.
+///
+/// Prefers the most severe diagnostic whose span touches the cursor.
+pub(crate) fn diagnostic_at(
+    diagnostics: &[ConsoleDiagnostic],
+    cursor: usize,
+) -> Option<&ConsoleDiagnostic> {
+    diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.contains(cursor))
+        // DiagnosticSeverity::ERROR is 1, HINT is 4.
+        .min_by_key(|diagnostic| diagnostic.severity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{Position, Range};
+
+    fn lsp_diagnostic(
+        start: (u32, u32),
+        end: (u32, u32),
+        severity: Option<DiagnosticSeverity>,
+        message: &str,
+    ) -> Diagnostic {
+        Diagnostic {
+            range: Range {
+                start: Position {
+                    line: start.0,
+                    character: start.1,
+                },
+                end: Position {
+                    line: end.0,
+                    character: end.1,
+                },
+            },
+            severity,
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ranges_are_mapped_to_byte_offsets() {
+        let store = DiagnosticsStore::default();
+        let text = "x <- \u{4e2d}\nfoo(bar)";
+        store.publish(
+            text,
+            &[lsp_diagnostic(
+                (1, 4),
+                (1, 7),
+                Some(DiagnosticSeverity::WARNING),
+                "No symbol named 'bar' in scope.",
+            )],
+        );
+
+        let diagnostics = store.for_buffer(text);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(&text[diagnostics[0].start..diagnostics[0].end], "bar");
+        assert_eq!(diagnostics[0].severity, DiagnosticSeverity::WARNING);
+    }
+
+    #[test]
+    fn missing_severity_is_an_error() {
+        let store = DiagnosticsStore::default();
+        store.publish("f(", &[lsp_diagnostic((0, 1), (0, 2), None, "Unmatched (")]);
+        assert_eq!(
+            store.for_buffer("f(")[0].severity,
+            DiagnosticSeverity::ERROR
+        );
+    }
+
+    #[test]
+    fn diagnostics_survive_typing_after_their_span() {
+        let store = DiagnosticsStore::default();
+        store.publish(
+            "foo(bar)",
+            &[lsp_diagnostic((0, 4), (0, 7), None, "No symbol")],
+        );
+        assert_eq!(store.for_buffer("foo(bar)").len(), 1);
+        assert_eq!(store.for_buffer("foo(bar) + 1").len(), 1);
+    }
+
+    #[test]
+    fn edited_spans_are_not_served() {
+        let store = DiagnosticsStore::default();
+        store.publish(
+            "foo(bar)",
+            &[lsp_diagnostic((0, 4), (0, 7), None, "No symbol")],
+        );
+        assert!(store.for_buffer("foo(baz)").is_empty());
+        assert!(store.for_buffer("foo(ba").is_empty());
+        assert!(store.for_buffer("").is_empty());
+    }
+
+    #[test]
+    fn diagnostic_at_prefers_errors() {
+        let diagnostics = vec![
+            ConsoleDiagnostic {
+                start: 0,
+                end: 8,
+                severity: DiagnosticSeverity::WARNING,
+                message: "warning".to_string(),
+            },
+            ConsoleDiagnostic {
+                start: 4,
+                end: 7,
+                severity: DiagnosticSeverity::ERROR,
+                message: "error".to_string(),
+            },
+        ];
+        assert_eq!(
+            diagnostic_at(&diagnostics, 5).map(|d| d.message.as_str()),
+            Some("error")
+        );
+        assert_eq!(
+            diagnostic_at(&diagnostics, 1).map(|d| d.message.as_str()),
+            Some("warning")
+        );
+        assert!(diagnostic_at(&diagnostics, 9).is_none());
+    }
+}
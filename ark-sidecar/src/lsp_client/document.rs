@@ -78,6 +78,15 @@ impl ConsoleDocument {
         &self.uri
     }
 
+    /// Get the current document version.
+    pub fn version(&self) -> i32 {
+        self.version
+    }
+
+    /// Get the current document content.
+    pub fn content(&self) -> &str {
+        &self.content
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,188 @@
+// Fuzzy (subsequence) matching for console completion.
+//
+// A pattern matches a candidate when its characters appear in order,
+// ignoring case. Among all such alignments the best-scoring one is kept:
+// matches at word starts (`read.csv` -> `c`, `readCsv` -> `C`) and runs of
+// consecutive characters score higher, skipped and trailing characters
+// cost a little so shorter candidates win ties.
+
+/// Score for every matched character.
+const SCORE_MATCH: i64 = 16;
+/// Bonus for matching at the start of a word.
+const BONUS_BOUNDARY: i64 = 10;
+/// Extra bonus for matching the candidate's first character.
+const BONUS_FIRST_CHAR: i64 = 8;
+/// Bonus for a match directly following the previous one.
+const BONUS_CONSECUTIVE: i64 = 8;
+/// Bonus for matching with the same case as typed.
+const BONUS_EXACT_CASE: i64 = 1;
+/// Penalty per candidate character skipped between or before matches.
+const PENALTY_GAP: i64 = 2;
+/// Penalty per candidate character after the last match.
+const PENALTY_TRAILING: i64 = 1;
+
+/// The best alignment of a pattern within a candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FuzzyMatch {
+    /// Higher is better.
+    pub score: i64,
+    /// Character indices of the matched characters in the candidate.
+    pub indices: Vec<usize>,
+}
+
+fn chars_match(text: char, pattern: char) -> bool {
+    text == pattern || text.to_lowercase().eq(pattern.to_lowercase())
+}
+
+fn is_word_start(text: &[char], index: usize) -> bool {
+    let Some(&previous) = index.checked_sub(1).and_then(|i| text.get(i)) else {
+        return true;
+    };
+    let current = text[index];
+    !previous.is_alphanumeric() || (previous.is_lowercase() && current.is_uppercase())
+}
+
+fn match_bonus(text: &[char], index: usize, pattern: char) -> i64 {
+    let mut bonus = 0;
+    if is_word_start(text, index) {
+        bonus += BONUS_BOUNDARY;
+    }
+    if index == 0 {
+        bonus += BONUS_FIRST_CHAR;
+    }
+    if text[index] == pattern {
+        bonus += BONUS_EXACT_CASE;
+    }
+    bonus
+}
+
+/// Match `pattern` as a subsequence of `candidate`.
+///
+/// Returns `None` when some pattern character can't be matched in order.
+/// An empty pattern matches everything with a score of zero.
+pub(crate) fn fuzzy_match(candidate: &str, pattern: &str) -> Option<FuzzyMatch> {
+    let pattern: Vec<char> = pattern.chars().collect();
+    if pattern.is_empty() {
+        return Some(FuzzyMatch {
+            score: 0,
+            indices: Vec::new(),
+        });
+    }
+    let text: Vec<char> = candidate.chars().collect();
+    if pattern.len() > text.len() {
+        return None;
+    }
+
+    // scores[i][j]: best score with pattern[..=i] matched and pattern[i] at
+    // text[j]; previous[i][j]: where pattern[i - 1] was matched.
+    let (rows, columns) = (pattern.len(), text.len());
+    let mut scores = vec![vec![None::<i64>; columns]; rows];
+    let mut previous = vec![vec![0usize; columns]; rows];
+
+    for (i, &pattern_char) in pattern.iter().enumerate() {
+        // Best `scores[i - 1][k] + k * PENALTY_GAP` over k < j - 1, so a
+        // gapped transition costs `(j - k - 1) * PENALTY_GAP`.
+        let mut best_gapped: Option<(i64, usize)> = None;
+        for j in i..columns {
+            if i > 0 && j >= 2 {
+                if let Some(score) = scores[i - 1][j - 2] {
+                    let value = score + (j - 2) as i64 * PENALTY_GAP;
+                    if best_gapped.is_none_or(|(best, _)| value > best) {
+                        best_gapped = Some((value, j - 2));
+                    }
+                }
+            }
+            if !chars_match(text[j], pattern_char) {
+                continue;
+            }
+
+            let from = if i == 0 {
+                Some((-(j as i64) * PENALTY_GAP, 0))
+            } else {
+                let consecutive =
+                    scores[i - 1][j - 1].map(|score| (score + BONUS_CONSECUTIVE, j - 1));
+                let gapped =
+                    best_gapped.map(|(value, k)| (value - (j - 1) as i64 * PENALTY_GAP, k));
+                match (consecutive, gapped) {
+                    (Some(a), Some(b)) => Some(if b.0 > a.0 { b } else { a }),
+                    (a, b) => a.or(b),
+                }
+            };
+            if let Some((score, k)) = from {
+                scores[i][j] = Some(score + SCORE_MATCH + match_bonus(&text, j, pattern_char));
+                previous[i][j] = k;
+            }
+        }
+    }
+
+    // Earliest end position wins ties.
+    let (mut j, score) = scores[rows - 1]
+        .iter()
+        .enumerate()
+        .filter_map(|(j, score)| {
+            score.map(|score| (j, score - (columns - j - 1) as i64 * PENALTY_TRAILING))
+        })
+        .fold(None, |best: Option<(usize, i64)>, (j, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((j, score)),
+        })?;
+
+    let mut indices = vec![0; rows];
+    for i in (0..rows).rev() {
+        indices[i] = j;
+        j = previous[i][j];
+    }
+    Some(FuzzyMatch { score, indices })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(candidate: &str, pattern: &str) -> Option<Vec<usize>> {
+        fuzzy_match(candidate, pattern).map(|m| m.indices)
+    }
+
+    fn score(candidate: &str, pattern: &str) -> i64 {
+        fuzzy_match(candidate, pattern).unwrap().score
+    }
+
+    #[test]
+    fn matches_subsequences_ignoring_case() {
+        assert_eq!(indices("read.csv", "rdcsv"), Some(vec![0, 3, 5, 6, 7]));
+        assert_eq!(indices("ggplot", "GPLOT"), Some(vec![0, 2, 3, 4, 5]));
+        assert_eq!(indices("mean", "man"), Some(vec![0, 2, 3]));
+        assert_eq!(indices("mean", "nm"), None);
+        assert_eq!(indices("sd", "sdx"), None);
+    }
+
+    #[test]
+    fn empty_pattern_matches_everything() {
+        assert_eq!(
+            fuzzy_match("anything", ""),
+            Some(FuzzyMatch {
+                score: 0,
+                indices: Vec::new()
+            })
+        );
+    }
+
+    #[test]
+    fn prefers_word_starts() {
+        // `c` and `s` align with `csv`, not the `s` in `datasets`.
+        assert_eq!(indices("datasets.csv", "dcsv"), Some(vec![0, 9, 10, 11]));
+        assert_eq!(indices("readCsv", "rc"), Some(vec![0, 4]));
+    }
+
+    #[test]
+    fn ranks_word_start_and_consecutive_matches_higher() {
+        assert!(score("read.csv", "read") > score("spread", "read"));
+        assert!(score("read_csv", "rcsv") > score("recursive", "rcsv"));
+        assert!(score("geom_point", "gpt") > score("ggplot", "gpt"));
+    }
+
+    #[test]
+    fn exact_case_breaks_ties() {
+        assert!(score("View", "Vi") > score("view", "Vi"));
+    }
+}
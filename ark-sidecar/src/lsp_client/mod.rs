@@ -1,48 +1,131 @@
-// Minimal LSP client for console completion.
+// Minimal LSP client for console completion and diagnostics.
 //
-// Connects to Ark's LSP server over TCP and provides textDocument/completion.
-// Manages a single in-memory document representing the console buffer.
+// Connects to Ark's LSP server over TCP and provides textDocument/completion
+// and textDocument/signatureHelp.
+// Manages a single in-memory document representing the console buffer and
+// keeps the diagnostics Ark publishes for it, plus semantic tokens when the
+// server offers them. The connection can be swapped for a new LSP server
+// after the console reattaches to a restarted kernel.
 
 pub(crate) mod completion;
+pub(crate) mod diagnostics;
 pub(crate) mod document;
+pub(crate) mod fuzzy;
 pub(crate) mod position;
+pub(crate) mod semantic_tokens;
+pub(crate) mod signature_help;
 pub(crate) mod transport;
 pub(crate) mod virtual_document;
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use lsp_types::{
-    ClientCapabilities, CompletionClientCapabilities, CompletionItemCapability, CompletionParams,
-    CompletionResponse, InitializeParams, InitializeResult, InitializedParams,
+    ClientCapabilities, CompletionClientCapabilities, CompletionItem, CompletionItemCapability,
+    CompletionItemCapabilityResolveSupport, CompletionParams, CompletionResponse, InitializeParams,
+    InitializeResult, InitializedParams, MarkupKind, ParameterInformationSettings,
+    PublishDiagnosticsClientCapabilities, PublishDiagnosticsParams,
+    SemanticTokensClientCapabilities, SemanticTokensClientCapabilitiesRequests,
+    SemanticTokensFullOptions, SemanticTokensLegend, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, SignatureHelp,
+    SignatureHelpClientCapabilities, SignatureHelpParams, SignatureInformationSettings,
     TextDocumentClientCapabilities, TextDocumentPositionParams, TextDocumentSyncClientCapabilities,
+    TokenFormat,
 };
-use tracing::{debug, info};
+use serde_json::Value;
+use tracing::{debug, info, warn};
 
+use self::diagnostics::DiagnosticsStore;
 use self::document::ConsoleDocument;
 use self::position::byte_offset_to_lsp_position;
-use self::transport::LspTransport;
+use self::semantic_tokens::{decode_semantic_tokens, SemanticKind, SemanticTokensStore};
+use self::transport::{LspTransport, NotificationHandler};
+
+/// How long a server being replaced gets to acknowledge `shutdown`.
+const LSP_SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
 
 /// LSP client for console completion.
 ///
 /// Maintains a TCP connection to Ark's LSP server and an in-memory document
 /// for the console buffer. Thread-safe: the document state is behind a Mutex.
 pub(crate) struct LspClient {
-    transport: LspTransport,
-    document: Mutex<ConsoleDocument>,
+    transport: Mutex<Arc<LspTransport>>,
+    document: Arc<Mutex<ConsoleDocument>>,
+    diagnostics: Arc<DiagnosticsStore>,
+    semantic_tokens: Arc<SemanticTokensStore>,
+    /// Legend for decoding semantic tokens, if the server computes them
+    /// for whole documents.
+    semantic_legend: Mutex<Option<SemanticTokensLegend>>,
 }
 
 impl LspClient {
     /// Connect to the LSP server at the given address and port.
     pub async fn connect(ip: &str, port: u16) -> Result<Self> {
         info!(ip = %ip, port = port, "LspClient: connecting to LSP server");
-        let transport = LspTransport::connect(ip, port).await?;
+        let document = Arc::new(Mutex::new(ConsoleDocument::new()));
+        let diagnostics = Arc::new(DiagnosticsStore::default());
+        let on_notification = notification_handler(&document, &diagnostics);
+        let transport = LspTransport::connect(ip, port, on_notification).await?;
         Ok(Self {
-            transport,
-            document: Mutex::new(ConsoleDocument::new()),
+            transport: Mutex::new(Arc::new(transport)),
+            document,
+            diagnostics,
+            semantic_tokens: Arc::new(SemanticTokensStore::default()),
+            semantic_legend: Mutex::new(None),
         })
     }
 
+    /// Switch to a new LSP server and initialize it with the current
+    /// console document, e.g. after the kernel restarted.
+    ///
+    /// The old server is shut down first, in case it is still running.
+    pub async fn reconnect(&self, ip: &str, port: u16) -> Result<InitializeResult> {
+        info!(ip = %ip, port = port, "LspClient: reconnecting to LSP server");
+        shutdown_transport(&self.transport()).await;
+        let on_notification = notification_handler(&self.document, &self.diagnostics);
+        let transport = LspTransport::connect(ip, port, on_notification).await?;
+        *self.transport.lock().expect("transport mutex poisoned") = Arc::new(transport);
+
+        // Diagnostics and tokens from the old server no longer apply
+        let content = self
+            .document
+            .lock()
+            .expect("document mutex poisoned")
+            .content()
+            .to_string();
+        self.diagnostics.publish(&content, &[]);
+        self.semantic_tokens.publish(&content, Vec::new());
+        self.initialize().await
+    }
+
+    fn transport(&self) -> Arc<LspTransport> {
+        Arc::clone(&self.transport.lock().expect("transport mutex poisoned"))
+    }
+
+    /// Shared handle to the diagnostics published for the console document.
+    pub fn diagnostics(&self) -> Arc<DiagnosticsStore> {
+        Arc::clone(&self.diagnostics)
+    }
+
+    /// Shared handle to the semantic tokens computed for the console document.
+    pub fn semantic_tokens(&self) -> Arc<SemanticTokensStore> {
+        Arc::clone(&self.semantic_tokens)
+    }
+
+    /// Whether the connection to the LSP server is still open.
+    pub fn is_connected(&self) -> bool {
+        self.transport().is_connected()
+    }
+
+    /// Version of the console document last synced to the server.
+    pub fn document_version(&self) -> i32 {
+        self.document
+            .lock()
+            .expect("document mutex poisoned")
+            .version()
+    }
+
     /// Send initialize request, initialized notification, and didOpen.
     pub async fn initialize(&self) -> Result<InitializeResult> {
         #[allow(deprecated)] // root_uri is deprecated in favor of workspace_folders
@@ -54,6 +137,13 @@ impl LspClient {
                     completion: Some(CompletionClientCapabilities {
                         completion_item: Some(CompletionItemCapability {
                             snippet_support: Some(false),
+                            documentation_format: Some(vec![
+                                MarkupKind::PlainText,
+                                MarkupKind::Markdown,
+                            ]),
+                            resolve_support: Some(CompletionItemCapabilityResolveSupport {
+                                properties: vec!["documentation".to_string(), "detail".to_string()],
+                            }),
                             ..Default::default()
                         }),
                         ..Default::default()
@@ -62,6 +152,31 @@ impl LspClient {
                         did_save: Some(false),
                         ..Default::default()
                     }),
+                    signature_help: Some(SignatureHelpClientCapabilities {
+                        signature_information: Some(SignatureInformationSettings {
+                            parameter_information: Some(ParameterInformationSettings {
+                                label_offset_support: Some(true),
+                            }),
+                            active_parameter_support: Some(true),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                    publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
+                        version_support: Some(true),
+                        ..Default::default()
+                    }),
+                    semantic_tokens: Some(SemanticTokensClientCapabilities {
+                        requests: SemanticTokensClientCapabilitiesRequests {
+                            range: Some(false),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                        },
+                        token_types: SemanticKind::token_types(),
+                        token_modifiers: Vec::new(),
+                        formats: vec![TokenFormat::RELATIVE],
+                        multiline_token_support: Some(false),
+                        ..Default::default()
+                    }),
                     ..Default::default()
                 }),
                 ..Default::default()
@@ -70,24 +185,30 @@ impl LspClient {
         };
 
         let result: InitializeResult = self
-            .transport
+            .transport()
             .request("initialize", &params)
             .await
             .context("LSP initialize failed")?;
 
+        let semantic_legend = full_document_semantic_legend(&result.capabilities);
         info!(
             server_name = ?result.server_info.as_ref().map(|s| &s.name),
+            semantic_tokens = semantic_legend.is_some(),
             "LspClient: server initialized"
         );
+        *self
+            .semantic_legend
+            .lock()
+            .expect("semantic legend mutex poisoned") = semantic_legend;
 
-        self.transport
+        self.transport()
             .notify("initialized", &InitializedParams {})
             .await
             .context("LSP initialized notification failed")?;
 
         // Open the console document
         let did_open = self.document.lock().expect("document mutex poisoned").did_open_params();
-        self.transport
+        self.transport()
             .notify("textDocument/didOpen", &did_open)
             .await
             .context("LSP didOpen failed")?;
@@ -120,7 +241,7 @@ impl LspClient {
             buffer_len = buffer.len(),
             "LspClient: sending textDocument/didChange"
         );
-        self.transport
+        self.transport()
             .notify("textDocument/didChange", &did_change)
             .await
             .context("LSP didChange failed")?;
@@ -163,7 +284,7 @@ impl LspClient {
         );
 
         let response: Option<CompletionResponse> = self
-            .transport
+            .transport()
             .request("textDocument/completion", &params)
             .await
             .context("LSP completion request failed")?;
@@ -181,21 +302,222 @@ impl LspClient {
         Ok(response)
     }
 
+    /// Fill in the documentation and detail of a completion item.
+    ///
+    /// Ark leaves these out of completion responses and computes them on
+    /// `completionItem/resolve`.
+    pub async fn resolve_completion_item(&self, item: &CompletionItem) -> Result<CompletionItem> {
+        debug!(label = %item.label, "LspClient: resolving completion item");
+
+        self.transport()
+            .request("completionItem/resolve", item)
+            .await
+            .context("LSP completion resolve request failed")
+    }
+
+    /// Request signature help for the call around the given byte offset.
+    ///
+    /// The caller is responsible for synchronizing the virtual document first.
+    pub async fn request_signature_help(
+        &self,
+        buffer: &str,
+        cursor_byte_offset: usize,
+    ) -> Result<Option<SignatureHelp>> {
+        let uri = {
+            let doc = self.document.lock().expect("document mutex poisoned");
+            doc.uri().clone()
+        };
+
+        let position = byte_offset_to_lsp_position(buffer, cursor_byte_offset);
+
+        let params = SignatureHelpParams {
+            context: None,
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: lsp_types::TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: Default::default(),
+        };
+
+        debug!(
+            line = position.line,
+            character = position.character,
+            cursor = cursor_byte_offset,
+            "LspClient: requesting signature help"
+        );
+
+        let response: Option<SignatureHelp> = self
+            .transport()
+            .request("textDocument/signatureHelp", &params)
+            .await
+            .context("LSP signature help request failed")?;
+
+        debug!(
+            count = response.as_ref().map_or(0, |help| help.signatures.len()),
+            "LspClient: signature help response received"
+        );
+
+        Ok(response)
+    }
+
+    /// Request semantic tokens for the synced console document and store
+    /// them, if the server computes them.
+    ///
+    /// Tokens that come back after the document changed again are dropped.
+    pub async fn refresh_semantic_tokens(&self) -> Result<()> {
+        let legend = self
+            .semantic_legend
+            .lock()
+            .expect("semantic legend mutex poisoned")
+            .clone();
+        let Some(legend) = legend else {
+            return Ok(());
+        };
+        let (uri, version, content) = {
+            let doc = self.document.lock().expect("document mutex poisoned");
+            (doc.uri().clone(), doc.version(), doc.content().to_string())
+        };
+
+        let params = SemanticTokensParams {
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+            text_document: lsp_types::TextDocumentIdentifier { uri },
+        };
+
+        debug!(version = version, "LspClient: requesting semantic tokens");
+
+        let response: Option<SemanticTokensResult> = self
+            .transport()
+            .request("textDocument/semanticTokens/full", &params)
+            .await
+            .context("LSP semantic tokens request failed")?;
+
+        if self.document_version() != version {
+            debug!(
+                version = version,
+                "LspClient: dropping semantic tokens for stale document version"
+            );
+            return Ok(());
+        }
+        let data = match response {
+            Some(SemanticTokensResult::Tokens(tokens)) => tokens.data,
+            Some(SemanticTokensResult::Partial(partial)) => partial.data,
+            None => Vec::new(),
+        };
+        let tokens = decode_semantic_tokens(&content, &data, &legend);
+        debug!(
+            count = tokens.len(),
+            version = version,
+            "LspClient: received semantic tokens"
+        );
+        self.semantic_tokens.publish(&content, tokens);
+        Ok(())
+    }
+
     /// Send shutdown request and exit notification.
     #[allow(dead_code)] // Kept for future graceful shutdown support
     pub async fn shutdown(&self) -> Result<()> {
-        debug!("LspClient: shutting down");
-        // shutdown expects a null result
-        let _: () = self
-            .transport
-            .request("shutdown", &serde_json::Value::Null)
-            .await
-            .unwrap_or(());
-        self.transport
-            .notify("exit", &serde_json::Value::Null)
-            .await
-            .ok();
-        info!("LspClient: shutdown complete");
+        shutdown_transport(&self.transport()).await;
         Ok(())
     }
 }
+
+/// Ask the server behind `transport` to exit, then stop reading from it.
+///
+/// Best-effort: a server that is gone or slow to answer is just
+/// disconnected.
+async fn shutdown_transport(transport: &LspTransport) {
+    debug!("LspClient: shutting down");
+    if transport.is_connected() {
+        // shutdown expects a null result
+        let shutdown = async {
+            let _: () = transport
+                .request("shutdown", &serde_json::Value::Null)
+                .await?;
+            transport.notify("exit", &serde_json::Value::Null).await
+        };
+        match tokio::time::timeout(LSP_SHUTDOWN_TIMEOUT, shutdown).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => debug!(error = ?err, "LspClient: shutdown request failed"),
+            Err(_) => debug!("LspClient: shutdown request timed out"),
+        }
+    }
+    transport.close();
+    info!("LspClient: shutdown complete");
+}
+
+/// The semantic token legend, if the server serves tokens for whole
+/// documents (`textDocument/semanticTokens/full`).
+fn full_document_semantic_legend(
+    capabilities: &ServerCapabilities,
+) -> Option<SemanticTokensLegend> {
+    let options = match capabilities.semantic_tokens_provider.as_ref()? {
+        SemanticTokensServerCapabilities::SemanticTokensOptions(options) => options,
+        SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(registration) => {
+            &registration.semantic_tokens_options
+        }
+    };
+    match options.full {
+        Some(SemanticTokensFullOptions::Bool(true) | SemanticTokensFullOptions::Delta { .. }) => {
+            Some(options.legend.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Route server notifications to the console document's diagnostics.
+fn notification_handler(
+    document: &Arc<Mutex<ConsoleDocument>>,
+    diagnostics: &Arc<DiagnosticsStore>,
+) -> NotificationHandler {
+    let document = Arc::clone(document);
+    let diagnostics = Arc::clone(diagnostics);
+    Box::new(move |method: &str, params: Option<Value>| {
+        if method == "textDocument/publishDiagnostics" {
+            handle_publish_diagnostics(&document, &diagnostics, params);
+        }
+    })
+}
+
+/// Store diagnostics Ark published for the current console document.
+///
+/// Publications for other documents or older document versions are
+/// dropped; their ranges would not line up with the current buffer.
+fn handle_publish_diagnostics(
+    document: &Mutex<ConsoleDocument>,
+    diagnostics: &DiagnosticsStore,
+    params: Option<Value>,
+) {
+    let params: PublishDiagnosticsParams =
+        match serde_json::from_value(params.unwrap_or(Value::Null)) {
+            Ok(params) => params,
+            Err(err) => {
+                warn!(error = ?err, "LspClient: invalid publishDiagnostics params");
+                return;
+            }
+        };
+
+    let document = document.lock().expect("document mutex poisoned");
+    if params.uri != *document.uri() {
+        debug!(uri = %params.uri.as_str(), "LspClient: ignoring diagnostics for other document");
+        return;
+    }
+    if params
+        .version
+        .is_some_and(|version| version != document.version())
+    {
+        debug!(
+            version = ?params.version,
+            current_version = document.version(),
+            "LspClient: ignoring diagnostics for stale document version"
+        );
+        return;
+    }
+
+    debug!(
+        count = params.diagnostics.len(),
+        version = document.version(),
+        "LspClient: received console diagnostics"
+    );
+    diagnostics.publish(document.content(), &params.diagnostics);
+}
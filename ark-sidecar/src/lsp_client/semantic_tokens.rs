@@ -0,0 +1,218 @@
+// Semantic tokens computed by the LSP server for the console document.
+//
+// Tree-sitter only sees syntax; the server knows which identifiers are
+// functions, parameters or package namespaces. Tokens are requested after
+// each debounced document sync and decoded against the legend the server
+// advertised. Like diagnostics, they are served back for a newer buffer
+// only up to the first edit, so text the server hasn't seen yet keeps its
+// tree-sitter colors.
+
+use std::sync::Mutex;
+
+use lsp_types::{Position, SemanticToken, SemanticTokenType, SemanticTokensLegend};
+
+use super::diagnostics::common_prefix_len;
+use super::position::lsp_position_to_byte_offset;
+
+/// What the server says an identifier is. Token types without a
+/// highlighting of their own are dropped while decoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SemanticKind {
+    Namespace,
+    Function,
+    Parameter,
+    Variable,
+    Type,
+}
+
+impl SemanticKind {
+    fn from_lsp(token_type: &SemanticTokenType) -> Option<Self> {
+        match token_type.as_str() {
+            "namespace" => Some(SemanticKind::Namespace),
+            "function" | "method" | "macro" => Some(SemanticKind::Function),
+            "parameter" => Some(SemanticKind::Parameter),
+            "variable" | "property" => Some(SemanticKind::Variable),
+            "type" | "class" => Some(SemanticKind::Type),
+            _ => None,
+        }
+    }
+
+    /// Token types the client asks the server for.
+    pub fn token_types() -> Vec<SemanticTokenType> {
+        vec![
+            SemanticTokenType::NAMESPACE,
+            SemanticTokenType::FUNCTION,
+            SemanticTokenType::METHOD,
+            SemanticTokenType::MACRO,
+            SemanticTokenType::PARAMETER,
+            SemanticTokenType::VARIABLE,
+            SemanticTokenType::PROPERTY,
+            SemanticTokenType::TYPE,
+            SemanticTokenType::CLASS,
+        ]
+    }
+}
+
+/// A semantic token with its range mapped to byte offsets in the console
+/// buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ConsoleSemanticToken {
+    pub start: usize,
+    pub end: usize,
+    pub kind: SemanticKind,
+}
+
+/// Decode the relative, UTF-16 based token data of a response for `text`.
+pub(crate) fn decode_semantic_tokens(
+    text: &str,
+    data: &[SemanticToken],
+    legend: &SemanticTokensLegend,
+) -> Vec<ConsoleSemanticToken> {
+    let mut line = 0;
+    let mut character = 0;
+    let mut tokens = Vec::new();
+    for token in data {
+        if token.delta_line > 0 {
+            line += token.delta_line;
+            character = token.delta_start;
+        } else {
+            character += token.delta_start;
+        }
+
+        let Some(kind) = legend
+            .token_types
+            .get(token.token_type as usize)
+            .and_then(SemanticKind::from_lsp)
+        else {
+            continue;
+        };
+        let start = lsp_position_to_byte_offset(text, &Position { line, character });
+        let end = lsp_position_to_byte_offset(
+            text,
+            &Position {
+                line,
+                character: character + token.length,
+            },
+        );
+        if start < end {
+            tokens.push(ConsoleSemanticToken { start, end, kind });
+        }
+    }
+    tokens
+}
+
+#[derive(Debug, Default)]
+struct TokenSet {
+    text: String,
+    tokens: Vec<ConsoleSemanticToken>,
+}
+
+/// Latest semantic tokens for the console document.
+#[derive(Debug, Default)]
+pub(crate) struct SemanticTokensStore {
+    latest: Mutex<TokenSet>,
+}
+
+impl SemanticTokensStore {
+    /// Replace the stored tokens with ones computed for `text`.
+    pub fn publish(&self, text: &str, tokens: Vec<ConsoleSemanticToken>) {
+        let mut latest = self.latest.lock().expect("semantic tokens mutex poisoned");
+        *latest = TokenSet {
+            text: text.to_string(),
+            tokens,
+        };
+    }
+
+    /// Tokens that still apply to `buffer`: those before its first
+    /// difference from the text they were computed for.
+    pub fn for_buffer(&self, buffer: &str) -> Vec<ConsoleSemanticToken> {
+        let latest = self.latest.lock().expect("semantic tokens mutex poisoned");
+        let unchanged = common_prefix_len(&latest.text, buffer);
+        latest
+            .tokens
+            .iter()
+            .filter(|token| token.end <= unchanged)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legend() -> SemanticTokensLegend {
+        SemanticTokensLegend {
+            token_types: vec![
+                SemanticTokenType::FUNCTION,
+                SemanticTokenType::PARAMETER,
+                SemanticTokenType::NAMESPACE,
+                SemanticTokenType::KEYWORD,
+            ],
+            token_modifiers: Vec::new(),
+        }
+    }
+
+    fn token(delta_line: u32, delta_start: u32, length: u32, token_type: u32) -> SemanticToken {
+        SemanticToken {
+            delta_line,
+            delta_start,
+            length,
+            token_type,
+            token_modifiers_bitset: 0,
+        }
+    }
+
+    fn spans(text: &str, tokens: &[ConsoleSemanticToken]) -> Vec<(String, SemanticKind)> {
+        tokens
+            .iter()
+            .map(|token| (text[token.start..token.end].to_string(), token.kind))
+            .collect()
+    }
+
+    #[test]
+    fn relative_positions_are_decoded_to_byte_ranges() {
+        let text = "f <- function(x) {\n  dplyr::filter(x)\n}";
+        let data = [
+            token(0, 0, 1, 0),
+            token(0, 14, 1, 1),
+            token(1, 2, 5, 2),
+            token(0, 7, 6, 0),
+            token(0, 7, 1, 1),
+        ];
+        assert_eq!(
+            spans(text, &decode_semantic_tokens(text, &data, &legend())),
+            vec![
+                ("f".to_string(), SemanticKind::Function),
+                ("x".to_string(), SemanticKind::Parameter),
+                ("dplyr".to_string(), SemanticKind::Namespace),
+                ("filter".to_string(), SemanticKind::Function),
+                ("x".to_string(), SemanticKind::Parameter),
+            ]
+        );
+    }
+
+    #[test]
+    fn utf16_columns_and_unknown_types_are_handled() {
+        let text = "\u{4e2d} <- 1; g(y)";
+        // `keyword` has no highlighting of its own; index 9 is not in the legend
+        let data = [token(0, 0, 1, 3), token(0, 1, 2, 9), token(0, 7, 1, 0)];
+        assert_eq!(
+            spans(text, &decode_semantic_tokens(text, &data, &legend())),
+            vec![("g".to_string(), SemanticKind::Function)]
+        );
+    }
+
+    #[test]
+    fn tokens_are_dropped_past_the_first_edit() {
+        let store = SemanticTokensStore::default();
+        let text = "mean(x)";
+        store.publish(
+            text,
+            decode_semantic_tokens(text, &[token(0, 0, 4, 0), token(0, 5, 1, 1)], &legend()),
+        );
+        assert_eq!(store.for_buffer("mean(x)").len(), 2);
+        assert_eq!(store.for_buffer("mean(y)").len(), 1);
+        assert!(store.for_buffer("").is_empty());
+    }
+}
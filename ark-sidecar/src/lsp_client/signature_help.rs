@@ -0,0 +1,184 @@
+// Signature help for the console document, fetched in the background.
+//
+// The hinter runs on every repaint and must not wait for the server. It
+// records which call it wants and reads whatever answer is stored; the
+// request goes out once the document is synced, and the signature shows on
+// the first repaint after it arrives. A call is identified by the buffer and
+// the offset just past its `(`, and its answer still applies to a newer
+// buffer as long as the text up to that offset is unchanged, so typing the
+// arguments doesn't ask again.
+
+use std::sync::Mutex;
+
+use lsp_types::SignatureHelp;
+
+use super::diagnostics::common_prefix_len;
+
+/// A call in the console buffer.
+#[derive(Debug, Clone)]
+struct CallKey {
+    buffer: String,
+    /// Byte offset just past the call's opening parenthesis.
+    pos: usize,
+}
+
+impl CallKey {
+    fn new(buffer: &str, pos: usize) -> Self {
+        Self {
+            buffer: buffer.to_string(),
+            pos,
+        }
+    }
+
+    /// Whether this is the call at `pos` in `buffer`.
+    fn matches(&self, buffer: &str, pos: usize) -> bool {
+        self.pos == pos && common_prefix_len(&self.buffer, buffer) >= pos
+    }
+}
+
+#[derive(Debug)]
+struct WantedCall {
+    call: CallKey,
+    requested: bool,
+}
+
+#[derive(Debug)]
+struct Answer {
+    call: CallKey,
+    /// `None` when the server had no signature or didn't answer in time.
+    help: Option<SignatureHelp>,
+}
+
+#[derive(Debug, Default)]
+struct SignatureState {
+    wanted: Option<WantedCall>,
+    answer: Option<Answer>,
+}
+
+/// Latest signature help for the console document.
+#[derive(Debug, Default)]
+pub(crate) struct SignatureHelpStore {
+    state: Mutex<SignatureState>,
+}
+
+impl SignatureHelpStore {
+    /// The stored signature of the call at `pos` in `buffer`, if answered.
+    pub fn for_call(&self, buffer: &str, pos: usize) -> Option<SignatureHelp> {
+        let state = self.state.lock().expect("signature help mutex poisoned");
+        let answer = state.answer.as_ref()?;
+        if !answer.call.matches(buffer, pos) {
+            return None;
+        }
+        answer.help.clone()
+    }
+
+    /// Ask for the call at `pos` in `buffer`.
+    ///
+    /// Returns false when that call is already answered or asked for.
+    pub fn want(&self, buffer: &str, pos: usize) -> bool {
+        let mut state = self.state.lock().expect("signature help mutex poisoned");
+        let known = state
+            .answer
+            .as_ref()
+            .map(|answer| &answer.call)
+            .into_iter()
+            .chain(state.wanted.as_ref().map(|wanted| &wanted.call))
+            .any(|call| call.matches(buffer, pos));
+        if known {
+            return false;
+        }
+        state.wanted = Some(WantedCall {
+            call: CallKey::new(buffer, pos),
+            requested: false,
+        });
+        true
+    }
+
+    /// Offset of the wanted call to request now that the server has
+    /// `synced`, unless it was requested already or `synced` no longer
+    /// contains it.
+    pub fn take_request(&self, synced: &str) -> Option<usize> {
+        let mut state = self.state.lock().expect("signature help mutex poisoned");
+        let wanted = state.wanted.as_mut()?;
+        if wanted.requested || !wanted.call.matches(synced, wanted.call.pos) {
+            return None;
+        }
+        wanted.requested = true;
+        Some(wanted.call.pos)
+    }
+
+    /// Store the answer for the call at `pos` in `buffer`.
+    pub fn publish(&self, buffer: &str, pos: usize, help: Option<SignatureHelp>) {
+        let mut state = self.state.lock().expect("signature help mutex poisoned");
+        if state
+            .wanted
+            .as_ref()
+            .is_some_and(|wanted| wanted.call.matches(buffer, pos))
+        {
+            state.wanted = None;
+        }
+        state.answer = Some(Answer {
+            call: CallKey::new(buffer, pos),
+            help,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::SignatureInformation;
+
+    fn help(label: &str) -> SignatureHelp {
+        SignatureHelp {
+            signatures: vec![SignatureInformation {
+                label: label.to_string(),
+                documentation: None,
+                parameters: None,
+                active_parameter: None,
+            }],
+            active_signature: None,
+            active_parameter: None,
+        }
+    }
+
+    fn label(help: Option<SignatureHelp>) -> Option<String> {
+        help.map(|help| help.signatures[0].label.clone())
+    }
+
+    #[test]
+    fn a_call_is_requested_once_after_it_is_synced() {
+        let store = SignatureHelpStore::default();
+        assert!(store.want("mean(", 5));
+        assert!(!store.want("mean(x", 5));
+
+        assert_eq!(store.take_request("mea"), None);
+        assert_eq!(store.take_request("mean(x"), Some(5));
+        assert_eq!(store.take_request("mean(x"), None);
+    }
+
+    #[test]
+    fn answers_apply_while_the_call_is_unchanged() {
+        let store = SignatureHelpStore::default();
+        store.want("mean(", 5);
+        store.publish("mean(", 5, Some(help("mean(x, ...)")));
+
+        assert_eq!(
+            label(store.for_call("mean(x, na.rm = ", 5)).as_deref(),
+            Some("mean(x, ...)")
+        );
+        assert!(!store.want("mean(x, ", 5));
+        assert_eq!(store.for_call("mead(", 5), None);
+        assert!(store.want("mead(", 5));
+    }
+
+    #[test]
+    fn unanswered_calls_are_not_asked_again() {
+        let store = SignatureHelpStore::default();
+        store.want("f(", 2);
+        store.publish("f(", 2, None);
+
+        assert_eq!(store.for_call("f(", 2), None);
+        assert!(!store.want("f(x", 2));
+    }
+}
@@ -6,10 +6,13 @@
 use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
-use tokio::sync::Mutex;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
 use tracing::{debug, trace, warn};
 
 /// JSON-RPC 2.0 request (has id, expects response).
@@ -82,20 +85,38 @@ impl std::fmt::Display for JsonRpcError {
     }
 }
 
+/// Callback invoked for each server notification with its method and params.
+pub(crate) type NotificationHandler = Box<dyn Fn(&str, Option<Value>) + Send + Sync>;
+
+type BoxedReader = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+
+/// Response channels of in-flight requests, keyed by request id.
+///
+/// `None` once the reader task has stopped, so new requests fail fast
+/// instead of waiting for a response that can never arrive.
+type PendingResponses = Arc<StdMutex<Option<HashMap<i64, oneshot::Sender<JsonRpcMessage>>>>>;
+
 /// Low-level JSON-RPC transport with Content-Length framing over TCP.
 ///
-/// Designed for single-threaded request-response usage (one request at a time).
-/// Reader and writer are behind Mutexes for future-proofing but current usage
-/// is strictly sequential.
+/// A background task owns the read half: it routes responses to the
+/// matching request by id, answers server requests, and hands server
+/// notifications (e.g. `textDocument/publishDiagnostics`) to the
+/// notification handler. Notifications therefore arrive even while no
+/// request is in flight.
 pub(crate) struct LspTransport {
-    reader: Mutex<BufReader<Box<dyn AsyncRead + Unpin + Send>>>,
-    writer: Mutex<Box<dyn AsyncWrite + Unpin + Send>>,
+    writer: Arc<MessageWriter>,
+    pending: PendingResponses,
     next_id: AtomicI64,
+    reader_task: JoinHandle<()>,
 }
 
 impl LspTransport {
     /// Connect to an LSP server at the given address and port.
-    pub async fn connect(addr: &str, port: u16) -> Result<Self> {
+    pub async fn connect(
+        addr: &str,
+        port: u16,
+        on_notification: NotificationHandler,
+    ) -> Result<Self> {
         debug!(addr = %addr, port = port, "LspTransport: connecting");
         let stream = TcpStream::connect((addr, port))
             .await
@@ -103,28 +124,43 @@ impl LspTransport {
         debug!("LspTransport: connected");
 
         let (read_half, write_half) = stream.into_split();
-        Ok(Self::from_io(read_half, write_half))
+        Ok(Self::from_io(read_half, write_half, on_notification))
     }
 
-    fn from_io<R, W>(reader: R, writer: W) -> Self
+    /// Build a transport over arbitrary I/O halves.
+    ///
+    /// Must be called from within a tokio runtime; the reader task is
+    /// spawned onto it.
+    fn from_io<R, W>(reader: R, writer: W, on_notification: NotificationHandler) -> Self
     where
         R: AsyncRead + Unpin + Send + 'static,
         W: AsyncWrite + Unpin + Send + 'static,
     {
+        let reader: BoxedReader = BufReader::new(Box::new(reader));
+        let writer = Arc::new(MessageWriter {
+            inner: Mutex::new(Box::new(writer)),
+        });
+        let pending: PendingResponses = Arc::new(StdMutex::new(Some(HashMap::new())));
+
+        let reader_task = tokio::spawn(run_reader(
+            reader,
+            Arc::clone(&writer),
+            Arc::clone(&pending),
+            on_notification,
+        ));
+
         Self {
-            reader: Mutex::new(BufReader::new(Box::new(reader))),
-            writer: Mutex::new(Box::new(writer)),
+            writer,
+            pending,
             next_id: AtomicI64::new(1),
+            reader_task,
         }
     }
 
     /// Send a JSON-RPC request and wait for the response.
     ///
-    /// This is a blocking call that holds both reader and writer locks.
-    /// Only one request should be in flight at a time.
-    ///
-    /// Server-initiated notifications (messages without an `id`) are
-    /// skipped automatically so they don't shadow the expected response.
+    /// Server requests and notifications received in the meantime are
+    /// handled by the reader task and never shadow the expected response.
     pub async fn request<P: Serialize, R: for<'de> Deserialize<'de>>(
         &self,
         method: &str,
@@ -148,71 +184,37 @@ impl LspTransport {
         );
         trace!(body = %String::from_utf8_lossy(&body), "LspTransport: request body");
 
-        self.write_message(&body).await?;
-
-        // Read messages until we get a response with a matching id.
-        // Server-initiated notifications (no id) and responses to other
-        // requests (mismatched id) are logged and skipped.
-        loop {
-            let response_body = self.read_message().await?;
-
-            trace!(
-                body = %String::from_utf8_lossy(&response_body),
-                "LspTransport: received message"
-            );
-
-            let message: JsonRpcMessage = serde_json::from_slice(&response_body)
-                .context("Failed to parse JSON-RPC response")?;
+        let (response_tx, response_rx) = oneshot::channel();
+        {
+            let mut pending = self
+                .pending
+                .lock()
+                .expect("pending responses mutex poisoned");
+            let Some(pending) = pending.as_mut() else {
+                return Err(anyhow!("LSP connection closed"));
+            };
+            pending.insert(id, response_tx);
+        }
 
-            if let Some(method) = message.method.as_deref() {
-                if let Some(request_id) = message.id.clone() {
-                    self.handle_server_request(request_id, method, message.params.as_ref())
-                        .await
-                        .with_context(|| {
-                            format!(
-                                "Failed to handle server request '{method}' while waiting for response id={id}"
-                            )
-                        })?;
-                } else {
-                    debug!(
-                        method = %method,
-                        "LspTransport: skipping server notification while waiting for response id={id}"
-                    );
-                }
-                continue;
-            }
+        if let Err(err) = self.writer.write_message(&body).await {
+            self.forget_pending(id);
+            return Err(err);
+        }
 
-            match &message.id {
-                None => {
-                    debug!(
-                        "LspTransport: skipping id-less message while waiting for response id={id}"
-                    );
-                    continue;
-                }
-                Some(resp_id) => {
-                    let expected_id = Value::Number(serde_json::Number::from(id));
-                    if *resp_id != expected_id {
-                        debug!(
-                            expected_id = id,
-                            actual_id = %resp_id,
-                            "LspTransport: skipping response with mismatched id"
-                        );
-                        continue;
-                    }
-                }
-            }
+        let message = response_rx
+            .await
+            .map_err(|_| anyhow!("LSP connection closed while waiting for response id={id}"))?;
 
-            if let Some(err) = message.error {
-                return Err(anyhow!("{}", err));
-            }
+        if let Some(err) = message.error {
+            return Err(anyhow!("{}", err));
+        }
 
-            let result_value = message.result.unwrap_or(Value::Null);
-            let result: R = serde_json::from_value(result_value)
-                .context("Failed to deserialize JSON-RPC result")?;
+        let result_value = message.result.unwrap_or(Value::Null);
+        let result: R = serde_json::from_value(result_value)
+            .context("Failed to deserialize JSON-RPC result")?;
 
-            debug!(method = %method, id = id, "LspTransport: request completed");
-            return Ok(result);
-        }
+        debug!(method = %method, id = id, "LspTransport: request completed");
+        Ok(result)
     }
 
     /// Send a JSON-RPC notification (no response expected).
@@ -233,10 +235,130 @@ impl LspTransport {
         );
         trace!(body = %String::from_utf8_lossy(&body), "LspTransport: notification body");
 
-        self.write_message(&body).await?;
-        Ok(())
+        self.writer.write_message(&body).await
     }
 
+    /// Whether the reader task is still receiving from the server.
+    pub fn is_connected(&self) -> bool {
+        self.pending
+            .lock()
+            .expect("pending responses mutex poisoned")
+            .is_some()
+    }
+
+    /// Stop reading from the server. Requests still waiting fail, and new
+    /// ones fail fast.
+    pub fn close(&self) {
+        self.reader_task.abort();
+        self.pending
+            .lock()
+            .expect("pending responses mutex poisoned")
+            .take();
+    }
+
+    fn forget_pending(&self, id: i64) {
+        let mut pending = self
+            .pending
+            .lock()
+            .expect("pending responses mutex poisoned");
+        if let Some(pending) = pending.as_mut() {
+            pending.remove(&id);
+        }
+    }
+}
+
+impl Drop for LspTransport {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+/// Read messages until the connection closes and dispatch each one.
+async fn run_reader(
+    mut reader: BoxedReader,
+    writer: Arc<MessageWriter>,
+    pending: PendingResponses,
+    on_notification: NotificationHandler,
+) {
+    loop {
+        let body = match read_message(&mut reader).await {
+            Ok(body) => body,
+            Err(err) => {
+                debug!(error = ?err, "LspTransport: reader stopped");
+                break;
+            }
+        };
+
+        trace!(
+            body = %String::from_utf8_lossy(&body),
+            "LspTransport: received message"
+        );
+
+        let message: JsonRpcMessage = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                warn!(error = ?err, "LspTransport: failed to parse JSON-RPC message");
+                continue;
+            }
+        };
+
+        if let Some(method) = message.method.as_deref() {
+            match message.id {
+                Some(request_id) => {
+                    if let Err(err) = writer
+                        .handle_server_request(request_id, method, message.params.as_ref())
+                        .await
+                    {
+                        warn!(
+                            error = ?err,
+                            method = %method,
+                            "LspTransport: failed to handle server request"
+                        );
+                        break;
+                    }
+                }
+                None => {
+                    debug!(method = %method, "LspTransport: received server notification");
+                    on_notification(method, message.params);
+                }
+            }
+            continue;
+        }
+
+        let Some(id) = message.id.as_ref().and_then(Value::as_i64) else {
+            debug!("LspTransport: skipping response without a numeric id");
+            continue;
+        };
+
+        let response_tx = pending
+            .lock()
+            .expect("pending responses mutex poisoned")
+            .as_mut()
+            .and_then(|pending| pending.remove(&id));
+        match response_tx {
+            // The requester may have stopped waiting (timeout); that's fine.
+            Some(response_tx) => {
+                let _ = response_tx.send(message);
+            }
+            None => debug!(id = id, "LspTransport: skipping response with unknown id"),
+        }
+    }
+
+    // Dropping the senders wakes every waiting request with an error.
+    pending
+        .lock()
+        .expect("pending responses mutex poisoned")
+        .take();
+}
+
+/// Write half of the transport, shared by requests and the reader task.
+///
+/// The Mutex keeps framed messages from interleaving.
+struct MessageWriter {
+    inner: Mutex<Box<dyn AsyncWrite + Unpin + Send>>,
+}
+
+impl MessageWriter {
     async fn handle_server_request(
         &self,
         id: Value,
@@ -308,7 +430,7 @@ impl LspTransport {
 
     /// Write a Content-Length framed message.
     async fn write_message(&self, body: &[u8]) -> Result<()> {
-        let mut writer = self.writer.lock().await;
+        let mut writer = self.inner.lock().await;
         let header = format!("Content-Length: {}\r\n\r\n", body.len());
         writer
             .write_all(header.as_bytes())
@@ -321,55 +443,86 @@ impl LspTransport {
         writer.flush().await.context("Failed to flush writer")?;
         Ok(())
     }
+}
+
+/// Read a Content-Length framed message.
+async fn read_message(reader: &mut BoxedReader) -> Result<Vec<u8>> {
+    // Read headers until empty line
+    let mut content_length: Option<usize> = None;
+    loop {
+        let mut header_line = String::new();
+        let bytes_read = reader
+            .read_line(&mut header_line)
+            .await
+            .context("Failed to read message header")?;
+        if bytes_read == 0 {
+            return Err(anyhow!("Connection closed while reading header"));
+        }
+
+        let trimmed = header_line.trim();
+        if trimmed.is_empty() {
+            // End of headers
+            break;
+        }
+
+        if let Some(value) = trimmed.strip_prefix("Content-Length:") {
+            content_length = Some(
+                value
+                    .trim()
+                    .parse::<usize>()
+                    .context("Invalid Content-Length value")?,
+            );
+        }
+        // Ignore other headers (e.g., Content-Type)
+    }
+
+    let length = content_length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
 
-    /// Read a Content-Length framed message.
-    async fn read_message(&self) -> Result<Vec<u8>> {
-        let mut reader = self.reader.lock().await;
+    let mut body = vec![0u8; length];
+    reader
+        .read_exact(&mut body)
+        .await
+        .context("Failed to read message body")?;
+
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, split, ReadHalf, WriteHalf};
 
-        // Read headers until empty line
+    async fn read_framed_message(
+        reader: &mut BufReader<ReadHalf<tokio::io::DuplexStream>>,
+    ) -> Vec<u8> {
         let mut content_length: Option<usize> = None;
         loop {
             let mut header_line = String::new();
-            let bytes_read = reader
-                .read_line(&mut header_line)
-                .await
-                .context("Failed to read message header")?;
-            if bytes_read == 0 {
-                return Err(anyhow!("Connection closed while reading header"));
-            }
+            let bytes_read = reader.read_line(&mut header_line).await.unwrap();
+            assert!(bytes_read > 0);
 
             let trimmed = header_line.trim();
             if trimmed.is_empty() {
-                // End of headers
                 break;
             }
 
             if let Some(value) = trimmed.strip_prefix("Content-Length:") {
-                content_length = Some(
-                    value
-                        .trim()
-                        .parse::<usize>()
-                        .context("Invalid Content-Length value")?,
-                );
+                content_length = Some(value.trim().parse::<usize>().unwrap());
             }
-            // Ignore other headers (e.g., Content-Type)
         }
 
-        let length = content_length.ok_or_else(|| anyhow!("Missing Content-Length header"))?;
-
-        let mut body = vec![0u8; length];
-        reader
-            .read_exact(&mut body)
-            .await
-            .context("Failed to read message body")?;
-
-        Ok(body)
+        let length = content_length.expect("missing content length");
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).await.unwrap();
+        body
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    async fn write_framed_message(writer: &mut WriteHalf<tokio::io::DuplexStream>, body: &[u8]) {
+        let header = format!("Content-Length: {}\r\n\r\n", body.len());
+        writer.write_all(header.as_bytes()).await.unwrap();
+        writer.write_all(body).await.unwrap();
+        writer.flush().await.unwrap();
+    }
 
     #[test]
     fn json_rpc_request_serialization() {
@@ -421,43 +574,6 @@ mod tests {
 
     #[tokio::test]
     async fn request_handles_server_request_before_response() {
-        use tokio::io::{duplex, split, ReadHalf, WriteHalf};
-
-        async fn read_framed_message(
-            reader: &mut BufReader<ReadHalf<tokio::io::DuplexStream>>,
-        ) -> Vec<u8> {
-            let mut content_length: Option<usize> = None;
-            loop {
-                let mut header_line = String::new();
-                let bytes_read = reader.read_line(&mut header_line).await.unwrap();
-                assert!(bytes_read > 0);
-
-                let trimmed = header_line.trim();
-                if trimmed.is_empty() {
-                    break;
-                }
-
-                if let Some(value) = trimmed.strip_prefix("Content-Length:") {
-                    content_length = Some(value.trim().parse::<usize>().unwrap());
-                }
-            }
-
-            let length = content_length.expect("missing content length");
-            let mut body = vec![0; length];
-            reader.read_exact(&mut body).await.unwrap();
-            body
-        }
-
-        async fn write_framed_message(
-            writer: &mut WriteHalf<tokio::io::DuplexStream>,
-            body: &[u8],
-        ) {
-            let header = format!("Content-Length: {}\r\n\r\n", body.len());
-            writer.write_all(header.as_bytes()).await.unwrap();
-            writer.write_all(body).await.unwrap();
-            writer.flush().await.unwrap();
-        }
-
         let (client_stream, server_stream) = duplex(8192);
         let (client_read, client_write) = split(client_stream);
 
@@ -499,7 +615,7 @@ mod tests {
             .await;
         });
 
-        let transport = LspTransport::from_io(client_read, client_write);
+        let transport = LspTransport::from_io(client_read, client_write, Box::new(|_, _| {}));
         let response: Vec<Value> = transport
             .request("textDocument/completion", &serde_json::json!({}))
             .await
@@ -508,4 +624,88 @@ mod tests {
         assert!(response.is_empty());
         server.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn notifications_are_delivered_without_pending_request() {
+        let (client_stream, server_stream) = duplex(8192);
+        let (client_read, client_write) = split(client_stream);
+        let (notification_tx, mut notification_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let _transport = LspTransport::from_io(
+            client_read,
+            client_write,
+            Box::new(move |method, params| {
+                notification_tx.send((method.to_string(), params)).unwrap();
+            }),
+        );
+
+        let (_server_read, mut server_write) = split(server_stream);
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": "inmemory://console.R", "diagnostics": [] }
+        });
+        write_framed_message(
+            &mut server_write,
+            serde_json::to_vec(&notification).unwrap().as_slice(),
+        )
+        .await;
+
+        let (method, params) = notification_rx.recv().await.unwrap();
+        assert_eq!(method, "textDocument/publishDiagnostics");
+        assert_eq!(params.unwrap()["uri"], "inmemory://console.R");
+    }
+
+    #[tokio::test]
+    async fn request_fails_when_connection_closes() {
+        let (client_stream, server_stream) = duplex(8192);
+        let (client_read, client_write) = split(client_stream);
+
+        let server = tokio::spawn(async move {
+            let (read_half, _write_half) = split(server_stream);
+            let mut reader = BufReader::new(read_half);
+            read_framed_message(&mut reader).await;
+            // Dropping both halves closes the connection without replying.
+        });
+
+        let transport = LspTransport::from_io(client_read, client_write, Box::new(|_, _| {}));
+        assert!(transport.is_connected());
+        let result: Result<Value> = transport
+            .request("textDocument/completion", &serde_json::json!({}))
+            .await;
+        server.await.unwrap();
+
+        assert!(result.is_err());
+        assert!(!transport.is_connected());
+        let result: Result<Value> = transport.request("shutdown", &Value::Null).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn close_fails_waiting_requests() {
+        let (client_stream, server_stream) = duplex(8192);
+        let (client_read, client_write) = split(client_stream);
+        let (read_half, _write_half) = split(server_stream);
+        let mut reader = BufReader::new(read_half);
+
+        let transport = Arc::new(LspTransport::from_io(
+            client_read,
+            client_write,
+            Box::new(|_, _| {}),
+        ));
+        let waiting = tokio::spawn({
+            let transport = Arc::clone(&transport);
+            async move {
+                transport
+                    .request::<_, Value>("textDocument/completion", &serde_json::json!({}))
+                    .await
+            }
+        });
+        // The server reads the request but never answers
+        read_framed_message(&mut reader).await;
+
+        transport.close();
+        assert!(waiting.await.unwrap().is_err());
+        assert!(!transport.is_connected());
+    }
 }
@@ -2,16 +2,25 @@ use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::Result;
-use lsp_types::CompletionResponse;
+use lsp_types::{CompletionItem, CompletionResponse, SignatureHelp};
 use tokio::runtime::Handle;
 use tokio::sync::Mutex as AsyncMutex;
 use tokio::time::{sleep_until, Instant};
 use tracing::{debug, warn};
 
+use super::diagnostics::DiagnosticsStore;
+use super::semantic_tokens::SemanticTokensStore;
+use super::signature_help::SignatureHelpStore;
 use super::LspClient;
 
 const VIRTUAL_DOCUMENT_DEBOUNCE: Duration = Duration::from_millis(150);
 
+/// Semantic tokens slower than this are dropped; tree-sitter colors stay.
+const SEMANTIC_TOKENS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Signature help slower than this is dropped; the call gets no hint.
+const SIGNATURE_HELP_TIMEOUT: Duration = Duration::from_millis(1000);
+
 #[derive(Debug, Clone)]
 struct PendingSync {
     generation: u64,
@@ -95,17 +104,24 @@ impl DebounceState {
     fn mark_synced(&mut self, generation: u64) {
         self.last_synced_generation = self.last_synced_generation.max(generation);
     }
+
+    fn is_synced(&self, buffer: &str) -> bool {
+        self.latest_buffer == buffer && self.last_synced_generation == self.latest_generation
+    }
 }
 
 /// Debounced controller for the console's virtual LSP document.
 ///
-/// User edits schedule background `didChange` notifications. Completion requests
-/// always flush the latest buffer first so Ark sees a fully up-to-date document.
+/// User edits schedule background `didChange` notifications, each followed
+/// by a semantic tokens request and, when the hinter asked for one, a
+/// signature help request. Completion requests always flush the latest
+/// buffer first so Ark sees a fully up-to-date document.
 pub(crate) struct DebouncedVirtualDocument {
     client: Arc<LspClient>,
     runtime_handle: Handle,
     send_lock: AsyncMutex<()>,
     state: Mutex<DebounceState>,
+    signature_help: Arc<SignatureHelpStore>,
 }
 
 impl DebouncedVirtualDocument {
@@ -115,6 +131,7 @@ impl DebouncedVirtualDocument {
             runtime_handle,
             send_lock: AsyncMutex::new(()),
             state: Mutex::new(DebounceState::default()),
+            signature_help: Arc::new(SignatureHelpStore::default()),
         })
     }
 
@@ -144,6 +161,16 @@ impl DebouncedVirtualDocument {
         }
     }
 
+    /// Diagnostics Ark published for the console document.
+    pub fn diagnostics(&self) -> Arc<DiagnosticsStore> {
+        self.client.diagnostics()
+    }
+
+    /// Semantic tokens the server computed for the console document.
+    pub fn semantic_tokens(&self) -> Arc<SemanticTokensStore> {
+        self.client.semantic_tokens()
+    }
+
     pub async fn complete(
         &self,
         buffer: &str,
@@ -155,6 +182,29 @@ impl DebouncedVirtualDocument {
             .await
     }
 
+    /// Resolve a completion item; the document doesn't need to be synced.
+    pub async fn resolve_completion(&self, item: &CompletionItem) -> Result<CompletionItem> {
+        self.client.resolve_completion_item(item).await
+    }
+
+    /// Signature help for the call whose arguments start at `pos`, as far
+    /// as the server has answered.
+    ///
+    /// Never waits for the server: an unanswered call is requested after
+    /// the next debounced sync, or right away if `buffer` is already synced.
+    pub fn signature_help(&self, buffer: &str, pos: usize) -> Option<SignatureHelp> {
+        if self.signature_help.want(buffer, pos) {
+            let synced = {
+                let state = self.state.lock().expect("virtual document state mutex poisoned");
+                state.is_synced(buffer)
+            };
+            if synced {
+                self.refresh_signature_help(buffer);
+            }
+        }
+        self.signature_help.for_call(buffer, pos)
+    }
+
     async fn flush_sync(&self, buffer: &str) -> Result<()> {
         let generation = {
             let mut state = self.state.lock().expect("virtual document state mutex poisoned");
@@ -164,7 +214,7 @@ impl DebouncedVirtualDocument {
         debug!(
             generation = generation,
             buffer_len = buffer.len(),
-            "VirtualDocument: flushing pending sync before completion"
+            "VirtualDocument: flushing pending sync before request"
         );
 
         self.sync_generation(generation, buffer.to_string(), "flush")
@@ -190,15 +240,19 @@ impl DebouncedVirtualDocument {
                 continue;
             }
 
-            if let Err(err) = self
+            match self
                 .sync_generation(pending.generation, pending.buffer.clone(), "debounced")
                 .await
             {
-                warn!(
+                Ok(()) => {
+                    self.refresh_semantic_tokens();
+                    self.refresh_signature_help(&pending.buffer);
+                }
+                Err(err) => warn!(
                     error = ?err,
                     generation = pending.generation,
                     "VirtualDocument: debounced sync failed"
-                );
+                ),
             }
 
             let should_exit = {
@@ -214,6 +268,59 @@ impl DebouncedVirtualDocument {
         }
     }
 
+    /// Request semantic tokens in the background, so a slow server delays
+    /// only the extra colors and not the next sync.
+    fn refresh_semantic_tokens(&self) {
+        let client = Arc::clone(&self.client);
+        self.runtime_handle.spawn(async move {
+            match tokio::time::timeout(SEMANTIC_TOKENS_TIMEOUT, client.refresh_semantic_tokens())
+                .await
+            {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => {
+                    debug!(error = ?err, "VirtualDocument: semantic tokens request failed")
+                }
+                Err(_) => debug!(
+                    timeout_ms = SEMANTIC_TOKENS_TIMEOUT.as_millis(),
+                    "VirtualDocument: semantic tokens request timed out"
+                ),
+            }
+        });
+    }
+
+    /// Request the signature the hinter is waiting for, if `synced`
+    /// contains its call, and store the answer in the background.
+    fn refresh_signature_help(&self, synced: &str) {
+        let Some(pos) = self.signature_help.take_request(synced) else {
+            return;
+        };
+        let client = Arc::clone(&self.client);
+        let store = Arc::clone(&self.signature_help);
+        let buffer = synced.to_string();
+        self.runtime_handle.spawn(async move {
+            let help = match tokio::time::timeout(
+                SIGNATURE_HELP_TIMEOUT,
+                client.request_signature_help(&buffer, pos),
+            )
+            .await
+            {
+                Ok(Ok(help)) => help.filter(|help| !help.signatures.is_empty()),
+                Ok(Err(err)) => {
+                    debug!(error = ?err, "VirtualDocument: signature help request failed");
+                    None
+                }
+                Err(_) => {
+                    debug!(
+                        timeout_ms = SIGNATURE_HELP_TIMEOUT.as_millis(),
+                        "VirtualDocument: signature help request timed out"
+                    );
+                    None
+                }
+            };
+            store.publish(&buffer, pos, help);
+        });
+    }
+
     async fn sync_generation(&self, generation: u64, buffer: String, reason: &str) -> Result<()> {
         let _send_guard = self.send_lock.lock().await;
 
@@ -306,4 +413,16 @@ mod tests {
         assert!(!state.should_sync(1));
         assert!(state.should_sync(2));
     }
+
+    #[test]
+    fn buffer_is_synced_once_its_generation_is() {
+        let now = Instant::now();
+        let mut state = DebounceState::default();
+
+        state.schedule("mean(", now, Duration::from_millis(50));
+        assert!(!state.is_synced("mean("));
+        state.mark_synced(1);
+        assert!(state.is_synced("mean("));
+        assert!(!state.is_synced("mean(x"));
+    }
 }
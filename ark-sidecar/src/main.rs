@@ -9,13 +9,14 @@ mod protocol;
 mod types;
 
 use anyhow::{anyhow, Context, Result};
+use runtimelib::ConnectionInfo;
 use tokio::runtime::Builder;
 use tracing::error;
 use uuid::Uuid;
 
 use crate::commands::{decode_code, parse_args};
 use crate::connection::read_connection;
-use crate::console::run_console;
+use crate::console::{load_console_config, run_console, run_history_command, run_monitor};
 use crate::handlers::{run_check, run_execute_request, run_lsp, run_plot_watcher};
 use crate::logging::init_logging;
 use crate::protocol::{emit_event, SidecarEvent};
@@ -23,7 +24,10 @@ use crate::types::{Command, SUPPORTED_SIGNATURE_SCHEME};
 
 fn main() {
     let cli = parse_args();
-    let console_mode = matches!(cli.command, Command::Console { .. });
+    let console_mode = matches!(
+        cli.command,
+        Command::Console { .. } | Command::Monitor { .. }
+    );
     let log_handle = init_logging(console_mode);
     if let Err(err) = run(cli, log_handle) {
         error!(error = ?err, "Ark sidecar error");
@@ -35,35 +39,6 @@ fn main() {
 }
 
 fn run(cli: crate::types::Cli, log_handle: crate::logging::LogReloadHandle) -> Result<()> {
-
-    // Extract connection_file from any command variant
-    let connection_file = match &cli.command {
-        Command::Lsp {
-            connection_file, ..
-        }
-        | Command::Execute {
-            connection_file, ..
-        }
-        | Command::WatchPlot {
-            connection_file, ..
-        }
-        | Command::Check {
-            connection_file, ..
-        }
-        | Command::Console {
-            connection_file, ..
-        } => connection_file,
-    };
-
-    let connection = read_connection(connection_file)?;
-
-    if connection.signature_scheme != SUPPORTED_SIGNATURE_SCHEME {
-        return Err(anyhow!(
-            "Unsupported signature scheme: {}",
-            connection.signature_scheme
-        ));
-    }
-
     let runtime = Builder::new_multi_thread()
         .worker_threads(2)
         .enable_all()
@@ -74,36 +49,74 @@ fn run(cli: crate::types::Cli, log_handle: crate::logging::LogReloadHandle) -> R
         let session_id = Uuid::new_v4().to_string();
         match cli.command {
             Command::Lsp {
+                connection_file,
                 ip_address,
                 timeout_ms,
-                ..
             } => {
+                let connection = kernel_connection(&connection_file)?;
                 run_lsp(&connection, &session_id, &ip_address, timeout_ms).await?;
             }
             Command::Execute {
+                connection_file,
                 code,
                 code_base64,
                 wait_for_idle,
                 timeout_ms,
-                ..
             } => {
+                let connection = kernel_connection(&connection_file)?;
                 let code = decode_code(&code, code_base64)?;
                 run_execute_request(&connection, &session_id, &code, timeout_ms, wait_for_idle)
                     .await?;
             }
-            Command::WatchPlot { timeout_ms, .. } => {
+            Command::WatchPlot {
+                connection_file,
+                timeout_ms,
+            } => {
+                let connection = kernel_connection(&connection_file)?;
                 // timeout_ms is available but run_plot_watcher doesn't use it directly
                 let _ = timeout_ms;
                 run_plot_watcher(&connection, &session_id, log_handle.clone()).await?;
             }
-            Command::Check { timeout_ms, .. } => {
+            Command::Check {
+                connection_file,
+                timeout_ms,
+            } => {
+                let connection = kernel_connection(&connection_file)?;
                 run_check(&connection, &session_id, timeout_ms).await?;
             }
-            Command::Console { r_binary_path, .. } => {
-                run_console(&connection, &session_id, r_binary_path.as_deref()).await?;
+            Command::Console(args) => {
+                let connection = kernel_connection(&args.connection_file)?;
+                let config = load_console_config(&args);
+                run_console(
+                    &connection,
+                    &args.connection_file,
+                    &session_id,
+                    args.r_binary_path.as_deref(),
+                    config,
+                    log_handle.clone(),
+                )
+                .await?;
             }
+            Command::Monitor(args) => {
+                let connection = kernel_connection(&args.connection_file)?;
+                run_monitor(&connection, &session_id, &args).await?;
+            }
+            // History lives in a local database and needs no kernel
+            Command::History { action } => run_history_command(&action)?,
         }
 
         Ok::<(), anyhow::Error>(())
     })
 }
+
+/// Read a kernel's connection file and check it can be spoken to.
+fn kernel_connection(connection_file: &str) -> Result<ConnectionInfo> {
+    let connection = read_connection(connection_file)?;
+    if connection.signature_scheme != SUPPORTED_SIGNATURE_SCHEME {
+        return Err(anyhow!(
+            "Unsupported signature scheme: {}",
+            connection.signature_scheme
+        ));
+    }
+    Ok(connection)
+}
@@ -1,4 +1,5 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
 
 #[derive(Parser, Debug)]
 #[command(
@@ -73,17 +74,232 @@ pub(crate) enum Command {
     },
 
     /// Interactive R console REPL
-    Console {
-        /// Path to Jupyter connection file
+    Console(ConsoleArgs),
+
+    /// Print the code and output of every client's executions, without a prompt
+    Monitor(MonitorArgs),
+
+    /// Manage the console history database
+    History {
+        #[command(subcommand)]
+        action: HistoryAction,
+    },
+}
+
+/// Operations on the console history database.
+#[derive(Subcommand, Debug)]
+pub(crate) enum HistoryAction {
+    /// Import an `.Rhistory` file
+    Import {
+        /// Path to the `.Rhistory` file
+        path: String,
+
+        /// Project to file the entries under (defaults to the current directory)
         #[arg(long)]
-        connection_file: String,
+        project: Option<String>,
+    },
 
-        /// Path to the R binary (displayed in startup banner)
+    /// Print the history to stdout
+    Export {
+        /// Output format
+        #[arg(long, value_enum, default_value_t = HistoryExportFormat::Rhistory)]
+        format: HistoryExportFormat,
+
+        /// Only export entries from this project directory
         #[arg(long)]
-        r_binary_path: Option<String>,
+        project: Option<String>,
     },
 }
 
+/// Output format of `history export`.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum HistoryExportFormat {
+    /// One command per line, as written by R's `savehistory()`
+    #[default]
+    Rhistory,
+    Json,
+}
+
+/// Arguments for the interactive console.
+///
+/// Options left unset fall back to the console config file
+/// (`$XDG_CONFIG_HOME/krarkode/console.json`).
+#[derive(Args, Debug)]
+pub(crate) struct ConsoleArgs {
+    /// Path to Jupyter connection file
+    #[arg(long)]
+    pub(crate) connection_file: String,
+
+    /// Path to the R binary (displayed in startup banner)
+    #[arg(long)]
+    pub(crate) r_binary_path: Option<String>,
+
+    /// Line editing keymap
+    #[arg(long, value_enum)]
+    pub(crate) edit_mode: Option<ConsoleEditMode>,
+
+    /// Left prompt template; `{cwd}` and `{env}` expand to kernel state
+    #[arg(long)]
+    pub(crate) prompt: Option<String>,
+
+    /// How completion candidates are matched against the typed word
+    #[arg(long, value_enum)]
+    pub(crate) completion_matching: Option<CompletionMatching>,
+
+    /// Don't suggest the rest of the line from history
+    #[arg(long)]
+    pub(crate) no_history_hints: bool,
+
+    /// Terminal graphics protocol for inline plots
+    #[arg(long, value_enum)]
+    pub(crate) image_protocol: Option<ImageProtocol>,
+
+    /// Prefer HTML and markdown output over plain text
+    #[arg(long)]
+    pub(crate) rich_output: bool,
+
+    /// What to do with output longer than --max-output-lines
+    #[arg(long, value_enum)]
+    pub(crate) long_output: Option<LongOutput>,
+
+    /// Lines of an execution's output printed before the rest is held back
+    #[arg(long)]
+    pub(crate) max_output_lines: Option<usize>,
+
+    /// Don't show a spinner while R is busy
+    #[arg(long)]
+    pub(crate) no_spinner: bool,
+
+    /// Seconds after which a finished execution prints its duration and
+    /// notifies the terminal (0 turns this off)
+    #[arg(long)]
+    pub(crate) notify_after: Option<u64>,
+
+    /// How long executions notify the terminal when they finish
+    #[arg(long, value_enum)]
+    pub(crate) notification: Option<Notification>,
+
+    /// Also print executions from other clients of the kernel, such as the editor
+    #[arg(long)]
+    pub(crate) follow: bool,
+
+    /// Show R help pages in the console instead of the editor's help panel
+    #[arg(long)]
+    pub(crate) help_pages: bool,
+
+    /// Keep the console open and reattach when the kernel disconnects
+    #[arg(long)]
+    pub(crate) auto_reconnect: bool,
+
+    /// Listen for code sent by the editor on a socket next to the
+    /// connection file
+    #[arg(long)]
+    pub(crate) inject_socket: bool,
+
+    /// Append a transcript of each execution's input and output to this file
+    #[arg(long)]
+    pub(crate) transcript: Option<String>,
+
+    /// Transcript format (default: jsonl for `.jsonl` files, log otherwise)
+    #[arg(long, value_enum)]
+    pub(crate) transcript_format: Option<TranscriptFormat>,
+}
+
+/// Arguments for `monitor`, which mirrors executions without a prompt.
+///
+/// Output settings left unset fall back to the console config file.
+#[derive(Args, Debug)]
+pub(crate) struct MonitorArgs {
+    /// Path to Jupyter connection file
+    #[arg(long)]
+    pub(crate) connection_file: String,
+
+    /// Terminal graphics protocol for inline plots
+    #[arg(long, value_enum)]
+    pub(crate) image_protocol: Option<ImageProtocol>,
+
+    /// Prefer HTML and markdown output over plain text
+    #[arg(long)]
+    pub(crate) rich_output: bool,
+}
+
+/// Keymap used by the console line editor.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ConsoleEditMode {
+    #[default]
+    Emacs,
+    Vi,
+}
+
+/// How console completion matches candidates against the typed word.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum CompletionMatching {
+    /// Candidates starting with the word, falling back to ignoring case
+    #[default]
+    Prefix,
+    /// Candidates containing the word's characters in order, best first
+    Fuzzy,
+}
+
+/// How the console draws image output.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ImageProtocol {
+    /// Pick a protocol from the terminal's environment variables
+    #[default]
+    Auto,
+    /// Kitty graphics protocol (kitty, Ghostty)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm, VS Code)
+    Iterm2,
+    /// Sixel graphics (foot, mlterm, xterm -ti vt340)
+    Sixel,
+    /// Print a placeholder instead of the image
+    None,
+}
+
+/// How the console handles an execution's output past the line limit.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LongOutput {
+    /// Print everything
+    #[default]
+    Print,
+    /// Stop at the limit and point to `:more`
+    Truncate,
+    /// Stop at the limit and open the full output in the pager
+    Page,
+}
+
+/// How the console notifies the terminal that a long execution finished.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Notification {
+    /// Pick an escape from the terminal's environment variables
+    #[default]
+    Auto,
+    /// OSC 9 desktop notification (iTerm2, WezTerm, Ghostty, kitty)
+    Osc9,
+    /// OSC 777 desktop notification (VTE terminals, urxvt)
+    Osc777,
+    /// Ring the terminal bell
+    Bell,
+    /// No notification
+    None,
+}
+
+/// File format of console transcripts.
+#[derive(ValueEnum, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TranscriptFormat {
+    /// R-style log with `> ` and `+ ` prompts
+    Log,
+    /// JSON lines named after Jupyter message types
+    Jsonl,
+}
+
 pub(crate) const LSP_COMM_TARGET: &str = "positron.lsp";
 pub(crate) const PLOT_COMM_TARGET: &str = "positron.plot";
 pub(crate) const UI_COMM_TARGET: &str = "positron.ui";